@@ -0,0 +1,93 @@
+//! Timings for the codec hot paths: every USB read goes through
+//! `usbmidi_unpack`, the splitter and `decode_event`, and every write
+//! through command encoding and `usbmidi_pack`.
+//!
+//! Run with `cargo bench`. This is a plain timing loop rather than a
+//! statistics harness, good enough to compare before and after a change on
+//! the same machine.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use automap::automap::sysex::decode_frame;
+use automap::{AutomapCommand, AutomapEvent, AutomapSysEx, Encoder, LcdLine, LcdOp, RingMode};
+
+// The USB-MIDI helpers are crate-private; pull the module in directly.
+#[allow(dead_code, unused_imports)]
+#[path = "../src/midi.rs"]
+mod midi;
+
+const TARGET: Duration = Duration::from_millis(300);
+
+fn bench<T>(name: &str, mut f: impl FnMut() -> T) {
+    for _ in 0..1_000 {
+        black_box(f());
+    }
+    let mut iters = 0u64;
+    let start = Instant::now();
+    while start.elapsed() < TARGET {
+        for _ in 0..1_000 {
+            black_box(f());
+        }
+        iters += 1_000;
+    }
+    let ns = start.elapsed().as_nanos() as f64 / iters as f64;
+    println!("{name:<32} {ns:>10.1} ns/iter");
+}
+
+fn main() {
+    // A full 64-byte transfer of encoder turns, as when spinning all eight
+    let turns: Vec<u8> = (0..16).flat_map(|i| [0xBF, 0x78 + (i % 8), 0x01]).collect();
+    let packets = midi::usbmidi_pack(&turns);
+    let lcd = AutomapSysEx::LcdText(vec![
+        LcdOp::Cursor {
+            col: 0,
+            line: LcdLine::LeftTop,
+        },
+        LcdOp::Text(b"Volume   Pan      Send A   Send B   Volume   Pan      Send A   "),
+        LcdOp::End,
+    ])
+    .to_bytes();
+
+    bench("usbmidi_pack (16 CCs)", || {
+        midi::usbmidi_pack(black_box(&turns))
+    });
+    bench("usbmidi_unpack (64 bytes)", || {
+        midi::usbmidi_unpack(black_box(&packets))
+    });
+    bench("MidiMessages (16 CCs)", || {
+        midi::MidiMessages::new(black_box(&turns)).count()
+    });
+    bench("split_midi_messages (16 CCs)", || {
+        midi::split_midi_messages(black_box(&turns))
+    });
+    bench("MidiStream::push_with (16 CCs)", || {
+        let mut n = 0;
+        midi::MidiStream::default().push_with(black_box(&turns), |_| n += 1);
+        n
+    });
+    bench("decode_event", || {
+        AutomapEvent::decode_event(black_box(&[0xBF, 0x7A, 0x41]))
+    });
+    bench("AutomapCommand::to_bytes", || {
+        black_box(AutomapCommand::EncoderRingMode {
+            encoder: Encoder::Encoder3,
+            mode: RingMode::CenteredBand,
+        })
+        .to_bytes()
+    });
+    bench("AutomapSysEx::to_bytes (LCD line)", || {
+        AutomapSysEx::LcdText(vec![
+            LcdOp::Cursor {
+                col: 0,
+                line: LcdLine::LeftTop,
+            },
+            LcdOp::Text(black_box(b"Volume   Pan      Send A   Send B   ")),
+            LcdOp::End,
+        ])
+        .to_bytes()
+    });
+    bench("decode_frame (LCD line)", || {
+        decode_frame(black_box(&lcd)).is_ok()
+    });
+}
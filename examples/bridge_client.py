@@ -0,0 +1,51 @@
+#!/usr/bin/env python3
+"""Drives the surface through the line bridge, as a DAW remote script would.
+
+Start the bridge next to the unit first:
+
+    cargo run --features cli,net -- bridge 127.0.0.1:7001
+
+Then run this script. Buttons A1-A8 toggle their LEDs, and turning an
+encoder moves its ring and shows the value above it on the left display.
+Only the standard library is used, so the same code works inside a host's
+embedded Python.
+"""
+
+import socket
+
+ADDR = ("127.0.0.1", 7001)
+
+
+def parse(line):
+    """Splits `type key=value ...` into the type and a dict of fields."""
+    ty, *fields = line.split(" ")
+    return ty, dict(field.split("=", 1) for field in fields)
+
+
+def main():
+    conn = socket.create_connection(ADDR)
+    send = lambda line: conn.sendall((line + "\n").encode())
+    lit = set()
+    values = [0] * 8
+
+    send("all_leds_off")
+    for line in conn.makefile("r", encoding="utf-8"):
+        ty, fields = parse(line.rstrip("\n"))
+        if ty == "error":
+            print("bridge:", line.strip())
+        elif ty == "button" and fields["pressed"] == "true":
+            button = fields["button"]
+            if not button.startswith("ButtonA"):
+                continue
+            on = button not in lit
+            (lit.add if on else lit.discard)(button)
+            send(f"button_led button={button} on={'true' if on else 'false'}")
+        elif ty == "encoder":
+            n = int(fields["encoder"].removeprefix("Encoder")) - 1
+            values[n] = max(0, min(11, values[n] + int(fields["clicks"])))
+            send(f"ring_value encoder={fields['encoder']} position={values[n]}")
+            send(f"lcd_text line=LeftBottom col={n * 9} text={values[n]:<8}")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,161 @@
+#ifndef AUTOMAP_H
+#define AUTOMAP_H
+
+/* Generated by cbindgen from src/automap/capi.rs. Do not edit. */
+
+#include <stdbool.h>
+#include <stdint.h>
+
+/**
+ * Bits of [`AutomapCEvent::group`], as in [`EventFilter`].
+ */
+#define AUTOMAP_GROUP_BUTTONS (1 << 0)
+
+#define AUTOMAP_GROUP_TRANSPORT (1 << 1)
+
+#define AUTOMAP_GROUP_ENCODERS (1 << 2)
+
+#define AUTOMAP_GROUP_POTS (1 << 3)
+
+#define AUTOMAP_GROUP_SLIDERS (1 << 4)
+
+#define AUTOMAP_GROUP_CROSSFADER (1 << 5)
+
+#define AUTOMAP_GROUP_SPEED_DIAL (1 << 6)
+
+#define AUTOMAP_GROUP_TOUCH (1 << 7)
+
+#define AUTOMAP_GROUP_TOUCHPAD (1 << 8)
+
+#define AUTOMAP_GROUP_PEDALS (1 << 9)
+
+#define AUTOMAP_GROUP_TEMPO (1 << 10)
+
+#define AUTOMAP_GROUP_DEVICE (1 << 11)
+
+/**
+ * What a call did.
+ */
+typedef enum AutomapStatus {
+  AUTOMAP_STATUS_OK = 0,
+  /**
+   * No event arrived within the timeout.
+   */
+  AUTOMAP_STATUS_TIMEOUT = 1,
+  /**
+   * A null handle or string, or a control, line or column that does not
+   * exist.
+   */
+  AUTOMAP_STATUS_INVALID_ARGUMENT = -1,
+  /**
+   * The unit could not be reached, or a USB transfer failed.
+   */
+  AUTOMAP_STATUS_IO = -2,
+} AutomapStatus;
+
+/**
+ * An open unit. Opaque to C.
+ */
+typedef struct AutomapCDevice AutomapCDevice;
+
+/**
+ * One event from the unit.
+ */
+typedef struct AutomapCEvent {
+  /**
+   * One of the `AUTOMAP_GROUP_*` bits.
+   */
+  uint16_t group;
+  /**
+   * The MIDI message as the unit sent it: a CC on the Automap channel,
+   * whose number in `data1` tells the control, or pitch bend.
+   */
+  uint8_t status;
+  uint8_t data1;
+  uint8_t data2;
+  /**
+   * Signed clicks of an encoder or the speed dial, the bend of the
+   * pitch wheel from -8192 to 8191, and `data2` for everything else.
+   */
+  int32_t value;
+  /**
+   * Microseconds from opening the device to the read that brought the
+   * event.
+   */
+  uint64_t time_us;
+} AutomapCEvent;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * The message of the last failure on this thread, empty if there was
+ * none. The string stays valid until the next failing call on the same
+ * thread.
+ */
+const char *automap_last_error(void);
+
+/**
+ * Opens the unit with serial number `serial`, or the first one found if
+ * `serial` is null, and puts it online. Returns null on failure.
+ *
+ * # Safety
+ *
+ * `serial` must be null or a NUL-terminated string.
+ */
+struct AutomapCDevice *automap_open(const char *serial);
+
+/**
+ * Clears the surface, tells the unit the host went offline, and frees
+ * `dev`. The handle is freed even if that fails. Null is ignored.
+ *
+ * # Safety
+ *
+ * `dev` must be null or a handle from [`automap_open()`] that has not
+ * been closed, and is not used again.
+ */
+enum AutomapStatus automap_close(struct AutomapCDevice *dev);
+
+/**
+ * Waits up to `timeout_ms` milliseconds for the next event and writes it
+ * to `out`. With a timeout of 0 only events already received are
+ * returned.
+ *
+ * # Safety
+ *
+ * `dev` must be a live handle, and `out` null or valid for writes.
+ */
+enum AutomapStatus automap_poll_event(struct AutomapCDevice *dev,
+                                      struct AutomapCEvent *out,
+                                      uint32_t timeout_ms);
+
+/**
+ * Switches the LED of the button with CC number `button` (0x18 for A1 to
+ * 0x37 for D8) on or off.
+ *
+ * # Safety
+ *
+ * `dev` must be a live handle.
+ */
+enum AutomapStatus automap_send_led(struct AutomapCDevice *dev, uint8_t button, bool on);
+
+/**
+ * Writes `text` on LCD line `line` (1 left top, 2 right top, 3 left
+ * bottom, 4 right bottom) from column `col` (0 to 71). Text past the end
+ * of the line is cut off.
+ *
+ * # Safety
+ *
+ * `dev` must be a live handle and `text` a NUL-terminated string.
+ */
+enum AutomapStatus automap_send_lcd_text(struct AutomapCDevice *dev,
+                                         uint8_t line,
+                                         uint8_t col,
+                                         const char *text);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* AUTOMAP_H */
@@ -0,0 +1,175 @@
+//! A ready-made event loop for applications driving the surface.
+//!
+//! Implement [`SurfaceApp`] and hand it to [`SurfaceRunner::run()`]. The
+//! runner owns the device: it dispatches events, calls
+//! [`tick()`](SurfaceApp::tick) at a fixed interval, asks the app to
+//! [`render()`](SurfaceApp::render) a [`SurfaceFrame`] and sends only what
+//! changed since the last frame. If the unit goes away it keeps reopening
+//! it and redraws everything once it is back.
+
+use std::time::{Duration, Instant};
+
+use crate::automap::command::AutomapCommand;
+use crate::automap::config::DeviceConfig;
+use crate::automap::device::AutomapDevice;
+use crate::automap::error::AutomapError;
+use crate::automap::lcd::{LcdBuffer, LcdScreen};
+use crate::automap::leds::LedState;
+use crate::automap::rt;
+use crate::automap::sysex::LcdLine;
+use crate::automap::timed::TimedEvent;
+
+/// Whether the runner should keep going.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    /// Close the device and return from [`SurfaceRunner::run()`].
+    Exit,
+}
+
+/// Everything the surface should show: LCD text and LED states.
+///
+/// Apps may redraw the whole frame each time; the runner works out what
+/// actually changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SurfaceFrame {
+    pub lcd: LcdScreen,
+    pub leds: LedState,
+}
+
+impl SurfaceFrame {
+    /// Writes `text` on the LCD at `col`.
+    pub fn text(&mut self, line: LcdLine, col: usize, text: impl AsRef<[u8]>) {
+        self.lcd.write(line, col, text.as_ref());
+    }
+
+    /// Sets an LED or ring as `cmd` would. Other commands are ignored.
+    pub fn led(&mut self, cmd: &AutomapCommand) {
+        self.leds.apply(cmd);
+    }
+}
+
+/// An application run by [`SurfaceRunner`].
+///
+/// The runner keeps the previous frame and passes it back to
+/// [`render()`](Self::render), so an app may either redraw from scratch or
+/// just update what it knows has changed.
+pub trait SurfaceApp {
+    /// Handles an event from the surface.
+    fn on_event(&mut self, event: &TimedEvent) -> Flow;
+
+    /// Draws what the surface should show now.
+    fn render(&mut self, frame: &mut SurfaceFrame);
+
+    /// Called every [`tick_interval`](SurfaceRunner::tick_interval), for
+    /// animation or polling the host application.
+    fn tick(&mut self, _now: Instant) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called each time the device is (re)opened, before the first frame.
+    fn on_connect(&mut self, _device: &mut AutomapDevice) {}
+
+    /// Called when the device is lost, before reconnecting.
+    fn on_disconnect(&mut self, _error: &std::io::Error) {}
+}
+
+/// Owns the device and drives a [`SurfaceApp`].
+#[derive(Debug, Clone)]
+pub struct SurfaceRunner {
+    config: DeviceConfig,
+    tick_interval: Duration,
+    reconnect_delay: Duration,
+}
+
+impl SurfaceRunner {
+    /// Defaults: a tick every 50 ms, a reconnect attempt every second.
+    pub fn new(config: DeviceConfig) -> Self {
+        SurfaceRunner {
+            config,
+            tick_interval: Duration::from_millis(50),
+            reconnect_delay: Duration::from_secs(1),
+        }
+    }
+
+    /// How often [`SurfaceApp::tick()`] is called.
+    pub fn tick_interval(mut self, interval: Duration) -> Self {
+        self.tick_interval = interval;
+        self
+    }
+
+    /// How long to wait between attempts to reopen a lost device.
+    pub fn reconnect_delay(mut self, delay: Duration) -> Self {
+        self.reconnect_delay = delay;
+        self
+    }
+
+    /// Runs `app` until it returns [`Flow::Exit`].
+    ///
+    /// The device is then closed, which clears the surface and goes offline
+    /// if the config asks for it.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the device cannot be opened in the first place; later
+    /// disconnects are retried instead.
+    pub async fn run<A: SurfaceApp>(&self, app: &mut A) -> Result<(), AutomapError> {
+        let mut device = self.config.open().await?;
+        let mut frame = SurfaceFrame::default();
+        loop {
+            app.on_connect(&mut device);
+            match self.session(app, &mut device, &mut frame).await {
+                Ok(()) => return Ok(device.close().await?),
+                Err(e) => app.on_disconnect(&e),
+            }
+            device = loop {
+                rt::sleep(self.reconnect_delay).await;
+                if let Ok(device) = self.config.open().await {
+                    break device;
+                }
+            };
+        }
+    }
+
+    /// Runs the event loop on one open device until the app exits or I/O fails.
+    async fn session<A: SurfaceApp>(
+        &self,
+        app: &mut A,
+        device: &mut AutomapDevice,
+        frame: &mut SurfaceFrame,
+    ) -> Result<(), std::io::Error> {
+        // Unknown on a fresh device, so the first frame is drawn in full. LEDs
+        // are diffed against the device's own shadow.
+        let mut lcd = LcdBuffer::new();
+        let mut next_tick = Instant::now() + self.tick_interval;
+        loop {
+            app.render(frame);
+            for cmd in device.leds().commands_to(&frame.leds) {
+                device.send_command(&cmd).await?;
+            }
+            lcd.back().clone_from(&frame.lcd);
+            if let Some(msg) = lcd.flush()
+                && let Err(e) = device.send_sysex(msg).await
+            {
+                lcd.invalidate();
+                return Err(e);
+            }
+
+            let wait = next_tick.saturating_duration_since(Instant::now());
+            if let Some(events) = rt::timeout(wait, device.read_timed_events()).await {
+                for event in events? {
+                    if app.on_event(&event) == Flow::Exit {
+                        return Ok(());
+                    }
+                }
+            }
+            let now = Instant::now();
+            if now >= next_tick {
+                next_tick = now + self.tick_interval;
+                if app.tick(now) == Flow::Exit {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! A line protocol for DAW remote scripts.
+//!
+//! Scripting hosts such as Python remote scripts or Lua extensions can
+//! usually open a TCP socket and read lines, but not speak USB. The bridge
+//! gives them the surface as plain text, one message per line, with the
+//! same types and fields as the [JSON format](crate::automap::json):
+//!
+//! ```text
+//! < encoder encoder=Encoder1 clicks=-2
+//! < button button=ButtonA1 pressed=true
+//! > button_led button=ButtonA1 on=true
+//! > ring_value encoder=Encoder1 position=5
+//! > lcd_text line=LeftTop col=0 text=Master volume
+//! ```
+//!
+//! A line is the type followed by `key=value` fields separated by spaces.
+//! Values are control names, integers, or `true` and `false`; `text` takes
+//! the rest of the line, spaces included, so it comes last. A line the
+//! bridge cannot use, or one the unit cannot take right now, is answered
+//! with `error <reason>`.
+//!
+//! `serve_lines()` (`net` feature) runs the bridge on a TCP port, and
+//! `examples/bridge_client.py` is a script driving it. Other transports,
+//! say a pipe to a child process, need only [`LineDecoder`] and the two
+//! conversions.
+
+use std::fmt::Write;
+
+use crate::automap::event::AutomapEvent;
+use crate::automap::json::{JsonError, JsonRequest, Value, event_fields, request_from_fields};
+
+/// Longest line read from a script, newline excluded.
+pub const MAX_LINE: usize = 1024;
+
+/// Encodes an event as one line, without the newline.
+pub fn event_to_line(event: &AutomapEvent) -> String {
+    let (ty, fields) = event_fields(event);
+    let mut out = ty.to_owned();
+    for (key, value) in fields {
+        let _ = match value {
+            Value::Str(s) => write!(out, " {key}={s}"),
+            Value::Int(n) => write!(out, " {key}={n}"),
+            Value::Bool(b) => write!(out, " {key}={b}"),
+        };
+    }
+    out
+}
+
+/// Parses a line from a script, with or without its line ending.
+///
+/// # Errors
+///
+/// Fails on an empty line or a field without `=`, an unknown type, or a
+/// missing or invalid field.
+pub fn parse_line(line: &str) -> Result<JsonRequest, JsonError> {
+    let mut rest = line.trim_end_matches(['\r', '\n']).trim_start();
+    let ty = next_word(&mut rest).ok_or(JsonError::Syntax)?;
+    let mut fields = vec![("type".to_owned(), Value::Str(ty.to_owned()))];
+    while let Some(word) = next_word(&mut rest) {
+        let (key, value) = word.split_once('=').ok_or(JsonError::Syntax)?;
+        let value = if key == "text" {
+            // `rest` starts right after the word, at its separator
+            let text = format!("{value}{rest}");
+            rest = "";
+            Value::Str(text)
+        } else if let Ok(n) = value.parse() {
+            Value::Int(n)
+        } else {
+            match value {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                _ => Value::Str(value.to_owned()),
+            }
+        };
+        fields.push((key.to_owned(), value));
+    }
+    request_from_fields(&fields)
+}
+
+/// Splits the first space-separated word off `rest`, leaving `rest` at the
+/// separator after it.
+fn next_word<'a>(rest: &mut &'a str) -> Option<&'a str> {
+    let s = rest.trim_start_matches(' ');
+    if s.is_empty() {
+        return None;
+    }
+    let end = s.find(' ').unwrap_or(s.len());
+    *rest = &s[end..];
+    Some(&s[..end])
+}
+
+/// Splits a byte stream into lines.
+#[derive(Debug, Clone, Default)]
+pub struct LineDecoder {
+    buf: Vec<u8>,
+    /// Set while skipping the rest of a line longer than [`MAX_LINE`].
+    overlong: bool,
+}
+
+impl LineDecoder {
+    /// Feeds received bytes, returning the lines completed by them, or
+    /// `None` in place of each line that was too long or not UTF-8.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Option<String>> {
+        let mut out = Vec::new();
+        for &b in bytes {
+            if b == b'\n' {
+                let line = std::mem::take(&mut self.buf);
+                let overlong = std::mem::take(&mut self.overlong);
+                out.push(
+                    String::from_utf8(line)
+                        .ok()
+                        .filter(|_| !overlong)
+                        .map(|l| l.trim_end_matches('\r').to_owned()),
+                );
+            } else if self.buf.len() < MAX_LINE {
+                self.buf.push(b);
+            } else {
+                self.overlong = true;
+            }
+        }
+        out
+    }
+}
+
+#[cfg(feature = "net")]
+pub use serve::serve_lines;
+
+#[cfg(feature = "net")]
+mod serve {
+    use std::io;
+
+    #[cfg(feature = "smol")]
+    use futures_lite::{AsyncReadExt, AsyncWriteExt};
+    #[cfg(feature = "smol")]
+    use smol::net::{TcpListener, TcpStream};
+    #[cfg(feature = "tokio")]
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    #[cfg(feature = "tokio")]
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::{LineDecoder, MAX_LINE, event_to_line, parse_line};
+    use crate::automap::device::AutomapDevice;
+    use crate::automap::error::{AutomapError, Recovery};
+    use crate::automap::json::JsonRequest;
+    use crate::automap::rt;
+    use crate::automap::sysex::{AutomapSysEx, LcdOp};
+    use crate::automap::timed::TimedEvent;
+
+    /// Serves `device` to line-protocol scripts connecting to `addr`, e.g.
+    /// `"127.0.0.1:7001"`.
+    ///
+    /// Scripts are served one at a time, as [`serve()`](crate::serve)
+    /// serves its clients. There is no authentication, so bind to the
+    /// loopback address unless the network is trusted.
+    ///
+    /// # Errors
+    ///
+    /// Returns when binding fails or the device itself fails.
+    pub async fn serve_lines(device: &mut AutomapDevice, addr: &str) -> Result<(), io::Error> {
+        let listener = TcpListener::bind(addr).await?;
+        loop {
+            let (stream, _) = listener.accept().await?;
+            serve_script(device, stream).await?;
+        }
+    }
+
+    enum Step {
+        Device(Result<Vec<TimedEvent>, io::Error>),
+        Script(Result<usize, io::Error>),
+    }
+
+    /// Bridges one script until it disconnects. Only device errors are
+    /// returned.
+    async fn serve_script(
+        device: &mut AutomapDevice,
+        mut stream: TcpStream,
+    ) -> Result<(), io::Error> {
+        let mut decoder = LineDecoder::default();
+        let mut buf = [0u8; 512];
+        loop {
+            let step = rt::race(
+                async { Step::Device(device.read_timed_events().await) },
+                async { Step::Script(stream.read(&mut buf).await) },
+            )
+            .await;
+            let mut out = String::new();
+            match step {
+                Step::Device(events) => {
+                    for timed in events? {
+                        out.push_str(&event_to_line(&timed.event));
+                        out.push('\n');
+                    }
+                }
+                Step::Script(Ok(0) | Err(_)) => return Ok(()),
+                Step::Script(Ok(n)) => {
+                    for line in decoder.push(&buf[..n]) {
+                        let Some(line) = line else {
+                            out.push_str(&format!(
+                                "error line longer than {MAX_LINE} bytes or not UTF-8\n"
+                            ));
+                            continue;
+                        };
+                        if line.trim().is_empty() {
+                            continue;
+                        }
+                        let result = match parse_line(&line) {
+                            Ok(request) => apply(device, request).await,
+                            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidInput, e)),
+                        };
+                        // The script hears of what it can fix, like LCD
+                        // text sent while offline; the rest ends the bridge
+                        match result {
+                            Ok(()) => {}
+                            Err(e)
+                                if e.kind() == io::ErrorKind::InvalidInput
+                                    || AutomapError::recovery_of(&e) == Recovery::Retry =>
+                            {
+                                out.push_str(&format!("error {e}\n"));
+                            }
+                            Err(e) => return Err(e),
+                        }
+                    }
+                }
+            }
+            if !out.is_empty() && stream.write_all(out.as_bytes()).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn apply(device: &mut AutomapDevice, request: JsonRequest) -> Result<(), io::Error> {
+        match request {
+            JsonRequest::Command(cmd) => device.send_command(&cmd).await,
+            JsonRequest::LcdText { line, col, text } => {
+                device
+                    .send_sysex(AutomapSysEx::LcdText(vec![
+                        LcdOp::Cursor { col, line },
+                        LcdOp::Text(text.as_bytes()),
+                        LcdOp::End,
+                    ]))
+                    .await
+            }
+            // Nothing to authorize on this transport
+            JsonRequest::Auth { .. } => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::cc::{Button, Encoder};
+    use crate::automap::command::AutomapCommand;
+    use crate::automap::sysex::LcdLine;
+
+    #[test]
+    fn test_lines_both_ways() {
+        let turn = AutomapEvent::Encoder {
+            encoder: Encoder::Encoder1,
+            clicks: -2,
+        };
+        assert_eq!(event_to_line(&turn), "encoder encoder=Encoder1 clicks=-2");
+
+        assert_eq!(
+            parse_line("button_led button=ButtonA1 on=true\r\n"),
+            Ok(JsonRequest::Command(AutomapCommand::ButtonLed {
+                button: Button::ButtonA1,
+                on: true,
+            }))
+        );
+        assert_eq!(
+            parse_line("lcd_text line=LeftTop col=9 text=Master  volume 2"),
+            Ok(JsonRequest::LcdText {
+                line: LcdLine::LeftTop,
+                col: 9,
+                text: "Master  volume 2".to_owned(),
+            })
+        );
+        assert_eq!(parse_line(""), Err(JsonError::Syntax));
+        assert_eq!(parse_line("button_led ButtonA1"), Err(JsonError::Syntax));
+        assert_eq!(
+            parse_line("button_led button=ButtonZ9 on=true"),
+            Err(JsonError::BadField("button"))
+        );
+
+        let mut lines = LineDecoder::default();
+        assert_eq!(
+            lines.push(b"all_leds_off\r\nech"),
+            [Some("all_leds_off".to_owned())]
+        );
+        assert_eq!(
+            lines.push(b"o value=1\n"),
+            [Some("echo value=1".to_owned())]
+        );
+        let long = vec![b'x'; MAX_LINE + 1];
+        assert_eq!(lines.push(&long), []);
+        assert_eq!(lines.push(b"\n"), [None]);
+    }
+}
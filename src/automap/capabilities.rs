@@ -0,0 +1,145 @@
+//! What the connected unit can do, so applications can adapt their layout.
+
+use crate::automap::cc::ProductType;
+use crate::automap::consts::ZERO_MKII_PRODUCT_ID;
+use crate::automap::event::AutomapEvent;
+use crate::automap::lcd::{LCD_COLUMNS, LCD_LINES};
+
+/// Novation units speaking the Automap protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    /// SL MkII keyboards (and the RemoteSL, which reports the same type).
+    SlMkII,
+    /// The original ZeRO SL.
+    ZeroSl,
+    /// ZeRO SL MkII.
+    ZeroMkII,
+    /// Remote SL Compact.
+    Compact,
+}
+
+impl Model {
+    /// Identifies the model from the Unit-Product-Type reply.
+    ///
+    /// The unit answers the same for both ZeRO generations, so the USB
+    /// product id tells them apart.
+    pub fn identify(product: ProductType, usb_product_id: u16) -> Model {
+        match product {
+            ProductType::RemoteSLorSLMKII => Model::SlMkII,
+            ProductType::ZeroSLorZeroMKII if usb_product_id == ZERO_MKII_PID => Model::ZeroMkII,
+            ProductType::ZeroSLorZeroMKII => Model::ZeroSl,
+            ProductType::Compact => Model::Compact,
+        }
+    }
+}
+
+const ZERO_MKII_PID: u16 = ZERO_MKII_PRODUCT_ID;
+
+/// Features of the connected unit.
+///
+/// Filled in by [`for_model()`](Self::for_model). What the older units lack
+/// follows the manual's notes, e.g. "AllLedsOff not implemented on the
+/// RemoteSL+ZeroSL".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub model: Model,
+    /// USB `bcdDevice` of the unit, e.g. `0x0102` for firmware 1.02.
+    pub firmware_version: u16,
+    pub has_keyboard: bool,
+    pub has_drumpads: bool,
+    pub has_touchpad: bool,
+    pub has_crossfader: bool,
+    /// Dedicated row-select buttons (the ZeROs select rows via Preview + touch).
+    pub has_row_select_buttons: bool,
+    pub supports_all_leds_off: bool,
+    pub supports_transport_lock_query: bool,
+    pub encoder_count: usize,
+    pub pot_count: usize,
+    pub slider_count: usize,
+    pub lcd_lines: usize,
+    pub lcd_columns: usize,
+    /// Label cells per LCD line, one per column of controls.
+    pub lcd_cells: usize,
+}
+
+impl Capabilities {
+    /// Capabilities of `model` running the given firmware.
+    pub fn for_model(model: Model, firmware_version: u16) -> Capabilities {
+        let mut caps = Capabilities {
+            model,
+            firmware_version,
+            has_keyboard: false,
+            has_drumpads: true,
+            has_touchpad: false,
+            has_crossfader: false,
+            has_row_select_buttons: false,
+            supports_all_leds_off: true,
+            supports_transport_lock_query: true,
+            encoder_count: 8,
+            pot_count: 8,
+            slider_count: 8,
+            lcd_lines: LCD_LINES,
+            lcd_columns: LCD_COLUMNS,
+            lcd_cells: 8,
+        };
+        match model {
+            Model::SlMkII => {
+                caps.has_keyboard = true;
+                caps.has_touchpad = true;
+                caps.has_row_select_buttons = true;
+            }
+            Model::ZeroMkII => caps.has_crossfader = true,
+            Model::ZeroSl => {
+                caps.supports_all_leds_off = false;
+                caps.supports_transport_lock_query = false;
+            }
+            Model::Compact => {
+                caps.has_keyboard = true;
+                caps.has_drumpads = false;
+                caps.has_row_select_buttons = true;
+            }
+        }
+        caps
+    }
+
+    /// Reads `event` the way this unit means it.
+    ///
+    /// CC 0x44 is the touchpad's X axis on an SL MkII and the crossfader on
+    /// a ZeRO SL MkII, which has no touchpad; on a unit with a crossfader
+    /// and no touchpad, [`TouchpadX1`](AutomapEvent::TouchpadX1) becomes
+    /// [`CrossFader`](AutomapEvent::CrossFader). Other events are returned
+    /// unchanged.
+    pub fn disambiguate(&self, event: AutomapEvent) -> AutomapEvent {
+        match event {
+            AutomapEvent::TouchpadX1 { value } if self.has_crossfader && !self.has_touchpad => {
+                AutomapEvent::CrossFader { value }
+            }
+            event => event,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zero_generations() {
+        let mk2 = Model::identify(ProductType::ZeroSLorZeroMKII, 0x000c);
+        assert_eq!(mk2, Model::ZeroMkII);
+        let caps = Capabilities::for_model(mk2, 0x0100);
+        assert!(caps.has_crossfader && caps.supports_all_leds_off && !caps.has_keyboard);
+
+        let mk1 = Model::identify(ProductType::ZeroSLorZeroMKII, 0x0005);
+        assert!(!Capabilities::for_model(mk1, 0).supports_all_leds_off);
+    }
+
+    #[test]
+    fn test_crossfader_shares_touchpad_x() {
+        let x = AutomapEvent::TouchpadX1 { value: 40 };
+        let zero = Capabilities::for_model(Model::ZeroMkII, 0x0100);
+        assert_eq!(zero.disambiguate(x), AutomapEvent::CrossFader { value: 40 });
+        let sl = Capabilities::for_model(Model::SlMkII, 0x0100);
+        assert_eq!(sl.disambiguate(x), x);
+    }
+}
@@ -0,0 +1,363 @@
+//! A C API (`capi` feature), for C and C++ audio applications and for
+//! languages with a C FFI.
+//!
+//! Every call blocks until it is done, driving the selected async runtime
+//! internally, so a handle must not be used from inside an async task.
+//! Build the shared library and the header with:
+//!
+//! ```text
+//! cargo rustc --release --lib --features capi --crate-type cdylib
+//! cbindgen --config cbindgen.toml --output include/automap.h
+//! ```
+//!
+//! The header is checked in, so only the first step is needed unless this
+//! module changes. A minimal program:
+//!
+//! ```c
+//! AutomapCDevice *dev = automap_open(NULL);
+//! if (!dev) { fprintf(stderr, "%s\n", automap_last_error()); return 1; }
+//! automap_send_lcd_text(dev, 1, 0, "Hello");
+//! AutomapCEvent ev;
+//! while (automap_poll_event(dev, &ev, 1000) != AUTOMAP_STATUS_IO) {
+//!     // ev.data1 is the control's CC number, ev.value what it did
+//! }
+//! automap_close(dev);
+//! ```
+//!
+//! Functions return an [`AutomapStatus`]; after a failure,
+//! [`automap_last_error()`] says what went wrong. Calls on a handle must
+//! not overlap, but different handles may be used from different
+//! threads.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString, c_char};
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+use crate::automap::cc::Button;
+use crate::automap::command::AutomapCommand;
+use crate::automap::config::DeviceConfig;
+use crate::automap::device::AutomapDevice;
+use crate::automap::event::{AutomapEvent, Wheel};
+use crate::automap::lcd::LCD_COLUMNS;
+use crate::automap::rt::{self, Executor};
+use crate::automap::subscribe::EventFilter;
+use crate::automap::sysex::{AutomapSysEx, LcdLine, LcdOp};
+use crate::automap::timed::TimedEvent;
+
+/// What a call did.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutomapStatus {
+    Ok = 0,
+    /// No event arrived within the timeout.
+    Timeout = 1,
+    /// A null handle or string, or a control, line or column that does not
+    /// exist.
+    InvalidArgument = -1,
+    /// The unit could not be reached, or a USB transfer failed.
+    Io = -2,
+}
+
+/// Bits of [`AutomapCEvent::group`], as in [`EventFilter`].
+pub const AUTOMAP_GROUP_BUTTONS: u16 = 1 << 0;
+pub const AUTOMAP_GROUP_TRANSPORT: u16 = 1 << 1;
+pub const AUTOMAP_GROUP_ENCODERS: u16 = 1 << 2;
+pub const AUTOMAP_GROUP_POTS: u16 = 1 << 3;
+pub const AUTOMAP_GROUP_SLIDERS: u16 = 1 << 4;
+pub const AUTOMAP_GROUP_CROSSFADER: u16 = 1 << 5;
+pub const AUTOMAP_GROUP_SPEED_DIAL: u16 = 1 << 6;
+pub const AUTOMAP_GROUP_TOUCH: u16 = 1 << 7;
+pub const AUTOMAP_GROUP_TOUCHPAD: u16 = 1 << 8;
+pub const AUTOMAP_GROUP_PEDALS: u16 = 1 << 9;
+pub const AUTOMAP_GROUP_TEMPO: u16 = 1 << 10;
+pub const AUTOMAP_GROUP_DEVICE: u16 = 1 << 11;
+
+/// One event from the unit.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutomapCEvent {
+    /// One of the `AUTOMAP_GROUP_*` bits.
+    pub group: u16,
+    /// The MIDI message as the unit sent it: a CC on the Automap channel,
+    /// whose number in `data1` tells the control, or pitch bend.
+    pub status: u8,
+    pub data1: u8,
+    pub data2: u8,
+    /// Signed clicks of an encoder or the speed dial, the bend of the
+    /// pitch wheel from -8192 to 8191, and `data2` for everything else.
+    pub value: i32,
+    /// Microseconds from opening the device to the read that brought the
+    /// event.
+    pub time_us: u64,
+}
+
+impl AutomapCEvent {
+    fn new(timed: &TimedEvent, opened: Instant) -> Self {
+        let bytes = timed.event.to_bytes();
+        let value = match timed.event {
+            AutomapEvent::Wheel {
+                wheel: Wheel::Pitch { value },
+            } => i32::from(value),
+            ref event => event.clicks().map_or(i32::from(bytes[2]), i32::from),
+        };
+        let micros = timed.at.saturating_duration_since(opened).as_micros();
+        AutomapCEvent {
+            group: EventFilter::of(&timed.event).bits(),
+            status: bytes[0],
+            data1: bytes[1],
+            data2: bytes[2],
+            value,
+            time_us: u64::try_from(micros).unwrap_or(u64::MAX),
+        }
+    }
+}
+
+/// An open unit. Opaque to C.
+pub struct AutomapCDevice {
+    exec: Executor,
+    device: AutomapDevice,
+    /// Events read but not yet polled.
+    pending: VecDeque<TimedEvent>,
+    opened: Instant,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn fail(status: AutomapStatus, e: impl Display) -> AutomapStatus {
+    let message = e.to_string().replace('\0', " ");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).unwrap_or_default());
+    status
+}
+
+fn io_status(result: Result<(), std::io::Error>) -> AutomapStatus {
+    match result {
+        Ok(()) => AutomapStatus::Ok,
+        Err(e) => fail(AutomapStatus::Io, e),
+    }
+}
+
+/// # Safety
+///
+/// `dev` must be null or a handle from [`automap_open()`] that has not
+/// been closed.
+unsafe fn handle<'a>(dev: *mut AutomapCDevice) -> Result<&'a mut AutomapCDevice, AutomapStatus> {
+    // SAFETY: the caller promises a live handle or null
+    unsafe { dev.as_mut() }.ok_or_else(|| fail(AutomapStatus::InvalidArgument, "null device"))
+}
+
+/// The message of the last failure on this thread, empty if there was
+/// none. The string stays valid until the next failing call on the same
+/// thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn automap_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// Opens the unit with serial number `serial`, or the first one found if
+/// `serial` is null, and puts it online. Returns null on failure.
+///
+/// # Safety
+///
+/// `serial` must be null or a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn automap_open(serial: *const c_char) -> *mut AutomapCDevice {
+    let mut config = DeviceConfig::new().auto_online(true).auto_clear(true);
+    if !serial.is_null() {
+        // SAFETY: the caller promises a NUL-terminated string
+        match unsafe { CStr::from_ptr(serial) }.to_str() {
+            Ok(serial) => config = config.serial(serial),
+            Err(e) => {
+                fail(AutomapStatus::InvalidArgument, e);
+                return std::ptr::null_mut();
+            }
+        }
+    }
+    let exec = match Executor::new() {
+        Ok(exec) => exec,
+        Err(e) => {
+            fail(AutomapStatus::Io, e);
+            return std::ptr::null_mut();
+        }
+    };
+    match exec.block_on(config.open()) {
+        Ok(device) => Box::into_raw(Box::new(AutomapCDevice {
+            exec,
+            device,
+            pending: VecDeque::new(),
+            opened: Instant::now(),
+        })),
+        Err(e) => {
+            fail(AutomapStatus::Io, e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Clears the surface, tells the unit the host went offline, and frees
+/// `dev`. The handle is freed even if that fails. Null is ignored.
+///
+/// # Safety
+///
+/// `dev` must be null or a handle from [`automap_open()`] that has not
+/// been closed, and is not used again.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn automap_close(dev: *mut AutomapCDevice) -> AutomapStatus {
+    if dev.is_null() {
+        return AutomapStatus::Ok;
+    }
+    // SAFETY: the caller hands over a live handle from `automap_open()`
+    let dev = unsafe { Box::from_raw(dev) };
+    let AutomapCDevice { exec, device, .. } = *dev;
+    io_status(exec.block_on(device.close()))
+}
+
+/// Waits up to `timeout_ms` milliseconds for the next event and writes it
+/// to `out`. With a timeout of 0 only events already received are
+/// returned.
+///
+/// # Safety
+///
+/// `dev` must be a live handle, and `out` null or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn automap_poll_event(
+    dev: *mut AutomapCDevice,
+    out: *mut AutomapCEvent,
+    timeout_ms: u32,
+) -> AutomapStatus {
+    // SAFETY: passed on from the caller
+    let dev = match unsafe { handle(dev) } {
+        Ok(dev) => dev,
+        Err(status) => return status,
+    };
+    if out.is_null() {
+        return fail(AutomapStatus::InvalidArgument, "null event");
+    }
+    if dev.pending.is_empty() {
+        let wait = Duration::from_millis(timeout_ms.into());
+        let read = dev.device.read_timed_events();
+        match dev.exec.block_on(rt::timeout(wait, read)) {
+            None => {}
+            Some(Ok(events)) => dev.pending.extend(events),
+            Some(Err(e)) => return fail(AutomapStatus::Io, e),
+        }
+    }
+    let Some(timed) = dev.pending.pop_front() else {
+        return AutomapStatus::Timeout;
+    };
+    // SAFETY: checked for null above; the caller promises it is writable
+    unsafe { out.write(AutomapCEvent::new(&timed, dev.opened)) };
+    AutomapStatus::Ok
+}
+
+/// Switches the LED of the button with CC number `button` (0x18 for A1 to
+/// 0x37 for D8) on or off.
+///
+/// # Safety
+///
+/// `dev` must be a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn automap_send_led(
+    dev: *mut AutomapCDevice,
+    button: u8,
+    on: bool,
+) -> AutomapStatus {
+    // SAFETY: passed on from the caller
+    let dev = match unsafe { handle(dev) } {
+        Ok(dev) => dev,
+        Err(status) => return status,
+    };
+    let Ok(button) = Button::try_from(button) else {
+        return fail(
+            AutomapStatus::InvalidArgument,
+            format_args!("no button with CC {button:#04x}"),
+        );
+    };
+    let cmd = AutomapCommand::ButtonLed { button, on };
+    io_status(dev.exec.block_on(dev.device.send_command(&cmd)))
+}
+
+/// Writes `text` on LCD line `line` (1 left top, 2 right top, 3 left
+/// bottom, 4 right bottom) from column `col` (0 to 71). Text past the end
+/// of the line is cut off.
+///
+/// # Safety
+///
+/// `dev` must be a live handle and `text` a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn automap_send_lcd_text(
+    dev: *mut AutomapCDevice,
+    line: u8,
+    col: u8,
+    text: *const c_char,
+) -> AutomapStatus {
+    // SAFETY: passed on from the caller
+    let dev = match unsafe { handle(dev) } {
+        Ok(dev) => dev,
+        Err(status) => return status,
+    };
+    let Some(line) = LcdLine::ALL.into_iter().find(|l| *l as u8 == line) else {
+        return fail(
+            AutomapStatus::InvalidArgument,
+            format_args!("no LCD line {line}"),
+        );
+    };
+    if usize::from(col) >= LCD_COLUMNS || text.is_null() {
+        return fail(AutomapStatus::InvalidArgument, "bad column or null text");
+    }
+    // SAFETY: checked for null above; the caller promises NUL termination
+    let text = unsafe { CStr::from_ptr(text) }.to_bytes();
+    let text = &text[..text.len().min(LCD_COLUMNS - usize::from(col))];
+    let msg = AutomapSysEx::LcdText(vec![
+        LcdOp::Cursor { col, line },
+        LcdOp::Text(text),
+        LcdOp::End,
+    ]);
+    io_status(dev.exec.block_on(dev.device.send_sysex(msg)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::cc::Encoder;
+
+    #[test]
+    fn test_events_and_arguments() {
+        let opened = Instant::now();
+        let turn = TimedEvent {
+            at: opened + Duration::from_millis(3),
+            event: AutomapEvent::Encoder {
+                encoder: Encoder::Encoder2,
+                clicks: -3,
+            },
+        };
+        let ev = AutomapCEvent::new(&turn, opened);
+        assert_eq!(ev.group, AUTOMAP_GROUP_ENCODERS);
+        assert_eq!((ev.status, ev.data1, ev.value), (0xBF, 0x79, -3));
+        assert_eq!(ev.time_us, 3000);
+
+        let groups = [
+            (AUTOMAP_GROUP_BUTTONS, EventFilter::BUTTONS),
+            (AUTOMAP_GROUP_TOUCH, EventFilter::TOUCH),
+            (AUTOMAP_GROUP_DEVICE, EventFilter::DEVICE),
+        ];
+        for (c, filter) in groups {
+            assert_eq!(c, filter.bits());
+        }
+
+        let mut out = ev;
+        // SAFETY: null handles are what is being tested
+        unsafe {
+            assert_eq!(
+                automap_poll_event(std::ptr::null_mut(), &mut out, 0),
+                AutomapStatus::InvalidArgument
+            );
+            assert_eq!(automap_close(std::ptr::null_mut()), AutomapStatus::Ok);
+        }
+        let message = unsafe { CStr::from_ptr(automap_last_error()) };
+        assert_eq!(message.to_str(), Ok("null device"));
+    }
+}
@@ -0,0 +1,523 @@
+//! Captures in formats other MIDI tools can open, and replaying them.
+//!
+//! A [`Capture`] collects what a [`Proxy`] tap sees, with the direction and
+//! time of each message, and saves it in one of two formats:
+//!
+//! - [midicsv](https://www.fourmilab.ch/webtools/midicsv/): the text form
+//!   of a Standard MIDI File, which `csvmidi` turns into a `.mid` for a
+//!   sequencer or MIDI editor. Track 1 holds what the unit sent and track 2
+//!   what was sent to it, at 120 BPM with 5000 ticks per quarter note, so a
+//!   tick is 0.1 ms. Real-time and system common messages have no place in
+//!   a MIDI file and are left out, as is the injected mark.
+//! - pcap, for Wireshark and `tcpdump -r`: one packet per message, with
+//!   link type 147 (`USER0`). A packet is a flags byte, bit 0 set for a
+//!   message to the unit and bit 1 for an injected one, then the MIDI
+//!   bytes.
+//!
+//! [`Capture::load()`] reads either back, and with the `mock` feature
+//! `Capture::replay()` plays what the unit sent through a `FakeZeroMkII`,
+//! so a session recorded on the real hardware can drive a test.
+//!
+//! ```no_run
+//! use std::sync::{Arc, Mutex};
+//! use automap::{Capture, Proxy};
+//!
+//! let capture = Arc::new(Mutex::new(Capture::new()));
+//! let tap = Arc::clone(&capture);
+//! let proxy = Proxy::new().on_message(move |msg| tap.lock().unwrap().record(msg));
+//! // ... run the proxy, then:
+//! capture.lock().unwrap().save("session.pcap")?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! [`Proxy`]: crate::Proxy
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::automap::proxy::{Direction, ProxiedMessage};
+
+/// Link type of the pcap files, the first of those reserved for private use.
+pub const PCAP_LINKTYPE: u32 = 147;
+
+const PCAP_MAGIC: u32 = 0xA1B2_C3D4;
+const PCAP_MAGIC_NANOS: u32 = 0xA1B2_3C4D;
+const PCAP_SNAPLEN: u32 = 0xFFFF;
+const FLAG_TO_DEVICE: u8 = 0x01;
+const FLAG_INJECTED: u8 = 0x02;
+
+/// MIDI file timing of the midicsv form: 500000 µs per quarter note.
+const MIDICSV_TEMPO: u64 = 500_000;
+const MIDICSV_DIVISION: u64 = 5000;
+
+/// Which file format to write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureFormat {
+    Midicsv,
+    Pcap,
+}
+
+impl CaptureFormat {
+    /// The format for a file name: `.csv` is midicsv, `.pcap` is pcap.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        match path.as_ref().extension()?.to_str()? {
+            "csv" => Some(CaptureFormat::Midicsv),
+            "pcap" => Some(CaptureFormat::Pcap),
+            _ => None,
+        }
+    }
+}
+
+/// One message of a [`Capture`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedMessage {
+    pub direction: Direction,
+    /// Time since the start of the capture.
+    pub offset: Duration,
+    pub injected: bool,
+    pub bytes: Vec<u8>,
+}
+
+/// Messages with their times, recorded or loaded from a file.
+#[derive(Debug, Clone)]
+pub struct Capture {
+    /// Wall-clock time of offset zero, for the pcap timestamps.
+    started: SystemTime,
+    start: Instant,
+    messages: Vec<CapturedMessage>,
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Capture {
+    /// An empty capture starting now.
+    pub fn new() -> Self {
+        Capture {
+            started: SystemTime::now(),
+            start: Instant::now(),
+            messages: Vec::new(),
+        }
+    }
+
+    /// Adds a message as a [`Proxy`](crate::Proxy) tap receives it.
+    pub fn record(&mut self, msg: &ProxiedMessage) {
+        self.messages.push(CapturedMessage {
+            direction: msg.direction,
+            offset: msg.at.saturating_duration_since(self.start),
+            injected: msg.injected,
+            bytes: msg.bytes.clone(),
+        });
+    }
+
+    /// Adds a message at `offset`, e.g. to build a capture for a test.
+    pub fn push(&mut self, direction: Direction, offset: Duration, bytes: impl Into<Vec<u8>>) {
+        self.messages.push(CapturedMessage {
+            direction,
+            offset,
+            injected: false,
+            bytes: bytes.into(),
+        });
+    }
+
+    /// The messages in the order they were recorded or appear in the file.
+    pub fn messages(&self) -> &[CapturedMessage] {
+        &self.messages
+    }
+
+    /// Writes the capture to `path` in the format its extension names.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `InvalidInput` if the extension is neither `.csv` nor
+    /// `.pcap`, or if writing fails.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let format = CaptureFormat::from_path(path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: expected a .csv or .pcap file", path.display()),
+            )
+        })?;
+        let mut out = Vec::new();
+        self.write(format, &mut out)?;
+        fs::write(path, out)
+    }
+
+    /// Writes the capture in `format`.
+    pub fn write(&self, format: CaptureFormat, out: &mut impl Write) -> io::Result<()> {
+        match format {
+            CaptureFormat::Midicsv => out.write_all(self.to_midicsv().as_bytes()),
+            CaptureFormat::Pcap => self.write_pcap(out),
+        }
+    }
+
+    /// Reads a capture saved in either format, telling them apart by
+    /// content rather than by name.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `InvalidData` if the file is in neither format, with the
+    /// line or offset of the first problem.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Parses the contents of a capture file.
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        let magic = data.get(..4).map(|m| [m[0], m[1], m[2], m[3]]);
+        let is_pcap = magic.is_some_and(|m| {
+            [u32::from_le_bytes(m), u32::from_be_bytes(m)]
+                .iter()
+                .any(|&n| n == PCAP_MAGIC || n == PCAP_MAGIC_NANOS)
+        });
+        if is_pcap {
+            return parse_pcap(data);
+        }
+        let text = std::str::from_utf8(data).map_err(|_| invalid("neither pcap nor midicsv"))?;
+        parse_midicsv(text)
+    }
+
+    fn to_midicsv(&self) -> String {
+        let mut out = format!("0, 0, Header, 1, 2, {MIDICSV_DIVISION}\n");
+        for (track, direction, title) in [
+            (1, Direction::FromDevice, "From unit"),
+            (2, Direction::ToDevice, "To unit"),
+        ] {
+            let _ = writeln!(out, "{track}, 0, Start_track");
+            let _ = writeln!(out, "{track}, 0, Title_t, \"{title}\"");
+            if track == 1 {
+                let _ = writeln!(out, "1, 0, Tempo, {MIDICSV_TEMPO}");
+            }
+            // Times in a track may not go backwards
+            let mut last = 0;
+            for msg in self.messages.iter().filter(|m| m.direction == direction) {
+                let Some(event) = midicsv_event(&msg.bytes) else {
+                    continue;
+                };
+                let ticks = msg.offset.as_micros() as u64 * MIDICSV_DIVISION / MIDICSV_TEMPO;
+                last = last.max(ticks);
+                let _ = writeln!(out, "{track}, {last}, {event}");
+            }
+            let _ = writeln!(out, "{track}, {last}, End_track");
+        }
+        out.push_str("0, 0, End_of_file\n");
+        out
+    }
+
+    fn write_pcap(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut header = Vec::with_capacity(24);
+        header.extend(PCAP_MAGIC.to_le_bytes());
+        header.extend(2u16.to_le_bytes());
+        header.extend(4u16.to_le_bytes());
+        header.extend([0; 8]); // time zone and accuracy
+        header.extend(PCAP_SNAPLEN.to_le_bytes());
+        header.extend(PCAP_LINKTYPE.to_le_bytes());
+        out.write_all(&header)?;
+
+        let epoch = self.started.duration_since(UNIX_EPOCH).unwrap_or_default();
+        for msg in &self.messages {
+            let at = epoch + msg.offset;
+            let mut flags = 0;
+            if msg.direction == Direction::ToDevice {
+                flags |= FLAG_TO_DEVICE;
+            }
+            if msg.injected {
+                flags |= FLAG_INJECTED;
+            }
+            let len = (msg.bytes.len() + 1) as u32;
+            let mut record = Vec::with_capacity(16 + len as usize);
+            record.extend((at.as_secs() as u32).to_le_bytes());
+            record.extend(at.subsec_micros().to_le_bytes());
+            record.extend(len.to_le_bytes());
+            record.extend(len.to_le_bytes());
+            record.push(flags);
+            record.extend(&msg.bytes);
+            out.write_all(&record)?;
+        }
+        Ok(())
+    }
+
+    /// Plays the messages the unit sent through `fake` at their recorded
+    /// times, counted from the call. Messages sent to the unit are skipped,
+    /// since the code under test sends its own; compare them with
+    /// [`FakeZeroMkII::received()`](crate::FakeZeroMkII::received) instead.
+    #[cfg(feature = "mock")]
+    pub async fn replay(&self, fake: &crate::automap::mock::FakeZeroMkII) {
+        let start = Instant::now();
+        for msg in &self.messages {
+            if msg.direction != Direction::FromDevice {
+                continue;
+            }
+            let wait = (start + msg.offset).saturating_duration_since(Instant::now());
+            if !wait.is_zero() {
+                crate::automap::rt::sleep(wait).await;
+            }
+            fake.send_midi(&msg.bytes);
+        }
+    }
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// The midicsv record of a message after the track and time, or `None` for
+/// messages a MIDI file cannot hold.
+fn midicsv_event(bytes: &[u8]) -> Option<String> {
+    let (&status, data) = bytes.split_first()?;
+    if status == 0xF0 {
+        let data: Vec<String> = data.iter().map(u8::to_string).collect();
+        return Some(format!(
+            "System_exclusive, {}, {}",
+            data.len(),
+            data.join(", ")
+        ));
+    }
+    let ch = status & 0x0F;
+    let (name, len) = match status & 0xF0 {
+        0x80 => ("Note_off_c", 2),
+        0x90 => ("Note_on_c", 2),
+        0xA0 => ("Poly_aftertouch_c", 2),
+        0xB0 => ("Control_c", 2),
+        0xC0 => ("Program_c", 1),
+        0xD0 => ("Channel_aftertouch_c", 1),
+        0xE0 => ("Pitch_bend_c", 2),
+        _ => return None,
+    };
+    let data = data.get(..len)?;
+    Some(match (status & 0xF0, data) {
+        (0xE0, &[lsb, msb]) => format!("{name}, {ch}, {}", u16::from(msb) << 7 | u16::from(lsb)),
+        (_, &[a, b]) => format!("{name}, {ch}, {a}, {b}"),
+        (_, &[a]) => format!("{name}, {ch}, {a}"),
+        _ => return None,
+    })
+}
+
+/// The MIDI bytes of a midicsv record's type and fields.
+fn midicsv_bytes(kind: &str, fields: &[u32]) -> Option<Vec<u8>> {
+    let byte = |i: usize| fields.get(i).and_then(|&n| u8::try_from(n).ok());
+    let status = match kind {
+        "System_exclusive" => {
+            let len = fields.first().copied()? as usize;
+            let data = fields.get(1..)?;
+            if data.len() != len {
+                return None;
+            }
+            let mut out = vec![0xF0];
+            for &b in data {
+                out.push(u8::try_from(b).ok()?);
+            }
+            return Some(out);
+        }
+        "Note_off_c" => 0x80,
+        "Note_on_c" => 0x90,
+        "Poly_aftertouch_c" => 0xA0,
+        "Control_c" => 0xB0,
+        "Program_c" => 0xC0,
+        "Channel_aftertouch_c" => 0xD0,
+        "Pitch_bend_c" => {
+            let value = *fields.get(1)?;
+            if value > 0x3FFF {
+                return None;
+            }
+            return Some(vec![
+                0xE0 | byte(0)?,
+                (value & 0x7F) as u8,
+                (value >> 7) as u8,
+            ]);
+        }
+        _ => return None,
+    };
+    let ch = byte(0).filter(|&c| c < 16)?;
+    let mut out = vec![status | ch, byte(1)?];
+    if status < 0xC0 {
+        out.push(byte(2)?);
+    }
+    Some(out)
+}
+
+fn parse_midicsv(text: &str) -> io::Result<Capture> {
+    let mut division = 0;
+    let mut tempo = MIDICSV_TEMPO;
+    let mut messages = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        let bad = || invalid(format!("line {}: not a midicsv record", i + 1));
+        let mut parts = line.splitn(4, ',').map(str::trim);
+        let (Some(track), Some(time), Some(kind)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(bad());
+        };
+        let track: u32 = track.parse().map_err(|_| bad())?;
+        let ticks: u64 = time.parse().map_err(|_| bad())?;
+        let rest = parts.next().unwrap_or("");
+        // Text records such as titles are not numbers and are not needed
+        let fields = || -> io::Result<Vec<u32>> {
+            rest.split(',')
+                .filter(|f| !f.trim().is_empty())
+                .map(|f| f.trim().parse().map_err(|_| bad()))
+                .collect()
+        };
+        match kind {
+            "Header" => division = u64::from(*fields()?.get(2).ok_or_else(bad)?),
+            "Tempo" => tempo = u64::from(*fields()?.first().ok_or_else(bad)?),
+            _ => {
+                let Some(bytes) = midicsv_bytes(kind, &fields().unwrap_or_default()) else {
+                    continue;
+                };
+                if division == 0 {
+                    return Err(invalid(format!("line {}: record before the header", i + 1)));
+                }
+                let direction = match track {
+                    1 => Direction::FromDevice,
+                    _ => Direction::ToDevice,
+                };
+                messages.push(CapturedMessage {
+                    direction,
+                    offset: Duration::from_micros(ticks * tempo / division),
+                    injected: false,
+                    bytes,
+                });
+            }
+        }
+    }
+    // Merge the two tracks back into one timeline
+    messages.sort_by_key(|m| m.offset);
+    Ok(Capture {
+        started: SystemTime::now(),
+        start: Instant::now(),
+        messages,
+    })
+}
+
+fn parse_pcap(data: &[u8]) -> io::Result<Capture> {
+    let header = data
+        .get(..24)
+        .ok_or_else(|| invalid("pcap header cut short"))?;
+    let magic_le = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let little = magic_le == PCAP_MAGIC || magic_le == PCAP_MAGIC_NANOS;
+    let u32_at = |b: &[u8], at: usize| {
+        let n = [b[at], b[at + 1], b[at + 2], b[at + 3]];
+        if little {
+            u32::from_le_bytes(n)
+        } else {
+            u32::from_be_bytes(n)
+        }
+    };
+    let nanos = u32_at(header, 0) == PCAP_MAGIC_NANOS;
+    let linktype = u32_at(header, 20);
+    if linktype != PCAP_LINKTYPE {
+        return Err(invalid(format!(
+            "pcap link type {linktype}, expected {PCAP_LINKTYPE}"
+        )));
+    }
+
+    let mut records = Vec::new();
+    let mut at = 24;
+    while at < data.len() {
+        let record = data
+            .get(at..at + 16)
+            .ok_or_else(|| invalid(format!("pcap record at byte {at} cut short")))?;
+        let secs = u64::from(u32_at(record, 0));
+        let frac = u64::from(u32_at(record, 4));
+        let len = u32_at(record, 8) as usize;
+        let packet = data
+            .get(at + 16..at + 16 + len)
+            .ok_or_else(|| invalid(format!("pcap record at byte {at} cut short")))?;
+        let (&flags, bytes) = packet
+            .split_first()
+            .ok_or_else(|| invalid(format!("empty pcap record at byte {at}")))?;
+        let time = Duration::from_secs(secs)
+            + if nanos {
+                Duration::from_nanos(frac)
+            } else {
+                Duration::from_micros(frac)
+            };
+        records.push((time, flags, bytes.to_vec()));
+        at += 16 + len;
+    }
+
+    let first = records.first().map_or(Duration::ZERO, |r| r.0);
+    let messages = records
+        .into_iter()
+        .map(|(time, flags, bytes)| CapturedMessage {
+            direction: if flags & FLAG_TO_DEVICE != 0 {
+                Direction::ToDevice
+            } else {
+                Direction::FromDevice
+            },
+            offset: time.saturating_sub(first),
+            injected: flags & FLAG_INJECTED != 0,
+            bytes,
+        })
+        .collect();
+    Ok(Capture {
+        started: UNIX_EPOCH + first,
+        start: Instant::now(),
+        messages,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Capture {
+        let mut capture = Capture::new();
+        capture.push(Direction::ToDevice, Duration::ZERO, [0xBF, 0x63, 0x01]);
+        capture.push(
+            Direction::FromDevice,
+            Duration::from_micros(2500),
+            [0xBF, 0x63, 0x01],
+        );
+        capture.push(
+            Direction::FromDevice,
+            Duration::from_millis(10),
+            [0xF0, 0x00, 0x20, 0x29, 0x03, 0x03, 0xF7],
+        );
+        capture.push(
+            Direction::FromDevice,
+            Duration::from_millis(12),
+            [0xE0, 0x01, 0x40],
+        );
+        capture
+    }
+
+    #[test]
+    fn test_formats_round_trip() {
+        let capture = sample();
+        let csv = capture.to_midicsv();
+        assert!(csv.starts_with("0, 0, Header, 1, 2, 5000\n"));
+        assert!(csv.contains("1, 25, Control_c, 15, 99, 1\n"));
+        assert!(csv.contains("1, 100, System_exclusive, 6, 0, 32, 41, 3, 3, 247\n"));
+        assert!(csv.contains("1, 120, Pitch_bend_c, 0, 8193\n"));
+        assert!(csv.ends_with("0, 0, End_of_file\n"));
+
+        for format in [CaptureFormat::Midicsv, CaptureFormat::Pcap] {
+            let mut out = Vec::new();
+            capture.write(format, &mut out).unwrap();
+            let loaded = Capture::from_bytes(&out).unwrap();
+            assert_eq!(loaded.messages(), capture.messages(), "{format:?}");
+        }
+
+        // Real-time bytes have no midicsv form
+        assert_eq!(midicsv_event(&[0xF8]), None);
+        assert!(Capture::from_bytes(b"1, 0, Control_c, 15, 99, 1\n").is_err());
+        assert!(Capture::from_bytes(b"one, 0, Start_track\n").is_err());
+        assert_eq!(
+            CaptureFormat::from_path("a/session.pcap"),
+            Some(CaptureFormat::Pcap)
+        );
+        assert_eq!(CaptureFormat::from_path("session.hex"), None);
+    }
+}
@@ -0,0 +1,185 @@
+//! Button combinations: hold a modifier, press a trigger.
+//!
+//! [`ChordDetector`] sits between the device and the application. Events
+//! that belong to a registered chord are swallowed and replaced by a single
+//! [`ChordOutput::Chord`]; everything else passes through unchanged.
+//!
+//! Because a modifier press may turn out to be the start of a chord, it is
+//! held back until the modifier is released. If no chord happened, the
+//! press and release are then passed on together, so a modifier still works
+//! as a plain button, just reported on release.
+
+use std::time::Instant;
+
+use crate::automap::gestures::PressSource;
+use crate::automap::timed::TimedEvent;
+
+/// A modifier held while a trigger is pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    pub modifier: PressSource,
+    pub trigger: PressSource,
+}
+
+/// What the detector passes on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordOutput {
+    /// An event not involved in any chord.
+    Event(TimedEvent),
+    /// A chord's trigger was pressed (`pressed: true`) or released.
+    Chord {
+        chord: Chord,
+        pressed: bool,
+        at: Instant,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HeldModifier {
+    source: PressSource,
+    press: TimedEvent,
+    /// A chord fired while held, so the modifier's own events are dropped.
+    used: bool,
+}
+
+/// Recognises registered chords in a stream of events.
+#[derive(Debug, Clone, Default)]
+pub struct ChordDetector {
+    chords: Vec<Chord>,
+    held: Vec<HeldModifier>,
+    /// Chords whose trigger is still down, so its release is swallowed too.
+    active: Vec<Chord>,
+}
+
+impl ChordDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `modifier` + `trigger` as a chord.
+    pub fn chord(mut self, modifier: PressSource, trigger: PressSource) -> Self {
+        let chord = Chord { modifier, trigger };
+        if !self.chords.contains(&chord) {
+            self.chords.push(chord);
+        }
+        self
+    }
+
+    /// Modifiers currently held down.
+    pub fn held_modifiers(&self) -> impl Iterator<Item = PressSource> + '_ {
+        self.held.iter().map(|h| h.source)
+    }
+
+    /// Feeds one event, returning what should be passed on.
+    pub fn process(&mut self, event: TimedEvent) -> Vec<ChordOutput> {
+        let Some((source, pressed)) = PressSource::from_event(&event.event) else {
+            return vec![ChordOutput::Event(event)];
+        };
+
+        if pressed {
+            // The newest held modifier wins if several chords match
+            let chord = self.held.iter_mut().rev().find_map(|held| {
+                let chord = Chord {
+                    modifier: held.source,
+                    trigger: source,
+                };
+                self.chords.contains(&chord).then(|| {
+                    held.used = true;
+                    chord
+                })
+            });
+            if let Some(chord) = chord {
+                self.active.push(chord);
+                return vec![ChordOutput::Chord {
+                    chord,
+                    pressed: true,
+                    at: event.at,
+                }];
+            }
+            if self.is_modifier(source) && !self.held.iter().any(|h| h.source == source) {
+                self.held.push(HeldModifier {
+                    source,
+                    press: event,
+                    used: false,
+                });
+                return Vec::new();
+            }
+        } else {
+            if let Some(i) = self.active.iter().position(|c| c.trigger == source) {
+                let chord = self.active.remove(i);
+                return vec![ChordOutput::Chord {
+                    chord,
+                    pressed: false,
+                    at: event.at,
+                }];
+            }
+            if let Some(i) = self.held.iter().position(|h| h.source == source) {
+                let held = self.held.remove(i);
+                return if held.used {
+                    Vec::new()
+                } else {
+                    vec![ChordOutput::Event(held.press), ChordOutput::Event(event)]
+                };
+            }
+        }
+
+        vec![ChordOutput::Event(event)]
+    }
+
+    fn is_modifier(&self, source: PressSource) -> bool {
+        self.chords.iter().any(|c| c.modifier == source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::cc::Button;
+    use crate::automap::event::AutomapEvent;
+
+    const A1: PressSource = PressSource::Button(Button::ButtonA1);
+    const B3: PressSource = PressSource::Button(Button::ButtonB3);
+
+    fn press(button: Button, pressed: bool) -> TimedEvent {
+        TimedEvent {
+            at: Instant::now(),
+            event: AutomapEvent::Button { button, pressed },
+        }
+    }
+
+    #[test]
+    fn test_chord_suppresses_constituents() {
+        let mut chords = ChordDetector::new().chord(A1, B3);
+
+        assert!(chords.process(press(Button::ButtonA1, true)).is_empty());
+        let out = chords.process(press(Button::ButtonB3, true));
+        assert!(matches!(
+            out[..],
+            [ChordOutput::Chord { chord, pressed: true, .. }] if chord == Chord { modifier: A1, trigger: B3 }
+        ));
+        let out = chords.process(press(Button::ButtonB3, false));
+        assert!(matches!(
+            out[..],
+            [ChordOutput::Chord { pressed: false, .. }]
+        ));
+        assert!(chords.process(press(Button::ButtonA1, false)).is_empty());
+    }
+
+    #[test]
+    fn test_unused_modifier_passes_through() {
+        let mut chords = ChordDetector::new().chord(A1, B3);
+
+        assert!(chords.process(press(Button::ButtonA1, true)).is_empty());
+        let out = chords.process(press(Button::ButtonB4, true));
+        assert!(matches!(out[..], [ChordOutput::Event(_)]));
+        let out = chords.process(press(Button::ButtonA1, false));
+        assert_eq!(out.len(), 2);
+        assert!(matches!(
+            out[0],
+            ChordOutput::Event(TimedEvent {
+                event: AutomapEvent::Button { pressed: true, .. },
+                ..
+            })
+        ));
+    }
+}
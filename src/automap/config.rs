@@ -0,0 +1,364 @@
+//! Open-time options for [`AutomapDevice`].
+
+use std::time::Duration;
+
+use crate::automap::cc::AlertType;
+use crate::automap::device::{AutomapDevice, USB_BUF};
+use crate::automap::error::AutomapError;
+use crate::automap::globals::GlobalField;
+use crate::automap::sysex::MAX_SYSEX_LEN;
+
+/// Which of the unit's USB interfaces to talk to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// The vendor Automap interface if present, else the class-compliant
+    /// MIDI streaming interface.
+    #[default]
+    Auto,
+    /// Only the vendor Automap interface.
+    Vendor,
+    /// Only the class-compliant MIDI streaming interface.
+    MidiStreaming,
+}
+
+/// How long the device waits between SysEx frames it sends.
+///
+/// The firmware drops data when large frames arrive back to back, as they
+/// do during a template upload or a run of LCD updates. After each frame,
+/// the next one waits [`gap`](Self::gap) plus [`per_byte`](Self::per_byte)
+/// for every byte of it. Short messages are never held back.
+///
+/// The default has not been measured on a unit. It leaves each frame the
+/// time it would take over the 5-pin MIDI port, 320 µs a byte, which the
+/// firmware has to keep up with anyway, plus a millisecond: about 27 ms
+/// after an 80-byte LCD frame. That is on the safe side and likely slower
+/// than USB needs; a faster setting should be checked against the unit
+/// with [`write_block_verified()`](AutomapDevice::write_block_verified).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pacing {
+    pub gap: Duration,
+    pub per_byte: Duration,
+}
+
+impl Pacing {
+    /// Sends every frame as soon as it is ready.
+    pub const OFF: Pacing = Pacing {
+        gap: Duration::ZERO,
+        per_byte: Duration::ZERO,
+    };
+
+    /// How long to wait after a frame of `len` bytes.
+    pub(crate) fn after(&self, len: usize) -> Duration {
+        self.gap + self.per_byte * u32::try_from(len).unwrap_or(u32::MAX)
+    }
+}
+
+impl Default for Pacing {
+    fn default() -> Self {
+        Pacing {
+            gap: Duration::from_millis(1),
+            per_byte: Duration::from_micros(320),
+        }
+    }
+}
+
+/// How to find and open the controller.
+///
+/// By default the Automap interface and its bulk endpoints are discovered
+/// from the USB descriptors when the device is opened, and the unit is
+/// taken over with the [handshake](Self::handshake). Obtain one with
+/// [`AutomapDevice::builder()`] and finish with [`open()`](Self::open):
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use automap::AutomapDevice;
+///
+/// # async fn open() -> Result<(), Box<dyn std::error::Error>> {
+/// let device = AutomapDevice::builder()
+///     .serial("ABC123")
+///     .auto_online(true)
+///     .keep_alive(Duration::from_secs(5))
+///     .open()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceConfig {
+    pub(crate) interface: Option<u8>,
+    pub(crate) endpoints: Option<(u8, u8)>,
+    pub(crate) serial: Option<String>,
+    pub(crate) read_buffer: usize,
+    pub(crate) read_transfers: usize,
+    pub(crate) max_sysex: usize,
+    pub(crate) auto_online: bool,
+    pub(crate) auto_clear: bool,
+    pub(crate) handshake: bool,
+    pub(crate) cc_channel: u8,
+    pub(crate) keep_alive: Option<Duration>,
+    pub(crate) backend: Backend,
+    pub(crate) detach_kernel_driver: bool,
+    pub(crate) memory_protect: Option<GlobalField>,
+    pub(crate) alert_fields: Vec<(AlertType, GlobalField)>,
+    pub(crate) pacing: Pacing,
+    pub(crate) coalesce_clicks: Option<Duration>,
+    pub(crate) midi_out_cables: [u8; 2],
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        DeviceConfig {
+            interface: None,
+            endpoints: None,
+            serial: None,
+            read_buffer: USB_BUF,
+            read_transfers: 1,
+            max_sysex: MAX_SYSEX_LEN,
+            auto_online: false,
+            auto_clear: false,
+            handshake: true,
+            cc_channel: 16,
+            keep_alive: None,
+            backend: Backend::Auto,
+            detach_kernel_driver: false,
+            memory_protect: None,
+            alert_fields: Vec::new(),
+            pacing: Pacing::default(),
+            coalesce_clicks: None,
+            midi_out_cables: [1, 2],
+        }
+    }
+}
+
+impl DeviceConfig {
+    /// Default configuration: everything discovered at open time.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use this interface number instead of discovering it.
+    pub fn interface(mut self, number: u8) -> Self {
+        self.interface = Some(number);
+        self
+    }
+
+    /// Use these endpoint addresses (host → device, device → host) instead
+    /// of the first bulk pair on the interface.
+    pub fn endpoints(mut self, ep_out: u8, ep_in: u8) -> Self {
+        self.endpoints = Some((ep_out, ep_in));
+        self
+    }
+
+    /// Open the unit with this USB serial number rather than the first found.
+    pub fn serial(mut self, serial: impl Into<String>) -> Self {
+        self.serial = Some(serial.into());
+        self
+    }
+
+    /// Bytes requested per USB read, rounded down to whole USB-MIDI packets.
+    ///
+    /// Defaults to [`USB_BUF`].
+    pub fn read_buffer_size(mut self, bytes: usize) -> Self {
+        self.read_buffer = (bytes / 4).max(1) * 4;
+        self
+    }
+
+    /// Number of IN transfers kept queued with the host controller.
+    ///
+    /// With the default of 1 a transfer is only submitted while a read is
+    /// waiting, so the unit can get ahead of the host during long dumps.
+    /// Two or more keep the endpoint busy between reads; combine with a
+    /// larger [`read_buffer_size`](Self::read_buffer_size) for template or
+    /// globals transfers. Can be changed later with
+    /// [`AutomapDevice::set_read_transfers()`].
+    pub fn read_transfers(mut self, count: usize) -> Self {
+        self.read_transfers = count.max(1);
+        self
+    }
+
+    /// Longest SysEx frame to buffer from the device, in bytes including
+    /// `F0` and `F7`.
+    ///
+    /// A longer frame is dropped, along with everything up to the next
+    /// status byte, so a unit that never sends `F7` cannot grow the receive
+    /// buffer without bound. Defaults to [`MAX_SYSEX_LEN`], which is also
+    /// the most it can be raised to: a longer frame would be buffered only
+    /// for decoding to reject it.
+    pub fn max_sysex_len(mut self, bytes: usize) -> Self {
+        self.max_sysex = bytes.clamp(2, MAX_SYSEX_LEN);
+        self
+    }
+
+    /// Tell the unit the host is online as soon as it is opened, and offline
+    /// again in [`AutomapDevice::close()`].
+    pub fn auto_online(mut self, enabled: bool) -> Self {
+        self.auto_online = enabled;
+        self
+    }
+
+    /// Blank the LCDs and switch off every LED the application lit when the
+    /// device is closed with [`AutomapDevice::close()`].
+    pub fn auto_clear(mut self, enabled: bool) -> Self {
+        self.auto_clear = enabled;
+        self
+    }
+
+    /// Take over the surface when the device is opened, on by default.
+    ///
+    /// The unit is told the host is online, asked its product type, has its
+    /// surface captured for [`AutomapDevice::takeover_snapshot()`], then its
+    /// LCDs blanked and LEDs switched off, and is pinged; opening returns
+    /// once the echo comes back, with the surface ready for commands. Units
+    /// that do not answer a readback, like the ZeRO SL with transport lock,
+    /// hold the open up by [`REPLY_TIMEOUT`](crate::REPLY_TIMEOUT) each. Like
+    /// [`auto_online`](Self::auto_online), it also puts the unit back
+    /// online in [`AutomapDevice::recover()`] and offline in
+    /// [`AutomapDevice::close()`].
+    ///
+    /// Turn it off to open the unit without sending anything.
+    pub fn handshake(mut self, enabled: bool) -> Self {
+        self.handshake = enabled;
+        self
+    }
+
+    /// MIDI channel (1-16) of the Automap CC messages. The unit uses 16.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is not in `1..=16`.
+    pub fn cc_channel(mut self, channel: u8) -> Self {
+        assert!(
+            (1..=16).contains(&channel),
+            "MIDI channel must be 1-16, got {channel}"
+        );
+        self.cc_channel = channel;
+        self
+    }
+
+    /// Send an echo request whenever nothing has been sent for `interval`,
+    /// so the unit (and any USB power management) sees the host as alive.
+    ///
+    /// The keep-alive is sent from [`AutomapDevice::read_events()`], which
+    /// then returns early with no events; its echo reply is swallowed.
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = Some(interval);
+        self
+    }
+
+    /// Which USB interface to use; see [`Backend`].
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// If the interface is held by a kernel driver (typically `snd-usb-audio`
+    /// on Linux), detach it and claim the interface anyway. The driver is
+    /// reattached when the device is dropped.
+    ///
+    /// Only Linux supports detaching; elsewhere a busy interface still fails
+    /// with [`AutomapError::InterfaceBusy`].
+    pub fn detach_kernel_driver(mut self, enabled: bool) -> Self {
+        self.detach_kernel_driver = enabled;
+        self
+    }
+
+    /// Where the memory-protect flag is in the globals, taken as set when
+    /// any of its bytes is nonzero.
+    ///
+    /// Its offset is in the globals offsets document, not the public
+    /// reference, so by default it is unknown: the flash saves and writes
+    /// of [`AutomapDevice`] cannot check it, and send
+    /// [`DeviceNotice::MemoryProtectUnknown`](crate::DeviceNotice::MemoryProtectUnknown)
+    /// instead.
+    pub fn memory_protect_flag(mut self, field: GlobalField) -> Self {
+        self.memory_protect = Some(field);
+        self
+    }
+
+    /// Where the setting an alert reports a change of is in the globals,
+    /// so [`AutomapDevice::alert_value()`] can read its new value.
+    ///
+    /// An alert only says what kind of setting changed. Where the channel,
+    /// transpose, octave, aftertouch and velocity curve settings live is
+    /// unpublished like the rest of the globals offsets, so by default
+    /// none is known.
+    pub fn alert_field(mut self, alert: AlertType, field: GlobalField) -> Self {
+        self.alert_fields.retain(|(a, _)| *a != alert);
+        self.alert_fields.push((alert, field));
+        self
+    }
+
+    /// How long to wait between SysEx frames; see [`Pacing`].
+    pub fn pacing(mut self, pacing: Pacing) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    /// Sum the clicks of each encoder and of the speed dial into one event
+    /// per read, rather than one per message the unit sent; see
+    /// [`coalesce_clicks()`](crate::coalesce_clicks).
+    ///
+    /// With a `window` above zero, a read that brings a turn keeps reading
+    /// until `window` after it, so [`AutomapDevice::read_events()`]
+    /// returns that much later. A few milliseconds catch most bursts.
+    pub fn coalesce_clicks(mut self, window: Duration) -> Self {
+        self.coalesce_clicks = Some(window);
+        self
+    }
+
+    /// USB-MIDI cables that reach MIDI OUT 1 and 2, for
+    /// [`AutomapDevice::midi_out()`].
+    ///
+    /// The Programmer's Reference does not give them. The default, cables 1
+    /// and 2, assumes the sockets follow the Automap port on cable 0.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a cable is not in `0..=15`.
+    pub fn midi_out_cables(mut self, out1: u8, out2: u8) -> Self {
+        assert!(
+            out1 < 16 && out2 < 16,
+            "USB-MIDI cables are 0-15, got {out1} and {out2}"
+        );
+        self.midi_out_cables = [out1, out2];
+        self
+    }
+
+    /// Opens the device with this configuration.
+    ///
+    /// Same as [`AutomapDevice::open()`].
+    pub async fn open(&self) -> Result<AutomapDevice, AutomapError> {
+        AutomapDevice::open(self).await
+    }
+
+    /// Whether the host tells the unit it is online and offline.
+    pub(crate) fn goes_online(&self) -> bool {
+        self.auto_online || self.handshake
+    }
+
+    /// Status byte of CC messages on the configured channel.
+    pub(crate) fn cc_status(&self) -> u8 {
+        0xB0 | (self.cc_channel - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_options() {
+        let config = DeviceConfig::new().read_buffer_size(130).cc_channel(1);
+        assert_eq!(config.read_buffer, 128);
+        assert_eq!(config.cc_status(), 0xB0);
+        assert_eq!(DeviceConfig::new().read_buffer_size(0).read_buffer, 4);
+        assert_eq!(DeviceConfig::default().cc_status(), 0xBF);
+        assert_eq!(DeviceConfig::new().read_transfers(0).read_transfers, 1);
+        let huge = DeviceConfig::new().max_sysex_len(usize::MAX);
+        assert_eq!(huge.max_sysex, MAX_SYSEX_LEN);
+        assert!(DeviceConfig::new().goes_online());
+        assert!(!DeviceConfig::new().handshake(false).goes_online());
+        assert_eq!(Pacing::default().after(100), Duration::from_millis(33));
+        assert_eq!(Pacing::OFF.after(100), Duration::ZERO);
+    }
+}
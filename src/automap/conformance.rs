@@ -0,0 +1,305 @@
+//! Hardware-in-the-loop checks driven by the unit's simulation commands.
+//!
+//! The unit acts on [`SimCmd`]s as if its controls had been used: a
+//! simulated button press sends the same event as a real one (Section 13,
+//! PDF page 29). A [`Conformance`] suite sends each [`Check`]'s stimulus,
+//! reads what comes back, and compares it with what the check expects. The
+//! [`ConformanceReport`] lists what each stimulus actually produced, so
+//! reports from two firmware revisions can be diffed line by line.
+//!
+//! The PDF maps simulation numbers to controls only in a drawing
+//! (Appendix 2, page 30). Encoders 1 to 8 are the eight encoders; for
+//! buttons and pots, [`Conformance::zero_mkii()`] only expects an event of
+//! the right [group](EventFilter), and the report records which control
+//! answered.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::automap::cc::Encoder;
+use crate::automap::device::AutomapDevice;
+use crate::automap::event::AutomapEvent;
+use crate::automap::info::DeviceInfo;
+use crate::automap::rt;
+use crate::automap::session::SessionState;
+use crate::automap::subscribe::EventFilter;
+use crate::automap::sysex::{DbSimMsg, SimCmd};
+
+/// How long a check waits for its events by default.
+pub const CHECK_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// An event a check waits for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expect {
+    /// Exactly this event.
+    Event(AutomapEvent),
+    /// Any event in these groups.
+    Any(EventFilter),
+}
+
+impl Expect {
+    pub fn matches(&self, event: &AutomapEvent) -> bool {
+        match self {
+            Expect::Event(expected) => expected == event,
+            Expect::Any(filter) => filter.intersects(EventFilter::of(event)),
+        }
+    }
+}
+
+/// One stimulus and the events it should produce.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: String,
+    /// Simulation commands sent in order.
+    pub stimulus: Vec<SimCmd>,
+    /// Events that must come back in this order. Others may come in
+    /// between.
+    pub expect: Vec<Expect>,
+}
+
+impl Check {
+    pub fn new(name: impl Into<String>) -> Self {
+        Check {
+            name: name.into(),
+            stimulus: Vec::new(),
+            expect: Vec::new(),
+        }
+    }
+
+    pub fn send(mut self, cmd: SimCmd) -> Self {
+        self.stimulus.push(cmd);
+        self
+    }
+
+    pub fn expect(mut self, expect: Expect) -> Self {
+        self.expect.push(expect);
+        self
+    }
+
+    /// Index of the first expectation not met by `received`, or `None` if
+    /// they all are.
+    fn unmet(&self, received: &[AutomapEvent]) -> Option<usize> {
+        let mut events = received.iter();
+        self.expect
+            .iter()
+            .position(|expect| !events.any(|e| expect.matches(e)))
+    }
+}
+
+/// What a check got back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckResult {
+    pub name: String,
+    /// Every event read while the check ran.
+    pub received: Vec<AutomapEvent>,
+    /// The expectations not met, in order. Empty if the check passed.
+    pub missing: Vec<Expect>,
+    /// From sending the stimulus to the last expected event, or to giving
+    /// up.
+    pub elapsed: Duration,
+}
+
+impl CheckResult {
+    pub fn passed(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+/// The outcome of a [`Conformance`] run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConformanceReport {
+    /// The unit the checks ran against.
+    pub device: DeviceInfo,
+    pub results: Vec<CheckResult>,
+}
+
+impl ConformanceReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed()).count()
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &CheckResult> {
+        self.results.iter().filter(|r| !r.passed())
+    }
+
+    pub fn all_passed(&self) -> bool {
+        self.results.iter().all(CheckResult::passed)
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.device)?;
+        for result in &self.results {
+            let verdict = if result.passed() { "pass" } else { "FAIL" };
+            write!(f, "{verdict} {}:", result.name)?;
+            if result.received.is_empty() {
+                write!(f, " nothing")?;
+            }
+            for event in &result.received {
+                write!(f, " {event:?}")?;
+            }
+            for expect in &result.missing {
+                write!(f, ", missing {expect:?}")?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "{} of {} passed", self.passed(), self.results.len())
+    }
+}
+
+/// A list of checks to run against a unit.
+#[derive(Debug, Clone)]
+pub struct Conformance {
+    checks: Vec<Check>,
+    timeout: Duration,
+}
+
+impl Default for Conformance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Conformance {
+    /// No checks, each waiting up to [`CHECK_TIMEOUT`].
+    pub fn new() -> Self {
+        Conformance {
+            checks: Vec::new(),
+            timeout: CHECK_TIMEOUT,
+        }
+    }
+
+    /// The checks for a ZeRO MkII: a click on each encoder, a press and
+    /// release of buttons 1 to 8, and a move of pots 1 to 8.
+    pub fn zero_mkii() -> Self {
+        let mut suite = Self::new();
+        for n in 1..=8u8 {
+            let encoder = Encoder::try_from(Encoder::Encoder1 as u8 + n - 1).unwrap();
+            suite = suite.check(
+                Check::new(format!("encoder {n}"))
+                    .send(SimCmd::Encoder {
+                        number_1_based: n,
+                        clicks_signed: 1,
+                    })
+                    .expect(Expect::Event(AutomapEvent::Encoder { encoder, clicks: 1 })),
+            );
+        }
+        for n in 1..=8u8 {
+            let press = |pressed| SimCmd::Button {
+                number_1_based: n,
+                pressed,
+            };
+            suite = suite.check(
+                Check::new(format!("button {n}"))
+                    .send(press(true))
+                    .send(press(false))
+                    .expect(Expect::Any(EventFilter::buttons()))
+                    .expect(Expect::Any(EventFilter::buttons())),
+            );
+        }
+        for n in 1..=8u8 {
+            suite = suite.check(
+                Check::new(format!("pot {n}"))
+                    .send(SimCmd::PotSlider {
+                        number_1_based: n,
+                        value: 64,
+                    })
+                    .expect(Expect::Any(EventFilter::pots() | EventFilter::sliders())),
+            );
+        }
+        suite
+    }
+
+    pub fn check(mut self, check: Check) -> Self {
+        self.checks.push(check);
+        self
+    }
+
+    /// How long each check waits for its events before failing.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn checks(&self) -> &[Check] {
+        &self.checks
+    }
+
+    /// Runs every check in turn, putting the unit online first if it is
+    /// not.
+    ///
+    /// A check that times out fails and the run goes on; only I/O errors
+    /// stop it.
+    pub async fn run(
+        &self,
+        device: &mut AutomapDevice,
+    ) -> Result<ConformanceReport, std::io::Error> {
+        if device.session_state() != SessionState::Online {
+            device.enter_automap_mode().await?;
+        }
+        let mut results = Vec::with_capacity(self.checks.len());
+        for check in &self.checks {
+            results.push(self.run_check(device, check).await?);
+        }
+        Ok(ConformanceReport {
+            device: device.info(),
+            results,
+        })
+    }
+
+    async fn run_check(
+        &self,
+        device: &mut AutomapDevice,
+        check: &Check,
+    ) -> Result<CheckResult, std::io::Error> {
+        let start = Instant::now();
+        for cmd in &check.stimulus {
+            device.send_dbsim(&DbSimMsg::Simulate(cmd.clone())).await?;
+        }
+        let mut received = Vec::new();
+        let mut unmet = check.unmet(&received);
+        while unmet.is_some() {
+            let left = self.timeout.saturating_sub(start.elapsed());
+            let Some(events) = rt::timeout(left, device.read_events()).await else {
+                break;
+            };
+            received.extend(events?);
+            unmet = check.unmet(&received);
+        }
+        Ok(CheckResult {
+            name: check.name.clone(),
+            received,
+            missing: unmet.map_or_else(Vec::new, |i| check.expect[i..].to_vec()),
+            elapsed: start.elapsed(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::cc::Button;
+
+    #[test]
+    fn test_expectations_in_order() {
+        let press = |pressed| AutomapEvent::Button {
+            button: Button::ButtonA1,
+            pressed,
+        };
+        let check = Check::new("a1")
+            .expect(Expect::Event(press(true)))
+            .expect(Expect::Any(EventFilter::buttons()));
+        let touch = AutomapEvent::EncoderTouch {
+            encoder: Encoder::Encoder1,
+            touched: true,
+        };
+
+        assert_eq!(check.unmet(&[]), Some(0));
+        assert_eq!(check.unmet(&[touch, press(true)]), Some(1));
+        assert_eq!(check.unmet(&[press(true), touch, press(false)]), None);
+        // The press has to come first
+        assert_eq!(check.unmet(&[press(false), press(true)]), Some(1));
+        assert_eq!(Conformance::zero_mkii().checks().len(), 24);
+    }
+}
@@ -0,0 +1,331 @@
+//! One identifier for every physical control.
+//!
+//! Each stage names controls its own way: [`Source`] lists what a
+//! translator can map, [`PressSource`] what can be pressed and [`Analog`]
+//! what has a continuous value. [`ControlId`] covers them all, so a mapping
+//! table, a state store or a MIDI-learn session can key on a single type;
+//! each of the narrower enums converts into it.
+//!
+//! A control is one thing on the panel whatever it reports: a pot's
+//! movement and its touch sensor are both [`ControlId::Pot`], and the
+//! speed dial's turns, push and touch are all [`ControlId::SpeedDial`].
+//! What the event said about it is a [`ControlValue`]; together they are
+//! [`AutomapEvent::control()`] and [`AutomapEvent::value()`], enough for
+//! logging or a mapping engine to handle every event the same way.
+
+use std::fmt;
+
+use crate::automap::cc::{
+    AutomapButton, Button, Encoder, PageButton, Pot, RowSelect, Slider, TransportButton,
+};
+use crate::automap::dsp::Analog;
+use crate::automap::event::{AutomapEvent, Wheel};
+use crate::automap::gestures::PressSource;
+use crate::automap::translator::Source;
+
+/// A foot pedal input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pedal {
+    Sustain,
+    Expression,
+}
+
+/// One axis of the SL MkII's touchpad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TouchpadAxis {
+    X1,
+    Y1,
+    X2,
+    Y2,
+}
+
+/// A physical control on the unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ControlId {
+    Button(Button),
+    /// Buttons D1-D6 while transport lock is on.
+    Transport(TransportButton),
+    Automap(AutomapButton),
+    Page(PageButton),
+    RowSelect(RowSelect),
+    /// The Compact's row select for its fourth row of encoders.
+    EncoderRowSelect,
+    Encoder(Encoder),
+    Pot(Pot),
+    Slider(Slider),
+    SpeedDial,
+    Preview,
+    CrossFader,
+    ModWheel,
+    PitchWheel,
+    Pedal(Pedal),
+    Touchpad(TouchpadAxis),
+}
+
+impl ControlId {
+    /// The control `event` came from, or `None` for events that are not
+    /// about a control, such as replies and alerts.
+    pub fn from_event(event: &AutomapEvent) -> Option<ControlId> {
+        Some(match *event {
+            AutomapEvent::Button { button, .. } => ControlId::Button(button),
+            AutomapEvent::TransportButton { button, .. } => ControlId::Transport(button),
+            AutomapEvent::AutomapButton { button, .. } => ControlId::Automap(button),
+            AutomapEvent::PageButton { button, .. } => ControlId::Page(button),
+            AutomapEvent::RowSelect { row, .. } => ControlId::RowSelect(row),
+            AutomapEvent::EncoderRowSelect { .. } => ControlId::EncoderRowSelect,
+            AutomapEvent::Encoder { encoder, .. }
+            | AutomapEvent::LiveEncoder { encoder, .. }
+            | AutomapEvent::EncoderTouch { encoder, .. } => ControlId::Encoder(encoder),
+            AutomapEvent::Pot { pot, .. } | AutomapEvent::PotTouch { pot, .. } => {
+                ControlId::Pot(pot)
+            }
+            AutomapEvent::Slider { slider, .. } | AutomapEvent::SliderTouch { slider, .. } => {
+                ControlId::Slider(slider)
+            }
+            AutomapEvent::SpeedDial { .. }
+            | AutomapEvent::SpeedDialButton { .. }
+            | AutomapEvent::SpeedDialTouch { .. } => ControlId::SpeedDial,
+            AutomapEvent::PreviewButton { .. } => ControlId::Preview,
+            AutomapEvent::CrossFader { .. } | AutomapEvent::CrossFadeTouch { .. } => {
+                ControlId::CrossFader
+            }
+            AutomapEvent::Wheel {
+                wheel: Wheel::Mod { .. },
+            } => ControlId::ModWheel,
+            AutomapEvent::Wheel {
+                wheel: Wheel::Pitch { .. },
+            } => ControlId::PitchWheel,
+            AutomapEvent::SustainPedal { .. } => ControlId::Pedal(Pedal::Sustain),
+            AutomapEvent::ExpressionPedal { .. } => ControlId::Pedal(Pedal::Expression),
+            AutomapEvent::TouchpadX1 { .. } => ControlId::Touchpad(TouchpadAxis::X1),
+            AutomapEvent::TouchpadY1 { .. } => ControlId::Touchpad(TouchpadAxis::Y1),
+            AutomapEvent::TouchpadX2 { .. } => ControlId::Touchpad(TouchpadAxis::X2),
+            AutomapEvent::TouchpadY2 { .. } => ControlId::Touchpad(TouchpadAxis::Y2),
+            _ => return None,
+        })
+    }
+}
+
+/// What an event reports about its control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlValue {
+    /// A button, or the speed dial, pressed or released.
+    Pressed(bool),
+    /// A touch sensor touched or let go.
+    Touched(bool),
+    /// The new position of a pot, slider, pedal, the crossfader, the
+    /// mod wheel or a touchpad axis, 0-127.
+    Absolute(u8),
+    /// Clicks an encoder or the speed dial turned, positive clockwise.
+    Relative(i8),
+    /// The pitch wheel's bend, -8192 to 8191 with 0 at rest.
+    Bend(i16),
+}
+
+impl AutomapEvent {
+    /// The control this event is about; see [`ControlId::from_event()`].
+    pub fn control(&self) -> Option<ControlId> {
+        ControlId::from_event(self)
+    }
+
+    /// What the event reports about its [`control()`](Self::control), or
+    /// `None` when it is not about a control.
+    pub fn value(&self) -> Option<ControlValue> {
+        Some(match *self {
+            AutomapEvent::Button { pressed, .. }
+            | AutomapEvent::TransportButton { pressed, .. }
+            | AutomapEvent::AutomapButton { pressed, .. }
+            | AutomapEvent::PageButton { pressed, .. }
+            | AutomapEvent::SpeedDialButton { pressed }
+            | AutomapEvent::PreviewButton { pressed }
+            | AutomapEvent::SustainPedal { pressed } => ControlValue::Pressed(pressed),
+            AutomapEvent::RowSelect { selected, .. }
+            | AutomapEvent::EncoderRowSelect { selected } => ControlValue::Pressed(selected),
+            AutomapEvent::EncoderTouch { touched, .. }
+            | AutomapEvent::PotTouch { touched, .. }
+            | AutomapEvent::SliderTouch { touched, .. }
+            | AutomapEvent::CrossFadeTouch { touched }
+            | AutomapEvent::SpeedDialTouch { touched } => ControlValue::Touched(touched),
+            AutomapEvent::Encoder { clicks, .. }
+            | AutomapEvent::LiveEncoder { clicks, .. }
+            | AutomapEvent::SpeedDial { clicks } => ControlValue::Relative(clicks),
+            AutomapEvent::Pot { value, .. }
+            | AutomapEvent::Slider { value, .. }
+            | AutomapEvent::Wheel {
+                wheel: Wheel::Mod { value },
+            }
+            | AutomapEvent::ExpressionPedal { value }
+            | AutomapEvent::CrossFader { value }
+            | AutomapEvent::TouchpadX1 { value }
+            | AutomapEvent::TouchpadY1 { value }
+            | AutomapEvent::TouchpadX2 { value }
+            | AutomapEvent::TouchpadY2 { value } => ControlValue::Absolute(value),
+            AutomapEvent::Wheel {
+                wheel: Wheel::Pitch { value },
+            } => ControlValue::Bend(value),
+            _ => return None,
+        })
+    }
+}
+
+impl From<Source> for ControlId {
+    fn from(source: Source) -> Self {
+        match source {
+            Source::Button(button) => ControlId::Button(button),
+            Source::Transport(button) => ControlId::Transport(button),
+            Source::Encoder(encoder) => ControlId::Encoder(encoder),
+            Source::Pot(pot) => ControlId::Pot(pot),
+            Source::Slider(slider) => ControlId::Slider(slider),
+            Source::CrossFader => ControlId::CrossFader,
+            Source::ExpressionPedal => ControlId::Pedal(Pedal::Expression),
+            Source::SustainPedal => ControlId::Pedal(Pedal::Sustain),
+        }
+    }
+}
+
+impl From<PressSource> for ControlId {
+    fn from(source: PressSource) -> Self {
+        match source {
+            PressSource::Button(button) => ControlId::Button(button),
+            PressSource::Transport(button) => ControlId::Transport(button),
+            PressSource::Automap(button) => ControlId::Automap(button),
+            PressSource::Page(button) => ControlId::Page(button),
+            PressSource::RowSelect(row) => ControlId::RowSelect(row),
+            PressSource::Preview => ControlId::Preview,
+            PressSource::SpeedDial => ControlId::SpeedDial,
+        }
+    }
+}
+
+impl From<Analog> for ControlId {
+    fn from(source: Analog) -> Self {
+        match source {
+            Analog::Pot(pot) => ControlId::Pot(pot),
+            Analog::Slider(slider) => ControlId::Slider(slider),
+            Analog::CrossFader => ControlId::CrossFader,
+            Analog::ExpressionPedal => ControlId::Pedal(Pedal::Expression),
+            Analog::TouchpadX1 => ControlId::Touchpad(TouchpadAxis::X1),
+            Analog::TouchpadY1 => ControlId::Touchpad(TouchpadAxis::Y1),
+            Analog::TouchpadX2 => ControlId::Touchpad(TouchpadAxis::X2),
+            Analog::TouchpadY2 => ControlId::Touchpad(TouchpadAxis::Y2),
+        }
+    }
+}
+
+macro_rules! from_control {
+    ($($ty:ident),*) => {$(
+        impl From<$ty> for ControlId {
+            fn from(control: $ty) -> Self {
+                ControlId::$ty(control)
+            }
+        }
+    )*};
+}
+
+from_control!(Button, Encoder, Pot, Slider, RowSelect, Pedal);
+
+/// The name printed on the panel where there is one, e.g. `Pot3`, `Play`
+/// or `SustainPedal`.
+impl fmt::Display for ControlId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlId::Button(button) => write!(f, "{button}"),
+            ControlId::Transport(button) => write!(f, "{button}"),
+            ControlId::Automap(button) => write!(f, "{button}"),
+            ControlId::Page(button) => write!(f, "{button}"),
+            ControlId::RowSelect(row) => write!(f, "RowSelect{row}"),
+            ControlId::EncoderRowSelect => f.write_str("EncoderRowSelect"),
+            ControlId::Encoder(encoder) => write!(f, "{encoder}"),
+            ControlId::Pot(pot) => write!(f, "{pot}"),
+            ControlId::Slider(slider) => write!(f, "{slider}"),
+            ControlId::SpeedDial => f.write_str("SpeedDial"),
+            ControlId::Preview => f.write_str("Preview"),
+            ControlId::CrossFader => f.write_str("CrossFader"),
+            ControlId::ModWheel => f.write_str("ModWheel"),
+            ControlId::PitchWheel => f.write_str("PitchWheel"),
+            ControlId::Pedal(pedal) => write!(f, "{pedal:?}Pedal"),
+            ControlId::Touchpad(axis) => write!(f, "Touchpad{axis:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_control_and_value() {
+        let events = [
+            AutomapEvent::Encoder {
+                encoder: Encoder::Encoder2,
+                clicks: -3,
+            },
+            AutomapEvent::SpeedDialButton { pressed: true },
+            AutomapEvent::SliderTouch {
+                slider: Slider::Slider8,
+                touched: false,
+            },
+            AutomapEvent::CrossFader { value: 64 },
+        ];
+        let decomposed: Vec<_> = events
+            .iter()
+            .map(|e| (e.control().unwrap(), e.value().unwrap()))
+            .collect();
+        assert_eq!(
+            decomposed,
+            [
+                (
+                    ControlId::Encoder(Encoder::Encoder2),
+                    ControlValue::Relative(-3)
+                ),
+                (ControlId::SpeedDial, ControlValue::Pressed(true)),
+                (
+                    ControlId::Slider(Slider::Slider8),
+                    ControlValue::Touched(false)
+                ),
+                (ControlId::CrossFader, ControlValue::Absolute(64)),
+            ]
+        );
+        let alert = AutomapEvent::TemplateChanged { special: true };
+        assert_eq!((alert.control(), alert.value()), (None, None));
+    }
+
+    #[test]
+    fn test_one_id_per_control() {
+        let moved = AutomapEvent::Pot {
+            pot: Pot::Pot3,
+            value: 10,
+        };
+        let touched = AutomapEvent::PotTouch {
+            pot: Pot::Pot3,
+            touched: true,
+        };
+        assert_eq!(
+            ControlId::from_event(&moved),
+            Some(ControlId::Pot(Pot::Pot3))
+        );
+        assert_eq!(
+            ControlId::from_event(&moved),
+            ControlId::from_event(&touched)
+        );
+        assert_eq!(
+            ControlId::from_event(&AutomapEvent::EchoResponse { value: 1 }),
+            None
+        );
+
+        let pedal = AutomapEvent::ExpressionPedal { value: 3 };
+        let (source, _) = Source::from_event(&pedal).unwrap();
+        let (analog, _) = Analog::from_event(&pedal).unwrap();
+        assert_eq!(ControlId::from(source), ControlId::from(analog));
+
+        let mut names = HashMap::new();
+        names.insert(ControlId::from(Pedal::Sustain), "hold");
+        assert_eq!(
+            names[&ControlId::from_event(&AutomapEvent::SustainPedal { pressed: true }).unwrap()],
+            "hold"
+        );
+        assert_eq!(ControlId::from(source).to_string(), "ExpressionPedal");
+        assert_eq!(ControlId::from(RowSelect::L2).to_string(), "RowSelectL2");
+    }
+}
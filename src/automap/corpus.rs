@@ -0,0 +1,184 @@
+//! Golden captures: logs of real traffic, checked against the decoders.
+//!
+//! A capture is a text file of [`ProxiedMessage`] lines as a [`Proxy`]
+//! tap prints them, each with the bytes seen on the wire and, after the
+//! `;`, what they decoded to when recorded:
+//!
+//! ```text
+//! # Ping, then button A1 pressed
+//! >  BF 63 01
+//! <  BF 63 01  ; Echo 1
+//! <  BF 18 01  ; ButtonA1 down
+//! ```
+//!
+//! [`check_dir()`] decodes every line of every `.hex` file in a directory
+//! again and reports each one that now decodes differently, so quirks of
+//! the firmware that have been seen once stay handled. Blank lines and
+//! lines starting with `#` are ignored, and a line without a `;` expects
+//! the message not to decode. [`append_capture()`] adds newly recorded
+//! messages to a capture.
+//!
+//! [`Proxy`]: crate::Proxy
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::automap::proxy::{Direction, ProxiedMessage};
+
+/// A line of a capture that no longer decodes as recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub file: PathBuf,
+    /// 1-based line number.
+    pub line: usize,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |s: &Option<String>| s.clone().unwrap_or_else(|| "nothing".into());
+        write!(
+            f,
+            "{}:{}: recorded as {}, now decodes as {}",
+            self.file.display(),
+            self.line,
+            show(&self.expected),
+            show(&self.actual)
+        )
+    }
+}
+
+/// Checks every `.hex` capture in `dir`, in file name order.
+///
+/// Fails if a file cannot be read or has a line that is not a message.
+pub fn check_dir(dir: impl AsRef<Path>) -> io::Result<Vec<Mismatch>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "hex") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    let mut mismatches = Vec::new();
+    for file in files {
+        mismatches.extend(check_capture(&file)?);
+    }
+    Ok(mismatches)
+}
+
+/// Checks one capture file.
+pub fn check_capture(file: impl AsRef<Path>) -> io::Result<Vec<Mismatch>> {
+    let file = file.as_ref();
+    let text = fs::read_to_string(file)?;
+    let mut mismatches = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (msg, expected) = parse_line(line).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: not a message", file.display(), i + 1),
+            )
+        })?;
+        let actual = msg.annotation();
+        if actual != expected {
+            mismatches.push(Mismatch {
+                file: file.to_path_buf(),
+                line: i + 1,
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Appends `messages` to the capture at `path`, creating it if needed.
+///
+/// Meant for a [`Proxy`](crate::Proxy) tap that collects what went by; the
+/// lines are written exactly as displayed, decoded form included, so the
+/// capture records today's decoding as the expected one.
+pub fn append_capture<'a>(
+    path: impl AsRef<Path>,
+    messages: impl IntoIterator<Item = &'a ProxiedMessage>,
+) -> io::Result<()> {
+    let mut out = String::new();
+    for msg in messages {
+        out.push_str(&msg.to_string());
+        out.push('\n');
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(out.as_bytes())
+}
+
+/// Splits a capture line into the message and its recorded decoding.
+fn parse_line(line: &str) -> Option<(ProxiedMessage, Option<String>)> {
+    let (wire, decoded) = match line.split_once(';') {
+        Some((wire, decoded)) => (wire, Some(decoded.trim().to_string())),
+        None => (line, None),
+    };
+    let mut chars = wire.chars();
+    let direction = match chars.next()? {
+        '<' => Direction::FromDevice,
+        '>' => Direction::ToDevice,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let (injected, rest) = match rest.strip_prefix('+') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let bytes = rest
+        .split_whitespace()
+        .map(|b| u8::from_str_radix(b, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    if bytes.is_empty() {
+        return None;
+    }
+    let msg = ProxiedMessage::from_log(direction, injected, bytes);
+    Some((msg, decoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_round_trips_a_recorded_line() {
+        let msg = ProxiedMessage::from_log(Direction::FromDevice, false, vec![0xBF, 0x18, 0x01]);
+        let line = msg.to_string();
+        let (parsed, decoded) = parse_line(&line).unwrap();
+        assert_eq!(parsed.bytes, msg.bytes);
+        assert_eq!(decoded, msg.annotation());
+        assert!(decoded.is_some());
+
+        let (injected, decoded) = parse_line(">+ BF 63 01").unwrap();
+        assert_eq!(injected.direction, Direction::ToDevice);
+        assert!(injected.injected);
+        assert_eq!(decoded, None);
+        assert!(parse_line("<  BF 18 zz").is_none());
+    }
+
+    #[test]
+    fn test_reports_changed_decoding() {
+        let path = env::temp_dir().join(format!("automap-corpus-{}.hex", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let msg = ProxiedMessage::from_log(Direction::FromDevice, false, vec![0xBF, 0x18, 0x01]);
+        append_capture(&path, [&msg]).unwrap();
+        assert!(check_capture(&path).unwrap().is_empty());
+
+        fs::write(&path, "# edited\n<  BF 18 01  ; something else\n").unwrap();
+        let mismatches = check_capture(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].line, 2);
+        assert_eq!(mismatches[0].actual, msg.annotation());
+    }
+}
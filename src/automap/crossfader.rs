@@ -0,0 +1,88 @@
+//! Crossfader curves for the ZeRO SL MkII.
+//!
+//! The crossfader only reports its position; the unit has no crossfader
+//! output to drive. Besides CC 0x42, the manual lists it on CC 0x44
+//! "(same as X1)", the SL MkII's touchpad X axis, so a ZeRO's crossfader
+//! can decode as [`AutomapEvent::TouchpadX1`]. The device rewrites those
+//! into [`AutomapEvent::CrossFader`] once it knows the model; see
+//! [`Capabilities::disambiguate()`](crate::Capabilities::disambiguate).
+//!
+//! [`CrossfaderCurve`] turns a position into gains for the two sides, the
+//! way a DJ mixer would.
+//!
+//! [`AutomapEvent::TouchpadX1`]: crate::AutomapEvent::TouchpadX1
+//! [`AutomapEvent::CrossFader`]: crate::AutomapEvent::CrossFader
+
+use std::f32::consts::FRAC_PI_2;
+
+/// How the two sides fade as the crossfader moves.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CrossfaderCurve {
+    /// Gains follow the position, so the middle is 6 dB down on each side.
+    #[default]
+    Linear,
+    /// Sine and cosine gains: the summed power stays the same, so the
+    /// middle is only 3 dB down. The usual choice for mixing.
+    ConstantPower,
+    /// Both sides at full level except within `cut_in` of the far end,
+    /// where a side fades out. The scratch curve; a `cut_in` of 0.0 cuts
+    /// instantly at the ends.
+    Cut { cut_in: f32 },
+}
+
+impl CrossfaderCurve {
+    /// The gains of the left and right sides, between 0.0 and 1.0, for a
+    /// position from 0.0 (fully left) to 1.0 (fully right).
+    pub fn gains(self, position: f32) -> (f32, f32) {
+        let p = position.clamp(0.0, 1.0);
+        match self {
+            CrossfaderCurve::Linear => (1.0 - p, p),
+            CrossfaderCurve::ConstantPower => ((p * FRAC_PI_2).cos(), (p * FRAC_PI_2).sin()),
+            CrossfaderCurve::Cut { cut_in } => {
+                let fade = |distance: f32| {
+                    if cut_in <= 0.0 {
+                        if distance > 0.0 { 1.0 } else { 0.0 }
+                    } else {
+                        (distance / cut_in).min(1.0)
+                    }
+                };
+                (fade(1.0 - p), fade(p))
+            }
+        }
+    }
+
+    /// [`gains()`](Self::gains) for a 7-bit crossfader value.
+    pub fn gains_for(self, value: u8) -> (f32, f32) {
+        self.gains(f32::from(value & 0x7F) / 127.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-6 && (a.1 - b.1).abs() < 1e-6
+    }
+
+    #[test]
+    fn test_curves() {
+        assert_eq!(CrossfaderCurve::Linear.gains_for(0), (1.0, 0.0));
+        assert_eq!(CrossfaderCurve::Linear.gains(0.25), (0.75, 0.25));
+
+        let (l, r) = CrossfaderCurve::ConstantPower.gains(0.5);
+        assert!((l * l + r * r - 1.0).abs() < 1e-6);
+        assert!(close(
+            CrossfaderCurve::ConstantPower.gains_for(127),
+            (0.0, 1.0)
+        ));
+
+        let scratch = CrossfaderCurve::Cut { cut_in: 0.1 };
+        assert_eq!(scratch.gains(0.5), (1.0, 1.0));
+        assert!(close(scratch.gains(0.05), (1.0, 0.5)));
+        assert_eq!(scratch.gains(1.0), (0.0, 1.0));
+        let hard = CrossfaderCurve::Cut { cut_in: 0.0 };
+        assert_eq!(hard.gains(0.01), (1.0, 1.0));
+        assert_eq!(hard.gains(0.0), (1.0, 0.0));
+    }
+}
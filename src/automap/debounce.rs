@@ -0,0 +1,244 @@
+//! Hysteresis for pots, sliders and the other continuous controls.
+//!
+//! A pot left resting between two steps tends to flip between them, and
+//! every flip is an event. [`Debouncer`] sits between the device and the
+//! application and lets a change through at once only if it moves at least
+//! [`delta`](Hysteresis::delta) away from the value last let through.
+//! Smaller changes are held back until the control has been still for
+//! [`settle`](Hysteresis::settle), so a control that comes to rest still
+//! reports where it stopped, once. Like the gesture recogniser it needs
+//! [`poll()`](Debouncer::poll)ing, ideally at
+//! [`next_deadline()`](Debouncer::next_deadline).
+//!
+//! Encoders, buttons and everything else pass through untouched.
+
+use std::time::{Duration, Instant};
+
+use crate::automap::event::AutomapEvent;
+use crate::automap::timed::TimedEvent;
+use crate::automap::translator::Source;
+
+/// How much one control has to move before it is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hysteresis {
+    /// Smallest change, in 7-bit steps, reported as soon as it arrives.
+    /// 0 and 1 report every change.
+    pub delta: u8,
+    /// How long a control must stay still before a smaller change is
+    /// reported.
+    pub settle: Duration,
+}
+
+impl Hysteresis {
+    /// Reports every change as it arrives.
+    pub const OFF: Hysteresis = Hysteresis {
+        delta: 1,
+        settle: Duration::ZERO,
+    };
+}
+
+impl Default for Hysteresis {
+    fn default() -> Self {
+        Hysteresis {
+            delta: 2,
+            settle: Duration::from_millis(40),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct State {
+    /// The value last let through.
+    reported: u8,
+    /// A smaller change waiting for the control to settle.
+    held: Option<TimedEvent>,
+}
+
+/// Drops jitter from continuous controls, with per-control settings.
+#[derive(Debug, Clone, Default)]
+pub struct Debouncer {
+    defaults: Hysteresis,
+    overrides: Vec<(Source, Hysteresis)>,
+    states: Vec<(Source, State)>,
+}
+
+impl Debouncer {
+    /// A debouncer using `defaults` for every control.
+    pub fn new(defaults: Hysteresis) -> Self {
+        Debouncer {
+            defaults,
+            ..Self::default()
+        }
+    }
+
+    /// Uses `hysteresis` for `source` instead of the defaults.
+    pub fn with_hysteresis(mut self, source: Source, hysteresis: Hysteresis) -> Self {
+        self.overrides.retain(|(s, _)| *s != source);
+        self.overrides.push((source, hysteresis));
+        self
+    }
+
+    /// Feeds one event, returning what should reach the application now.
+    ///
+    /// Also runs [`poll()`](Self::poll) at the event's time, so held
+    /// changes of other controls come first. The first value of a control,
+    /// and its end stops 0 and 127, are always reported.
+    pub fn process(&mut self, event: &TimedEvent) -> Vec<TimedEvent> {
+        let mut out = self.poll(event.at);
+        let Some((source, value)) = continuous(&event.event) else {
+            out.push(*event);
+            return out;
+        };
+        let delta = self.hysteresis(source).delta;
+        let Some(i) = self.states.iter().position(|(s, _)| *s == source) else {
+            self.states.push((
+                source,
+                State {
+                    reported: value,
+                    held: None,
+                },
+            ));
+            out.push(*event);
+            return out;
+        };
+        let state = &mut self.states[i].1;
+        if value == state.reported {
+            // Back where it was: whatever was held is jitter
+            state.held = None;
+        } else if value.abs_diff(state.reported) >= delta || value == 0 || value == 0x7F {
+            state.reported = value;
+            state.held = None;
+            out.push(*event);
+        } else {
+            state.held = Some(*event);
+        }
+        out
+    }
+
+    /// Reports held changes of controls that have been still for their
+    /// settle time by `now`, each at the time it arrived.
+    pub fn poll(&mut self, now: Instant) -> Vec<TimedEvent> {
+        let mut out = Vec::new();
+        for i in 0..self.states.len() {
+            let (source, state) = self.states[i];
+            let Some(held) = state.held else {
+                continue;
+            };
+            if now >= held.at + self.hysteresis(source).settle {
+                if let Some((_, value)) = continuous(&held.event) {
+                    self.states[i].1.reported = value;
+                }
+                self.states[i].1.held = None;
+                out.push(held);
+            }
+        }
+        out.sort_by_key(|e| e.at);
+        out
+    }
+
+    /// The earliest time at which [`poll()`](Self::poll) could report a
+    /// held change, if any is waiting.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.states
+            .iter()
+            .filter_map(|&(source, state)| Some(state.held?.at + self.hysteresis(source).settle))
+            .min()
+    }
+
+    /// Forgets every control's last value, so the next value of each is
+    /// reported whatever it is. Held changes are dropped.
+    pub fn reset(&mut self) {
+        self.states.clear();
+    }
+
+    fn hysteresis(&self, source: Source) -> Hysteresis {
+        self.overrides
+            .iter()
+            .find(|(s, _)| *s == source)
+            .map_or(self.defaults, |(_, h)| *h)
+    }
+}
+
+/// The control and 7-bit value of an event from a continuous control.
+fn continuous(event: &AutomapEvent) -> Option<(Source, u8)> {
+    let (source, value) = Source::from_event(event)?;
+    matches!(
+        source,
+        Source::Pot(_) | Source::Slider(_) | Source::CrossFader | Source::ExpressionPedal
+    )
+    .then_some((source, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::cc::{Button, Pot};
+
+    fn pot(at: Instant, value: u8) -> TimedEvent {
+        TimedEvent {
+            at,
+            event: AutomapEvent::Pot {
+                pot: Pot::Pot1,
+                value,
+            },
+        }
+    }
+
+    fn values(events: &[TimedEvent]) -> Vec<u8> {
+        events
+            .iter()
+            .map(|e| match e.event {
+                AutomapEvent::Pot { value, .. } => value,
+                _ => panic!("not a pot: {e:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_drops_oscillation_and_reports_rest() {
+        let t0 = Instant::now();
+        let ms = Duration::from_millis;
+        let mut d = Debouncer::default();
+
+        assert_eq!(values(&d.process(&pot(t0, 60))), [60]);
+        assert!(d.process(&pot(t0 + ms(10), 61)).is_empty());
+        assert!(d.process(&pot(t0 + ms(20), 60)).is_empty());
+        assert_eq!(d.next_deadline(), None);
+        assert!(d.process(&pot(t0 + ms(30), 61)).is_empty());
+        assert_eq!(d.next_deadline(), Some(t0 + ms(70)));
+        assert_eq!(values(&d.poll(t0 + ms(70))), [61]);
+        // A real move goes straight through
+        assert_eq!(values(&d.process(&pot(t0 + ms(80), 64))), [64]);
+        assert_eq!(values(&d.process(&pot(t0 + ms(90), 127))), [127]);
+    }
+
+    #[test]
+    fn test_per_control_settings_and_pass_through() {
+        let t0 = Instant::now();
+        let mut d = Debouncer::new(Hysteresis::OFF).with_hysteresis(
+            Source::Pot(Pot::Pot1),
+            Hysteresis {
+                delta: 8,
+                settle: Duration::from_secs(1),
+            },
+        );
+        let fader = TimedEvent {
+            at: t0,
+            event: AutomapEvent::CrossFader { value: 10 },
+        };
+        let button = TimedEvent {
+            at: t0,
+            event: AutomapEvent::Button {
+                button: Button::ButtonA1,
+                pressed: true,
+            },
+        };
+        d.process(&pot(t0, 10));
+        assert!(d.process(&pot(t0, 15)).is_empty());
+        assert_eq!(d.process(&fader), [fader]);
+        let mut moved = fader;
+        moved.event = AutomapEvent::CrossFader { value: 11 };
+        assert_eq!(d.process(&moved), [moved]);
+        assert_eq!(d.process(&button), [button]);
+    }
+}
@@ -1,6 +1,7 @@
+use nusb::descriptors::{ConfigurationDescriptor, EndpointDescriptor, TransferType};
 use nusb::io::EndpointWrite;
-use nusb::transfer::{Bulk, In, Out};
-use nusb::{self, io::EndpointRead};
+use nusb::transfer::{Bulk, BulkOrInterrupt, Direction, EndpointDirection, In, Interrupt, Out};
+use nusb::{self, Endpoint, io::EndpointRead};
 
 // Conditional imports for async traits based on selected runtime
 #[cfg(feature = "tokio")]
@@ -9,45 +10,452 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt};
 #[cfg(feature = "smol")]
 use futures_lite::{AsyncReadExt, AsyncWriteExt};
 
-use std::error::Error;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use crate::automap::capabilities::{Capabilities, Model};
+use crate::automap::cc::{AlertType, Encoder, EncoderPosition, ParameterRequestType, RingMode};
 use crate::automap::command::AutomapCommand;
-use crate::automap::event::AutomapEvent;
-use crate::midi::{split_midi_messages, usbmidi_pack, usbmidi_unpack};
+use crate::automap::config::{Backend, DeviceConfig, Pacing};
+use crate::automap::consts::{NOVATION_ID, SYSEX_HEADER_LEN, VENDOR_ID, ZERO_MKII_PRODUCT_ID};
+use crate::automap::error::AutomapError;
+use crate::automap::event::{AutomapEvent, ParameterResponse};
+use crate::automap::extension::{CustomEvent, ExtensionKey, Extensions};
+use crate::automap::globals::GlobalField;
+use crate::automap::handle::{AutomapHandle, MidiOut};
+use crate::automap::info::DeviceInfo;
+use crate::automap::latency::LatencyStats;
+use crate::automap::layers::Layer;
+use crate::automap::lcd::LcdScreen;
+use crate::automap::leds::{LedBitmap, LedState, all_off_commands};
+use crate::automap::midi_in::{MAX_MIDI_IN, MidiInMessage};
+#[cfg(feature = "mock")]
+use crate::automap::mock::FakeZeroMkII;
+use crate::automap::notice::{DeviceNotice, NoticeStream, Notifiers};
+use crate::automap::probe::{InterfaceAccess, InterfaceKind, InterfaceProbe, ProbeReport};
+use crate::automap::relative::coalesce_clicks;
+use crate::automap::requests::ParameterRequests;
+use crate::automap::rt;
+use crate::automap::session::{Session, SessionState};
+use crate::automap::simulator::Simulator;
+use crate::automap::snapshot::SurfaceSnapshot;
+use crate::automap::state::{Snapshot, SnapshotCollector};
+use crate::automap::stats::DeviceStats;
+use crate::automap::subscribe::{EventFilter, EventReceiver, Subscribers, Subscription};
+use crate::automap::template::{TEMPLATE_LEN, Template};
+use crate::automap::timed::TimedEvent;
+use crate::automap::transfer::{Phase, Transfer, VerifyError, mismatches};
+#[cfg(target_os = "linux")]
+use crate::automap::udev::udev_rule;
+use crate::automap::unknown::{UnknownMessage, classify};
+use crate::midi::{MidiStream, usbmidi_pack_cable, usbmidi_unpack_cables};
 
-use super::sysex::AutomapSysEx;
+use super::sysex::{
+    AutomapSysEx, DbSimMsg, DbTarget, DecodedMsg, LcdClear, LcdOp, SimCmd, SimHighLevel,
+    decode_frame,
+};
 
-const VID: u16 = 0x1235;
-const PID: u16 = 0x000c;
-
-// ZeRO MkII vendor interface (from your lsusb -v dump)
-const IFACE: u8 = 2;
-
-const EP_OUT: u8 = 0x06; // host -> device
-const EP_IN: u8 = 0x86; // device -> host
+const VID: u16 = VENDOR_ID;
+const PID: u16 = ZERO_MKII_PRODUCT_ID;
 
 // const USB_PKT: usize = 4; // USB-MIDI event packet size
+/// Default bytes per USB read; see [`DeviceConfig::read_buffer_size()`].
 pub const USB_BUF: usize = 64; // endpoint wMaxPacketSize = 32 bytes => multiple of 4 ok
 
+/// How long `ping()` and the readback requests wait for a reply before giving up.
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Most notices kept for [`AutomapDevice::take_notices()`].
+const MAX_NOTICES: usize = 64;
+
+/// Stalls and empty reads in a row one read tolerates before giving up.
+const READ_RETRIES: u32 = 5;
+
+/// Wait before the first retry of a read, doubled for each one after.
+const READ_BACKOFF: Duration = Duration::from_millis(1);
+
+type UnknownHook = Box<dyn FnMut(&UnknownMessage) + Send>;
+
+/// A decoded message from the device: a CC event or a complete SysEx frame.
+enum Incoming {
+    Event(AutomapEvent),
+    SysEx(Vec<u8>),
+}
+
 pub struct AutomapDevice {
-    reader: EndpointRead<Bulk>,
-    writer: EndpointWrite<Bulk>,
+    /// `None` after clearing a halt failed, until [`recover()`](Self::recover).
+    reader: Option<Reader>,
+    outbox: Arc<Outbox>,
+    config: DeviceConfig,
+    /// Reassembles SysEx frames that span several USB transfers.
+    rx: MidiStream,
+    /// Scratch buffer for USB reads, kept to avoid allocating on every read.
+    read_buf: Vec<u8>,
+    notices: VecDeque<DeviceNotice>,
+    notifiers: Notifiers,
+    /// Events read while waiting for a reply, handed out by the next `read_events()`.
+    pending: VecDeque<TimedEvent>,
+    latency: LatencyStats,
+    subscribers: Subscribers,
+    unknown_hooks: Vec<UnknownHook>,
+    /// Shared with the read closure, which cannot borrow the device.
+    extensions: Arc<Mutex<Extensions>>,
+    custom_events: VecDeque<CustomEvent>,
+    /// Reassembles messages on each cable other than the Automap port's.
+    cable_rx: Vec<(u8, MidiStream)>,
+    midi_in: VecDeque<MidiInMessage>,
+    echo_nonce: u8,
+    /// Nonce of the outstanding keep-alive echo, whose reply is not reported.
+    keep_alive_nonce: Option<u8>,
+    info: DeviceInfo,
+    /// What the handshake found out, until the unit is reopened.
+    capabilities: Option<Capabilities>,
+    /// The surface as the handshake found it, before clearing it.
+    takeover: Option<SurfaceSnapshot>,
+    /// Whether flash saves go ahead even with memory protect on.
+    override_memory_protect: bool,
+    /// The simulated unit this device was opened on, to reconnect to.
+    #[cfg(feature = "mock")]
+    fake: Option<FakeZeroMkII>,
 }
 
 impl AutomapDevice {
-    pub async fn new() -> Result<AutomapDevice, Box<dyn Error>> {
-        let device_info = nusb::list_devices()
-            .await?
-            .find(|dev| dev.vendor_id() == VID && dev.product_id() == PID)
-            .expect("device not found");
+    /// Opens the first ZeRO MkII found with the default configuration.
+    ///
+    /// Shortcut for `AutomapDevice::builder().open()`, so the surface is
+    /// taken over by the [handshake](DeviceConfig::handshake) and ready for
+    /// commands when this returns.
+    pub async fn new() -> Result<AutomapDevice, AutomapError> {
+        Self::open(&DeviceConfig::default()).await
+    }
+
+    /// Starts configuring a device to open; see [`DeviceConfig`].
+    pub fn builder() -> DeviceConfig {
+        DeviceConfig::new()
+    }
+
+    /// Opens a ZeRO MkII as described by `config`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the unit cannot be found or claimed, or if the
+    /// [handshake](DeviceConfig::handshake) does not complete, e.g. with an
+    /// `ErrorKind::TimedOut` I/O error when the unit never answers its echo.
+    pub async fn open(config: &DeviceConfig) -> Result<AutomapDevice, AutomapError> {
+        let (reader, writer, device_info) = connect(config).await?;
+        let device =
+            Self::with_endpoints(reader, writer, config, DeviceInfo::from_usb(&device_info));
+        device.start().await
+    }
+
+    /// Opens a device talking to `fake` instead of USB hardware.
+    ///
+    /// Everything works as with a real unit, including the handshake, and
+    /// [`recover()`](Self::recover) reconnects to `fake` once it is plugged
+    /// in. The endpoint and backend settings in `config` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Only fails if the handshake does.
+    #[cfg(feature = "mock")]
+    pub async fn open_mock(
+        fake: &FakeZeroMkII,
+        config: &DeviceConfig,
+    ) -> Result<AutomapDevice, AutomapError> {
+        let reader = Reader::Mock(fake.clone());
+        let writer = Writer::Mock(fake.clone());
+        let info = DeviceInfo {
+            serial: None,
+            bus_id: "mock".to_string(),
+            address: 0,
+            port_chain: Vec::new(),
+            product_id: PID,
+            // The fake reports firmware 1.00
+            firmware_version: 0x0100,
+            protocol_version: None,
+            model: None,
+        };
+        let mut device = Self::with_endpoints(reader, writer, config, info);
+        device.fake = Some(fake.clone());
+        device.start().await
+    }
+
+    fn with_endpoints(
+        reader: Reader,
+        writer: Writer,
+        config: &DeviceConfig,
+        info: DeviceInfo,
+    ) -> AutomapDevice {
+        AutomapDevice {
+            reader: Some(reader),
+            outbox: Arc::new(Outbox {
+                writer: rt::Mutex::new(Some(writer)),
+                cc_status: config.cc_status(),
+                leds: Mutex::default(),
+                lcd: Mutex::default(),
+                last_tx: Mutex::new(Instant::now()),
+                pacing: config.pacing,
+                next_frame: rt::Mutex::new(Instant::now()),
+                midi_out_cables: config.midi_out_cables,
+                parameter_requests: Mutex::default(),
+                session: Session::new(SessionState::Claimed),
+                stats: Mutex::new(DeviceStats::new(Instant::now())),
+            }),
+            config: config.clone(),
+            rx: MidiStream::new(config.max_sysex),
+            read_buf: vec![0; config.read_buffer],
+            notices: VecDeque::new(),
+            notifiers: Notifiers::default(),
+            pending: VecDeque::new(),
+            latency: LatencyStats::default(),
+            subscribers: Subscribers::default(),
+            unknown_hooks: Vec::new(),
+            extensions: Arc::default(),
+            custom_events: VecDeque::new(),
+            cable_rx: Vec::new(),
+            midi_in: VecDeque::new(),
+            echo_nonce: 0,
+            keep_alive_nonce: None,
+            info,
+            capabilities: None,
+            takeover: None,
+            override_memory_protect: false,
+            #[cfg(feature = "mock")]
+            fake: None,
+        }
+    }
+
+    /// Puts a freshly opened device online if configured to.
+    async fn start(mut self) -> Result<AutomapDevice, AutomapError> {
+        if self.config.handshake {
+            self.handshake().await?;
+        } else if self.config.auto_online {
+            self.send_sysex(AutomapSysEx::OnlineOffline { online: true })
+                .await?;
+        }
+        Ok(self)
+    }
+
+    /// The takeover sequence of [`DeviceConfig::handshake()`].
+    async fn handshake(&mut self) -> Result<(), AutomapError> {
+        self.send_sysex(AutomapSysEx::OnlineOffline { online: true })
+            .await?;
+        let capabilities = self.capabilities().await?;
+        self.capabilities = Some(capabilities);
+        self.takeover = Some(self.snapshot_state().await?);
+        self.send_sysex(AutomapSysEx::LcdText(vec![
+            LcdOp::Clear(LcdClear::BothDisplays),
+            LcdOp::End,
+        ]))
+        .await?;
+        self.clear_all_leds().await?;
+        self.ping().await?;
+        Ok(())
+    }
+
+    /// Reconnects to the unit after it went away, typically across a
+    /// suspend/resume, and puts the surface back.
+    ///
+    /// Call this once [`read_events()`](Self::read_events) or a send has
+    /// failed with `ErrorKind::ConnectionAborted` (the USB device is gone,
+    /// `ENODEV`) or `NotConnected`, which leaves the session
+    /// [`Detached`](SessionState::Detached). Stalled endpoints (`EPIPE`)
+    /// are cleared automatically and need no recovery.
+    ///
+    /// The unit is opened again with the original configuration, told the
+    /// host is online if it was before the unit went away, or if
+    /// [`auto_online`](DeviceConfig::auto_online) or the
+    /// [handshake](DeviceConfig::handshake) is set,
+    /// and sent the LCD text and LED states this device and its handles had
+    /// set. Subscriptions and handles keep working. If the unit is not back
+    /// yet this fails and can simply be retried.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from reopening the unit or replaying its state.
+    pub async fn recover(&mut self) -> Result<(), AutomapError> {
+        let was_online = matches!(
+            self.outbox.session.before_detach(),
+            SessionState::Online | SessionState::Degraded
+        );
+        // The old interface has to be released before it can be claimed
+        // again, in case the unit kept its USB address
+        self.reader = None;
+        *self.outbox.writer.lock().await = None;
+        self.outbox.session.set(SessionState::Detached);
+
+        let (reader, writer, info) = self.reconnect().await?;
+        self.reader = Some(reader);
+        *self.outbox.writer.lock().await = Some(writer);
+        self.outbox.session.set(SessionState::Claimed);
+        self.rx = MidiStream::new(self.config.max_sysex);
+        self.keep_alive_nonce = None;
+        if let Some(info) = info {
+            self.info = info;
+        }
+        self.capabilities = None;
+
+        if was_online || self.config.goes_online() {
+            self.send_sysex(AutomapSysEx::OnlineOffline { online: true })
+                .await?;
+        }
+        if let Some(lcd) = self.lcd() {
+            self.send_sysex(AutomapSysEx::LcdText(lcd.to_ops())).await?;
+        }
+        let leds = self.leds();
+        for cmd in LedState::default().commands_to(&leds) {
+            self.send_command(&cmd).await?;
+        }
+        self.notice(DeviceNotice::Resynced);
+        Ok(())
+    }
+
+    /// Opens the endpoints again for [`recover()`](Self::recover), with the
+    /// unit's new USB details if it is real hardware.
+    async fn reconnect(&self) -> Result<(Reader, Writer, Option<DeviceInfo>), AutomapError> {
+        #[cfg(feature = "mock")]
+        if let Some(fake) = &self.fake {
+            if !fake.is_plugged_in() {
+                return Err(AutomapError::NotFound);
+            }
+            return Ok((Reader::Mock(fake.clone()), Writer::Mock(fake.clone()), None));
+        }
+        let (reader, writer, info) = connect(&self.config).await?;
+        Ok((reader, writer, Some(DeviceInfo::from_usb(&info))))
+    }
+
+    /// Checks which of the unit's interfaces can be opened, without keeping
+    /// the device open.
+    ///
+    /// Each vendor or MIDI streaming interface is claimed and released again.
+    /// Use the report to tell users what is wrong before calling
+    /// [`open()`](Self::open), e.g. that the Automap interface needs WinUSB.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no unit matches `config`, or the device cannot be opened.
+    pub async fn probe(config: &DeviceConfig) -> Result<ProbeReport, AutomapError> {
+        let device_info = find_device(config).await?;
+        let device = open_device(&device_info).await?;
+
+        let mut interfaces = Vec::new();
+        if let Ok(desc) = device.active_configuration() {
+            for iface in desc.interfaces() {
+                let number = iface.interface_number();
+                let alt = iface.first_alt_setting();
+                let kind = InterfaceKind::from_class(alt.class(), alt.subclass());
+                if matches!(kind, InterfaceKind::Other { .. }) {
+                    continue;
+                }
+                let access = match device.claim_interface(number).await {
+                    Ok(_) => InterfaceAccess::Available,
+                    Err(e) => match claim_error(e, &device_info, number) {
+                        AutomapError::InterfaceBusy { driver, .. } => {
+                            InterfaceAccess::Busy { driver }
+                        }
+                        AutomapError::DriverNotBound { .. } => InterfaceAccess::DriverNotBound,
+                        other => InterfaceAccess::Error(other.to_string()),
+                    },
+                };
+                interfaces.push(InterfaceProbe {
+                    number,
+                    kind,
+                    access,
+                });
+            }
+        }
+
+        Ok(ProbeReport {
+            product: device_info.product_string().map(str::to_owned),
+            serial: device_info.serial_number().map(str::to_owned),
+            interfaces,
+        })
+    }
+
+    /// Closes the device.
+    ///
+    /// With [`auto_clear`](DeviceConfig::auto_clear) the LCDs are blanked and
+    /// the LEDs lit through this device are switched off; with
+    /// [`auto_online`](DeviceConfig::auto_online) or the
+    /// [handshake](DeviceConfig::handshake) the unit is told the host went
+    /// offline. Dropping the device instead skips both.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB write fails.
+    pub async fn close(mut self) -> Result<(), std::io::Error> {
+        self.outbox.session.set(SessionState::Closing);
+        if self.config.auto_clear {
+            self.send_sysex(AutomapSysEx::LcdText(vec![
+                LcdOp::Clear(LcdClear::BothDisplays),
+                LcdOp::End,
+            ]))
+            .await?;
+            for cmd in self.leds().commands_to(&LedState::default()) {
+                self.send_command(&cmd).await?;
+            }
+        }
+        if self.config.goes_online() {
+            self.send_sysex(AutomapSysEx::OnlineOffline { online: false })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// The configuration this device was opened with.
+    pub fn config(&self) -> &DeviceConfig {
+        &self.config
+    }
 
-        let device = device_info.open().await?;
-        let interface = device.claim_interface(IFACE).await?;
+    /// Where the session with the unit stands. Each change is also queued
+    /// as a [`DeviceNotice::SessionChanged`].
+    pub fn session_state(&self) -> SessionState {
+        self.outbox.session.state()
+    }
 
-        let reader = interface.endpoint::<Bulk, In>(EP_IN)?.reader(64);
-        let writer = interface.endpoint::<Bulk, Out>(EP_OUT)?.writer(64);
+    /// Puts the unit into Automap mode, so it takes LED and LCD output and
+    /// reports its controls to the host.
+    ///
+    /// This is the online half of the OnlineOffline handshake, which the
+    /// unit obeys even in Advanced Template mode. Calling it again while
+    /// online is harmless, and pulls the unit back if the user switched it
+    /// to a template in the meantime.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::NotConnected` while the session is
+    /// [`Detached`](SessionState::Detached), or an error if the USB write
+    /// fails.
+    pub async fn enter_automap_mode(&mut self) -> Result<(), std::io::Error> {
+        if self.session_state() == SessionState::Detached {
+            return Err(std::io::ErrorKind::NotConnected.into());
+        }
+        self.send_sysex(AutomapSysEx::OnlineOffline { online: true })
+            .await
+    }
 
-        Ok(AutomapDevice { reader, writer })
+    /// Hands the unit back to its own templates.
+    ///
+    /// Going offline alone leaves the unit showing "Automap is OFFLINE",
+    /// so it is then forced into play mode to run the current template as
+    /// if no host had been there. Does nothing if the unit is not online.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::NotConnected` while the session is
+    /// [`Detached`](SessionState::Detached), or an error if a USB write
+    /// fails.
+    pub async fn release_to_standalone(&mut self) -> Result<(), std::io::Error> {
+        match self.session_state() {
+            SessionState::Detached => return Err(std::io::ErrorKind::NotConnected.into()),
+            SessionState::Claimed => return Ok(()),
+            _ => {}
+        }
+        self.send_sysex(AutomapSysEx::OnlineOffline { online: false })
+            .await?;
+        self.send_dbsim(&DbSimMsg::HighLevel(SimHighLevel::ForcePlayMode))
+            .await
     }
 
     /// Sends a SysEx message to the device.
@@ -58,14 +466,15 @@ impl AutomapDevice {
     ///
     /// * `msg` - The SysEx message to send
     ///
+    /// Sending `OnlineOffline` moves the [session](Self::session_state)
+    /// online or back to standalone mode.
+    ///
     /// # Errors
     ///
-    /// Returns an error if the USB write fails.
+    /// Returns an error if the USB write fails, or one carrying
+    /// [`AutomapError::NotOnline`] for LCD text while the unit is not online.
     pub async fn send_sysex(&mut self, msg: AutomapSysEx<'_>) -> Result<(), std::io::Error> {
-        self.writer
-            .write_all(&usbmidi_pack(&msg.to_bytes()))
-            .await?;
-        self.writer.flush().await
+        self.outbox.send_sysex(msg).await
     }
 
     /// Sends a command to the device.
@@ -79,12 +488,134 @@ impl AutomapDevice {
     ///
     /// # Errors
     ///
-    /// Returns an error if the USB write fails.
+    /// Returns an error if the USB write fails, or one carrying
+    /// [`AutomapError::NotOnline`] for LED and ring commands while the unit
+    /// is not online.
     pub async fn send_command(&mut self, cmd: &AutomapCommand) -> Result<(), std::io::Error> {
-        self.writer
-            .write_all(&usbmidi_pack(&cmd.to_bytes()))
-            .await?;
-        self.writer.flush().await
+        self.outbox.send_command(cmd).await
+    }
+
+    /// Shows `position` on an encoder ring in `mode`.
+    ///
+    /// The mode CC is only sent when the [LED shadow](Self::leds) says the
+    /// ring was in another mode, so a stream of value updates in one mode
+    /// costs one CC each instead of two.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the USB write fails, or `NotConnected` while the
+    /// unit is not online.
+    pub async fn set_ring(
+        &mut self,
+        encoder: Encoder,
+        mode: RingMode,
+        position: EncoderPosition,
+    ) -> Result<(), std::io::Error> {
+        self.outbox.set_ring(encoder, mode, position).await
+    }
+
+    /// Sends a Data-Block or Simulation message to the device.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the USB write fails.
+    pub async fn send_dbsim(&mut self, msg: &DbSimMsg<'_>) -> Result<(), std::io::Error> {
+        self.write_midi(&msg.to_bytes()).await
+    }
+
+    /// LED and ring states sent through this device and its handles so far.
+    pub fn leds(&self) -> LedState {
+        self.outbox.leds()
+    }
+
+    /// LCD text sent through this device and its handles so far, or `None`
+    /// if none has been.
+    pub fn lcd(&self) -> Option<LcdScreen> {
+        self.outbox.lcd()
+    }
+
+    /// Changes how many IN transfers are kept queued; see
+    /// [`DeviceConfig::read_transfers()`].
+    ///
+    /// Raise it around a long dump and lower it again afterwards. Lowering
+    /// it lets the transfers already queued complete first.
+    pub fn set_read_transfers(&mut self, count: usize) {
+        self.config.read_transfers = count.max(1);
+        if let Some(reader) = &mut self.reader {
+            reader.set_num_transfers(self.config.read_transfers);
+        }
+    }
+
+    /// Bytes discarded while resynchronizing on malformed USB-MIDI packets,
+    /// since the [stats](Self::stats) were last reset.
+    pub fn discarded_bytes(&self) -> u64 {
+        self.outbox.stats.lock().unwrap().discarded_bytes
+    }
+
+    /// Traffic counters since the device was opened or
+    /// [`reset_stats()`](Self::reset_stats) was last called.
+    pub fn stats(&self) -> DeviceStats {
+        self.outbox.stats.lock().unwrap().clone()
+    }
+
+    /// Starts the counters of [`stats()`](Self::stats) again from zero.
+    pub fn reset_stats(&mut self) {
+        *self.outbox.stats.lock().unwrap() = DeviceStats::new(Instant::now());
+    }
+
+    /// Takes the notices collected since the last call, oldest first.
+    ///
+    /// Notices are gathered while reading events; check them after
+    /// [`read_events()`](Self::read_events) returns.
+    pub fn take_notices(&mut self) -> Vec<DeviceNotice> {
+        self.sync_session();
+        self.notices.drain(..).collect()
+    }
+
+    /// Starts receiving every notice from now on, as it is noticed.
+    ///
+    /// The stream is fed by whoever reads the device, alongside
+    /// [`take_notices()`](Self::take_notices), and ends when the device is
+    /// dropped.
+    pub fn notices(&mut self) -> NoticeStream {
+        self.notifiers.stream()
+    }
+
+    /// A cloneable handle for sending commands from other tasks while this
+    /// device keeps reading events.
+    ///
+    /// Writes from the device and all of its handles are serialized, one
+    /// message at a time. A handle keeps the OUT endpoint open, so it can
+    /// still reach the unit after the device itself has been dropped.
+    pub fn handle(&self) -> AutomapHandle {
+        AutomapHandle::new(self.outbox.clone())
+    }
+
+    /// A sender for MIDI OUT `port`, 1 or 2, so the unit can be used as a
+    /// MIDI interface alongside the surface.
+    ///
+    /// ```no_run
+    /// # async fn play(device: &automap::AutomapDevice) -> std::io::Result<()> {
+    /// device.midi_out(1).send(&[0x90, 60, 100]).await
+    /// # }
+    /// ```
+    ///
+    /// The sockets are USB-MIDI cables of the unit's class-compliant MIDI
+    /// interface, while the vendor interface opened by default carries only
+    /// the Automap port, so open the device with
+    /// [`Backend::MidiStreaming`] to reach them. The cable numbers are set
+    /// by [`DeviceConfig::midi_out_cables()`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `port` is not 1 or 2.
+    pub fn midi_out(&self, port: u8) -> MidiOut {
+        self.handle().midi_out(port)
+    }
+
+    /// Plays simulated keys on the unit; see [`Simulator`].
+    pub fn simulator(&self) -> Simulator {
+        self.handle().simulator()
     }
 
     /// Reads events from the device.
@@ -97,27 +628,1914 @@ impl AutomapDevice {
     /// A vector of successfully decoded events. Invalid or unrecognized MIDI
     /// messages are silently skipped.
     ///
+    /// With a [`keep_alive`](DeviceConfig::keep_alive) interval configured,
+    /// this returns an empty vector after sending a keep-alive if nothing
+    /// arrives before it is due.
+    ///
     /// # Errors
     ///
     /// Returns an error if the USB read fails.
     pub async fn read_events(&mut self) -> Result<Vec<AutomapEvent>, std::io::Error> {
-        let mut buf = vec![0u8; USB_BUF];
-        let mut events = Vec::new();
+        let events = self.read_timed_events().await?;
+        Ok(events.into_iter().map(|timed| timed.event).collect())
+    }
 
-        match self.reader.read(&mut buf).await {
-            Ok(n) if n >= 4 => {
-                let n4 = n - (n % 4);
-                let raw = usbmidi_unpack(&buf[..n4]);
-                for msg in split_midi_messages(&raw) {
-                    if let Ok(event) = AutomapEvent::decode_event(&msg) {
-                        events.push(event);
-                    }
+    /// Like [`read_events()`](Self::read_events), but each event carries the
+    /// time it arrived, for gesture detection or latency analysis.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the USB read fails.
+    pub async fn read_timed_events(&mut self) -> Result<Vec<TimedEvent>, std::io::Error> {
+        let mut events = self.next_timed_events().await?;
+        if let Some(window) = self.config.coalesce_clicks {
+            events = self.read_burst(events, window).await?;
+        }
+        let dropped = self.subscribers.publish(&events);
+        self.sync_session();
+        if dropped > 0 {
+            self.push_notice(DeviceNotice::QueueOverflow { dropped });
+        }
+        Ok(events)
+    }
+
+    /// Calls `f` with every message read that the decoders do not fully
+    /// understand, whether or not it is also returned as an event.
+    ///
+    /// See [`UnknownKind`](crate::UnknownKind) for what is reported. The
+    /// hook runs inside the read, so it should be quick; collecting the
+    /// messages into a queue is the usual approach.
+    pub fn on_unknown(&mut self, f: impl FnMut(&UnknownMessage) + Send + 'static) {
+        self.unknown_hooks.push(Box::new(f));
+    }
+
+    /// Registers a decoder for the messages `key` picks out, run before the
+    /// built-in ones.
+    ///
+    /// When `f` returns a value, the message is consumed: it is not decoded
+    /// into an [`AutomapEvent`] or reported as unknown, and the value is
+    /// kept for [`take_custom_events()`](Self::take_custom_events). When it
+    /// returns `None`, the next decoder for the key is tried, then the
+    /// built-in decoders.
+    pub fn register_decoder<T: std::any::Any + Send>(
+        &mut self,
+        key: ExtensionKey,
+        f: impl FnMut(&[u8]) -> Option<T> + Send + 'static,
+    ) {
+        self.extensions.lock().unwrap().register(key, f);
+    }
+
+    /// Takes the values produced by [custom decoders](Self::register_decoder)
+    /// since the last call, oldest first.
+    ///
+    /// They are gathered while reading events and kept until taken.
+    pub fn take_custom_events(&mut self) -> Vec<CustomEvent> {
+        self.custom_events.drain(..).collect()
+    }
+
+    /// Takes the MIDI read from the unit's MIDI IN sockets since the last
+    /// call, oldest first; see [`midi_in`](crate::automap::midi_in).
+    ///
+    /// It is gathered while reading events, and a read that brings only
+    /// such MIDI returns no events. Only the last 1024 messages are kept.
+    pub fn take_midi_in(&mut self) -> Vec<MidiInMessage> {
+        self.midi_in.drain(..).collect()
+    }
+
+    /// Starts receiving a copy of every event matching `filter`.
+    ///
+    /// Subscriptions are fed by whoever reads the device: each batch returned
+    /// by [`read_events()`](Self::read_events) is also queued for every
+    /// matching subscription. When nothing else needs the events directly,
+    /// run [`pump()`](Self::pump) as the single reader. Subscriptions end
+    /// when the device is dropped.
+    pub fn subscribe(&mut self, filter: EventFilter) -> Subscription {
+        self.subscribers.subscribe(filter)
+    }
+
+    /// Starts receiving every event through a queue of at most `capacity`.
+    ///
+    /// Unlike [`subscribe()`](Self::subscribe), a receiver that falls behind
+    /// does not grow without bound: the oldest events are dropped and its
+    /// next `recv()` reports how many it missed.
+    pub fn broadcast(&mut self, capacity: usize) -> EventReceiver {
+        self.subscribers.broadcast(capacity)
+    }
+
+    /// Reads events until the USB read fails, handing them only to
+    /// [subscriptions](Self::subscribe).
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that ended the read loop.
+    pub async fn pump(&mut self) -> Result<(), std::io::Error> {
+        loop {
+            self.read_timed_events().await?;
+        }
+    }
+
+    /// Reads on until `window` after the first turn in `events`, then sums
+    /// the clicks of each control.
+    async fn read_burst(
+        &mut self,
+        mut events: Vec<TimedEvent>,
+        window: Duration,
+    ) -> Result<Vec<TimedEvent>, std::io::Error> {
+        if let Some(first) = events.iter().find(|e| e.event.clicks().is_some()) {
+            let until = first.at + window;
+            loop {
+                let left = until.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    break;
+                }
+                match rt::timeout(left, self.next_timed_events()).await {
+                    Some(more) => events.extend(more?),
+                    None => break,
                 }
             }
-            Ok(_) => {} // Short read, no complete packets
-            Err(e) => return Err(e),
         }
+        Ok(coalesce_clicks(events))
+    }
+
+    async fn next_timed_events(&mut self) -> Result<Vec<TimedEvent>, std::io::Error> {
+        if !self.pending.is_empty() {
+            // Replies awaited elsewhere may have queued a keep-alive echo
+            let pending: Vec<_> = self.pending.drain(..).collect();
+            return Ok(pending
+                .into_iter()
+                .filter(|timed| !self.take_keep_alive(&timed.event))
+                .collect());
+        }
+        let (at, batch) = match self.config.keep_alive {
+            Some(interval) => {
+                let last_tx = *self.outbox.last_tx.lock().unwrap();
+                let idle = interval.saturating_sub(last_tx.elapsed());
+                match rt::timeout(idle, self.read_batch()).await {
+                    Some(batch) => batch?,
+                    None => {
+                        if self.keep_alive_nonce.is_some() {
+                            // The last keep-alive was never answered
+                            self.outbox
+                                .session
+                                .step(SessionState::Online, SessionState::Degraded);
+                            self.notice(DeviceNotice::KeepAliveMissed);
+                        }
+                        let nonce = self.next_nonce();
+                        self.keep_alive_nonce = Some(nonce);
+                        self.send_command(&AutomapCommand::EchoRequest { value: nonce })
+                            .await?;
+                        return Ok(Vec::new());
+                    }
+                }
+            }
+            None => self.read_batch().await?,
+        };
 
+        let mut events = Vec::new();
+        for incoming in batch {
+            match incoming {
+                Incoming::Event(event) if self.take_keep_alive(&event) => {}
+                Incoming::Event(event) => events.push(TimedEvent { at, event }),
+                Incoming::SysEx(_) => {}
+            }
+        }
         Ok(events)
     }
+
+    /// Whether `event` answers the outstanding keep-alive, which it then
+    /// settles.
+    fn take_keep_alive(&mut self, event: &AutomapEvent) -> bool {
+        match *event {
+            AutomapEvent::EchoResponse { value } if self.keep_alive_nonce == Some(value) => {
+                self.keep_alive_nonce = None;
+                self.outbox
+                    .session
+                    .step(SessionState::Degraded, SessionState::Online);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Measures the round-trip latency to the device.
+    ///
+    /// Sends an `EchoRequest` carrying a fresh nonce and waits for the matching
+    /// `EchoResponse`. Events that arrive in the meantime are kept and returned
+    /// by the next call to `read_events()`. Every ping, successful or not, is
+    /// recorded in [`latency()`](Self::latency).
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::TimedOut` if no echo arrives within [`REPLY_TIMEOUT`],
+    /// or an error if the USB transfer fails.
+    pub async fn ping(&mut self) -> Result<Duration, std::io::Error> {
+        let nonce = self.next_nonce();
+
+        let start = Instant::now();
+        self.send_command(&AutomapCommand::EchoRequest { value: nonce })
+            .await?;
+
+        let reply = self
+            .await_reply(|incoming| match incoming {
+                Incoming::Event(AutomapEvent::EchoResponse { value }) if *value == nonce => {
+                    Some(start.elapsed())
+                }
+                _ => None,
+            })
+            .await?;
+
+        match reply {
+            Some(rtt) => {
+                self.latency.record(rtt);
+                self.outbox
+                    .session
+                    .step(SessionState::Degraded, SessionState::Online);
+                Ok(rtt)
+            }
+            None => {
+                self.latency.record_timeout();
+                self.outbox
+                    .session
+                    .step(SessionState::Online, SessionState::Degraded);
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "no echo response from device",
+                ))
+            }
+        }
+    }
+
+    /// Rolling round-trip statistics collected by [`ping()`](Self::ping).
+    pub fn latency(&self) -> &LatencyStats {
+        &self.latency
+    }
+
+    /// Which unit this is: its serial number, where it is on the bus, its
+    /// firmware and protocol versions, and its model once known.
+    ///
+    /// Reopening the unit in [`recover()`](Self::recover) refreshes the
+    /// USB details and forgets the rest until the unit reports it again.
+    pub fn info(&self) -> DeviceInfo {
+        DeviceInfo {
+            model: self.capabilities.map(|caps| caps.model),
+            ..self.info.clone()
+        }
+    }
+
+    /// Asks the unit what it is and returns its capabilities.
+    ///
+    /// The model comes from the Unit-Product-Type parameter request and the
+    /// firmware version from the USB device descriptor. If the unit does not
+    /// answer within [`REPLY_TIMEOUT`] it is assumed to be a ZeRO MkII, the
+    /// only product this crate opens. Once the unit has answered, here or
+    /// in the [handshake](DeviceConfig::handshake), the result is returned
+    /// without asking again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB transfer fails.
+    pub async fn capabilities(&mut self) -> Result<Capabilities, std::io::Error> {
+        if let Some(capabilities) = self.capabilities {
+            return Ok(capabilities);
+        }
+        self.send_command(&AutomapCommand::ParameterRequest {
+            request_type: ParameterRequestType::UnitProductType,
+        })
+        .await?;
+        let product = self
+            .await_reply(|incoming| match incoming {
+                Incoming::Event(AutomapEvent::Parameter {
+                    response: ParameterResponse::ProductType(product),
+                }) => Some(*product),
+                _ => None,
+            })
+            .await?;
+
+        let model = product.map_or(Model::ZeroMkII, |p| {
+            Model::identify(p, self.info.product_id)
+        });
+        let capabilities = Capabilities::for_model(model, self.info.firmware_version);
+        if product.is_some() {
+            self.capabilities = Some(capabilities);
+        }
+        Ok(capabilities)
+    }
+
+    /// Switches off every LED and ring on the surface.
+    ///
+    /// Sends `AllLedsOff` where the unit implements it, and otherwise the
+    /// [single-LED commands](crate::automap::leds::all_off_commands) to the
+    /// same effect, in one transfer. The model comes from
+    /// [`capabilities()`](Self::capabilities).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB transfer fails, or one carrying
+    /// [`AutomapError::NotOnline`] while the unit is not online.
+    pub async fn clear_all_leds(&mut self) -> Result<(), std::io::Error> {
+        if self.capabilities().await?.supports_all_leds_off {
+            self.send_command(&AutomapCommand::AllLedsOff).await
+        } else {
+            self.outbox.send_commands(&all_off_commands()).await
+        }
+    }
+
+    /// Reads `len` bytes of the current template or globals from the unit.
+    ///
+    /// `cn` is the 1-based control number for [`DbTarget::Control`] and
+    /// ignored otherwise. Events arriving while waiting are kept for the
+    /// next `read_events()`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TimedOut`](std::io::ErrorKind::TimedOut) if the unit does not
+    /// answer within [`REPLY_TIMEOUT`], or an error if a USB transfer fails.
+    pub async fn read_data_block(
+        &mut self,
+        target: DbTarget,
+        cn: u8,
+        offset: u16,
+        len: u16,
+    ) -> Result<Vec<u8>, std::io::Error> {
+        let cn = (target == DbTarget::Control).then_some(cn);
+        self.send_dbsim(&DbSimMsg::DbRead {
+            target,
+            cn,
+            offset,
+            len,
+        })
+        .await?;
+
+        let reply = self
+            .await_reply(|incoming| {
+                let Incoming::SysEx(frame) = incoming else {
+                    return None;
+                };
+                match decode_frame(frame) {
+                    Ok((
+                        _,
+                        _,
+                        _,
+                        DecodedMsg::DbSim(DbSimMsg::DbData {
+                            target: t,
+                            cn: c,
+                            offset: o,
+                            data,
+                        }),
+                    )) if t == target && c == cn && o == offset => Some(data.to_vec()),
+                    _ => None,
+                }
+            })
+            .await?;
+        reply.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "no data-block response from device",
+            )
+        })
+    }
+
+    /// Reads `len` bytes starting at `offset` as a series of
+    /// [`read_data_block()`](Self::read_data_block) requests, reporting
+    /// progress to `transfer` after each one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Interrupted`](std::io::ErrorKind::Interrupted) if the
+    /// transfer's cancel token fires between chunks, or any error from
+    /// `read_data_block()`.
+    pub async fn read_block(
+        &mut self,
+        target: DbTarget,
+        cn: u8,
+        offset: u16,
+        len: u16,
+        transfer: &mut Transfer<'_>,
+    ) -> Result<Vec<u8>, std::io::Error> {
+        self.read_chunks(target, cn, offset, len, transfer, Phase::Read)
+            .await
+    }
+
+    /// Writes `data` starting at `offset` as a series of DbWrite messages,
+    /// reporting progress to `transfer` after each one.
+    ///
+    /// A cancelled transfer stops between messages, leaving the bytes
+    /// before that point written. Nothing is written while memory protect
+    /// is on, checked as for
+    /// [`save_globals_to_flash()`](Self::save_globals_to_flash).
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::PermissionDenied` carrying
+    /// [`AutomapError::MemoryProtected`] if memory protect is on,
+    /// [`Interrupted`](std::io::ErrorKind::Interrupted) if the transfer's
+    /// cancel token fires between chunks, or an error if a USB write fails.
+    pub async fn write_block(
+        &mut self,
+        target: DbTarget,
+        cn: u8,
+        offset: u16,
+        data: &[u8],
+        transfer: &mut Transfer<'_>,
+    ) -> Result<(), std::io::Error> {
+        self.check_memory_protect().await?;
+        self.write_chunks(target, cn, offset, data, transfer, Phase::Write)
+            .await
+    }
+
+    /// Writes `data` like [`write_block()`](Self::write_block), then reads
+    /// it back and compares.
+    ///
+    /// The current contents are read first. If the write fails, is
+    /// cancelled, or does not read back as written, they are written back
+    /// and verified in turn, so the block ends up either fully updated or
+    /// as it was. The rollback runs even when the transfer has been
+    /// cancelled. Memory protect is checked first, as by `write_block()`.
+    ///
+    /// # Errors
+    ///
+    /// See [`VerifyError`]; memory protect being on is reported as
+    /// [`VerifyError::Io`].
+    pub async fn write_block_verified(
+        &mut self,
+        target: DbTarget,
+        cn: u8,
+        offset: u16,
+        data: &[u8],
+        transfer: &mut Transfer<'_>,
+    ) -> Result<(), VerifyError> {
+        let len = block_len(data)?;
+        self.check_memory_protect().await?;
+        let backup = self
+            .read_chunks(target, cn, offset, len, transfer, Phase::Read)
+            .await?;
+
+        let written = match self
+            .write_chunks(target, cn, offset, data, transfer, Phase::Write)
+            .await
+        {
+            Ok(()) => self
+                .read_chunks(target, cn, offset, len, transfer, Phase::Verify)
+                .await
+                .map(|readback| mismatches(offset, data, &readback)),
+            Err(e) => Err(e),
+        };
+        let failure = match written {
+            Ok(offsets) if offsets.is_empty() => return Ok(()),
+            Ok(offsets) => VerifyError::Mismatch { offsets },
+            Err(e) => VerifyError::Io(e),
+        };
+
+        let restored = match self
+            .write_chunks(target, cn, offset, &backup, transfer, Phase::Rollback)
+            .await
+        {
+            Ok(()) => self
+                .read_chunks(target, cn, offset, len, transfer, Phase::Rollback)
+                .await
+                .and_then(|readback| {
+                    if mismatches(offset, &backup, &readback).is_empty() {
+                        Ok(())
+                    } else {
+                        Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "restored contents do not read back",
+                        ))
+                    }
+                }),
+            Err(e) => Err(e),
+        };
+        match restored {
+            Ok(()) => Err(failure),
+            Err(error) => Err(VerifyError::RollbackFailed {
+                offsets: match failure {
+                    VerifyError::Mismatch { offsets } => offsets,
+                    _ => Vec::new(),
+                },
+                error,
+            }),
+        }
+    }
+
+    /// Reads one member of the globals.
+    ///
+    /// # Errors
+    ///
+    /// As [`read_data_block()`](Self::read_data_block).
+    pub async fn read_global(&mut self, field: GlobalField) -> Result<Vec<u8>, std::io::Error> {
+        self.read_data_block(DbTarget::Globals, 0, field.offset, field.len)
+            .await
+    }
+
+    /// Changes one member of the globals, in the unit's RAM. The unit
+    /// takes the change in standalone mode too.
+    ///
+    /// Refused while memory protect is on, checked as for
+    /// [`save_globals_to_flash()`](Self::save_globals_to_flash), except
+    /// for the [flag](DeviceConfig::memory_protect_flag) itself, so that
+    /// protect can be turned off.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`InvalidInput`](std::io::ErrorKind::InvalidInput) if `data`
+    /// is not `field.len` bytes or has a byte above 0x7F,
+    /// `ErrorKind::PermissionDenied` carrying
+    /// [`AutomapError::MemoryProtected`] if memory protect is on, or an
+    /// error if the USB write fails.
+    pub async fn write_global(
+        &mut self,
+        field: GlobalField,
+        data: &[u8],
+    ) -> Result<(), std::io::Error> {
+        if data.len() != usize::from(field.len) || data.iter().any(|&b| b > 0x7F) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "global data must be the field's length and 7-bit",
+            ));
+        }
+        if self.config.memory_protect != Some(field) {
+            self.check_memory_protect().await?;
+        }
+        self.send_dbsim(&DbSimMsg::DbWrite {
+            target: DbTarget::Globals,
+            cn: None,
+            offset: field.offset,
+            data,
+        })
+        .await
+    }
+
+    /// Saves the globals in RAM to flash, so changes made with
+    /// [`write_global()`](Self::write_global) survive power-off.
+    ///
+    /// The unit does not acknowledge the save. It handles its MIDI input
+    /// in order, so an echo sent after it comes back once the save has been
+    /// carried out, and that is what this waits for.
+    ///
+    /// A unit with memory protect turned on drops the save without saying
+    /// so. If the config names the
+    /// [flag](DeviceConfig::memory_protect_flag), it is read first and the
+    /// save refused while it is set, unless
+    /// [overridden](Self::override_memory_protect). If it does not, the
+    /// save goes ahead unchecked and
+    /// [`DeviceNotice::MemoryProtectUnknown`] is sent, as the save may be
+    /// dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::PermissionDenied` carrying
+    /// [`AutomapError::MemoryProtected`] if memory protect is on,
+    /// `ErrorKind::TimedOut` if the echo does not come back within
+    /// [`REPLY_TIMEOUT`], or an error if a USB transfer fails.
+    pub async fn save_globals_to_flash(&mut self) -> Result<(), std::io::Error> {
+        self.save_to_flash(SimHighLevel::SaveGlobalsToFlash).await
+    }
+
+    /// Saves the current template in RAM to flash, over the stored copy
+    /// in its slot.
+    ///
+    /// Confirmed as [`save_globals_to_flash()`](Self::save_globals_to_flash)
+    /// is.
+    ///
+    /// # Errors
+    ///
+    /// As [`save_globals_to_flash()`](Self::save_globals_to_flash).
+    pub async fn save_current_template_to_flash(&mut self) -> Result<(), std::io::Error> {
+        self.save_to_flash(SimHighLevel::SaveCurrentTemplateToFlash)
+            .await
+    }
+
+    /// Lets flash saves and writes go ahead while memory protect is on, as
+    /// if the flag were clear, without reading it. Off by default.
+    pub fn override_memory_protect(&mut self, enabled: bool) {
+        self.override_memory_protect = enabled;
+    }
+
+    /// Reads the memory-protect flag, if the config says where it is.
+    ///
+    /// Returns `None` if the location is not configured.
+    ///
+    /// # Errors
+    ///
+    /// As [`read_data_block()`](Self::read_data_block).
+    pub async fn memory_protect(&mut self) -> Result<Option<bool>, std::io::Error> {
+        let Some(field) = self.config.memory_protect else {
+            return Ok(None);
+        };
+        let flag = self.read_global(field).await?;
+        Ok(Some(flag.iter().any(|&b| b != 0)))
+    }
+
+    /// Reads the new value of the setting an
+    /// [`Alert`](AutomapEvent::Alert) reports a change of, from where
+    /// [`DeviceConfig::alert_field()`] says it is.
+    ///
+    /// Returns `None` if the location is not configured.
+    ///
+    /// # Errors
+    ///
+    /// As [`read_data_block()`](Self::read_data_block).
+    pub async fn alert_value(
+        &mut self,
+        alert: AlertType,
+    ) -> Result<Option<Vec<u8>>, std::io::Error> {
+        let field = self.config.alert_fields.iter().find(|(a, _)| *a == alert);
+        let Some(&(_, field)) = field else {
+            return Ok(None);
+        };
+        self.read_global(field).await.map(Some)
+    }
+
+    /// Refuses a write while memory protect is on, unless overridden, and
+    /// warns when the flag cannot be read.
+    async fn check_memory_protect(&mut self) -> Result<(), std::io::Error> {
+        if self.override_memory_protect {
+            return Ok(());
+        }
+        match self.memory_protect().await? {
+            Some(true) => Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                AutomapError::MemoryProtected,
+            )),
+            Some(false) => Ok(()),
+            None => {
+                self.notice(DeviceNotice::MemoryProtectUnknown);
+                Ok(())
+            }
+        }
+    }
+
+    async fn save_to_flash(&mut self, op: SimHighLevel) -> Result<(), std::io::Error> {
+        self.check_memory_protect().await?;
+        self.send_dbsim(&DbSimMsg::HighLevel(op)).await?;
+        // Not a ping: the time taken by the save would skew `latency()`
+        let nonce = self.next_nonce();
+        self.send_command(&AutomapCommand::EchoRequest { value: nonce })
+            .await?;
+        let reply = self
+            .await_reply(|incoming| match incoming {
+                Incoming::Event(AutomapEvent::EchoResponse { value }) if *value == nonce => {
+                    Some(())
+                }
+                _ => None,
+            })
+            .await?;
+        reply.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "device did not confirm the save",
+            )
+        })
+    }
+
+    /// Asks the unit for the template it is running, and parses it.
+    ///
+    /// The unit answers `SendCurrentTemplateToHost` with the template in
+    /// one or more Upload Template frames. Their data is put together until
+    /// a whole template ([`TEMPLATE_LEN`] bytes) has arrived, or until no
+    /// frame has come for [`REPLY_TIMEOUT`]. Events arriving in the
+    /// meantime are queued for `read_events()`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrorKind::TimedOut` if no frame arrives,
+    /// `ErrorKind::InvalidData` wrapping a [`TemplateError`] if what arrived
+    /// is not a template, or an error if a USB transfer fails.
+    ///
+    /// [`TemplateError`]: crate::template::TemplateError
+    pub async fn fetch_current_template(&mut self) -> Result<Template, std::io::Error> {
+        self.send_dbsim(&DbSimMsg::HighLevel(
+            SimHighLevel::SendCurrentTemplateToHost,
+        ))
+        .await?;
+        let mut data = Vec::new();
+        while data.len() < usize::from(TEMPLATE_LEN) {
+            let Some(frames) = self.await_replies(upload_template_data).await? else {
+                break;
+            };
+            data.extend(frames.concat());
+        }
+        if data.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "no template from device",
+            ));
+        }
+        Template::from_bytes(data)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// `read_block()` reporting as `phase`. A rollback ignores the cancel
+    /// token.
+    async fn read_chunks(
+        &mut self,
+        target: DbTarget,
+        cn: u8,
+        offset: u16,
+        len: u16,
+        transfer: &mut Transfer<'_>,
+        phase: Phase,
+    ) -> Result<Vec<u8>, std::io::Error> {
+        let total = usize::from(len);
+        let mut data = Vec::with_capacity(total);
+        transfer.report(phase, 0, total);
+        for (chunk_offset, chunk_len) in transfer.chunks(offset, len) {
+            if phase != Phase::Rollback {
+                transfer.check_cancelled()?;
+            }
+            let chunk = self
+                .read_data_block(target, cn, chunk_offset, chunk_len)
+                .await?;
+            data.extend_from_slice(&chunk);
+            transfer.report(phase, data.len().min(total), total);
+        }
+        Ok(data)
+    }
+
+    /// `write_block()` reporting as `phase`. A rollback ignores the cancel
+    /// token.
+    async fn write_chunks(
+        &mut self,
+        target: DbTarget,
+        cn: u8,
+        offset: u16,
+        data: &[u8],
+        transfer: &mut Transfer<'_>,
+        phase: Phase,
+    ) -> Result<(), std::io::Error> {
+        let len = block_len(data)?;
+        let cn = (target == DbTarget::Control).then_some(cn);
+        transfer.report(phase, 0, data.len());
+        for (chunk_offset, chunk_len) in transfer.chunks(offset, len) {
+            if phase != Phase::Rollback {
+                transfer.check_cancelled()?;
+            }
+            let start = usize::from(chunk_offset - offset);
+            let end = start + usize::from(chunk_len);
+            self.send_dbsim(&DbSimMsg::DbWrite {
+                target,
+                cn,
+                offset: chunk_offset,
+                data: &data[start..end],
+            })
+            .await?;
+            transfer.report(phase, end, data.len());
+        }
+        Ok(())
+    }
+
+    /// Reads the unit's LED bitmap, or `None` if it does not answer within
+    /// [`REPLY_TIMEOUT`]. Which bit is which LED is undocumented; see
+    /// [`LedBitmap`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB transfer fails.
+    pub async fn read_led_bitmap(&mut self) -> Result<Option<LedBitmap>, std::io::Error> {
+        self.send_dbsim(&DbSimMsg::Simulate(SimCmd::LedBitmapRequest))
+            .await?;
+        self.await_reply(|incoming| match sim_reply(incoming)? {
+            SimCmd::LedBitmapResponse { data } => LedBitmap::from_payload(&data),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Captures the current surface state so it can be put back later.
+    ///
+    /// After the [handshake](DeviceConfig::handshake) the surface is
+    /// already blank; the state it replaced is in
+    /// [`takeover_snapshot()`](Self::takeover_snapshot).
+    ///
+    /// Reads the LCD text and transport lock state from the unit, and
+    /// copies the LED shadow kept by this device. LEDs and rings are not
+    /// read from the unit, so those it lit without this device are not
+    /// captured, nor restored. Readbacks the unit does not answer within
+    /// [`REPLY_TIMEOUT`] are left as `None`; events arriving in the meantime
+    /// are returned by the next call to `read_events()`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB transfer fails.
+    pub async fn snapshot_state(&mut self) -> Result<SurfaceSnapshot, std::io::Error> {
+        self.send_dbsim(&DbSimMsg::Simulate(SimCmd::LcdTextRequest))
+            .await?;
+        let lcd = self
+            .await_reply(|incoming| match sim_reply(incoming)? {
+                SimCmd::LcdTextResponse { text } => Some(LcdScreen::from_bytes(&text)),
+                _ => None,
+            })
+            .await?;
+
+        self.send_command(&AutomapCommand::ParameterRequest {
+            request_type: ParameterRequestType::TransportLockState,
+        })
+        .await?;
+        let transport_lock = self
+            .await_reply(|incoming| match incoming {
+                Incoming::Event(
+                    AutomapEvent::TransportLockStatus { enabled }
+                    | AutomapEvent::Parameter {
+                        response: ParameterResponse::TransportLock(enabled),
+                    },
+                ) => Some(*enabled),
+                _ => None,
+            })
+            .await?;
+
+        Ok(SurfaceSnapshot {
+            lcd,
+            transport_lock,
+            leds: self.leds(),
+        })
+    }
+
+    /// Waits for the unit to send a snapshot of its control positions and
+    /// gathers it.
+    ///
+    /// The programmer's reference documents no host request for a
+    /// snapshot, so the burst only starts when the user triggers the
+    /// snapshot function on the unit. This waits up to `wait` for its first
+    /// value, then collects until no value
+    /// has arrived for `quiet`. Other events are kept for the next
+    /// `read_events()`. The result is empty if nothing arrived in time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB transfer fails.
+    pub async fn collect_snapshot(
+        &mut self,
+        wait: Duration,
+        quiet: Duration,
+    ) -> Result<Snapshot, std::io::Error> {
+        let mut collector = SnapshotCollector::new();
+        loop {
+            let limit = if collector.is_empty() { wait } else { quiet };
+            let Some(batch) = rt::timeout(limit, self.read_batch()).await else {
+                return Ok(collector.finish());
+            };
+            let (at, batch) = batch?;
+            for incoming in batch {
+                if let Incoming::Event(event) = incoming
+                    && !collector.push(&event)
+                {
+                    self.pending.push_back(TimedEvent { at, event });
+                }
+            }
+        }
+    }
+
+    /// The surface as the [handshake](DeviceConfig::handshake) found it,
+    /// taken before it blanked the LCDs and switched the LEDs off, for
+    /// [`restore_state()`](Self::restore_state) when handing the unit back.
+    /// `None` if the device was opened without the handshake.
+    ///
+    /// It holds the LCD text and transport lock only; nothing had been
+    /// sent through this device yet, so its LED shadow is empty.
+    pub fn takeover_snapshot(&self) -> Option<&SurfaceSnapshot> {
+        self.takeover.as_ref()
+    }
+
+    /// Puts back the LCD text and transport lock
+    /// [`snapshot_state()`](Self::snapshot_state) read from the unit, and
+    /// the LEDs and rings as this device had set them.
+    ///
+    /// Only the LCD and transport lock are restored as the unit showed
+    /// them. For LEDs and rings it sends whatever commands undo changes
+    /// made through this device since the snapshot; LEDs the snapshot knew
+    /// nothing about, including any the unit had lit by itself, are
+    /// switched off. With the [takeover snapshot](Self::takeover_snapshot)
+    /// that means every LED and ring ends up off.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB write fails.
+    pub async fn restore_state(
+        &mut self,
+        snapshot: &SurfaceSnapshot,
+    ) -> Result<(), std::io::Error> {
+        if let Some(lcd) = &snapshot.lcd {
+            self.send_sysex(AutomapSysEx::LcdText(lcd.to_ops())).await?;
+        }
+        if let Some(enabled) = snapshot.transport_lock {
+            self.send_command(&AutomapCommand::TransportLockSet { enabled })
+                .await?;
+        }
+        for cmd in self.leds().commands_to(&snapshot.leds) {
+            self.send_command(&cmd).await?;
+        }
+        Ok(())
+    }
+
+    /// Shows a mapping layer: redraws its LCD and sends the LED commands
+    /// that differ from what the surface currently shows.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB write fails.
+    pub async fn show_layer(&mut self, layer: &Layer) -> Result<(), std::io::Error> {
+        self.send_sysex(AutomapSysEx::LcdText(layer.lcd.to_ops()))
+            .await?;
+        for cmd in self.leds().commands_to(&layer.leds) {
+            self.send_command(&cmd).await?;
+        }
+        Ok(())
+    }
+
+    /// Packs raw MIDI into USB-MIDI packets and writes them out.
+    pub(crate) async fn write_midi(&mut self, midi: &[u8]) -> Result<(), std::io::Error> {
+        self.outbox.write_midi(midi).await
+    }
+
+    /// Queues a notice for `take_notices()`, keeping only the most recent
+    /// [`MAX_NOTICES`] if nobody collects them.
+    fn notice(&mut self, notice: DeviceNotice) {
+        self.sync_session();
+        self.push_notice(notice);
+    }
+
+    /// Queues the messages `bytes` from `cable` complete for
+    /// `take_midi_in()`, keeping only the most recent [`MAX_MIDI_IN`].
+    fn push_midi_in(&mut self, at: Instant, cable: u8, bytes: &[u8]) {
+        let max_sysex = self.config.max_sysex;
+        let i = match self.cable_rx.iter().position(|(c, _)| *c == cable) {
+            Some(i) => i,
+            None => {
+                self.cable_rx.push((cable, MidiStream::new(max_sysex)));
+                self.cable_rx.len() - 1
+            }
+        };
+        let queue = &mut self.midi_in;
+        self.cable_rx[i].1.push_with(bytes, |msg| {
+            // Oversized SysEx frames are dropped
+            let Ok(msg) = msg else { return };
+            if queue.len() == MAX_MIDI_IN {
+                queue.pop_front();
+            }
+            queue.push_back(MidiInMessage {
+                at,
+                cable,
+                bytes: msg.to_vec(),
+            });
+        });
+    }
+
+    fn push_notice(&mut self, notice: DeviceNotice) {
+        if self.notices.len() == MAX_NOTICES {
+            self.notices.pop_front();
+        }
+        self.notices.push_back(notice);
+        self.notifiers.publish(notice);
+    }
+
+    /// Queues the session changes made so far, including by handles, each
+    /// followed by `Disconnected` or `Connected` when it is one.
+    fn sync_session(&mut self) {
+        for change in self.outbox.session.take_changes() {
+            self.push_notice(change);
+            match change {
+                DeviceNotice::SessionChanged {
+                    to: SessionState::Detached,
+                    ..
+                } => self.push_notice(DeviceNotice::Disconnected),
+                DeviceNotice::SessionChanged {
+                    from: SessionState::Detached,
+                    ..
+                } => self.push_notice(DeviceNotice::Connected),
+                _ => {}
+            }
+        }
+    }
+
+    /// Follows the unit into or out of Automap mode when the user changes
+    /// templates on it.
+    fn follow_template(&mut self, special: bool) {
+        let session = &self.outbox.session;
+        if special {
+            session.step(SessionState::Claimed, SessionState::Online);
+        } else {
+            session.step(SessionState::Online, SessionState::Claimed);
+            session.step(SessionState::Degraded, SessionState::Claimed);
+        }
+    }
+
+    fn next_nonce(&mut self) -> u8 {
+        let nonce = self.echo_nonce;
+        self.echo_nonce = (self.echo_nonce + 1) & 0x7F;
+        nonce
+    }
+
+    /// Reads until `matches` picks out a reply, or [`REPLY_TIMEOUT`] elapses.
+    ///
+    /// Every event that is not the reply is queued for `read_events()`;
+    /// unrelated SysEx frames are dropped, as `read_events()` would.
+    async fn await_reply<T>(
+        &mut self,
+        mut matches: impl FnMut(&Incoming) -> Option<T>,
+    ) -> Result<Option<T>, std::io::Error> {
+        let wait = async {
+            loop {
+                let mut reply = None;
+                let (at, batch) = self.read_batch().await?;
+                for incoming in batch {
+                    if reply.is_none() {
+                        reply = matches(&incoming);
+                        if reply.is_some() {
+                            continue;
+                        }
+                    }
+                    if let Incoming::Event(event) = incoming {
+                        self.pending.push_back(TimedEvent { at, event });
+                    }
+                }
+                if let Some(reply) = reply {
+                    return Ok(reply);
+                }
+            }
+        };
+        rt::timeout(REPLY_TIMEOUT, wait).await.transpose()
+    }
+
+    /// Like `await_reply()`, but takes every reply in the first transfer
+    /// that has one, for answers that span several frames.
+    async fn await_replies<T>(
+        &mut self,
+        mut matches: impl FnMut(&Incoming) -> Option<T>,
+    ) -> Result<Option<Vec<T>>, std::io::Error> {
+        let wait = async {
+            loop {
+                let mut replies = Vec::new();
+                let (at, batch) = self.read_batch().await?;
+                for incoming in batch {
+                    if let Some(reply) = matches(&incoming) {
+                        replies.push(reply);
+                    } else if let Incoming::Event(event) = incoming {
+                        self.pending.push_back(TimedEvent { at, event });
+                    }
+                }
+                if !replies.is_empty() {
+                    return Ok(replies);
+                }
+            }
+        };
+        rt::timeout(REPLY_TIMEOUT, wait).await.transpose()
+    }
+
+    /// Reads a single USB transfer and decodes the messages it completes,
+    /// along with the time the transfer completed.
+    async fn read_batch(&mut self) -> Result<(Instant, Vec<Incoming>), std::io::Error> {
+        let mut out = Vec::new();
+        let mut unknown = Vec::new();
+        let capture = !self.unknown_hooks.is_empty();
+        let mut custom = Vec::new();
+        let extensions = self.extensions.clone();
+        let cc_status = self.config.cc_status();
+        let capabilities = self.capabilities;
+        let outbox = Arc::clone(&self.outbox);
+        let mut decode_errors = 0;
+        let at = self
+            .read_messages(|msg| {
+                if let Some(decoded) = extensions.lock().unwrap().decode(msg, cc_status) {
+                    custom.push(decoded);
+                    return;
+                }
+                if capture && let Some(kind) = classify(msg, cc_status) {
+                    unknown.push((kind, msg.to_vec()));
+                }
+                if msg.first() == Some(&0xF0) {
+                    out.push(Incoming::SysEx(msg.to_vec()));
+                } else if msg[0] != cc_status && msg[0] & 0xF0 != 0xE0 {
+                    // Not on the Automap channel, nor the pitch wheel on the
+                    // keyboard's port
+                } else if let Ok(event) = AutomapEvent::decode_event(msg) {
+                    let event = capabilities.map_or(event, |caps| caps.disambiguate(event));
+                    let event = outbox.resolve_parameter(event);
+                    out.push(Incoming::Event(event));
+                } else {
+                    decode_errors += 1;
+                }
+            })
+            .await?;
+        self.outbox.stats.lock().unwrap().decode_errors += decode_errors;
+        self.custom_events
+            .extend(
+                custom
+                    .into_iter()
+                    .map(|(key, value)| CustomEvent { at, key, value }),
+            );
+        for (kind, bytes) in unknown {
+            let msg = UnknownMessage { at, kind, bytes };
+            for hook in &mut self.unknown_hooks {
+                hook(&msg);
+            }
+        }
+        for incoming in &out {
+            if let Incoming::SysEx(frame) = incoming
+                && self.info.protocol_version.is_none()
+                && frame.len() > SYSEX_HEADER_LEN
+                && frame.starts_with(&NOVATION_ID)
+            {
+                self.info.protocol_version = Some((frame[6], frame[7]));
+            }
+            if let Some(special) = template_change(incoming) {
+                self.follow_template(special);
+            }
+        }
+        Ok((at, out))
+    }
+
+    /// Reads a single USB transfer and calls `f` with every MIDI message it
+    /// completes, whatever its channel. Returns when the transfer completed.
+    ///
+    /// Reads that complete empty are retried, and a stalled endpoint is
+    /// cleared and read again, waiting a little longer each time. After
+    /// [`READ_RETRIES`] of them in a row the read fails with
+    /// [`AutomapError::Stalled`].
+    pub(crate) async fn read_messages(
+        &mut self,
+        mut f: impl FnMut(&[u8]),
+    ) -> Result<Instant, std::io::Error> {
+        let mut backoff = Backoff::new(READ_BACKOFF, READ_RETRIES);
+        loop {
+            let reader = self
+                .reader
+                .as_mut()
+                .ok_or(std::io::ErrorKind::NotConnected)?;
+            let read = reader.read(&mut self.read_buf).await;
+            let at = Instant::now();
+            match read {
+                Ok(n) if n > 0 => {
+                    let mut raw = Vec::with_capacity(n);
+                    let mut cabled = Vec::new();
+                    let discarded =
+                        usbmidi_unpack_cables(&self.read_buf[..n], &mut raw, |cable, bytes| {
+                            cabled.push((cable, bytes.to_vec()));
+                        });
+                    for (cable, bytes) in cabled {
+                        self.push_midi_in(at, cable, &bytes);
+                    }
+                    if discarded > 0 {
+                        self.notice(DeviceNotice::StreamCorruption { discarded });
+                    }
+                    let mut stats = self.outbox.stats.lock().unwrap();
+                    stats.bytes_received += n as u64;
+                    stats.discarded_bytes += discarded as u64;
+                    self.rx.push_with(&raw, |msg| match msg {
+                        Ok(msg) => {
+                            stats.received.count(msg);
+                            f(msg);
+                        }
+                        // An oversized SysEx has already been dropped by the stream
+                        Err(_) => stats.decode_errors += 1,
+                    });
+                    return Ok(at);
+                }
+                // Zero-length read: reading again straight away would spin
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset => {
+                    // Stalled: whatever was in flight is lost, but reads can resume
+                    self.outbox
+                        .session
+                        .step(SessionState::Online, SessionState::Degraded);
+                    let reader = self.reader.take().unwrap();
+                    let cleared = reader
+                        .clear_halt(&self.config)
+                        .await
+                        .inspect_err(|_| self.outbox.session.set(SessionState::Detached))?;
+                    self.reader = Some(cleared);
+                }
+                Err(e) => {
+                    if e.kind() == std::io::ErrorKind::ConnectionAborted {
+                        self.outbox.session.set(SessionState::Detached);
+                    }
+                    return Err(e);
+                }
+            }
+            let Some(delay) = backoff.next() else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::BrokenPipe,
+                    AutomapError::Stalled {
+                        attempts: READ_RETRIES,
+                    },
+                ));
+            };
+            self.outbox.stats.lock().unwrap().read_retries += 1;
+            rt::sleep(delay).await;
+        }
+    }
+}
+
+/// The sending side of a device, shared by the device and its handles.
+pub(crate) struct Outbox {
+    /// `None` while the device is recovering, or after clearing a halt failed.
+    writer: rt::Mutex<Option<Writer>>,
+    cc_status: u8,
+    /// Shadow of the LED commands sent so far.
+    leds: Mutex<LedState>,
+    /// Shadow of the LCD text sent so far, `None` until some is.
+    lcd: Mutex<Option<LcdScreen>>,
+    /// When the last message went out, for scheduling keep-alives.
+    last_tx: Mutex<Instant>,
+    pacing: Pacing,
+    /// When the next SysEx frame may go out. Held from the wait until the
+    /// frame is written, so frames from several handles stay spaced.
+    next_frame: rt::Mutex<Instant>,
+    /// Cables of MIDI OUT 1 and 2.
+    pub(crate) midi_out_cables: [u8; 2],
+    /// Parameter requests waiting for an answer, from handles too.
+    parameter_requests: Mutex<ParameterRequests>,
+    pub(crate) session: Session,
+    /// Shared with handles, so their writes are counted too.
+    stats: Mutex<DeviceStats>,
+}
+
+impl Outbox {
+    /// Packs raw MIDI into USB-MIDI packets and writes them out, leaving
+    /// SysEx frames the configured [`Pacing`].
+    pub(crate) async fn write_midi(&self, midi: &[u8]) -> Result<(), std::io::Error> {
+        self.write_cable(0, midi).await
+    }
+
+    /// [`write_midi()`](Self::write_midi) on USB-MIDI cable `cable`, which
+    /// the unit routes to one of its ports. SysEx frames share the pacing
+    /// of the Automap port's.
+    pub(crate) async fn write_cable(&self, cable: u8, midi: &[u8]) -> Result<(), std::io::Error> {
+        if midi.first() != Some(&0xF0) || self.pacing == Pacing::OFF {
+            return self.write_now(cable, midi).await;
+        }
+        let mut next = self.next_frame.lock().await;
+        let wait = next.saturating_duration_since(Instant::now());
+        if !wait.is_zero() {
+            rt::sleep(wait).await;
+        }
+        self.write_now(cable, midi).await?;
+        *next = Instant::now() + self.pacing.after(midi.len());
+        Ok(())
+    }
+
+    /// [`write_midi()`](Self::write_midi) without pacing.
+    ///
+    /// A stalled endpoint is cleared and the write tried once more.
+    async fn write_now(&self, cable: u8, midi: &[u8]) -> Result<(), std::io::Error> {
+        let started = Instant::now();
+        let packets = usbmidi_pack_cable(midi, cable);
+        let mut slot = self.writer.lock().await;
+        let writer = slot.as_mut().ok_or(std::io::ErrorKind::NotConnected)?;
+        let mut written = writer.write_packets(&packets).await;
+        if matches!(&written, Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset) {
+            self.session
+                .step(SessionState::Online, SessionState::Degraded);
+            let cleared = slot
+                .take()
+                .unwrap()
+                .clear_halt()
+                .await
+                .inspect_err(|_| self.session.set(SessionState::Detached))?;
+            let writer = slot.insert(cleared);
+            self.stats.lock().unwrap().write_retries += 1;
+            written = writer.write_packets(&packets).await;
+        }
+        if let Err(e) = &written
+            && e.kind() == std::io::ErrorKind::ConnectionAborted
+        {
+            self.session.set(SessionState::Detached);
+        }
+        written?;
+        let now = Instant::now();
+        *self.last_tx.lock().unwrap() = now;
+        let mut stats = self.stats.lock().unwrap();
+        stats.sent.count(midi);
+        stats.bytes_sent += packets.len() as u64;
+        stats.writes += 1;
+        stats.write_time += now - started;
+        Ok(())
+    }
+
+    /// Sends a SysEx message, keeping the LCD shadow and the session up to
+    /// date.
+    pub(crate) async fn send_sysex(&self, msg: AutomapSysEx<'_>) -> Result<(), std::io::Error> {
+        if matches!(msg, AutomapSysEx::LcdText(_)) {
+            self.check_online()?;
+        }
+        self.write_midi(&msg.clone().to_bytes()).await?;
+        match &msg {
+            AutomapSysEx::LcdText(ops) => self
+                .lcd
+                .lock()
+                .unwrap()
+                .get_or_insert_with(LcdScreen::default)
+                .apply(ops),
+            AutomapSysEx::OnlineOffline { online: true } => {
+                self.session
+                    .step(SessionState::Claimed, SessionState::Online);
+            }
+            AutomapSysEx::OnlineOffline { online: false } => {
+                self.session
+                    .step(SessionState::Online, SessionState::Claimed);
+                self.session
+                    .step(SessionState::Degraded, SessionState::Claimed);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn send_command(&self, cmd: &AutomapCommand) -> Result<(), std::io::Error> {
+        if drives_surface(cmd) {
+            self.check_online()?;
+        }
+        let mut bytes = cmd.to_bytes();
+        bytes[0] = self.cc_status;
+        let cmds = std::slice::from_ref(cmd);
+        let noted = self.note_requests(cmds);
+        if let Err(e) = self.write_midi(&bytes).await {
+            self.withdraw_requests(cmds, noted);
+            return Err(e);
+        }
+        self.leds.lock().unwrap().apply(cmd);
+        Ok(())
+    }
+
+    /// Sends several commands in a single transfer.
+    pub(crate) async fn send_commands(
+        &self,
+        cmds: &[AutomapCommand],
+    ) -> Result<(), std::io::Error> {
+        if cmds.iter().any(drives_surface) {
+            self.check_online()?;
+        }
+        let mut bytes = Vec::with_capacity(cmds.len() * 3);
+        for cmd in cmds {
+            let mut msg = cmd.to_bytes();
+            msg[0] = self.cc_status;
+            bytes.extend_from_slice(&msg);
+        }
+        let noted = self.note_requests(cmds);
+        if let Err(e) = self.write_midi(&bytes).await {
+            self.withdraw_requests(cmds, noted);
+            return Err(e);
+        }
+        let mut leds = self.leds.lock().unwrap();
+        for cmd in cmds {
+            leds.apply(cmd);
+        }
+        Ok(())
+    }
+
+    /// Notes the parameter requests among `cmds`, about to be written, so
+    /// their answers can be told apart. They are noted before the write
+    /// because the device may read an answer before the writer returns.
+    /// Returns when they were noted, for
+    /// [`withdraw_requests()`](Self::withdraw_requests).
+    fn note_requests(&self, cmds: &[AutomapCommand]) -> Instant {
+        let now = Instant::now();
+        let mut requests = self.parameter_requests.lock().unwrap();
+        for request_type in parameter_requests(cmds) {
+            requests.sent(request_type, now);
+        }
+        now
+    }
+
+    /// Forgets the requests of `cmds` noted at `at`, after their write
+    /// failed.
+    fn withdraw_requests(&self, cmds: &[AutomapCommand], at: Instant) {
+        let mut requests = self.parameter_requests.lock().unwrap();
+        for request_type in parameter_requests(cmds) {
+            requests.withdraw(request_type, at);
+        }
+    }
+
+    /// `event` with a parameter response typed by the request it answers.
+    fn resolve_parameter(&self, event: AutomapEvent) -> AutomapEvent {
+        self.parameter_requests
+            .lock()
+            .unwrap()
+            .resolve(event, Instant::now())
+    }
+
+    /// Sends the commands of [`LedState::ring_commands()`] in one transfer.
+    pub(crate) async fn set_ring(
+        &self,
+        encoder: Encoder,
+        mode: RingMode,
+        position: EncoderPosition,
+    ) -> Result<(), std::io::Error> {
+        let cmds = self.leds().ring_commands(encoder, mode, position);
+        self.send_commands(&cmds).await
+    }
+
+    pub(crate) fn leds(&self) -> LedState {
+        self.leds.lock().unwrap().clone()
+    }
+
+    /// Fails unless LED and LCD output would reach the surface.
+    fn check_online(&self) -> Result<(), std::io::Error> {
+        match self.session.state() {
+            // Without a writer the write itself reports the problem
+            SessionState::Claimed => Err(std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                AutomapError::NotOnline,
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn lcd(&self) -> Option<LcdScreen> {
+        self.lcd.lock().unwrap().clone()
+    }
+}
+
+/// The unit's IN endpoint. It is a bulk endpoint, but some enumeration
+/// paths report the MIDI endpoints as interrupt ones.
+enum Reader {
+    Bulk(EndpointRead<Bulk>),
+    Interrupt(EndpointRead<Interrupt>),
+    #[cfg(feature = "mock")]
+    Mock(FakeZeroMkII),
+}
+
+impl Reader {
+    fn open(
+        interface: &nusb::Interface,
+        endpoints: &Endpoints,
+        config: &DeviceConfig,
+    ) -> Result<Self, nusb::Error> {
+        let size = config.read_buffer;
+        let mut reader = match endpoints.in_type {
+            TransferType::Interrupt => Reader::Interrupt(
+                interface
+                    .endpoint::<Interrupt, In>(endpoints.ep_in)?
+                    .reader(size),
+            ),
+            _ => Reader::Bulk(
+                interface
+                    .endpoint::<Bulk, In>(endpoints.ep_in)?
+                    .reader(size),
+            ),
+        };
+        reader.set_num_transfers(config.read_transfers);
+        Ok(reader)
+    }
+
+    fn set_num_transfers(&mut self, count: usize) {
+        match self {
+            Reader::Bulk(reader) => reader.set_num_transfers(count),
+            Reader::Interrupt(reader) => reader.set_num_transfers(count),
+            #[cfg(feature = "mock")]
+            Reader::Mock(_) => {}
+        }
+    }
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        match self {
+            Reader::Bulk(reader) => reader.read(buf).await,
+            Reader::Interrupt(reader) => reader.read(buf).await,
+            #[cfg(feature = "mock")]
+            Reader::Mock(fake) => fake.read_packets(buf).await,
+        }
+    }
+
+    /// Clears a STALL, rebuilding the reader around the same endpoint.
+    async fn clear_halt(self, config: &DeviceConfig) -> Result<Self, std::io::Error> {
+        let size = config.read_buffer;
+        let mut reader = match self {
+            Reader::Bulk(reader) => {
+                Reader::Bulk(clear_halt(reader.into_inner()).await?.reader(size))
+            }
+            Reader::Interrupt(reader) => {
+                Reader::Interrupt(clear_halt(reader.into_inner()).await?.reader(size))
+            }
+            #[cfg(feature = "mock")]
+            Reader::Mock(fake) => return Ok(Reader::Mock(fake)),
+        };
+        reader.set_num_transfers(config.read_transfers);
+        Ok(reader)
+    }
+}
+
+/// The unit's OUT endpoint; see [`Reader`].
+enum Writer {
+    Bulk(EndpointWrite<Bulk>),
+    Interrupt(EndpointWrite<Interrupt>),
+    #[cfg(feature = "mock")]
+    Mock(FakeZeroMkII),
+}
+
+impl Writer {
+    fn open(interface: &nusb::Interface, endpoints: &Endpoints) -> Result<Self, nusb::Error> {
+        Ok(match endpoints.out_type {
+            TransferType::Interrupt => Writer::Interrupt(
+                interface
+                    .endpoint::<Interrupt, Out>(endpoints.ep_out)?
+                    .writer(64),
+            ),
+            _ => Writer::Bulk(
+                interface
+                    .endpoint::<Bulk, Out>(endpoints.ep_out)?
+                    .writer(64),
+            ),
+        })
+    }
+
+    async fn write_packets(&mut self, packets: &[u8]) -> Result<(), std::io::Error> {
+        match self {
+            Writer::Bulk(writer) => {
+                writer.write_all(packets).await?;
+                writer.flush().await
+            }
+            Writer::Interrupt(writer) => {
+                writer.write_all(packets).await?;
+                writer.flush().await
+            }
+            #[cfg(feature = "mock")]
+            Writer::Mock(fake) => fake.write_packets(packets),
+        }
+    }
+
+    /// Clears a STALL, rebuilding the writer around the same endpoint.
+    async fn clear_halt(self) -> Result<Self, std::io::Error> {
+        Ok(match self {
+            Writer::Bulk(writer) => Writer::Bulk(clear_halt(writer.into_inner()).await?.writer(64)),
+            Writer::Interrupt(writer) => {
+                Writer::Interrupt(clear_halt(writer.into_inner()).await?.writer(64))
+            }
+            #[cfg(feature = "mock")]
+            Writer::Mock(fake) => Writer::Mock(fake),
+        })
+    }
+}
+
+/// Whether `incoming` says a special template was loaded or unloaded, which
+/// the unit reports both as a CC and as an OnlineOffline frame.
+fn template_change(incoming: &Incoming) -> Option<bool> {
+    match incoming {
+        Incoming::Event(AutomapEvent::TemplateChanged { special }) => Some(*special),
+        Incoming::SysEx(frame) => match decode_frame(frame) {
+            Ok((_, _, _, DecodedMsg::Automap(AutomapSysEx::OnlineOffline { online }))) => {
+                Some(online)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Doubling delays between retries, up to a number of them.
+struct Backoff {
+    next: Duration,
+    left: u32,
+}
+
+impl Backoff {
+    fn new(first: Duration, retries: u32) -> Self {
+        Backoff {
+            next: first,
+            left: retries,
+        }
+    }
+
+    /// How long to wait before the next retry, or `None` once they are
+    /// used up.
+    fn next(&mut self) -> Option<Duration> {
+        self.left = self.left.checked_sub(1)?;
+        let delay = self.next;
+        self.next *= 2;
+        Some(delay)
+    }
+}
+
+/// The request types of the parameter requests among `cmds`.
+fn parameter_requests(cmds: &[AutomapCommand]) -> impl Iterator<Item = ParameterRequestType> + '_ {
+    cmds.iter().filter_map(|cmd| match *cmd {
+        AutomapCommand::ParameterRequest { request_type } => Some(request_type),
+        _ => None,
+    })
+}
+
+/// Whether `cmd` sets LEDs or rings, which the unit ignores unless online.
+fn drives_surface(cmd: &AutomapCommand) -> bool {
+    !matches!(
+        cmd,
+        AutomapCommand::TransportLockSet { .. }
+            | AutomapCommand::ParameterRequest { .. }
+            | AutomapCommand::EchoRequest { .. }
+    )
+}
+
+/// `data.len()` as a data-block length.
+fn block_len(data: &[u8]) -> Result<u16, std::io::Error> {
+    u16::try_from(data.len())
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "data block too long"))
+}
+
+/// Clears a STALL on `endpoint` once its outstanding transfers are done.
+async fn clear_halt<EpType: BulkOrInterrupt, Dir: EndpointDirection>(
+    mut endpoint: Endpoint<EpType, Dir>,
+) -> Result<Endpoint<EpType, Dir>, std::io::Error> {
+    endpoint.cancel_all();
+    while endpoint.pending() > 0 {
+        endpoint.next_complete().await;
+    }
+    endpoint.clear_halt().await?;
+    Ok(endpoint)
+}
+
+/// Finds the unit, claims its interface and opens both endpoints.
+async fn connect(
+    config: &DeviceConfig,
+) -> Result<(Reader, Writer, nusb::DeviceInfo), AutomapError> {
+    let device_info = find_device(config).await?;
+    let device = open_device(&device_info).await?;
+    let endpoints = device
+        .active_configuration()
+        .ok()
+        .and_then(|desc| find_endpoints(&desc, config))
+        .ok_or(AutomapError::NoInterface)?;
+
+    let (interface, endpoints) = match claim(&device, &device_info, config, endpoints).await {
+        // Without WinUSB on the Automap interface, fall back to the MIDI
+        // streaming interface if that one is usable
+        Err(AutomapError::DriverNotBound { .. })
+            if config.backend == Backend::Auto && config.interface.is_none() =>
+        {
+            let midi = config.clone().backend(Backend::MidiStreaming);
+            let endpoints = device
+                .active_configuration()
+                .ok()
+                .and_then(|desc| find_endpoints(&desc, &midi))
+                .ok_or(AutomapError::NoInterface)?;
+            claim(&device, &device_info, config, endpoints).await?
+        }
+        other => other?,
+    };
+
+    let reader = Reader::open(&interface, &endpoints, config)?;
+    let writer = Writer::open(&interface, &endpoints)?;
+
+    Ok((reader, writer, device_info))
+}
+
+/// Interface and endpoint addresses used to talk to the unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Endpoints {
+    interface: u8,
+    ep_out: u8,
+    ep_in: u8,
+    out_type: TransferType,
+    in_type: TransferType,
+}
+
+/// First connected unit matching the configured serial, if any.
+async fn find_device(config: &DeviceConfig) -> Result<nusb::DeviceInfo, AutomapError> {
+    nusb::list_devices()
+        .await?
+        .find(|dev| {
+            dev.vendor_id() == VID
+                && dev.product_id() == PID
+                && config
+                    .serial
+                    .as_deref()
+                    .is_none_or(|serial| dev.serial_number() == Some(serial))
+        })
+        .ok_or(AutomapError::NotFound)
+}
+
+async fn open_device(info: &nusb::DeviceInfo) -> Result<nusb::Device, AutomapError> {
+    info.open().await.map_err(|e| {
+        if e.kind() == nusb::ErrorKind::PermissionDenied {
+            permission_denied(info)
+        } else {
+            e.into()
+        }
+    })
+}
+
+/// Claims the interface in `endpoints`, detaching a kernel driver if allowed.
+async fn claim(
+    device: &nusb::Device,
+    info: &nusb::DeviceInfo,
+    config: &DeviceConfig,
+    endpoints: Endpoints,
+) -> Result<(nusb::Interface, Endpoints), AutomapError> {
+    let claimed = match device.claim_interface(endpoints.interface).await {
+        // Released (and the kernel driver reattached) when the device is dropped
+        Err(e) if e.kind() == nusb::ErrorKind::Busy && config.detach_kernel_driver => {
+            device.detach_and_claim_interface(endpoints.interface).await
+        }
+        other => other,
+    };
+    match claimed {
+        Ok(interface) => Ok((interface, endpoints)),
+        Err(e) => Err(claim_error(e, info, endpoints.interface)),
+    }
+}
+
+/// Turns a failed claim into the most specific error we can give.
+fn claim_error(e: nusb::Error, info: &nusb::DeviceInfo, interface: u8) -> AutomapError {
+    match e.kind() {
+        nusb::ErrorKind::Busy => AutomapError::InterfaceBusy {
+            interface,
+            driver: kernel_driver(info, interface),
+        },
+        // nusb reports an interface without WinUSB as unsupported
+        nusb::ErrorKind::Unsupported if cfg!(all(target_os = "windows", feature = "windows")) => {
+            AutomapError::DriverNotBound { interface }
+        }
+        nusb::ErrorKind::PermissionDenied => permission_denied(info),
+        _ => e.into(),
+    }
+}
+
+/// Describes which device node could not be opened, and how to fix it.
+fn permission_denied(info: &nusb::DeviceInfo) -> AutomapError {
+    #[cfg(target_os = "linux")]
+    let (devpath, suggested_udev_rule) = (
+        format!(
+            "/dev/bus/usb/{:03}/{:03}",
+            info.busnum(),
+            info.device_address()
+        ),
+        Some(udev_rule(VID, PID)),
+    );
+    #[cfg(not(target_os = "linux"))]
+    let (devpath, suggested_udev_rule) = (
+        format!("bus {} device {}", info.bus_id(), info.device_address()),
+        None,
+    );
+    AutomapError::PermissionDenied {
+        devpath,
+        suggested_udev_rule,
+    }
+}
+
+/// Name of the kernel driver bound to `interface`, read from sysfs.
+#[cfg(target_os = "linux")]
+fn kernel_driver(info: &nusb::DeviceInfo, interface: u8) -> Option<String> {
+    let dev = info.sysfs_path();
+    let name = dev.file_name()?.to_str()?;
+    // Interface directories are named <bus>-<port>:<config>.<interface>
+    std::fs::read_dir(dev)
+        .ok()?
+        .filter_map(Result::ok)
+        .find(|entry| {
+            let entry = entry.file_name();
+            let rest = entry
+                .to_str()
+                .and_then(|e| e.strip_prefix(name)?.strip_prefix(':'));
+            rest.and_then(|rest| rest.split('.').nth(1)) == Some(&interface.to_string())
+        })
+        .and_then(|entry| std::fs::read_link(entry.path().join("driver")).ok())
+        .and_then(|driver| Some(driver.file_name()?.to_string_lossy().into_owned()))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn kernel_driver(_info: &nusb::DeviceInfo, _interface: u8) -> Option<String> {
+    None
+}
+
+/// Picks the Automap interface and its bulk endpoints from the descriptors.
+///
+/// A vendor-specific interface (the ZeRO MkII's Automap port) is preferred
+/// over a standard MIDI streaming one unless `config` picks a [`Backend`];
+/// overrides in `config` restrict the search to the given interface and
+/// endpoint addresses.
+fn find_endpoints(desc: &ConfigurationDescriptor, config: &DeviceConfig) -> Option<Endpoints> {
+    let mut best: Option<(u8, Endpoints)> = None;
+
+    for iface in desc.interfaces() {
+        let number = iface.interface_number();
+        if config.interface.is_some_and(|n| n != number) {
+            continue;
+        }
+        let alt = iface.first_alt_setting();
+        let kind = InterfaceKind::from_class(alt.class(), alt.subclass());
+        let rank = match kind {
+            InterfaceKind::Vendor if kind.serves(config.backend) => 0,
+            InterfaceKind::MidiStreaming if kind.serves(config.backend) => 1,
+            // An explicitly chosen interface is used whatever its class
+            _ if config.interface.is_some() => 2,
+            _ => continue,
+        };
+
+        // Bulk endpoints if the interface has them, interrupt ones otherwise
+        let find = |dir: Direction, wanted: Option<u8>| {
+            let usable = |ep: &EndpointDescriptor, ty| match wanted {
+                Some(addr) => ep.address() == addr && ep.transfer_type() == ty,
+                None => ep.direction() == dir && ep.transfer_type() == ty,
+            };
+            [TransferType::Bulk, TransferType::Interrupt]
+                .into_iter()
+                .find_map(|ty| alt.endpoints().find(|ep| usable(ep, ty)))
+                .map(|ep| (ep.address(), ep.transfer_type()))
+        };
+        let (Some((ep_out, out_type)), Some((ep_in, in_type))) = (
+            find(Direction::Out, config.endpoints.map(|(out, _)| out)),
+            find(Direction::In, config.endpoints.map(|(_, ep_in)| ep_in)),
+        ) else {
+            continue;
+        };
+
+        if best.is_none_or(|(r, _)| rank < r) {
+            let endpoints = Endpoints {
+                interface: number,
+                ep_out,
+                ep_in,
+                out_type,
+                in_type,
+            };
+            best = Some((rank, endpoints));
+        }
+    }
+
+    best.map(|(_, endpoints)| endpoints)
+}
+
+/// The simulation sub-command carried by a SysEx reply, if any.
+fn sim_reply(incoming: &Incoming) -> Option<SimCmd> {
+    let Incoming::SysEx(frame) = incoming else {
+        return None;
+    };
+    match decode_frame(frame) {
+        Ok((_, _, _, DecodedMsg::DbSim(DbSimMsg::Simulate(cmd)))) => Some(cmd),
+        _ => None,
+    }
+}
+
+/// The data of an Upload Template frame, if `incoming` is one.
+fn upload_template_data(incoming: &Incoming) -> Option<Vec<u8>> {
+    let Incoming::SysEx(frame) = incoming else {
+        return None;
+    };
+    match decode_frame(frame) {
+        Ok((_, _, _, DecodedMsg::Automap(AutomapSysEx::UploadTemplate { data }))) => {
+            Some(data.to_vec())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Audio control (0), MIDI streaming (1) and vendor (2) interfaces, each
+    /// streaming one with an OUT/IN pair of `ep_type` (2 bulk, 3 interrupt),
+    /// like the ZeRO MkII.
+    fn zero_mkii_config_descriptor(ep_type: u8) -> Vec<u8> {
+        let mut d = vec![0x09, 0x02, 0, 0, 0x03, 0x01, 0x00, 0x80, 0x32];
+        d.extend_from_slice(&[0x09, 0x04, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00]);
+        for (number, class, subclass, ep_out, ep_in) in
+            [(1, 0x01, 0x03, 0x02, 0x82), (2, 0xFF, 0x00, 0x06, 0x86)]
+        {
+            d.extend_from_slice(&[0x09, 0x04, number, 0x00, 0x02, class, subclass, 0x00, 0x00]);
+            d.extend_from_slice(&[0x07, 0x05, ep_out, ep_type, 0x40, 0x00, 0x01]);
+            d.extend_from_slice(&[0x07, 0x05, ep_in, ep_type, 0x40, 0x00, 0x01]);
+        }
+        let len = d.len() as u16;
+        d[2..4].copy_from_slice(&len.to_le_bytes());
+        d
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(READ_BACKOFF, READ_RETRIES);
+        let delays: Vec<u64> = std::iter::from_fn(|| backoff.next())
+            .map(|d| d.as_millis() as u64)
+            .collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16]);
+        assert_eq!(backoff.next(), None);
+    }
+
+    #[test]
+    fn test_find_endpoints() {
+        let bytes = zero_mkii_config_descriptor(0x02);
+        let desc = ConfigurationDescriptor::new(&bytes).unwrap();
+
+        let found = find_endpoints(&desc, &DeviceConfig::default());
+        assert_eq!(
+            found,
+            Some(Endpoints {
+                interface: 2,
+                ep_out: 0x06,
+                ep_in: 0x86,
+                out_type: TransferType::Bulk,
+                in_type: TransferType::Bulk,
+            })
+        );
+
+        let midi = find_endpoints(&desc, &DeviceConfig::new().interface(1));
+        assert_eq!(midi.map(|e| (e.ep_out, e.ep_in)), Some((0x02, 0x82)));
+
+        let class = find_endpoints(&desc, &DeviceConfig::new().backend(Backend::MidiStreaming));
+        assert_eq!(class.map(|e| e.interface), Some(1));
+
+        let missing = DeviceConfig::new().endpoints(0x06, 0x87);
+        assert_eq!(find_endpoints(&desc, &missing), None);
+
+        let bytes = zero_mkii_config_descriptor(0x03);
+        let desc = ConfigurationDescriptor::new(&bytes).unwrap();
+        let interrupt = find_endpoints(&desc, &DeviceConfig::default()).unwrap();
+        assert_eq!((interrupt.ep_out, interrupt.ep_in), (0x06, 0x86));
+        assert_eq!(interrupt.in_type, TransferType::Interrupt);
+    }
 }
@@ -0,0 +1,324 @@
+//! Smoothing for the analog controls.
+//!
+//! Sliders, the crossfader, the expression pedal and the touchpad all
+//! report 7-bit values, so a slow sweep arrives as a staircase of 128
+//! steps, each one landing at once. [`Smoother`] turns those streams into
+//! values between 0.0 and 1.0 that glide from step to step, through a
+//! [`OnePole`] lowpass or a [`SlewLimiter`] per control. It reports a new
+//! value for every event, and after that every [`tick`](Smoother::tick)
+//! until the control has caught up, so it needs
+//! [`poll()`](Smoother::poll)ing, ideally at
+//! [`next_deadline()`](Smoother::next_deadline).
+//!
+//! The filters work on their own too: feed them a target and the time
+//! since the last step.
+
+use std::time::{Duration, Instant};
+
+use crate::automap::cc::{Pot, Slider};
+use crate::automap::event::AutomapEvent;
+use crate::automap::timed::TimedEvent;
+
+/// Distance from the target below which a filter counts as caught up,
+/// well under one 7-bit step.
+const SETTLED: f32 = 1.0 / 1024.0;
+
+/// A one-pole lowpass: each step covers the same fraction of the remaining
+/// distance per unit of time, so it approaches the target exponentially.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OnePole {
+    /// Time to cover about 63% of a jump.
+    pub time_constant: Duration,
+}
+
+impl OnePole {
+    /// Moves `value` towards `target` by what `dt` allows.
+    pub fn step(self, value: f32, target: f32, dt: Duration) -> f32 {
+        if self.time_constant.is_zero() {
+            return target;
+        }
+        let k = 1.0 - (-dt.as_secs_f32() / self.time_constant.as_secs_f32()).exp();
+        value + (target - value) * k
+    }
+}
+
+/// A slew limiter: moves towards the target at a fixed rate, then stops.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SlewLimiter {
+    /// Largest change per second, 1.0 being the full range.
+    pub per_second: f32,
+}
+
+impl SlewLimiter {
+    /// Moves `value` towards `target` by at most what `dt` allows.
+    pub fn step(self, value: f32, target: f32, dt: Duration) -> f32 {
+        let max = self.per_second * dt.as_secs_f32();
+        value + (target - value).clamp(-max, max)
+    }
+}
+
+/// How a control's values are smoothed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Filter {
+    /// Every value is reported as it arrives.
+    #[default]
+    None,
+    OnePole(OnePole),
+    Slew(SlewLimiter),
+}
+
+impl Filter {
+    fn step(self, value: f32, target: f32, dt: Duration) -> f32 {
+        let next = match self {
+            Filter::None => target,
+            Filter::OnePole(f) => f.step(value, target, dt),
+            Filter::Slew(f) => f.step(value, target, dt),
+        };
+        if (target - next).abs() < SETTLED {
+            target
+        } else {
+            next
+        }
+    }
+}
+
+/// A control with a continuous 7-bit value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Analog {
+    Pot(Pot),
+    Slider(Slider),
+    CrossFader,
+    ExpressionPedal,
+    TouchpadX1,
+    TouchpadY1,
+    TouchpadX2,
+    TouchpadY2,
+}
+
+impl Analog {
+    /// The control and 7-bit value of an event from an analog control.
+    pub fn from_event(event: &AutomapEvent) -> Option<(Analog, u8)> {
+        Some(match *event {
+            AutomapEvent::Pot { pot, value } => (Analog::Pot(pot), value & 0x7F),
+            AutomapEvent::Slider { slider, value } => (Analog::Slider(slider), value & 0x7F),
+            AutomapEvent::CrossFader { value } => (Analog::CrossFader, value),
+            AutomapEvent::ExpressionPedal { value } => (Analog::ExpressionPedal, value),
+            AutomapEvent::TouchpadX1 { value } => (Analog::TouchpadX1, value),
+            AutomapEvent::TouchpadY1 { value } => (Analog::TouchpadY1, value),
+            AutomapEvent::TouchpadX2 { value } => (Analog::TouchpadX2, value),
+            AutomapEvent::TouchpadY2 { value } => (Analog::TouchpadY2, value),
+            _ => return None,
+        })
+    }
+}
+
+/// A smoothed value of a control, between 0.0 and 1.0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmoothedValue {
+    pub source: Analog,
+    pub value: f32,
+    pub at: Instant,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct State {
+    value: f32,
+    target: f32,
+    /// When `value` was last stepped.
+    at: Instant,
+}
+
+/// Smooths analog controls, with a filter per control.
+#[derive(Debug, Clone)]
+pub struct Smoother {
+    defaults: Filter,
+    overrides: Vec<(Analog, Filter)>,
+    tick: Duration,
+    states: Vec<(Analog, State)>,
+}
+
+impl Default for Smoother {
+    fn default() -> Self {
+        Smoother::new(Filter::default())
+    }
+}
+
+impl Smoother {
+    /// A smoother using `defaults` for every control, ticking every 10 ms.
+    pub fn new(defaults: Filter) -> Self {
+        Smoother {
+            defaults,
+            overrides: Vec::new(),
+            tick: Duration::from_millis(10),
+            states: Vec::new(),
+        }
+    }
+
+    /// Uses `filter` for `source` instead of the defaults.
+    pub fn with_filter(mut self, source: Analog, filter: Filter) -> Self {
+        self.overrides.retain(|(s, _)| *s != source);
+        self.overrides.push((source, filter));
+        self
+    }
+
+    /// How often a control that has not caught up reports a new value.
+    pub fn tick(mut self, tick: Duration) -> Self {
+        self.tick = tick;
+        self
+    }
+
+    /// Feeds one event, returning the values it produces.
+    ///
+    /// Also runs [`poll()`](Self::poll) at the event's time. The first
+    /// value of a control is taken as it is; events from other controls
+    /// are ignored.
+    pub fn process(&mut self, event: &TimedEvent) -> Vec<SmoothedValue> {
+        let mut out = self.poll(event.at);
+        let Some((source, raw)) = Analog::from_event(&event.event) else {
+            return out;
+        };
+        let target = f32::from(raw) / 127.0;
+        let at = event.at;
+        let filter = self.filter(source);
+        let state = match self.states.iter().position(|(s, _)| *s == source) {
+            Some(i) => {
+                let state = &mut self.states[i].1;
+                state.value = filter.step(
+                    state.value,
+                    state.target,
+                    at.saturating_duration_since(state.at),
+                );
+                state.target = target;
+                state.at = at;
+                if filter == Filter::None {
+                    state.value = target;
+                }
+                // Otherwise the next tick has the first step towards it
+                *state
+            }
+            None => {
+                let state = State {
+                    value: target,
+                    target,
+                    at,
+                };
+                self.states.push((source, state));
+                state
+            }
+        };
+        out.retain(|v| v.source != source);
+        out.push(SmoothedValue {
+            source,
+            value: state.value,
+            at,
+        });
+        out
+    }
+
+    /// Steps every control that is still catching up and whose tick is due
+    /// by `now`, reporting each one's new value.
+    pub fn poll(&mut self, now: Instant) -> Vec<SmoothedValue> {
+        let mut out = Vec::new();
+        for i in 0..self.states.len() {
+            let (source, state) = self.states[i];
+            if state.value == state.target || now < state.at + self.tick {
+                continue;
+            }
+            let value = self
+                .filter(source)
+                .step(state.value, state.target, now - state.at);
+            self.states[i].1.value = value;
+            self.states[i].1.at = now;
+            out.push(SmoothedValue {
+                source,
+                value,
+                at: now,
+            });
+        }
+        out
+    }
+
+    /// When [`poll()`](Self::poll) next has a value to report, if any
+    /// control is still catching up.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.states
+            .iter()
+            .filter(|(_, state)| state.value != state.target)
+            .map(|(_, state)| state.at + self.tick)
+            .min()
+    }
+
+    fn filter(&self, source: Analog) -> Filter {
+        self.overrides
+            .iter()
+            .find(|(s, _)| *s == source)
+            .map_or(self.defaults, |(_, f)| *f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pedal(at: Instant, value: u8) -> TimedEvent {
+        TimedEvent {
+            at,
+            event: AutomapEvent::ExpressionPedal { value },
+        }
+    }
+
+    #[test]
+    fn test_filters() {
+        let tau = Duration::from_millis(100);
+        let lowpass = OnePole { time_constant: tau };
+        let after = lowpass.step(0.0, 1.0, tau);
+        assert!((after - 0.632).abs() < 0.001);
+        assert_eq!(lowpass.step(0.5, 0.5, tau), 0.5);
+
+        let slew = SlewLimiter { per_second: 2.0 };
+        let ms = Duration::from_millis;
+        assert_eq!(slew.step(0.0, 1.0, ms(100)), 0.2);
+        assert_eq!(slew.step(0.9, 0.0, ms(100)), 0.7);
+        assert_eq!(slew.step(0.1, 0.0, ms(100)), 0.0);
+    }
+
+    #[test]
+    fn test_glides_until_caught_up() {
+        let t0 = Instant::now();
+        let ms = Duration::from_millis;
+        let slew = Filter::Slew(SlewLimiter { per_second: 10.0 });
+        let mut s = Smoother::default().with_filter(Analog::ExpressionPedal, slew);
+
+        assert_eq!(s.process(&pedal(t0, 0))[0].value, 0.0);
+        assert_eq!(s.next_deadline(), None);
+        // The jump starts from where the pedal was
+        assert_eq!(s.process(&pedal(t0 + ms(5), 127))[0].value, 0.0);
+        assert_eq!(s.next_deadline(), Some(t0 + ms(15)));
+        let mut now = t0 + ms(5);
+        let mut steps = Vec::new();
+        while let Some(deadline) = s.next_deadline() {
+            now = deadline;
+            steps.extend(s.poll(now).into_iter().map(|v| v.value));
+        }
+        assert_eq!(steps.len(), 10);
+        assert!(steps.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(steps.last(), Some(&1.0));
+        assert_eq!(now, t0 + ms(105));
+    }
+
+    #[test]
+    fn test_unfiltered_controls_report_as_they_arrive() {
+        let t0 = Instant::now();
+        let mut s = Smoother::default();
+        s.process(&TimedEvent {
+            at: t0,
+            event: AutomapEvent::TouchpadX1 { value: 0 },
+        });
+        let moved = s.process(&TimedEvent {
+            at: t0,
+            event: AutomapEvent::TouchpadX1 { value: 127 },
+        });
+        assert_eq!(moved[0].value, 1.0);
+        assert_eq!(s.next_deadline(), None);
+    }
+}
@@ -0,0 +1,250 @@
+//! Errors from opening and driving the device.
+
+use std::fmt;
+use std::io;
+
+/// What it takes to get going again after an error, from
+/// [`AutomapError::recovery()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// Trying again may work, perhaps after doing what the error's
+    /// [hint](AutomapError::hint) says: a reply timed out, a transfer was
+    /// cancelled, output was sent while offline.
+    Retry,
+    /// The connection is lost or unusable:
+    /// [`recover()`](crate::AutomapDevice::recover) the device, or open it
+    /// again once the unit is back.
+    Reconnect,
+    /// Nothing the program does will help until something outside it
+    /// changes, like permissions, drivers or the configuration.
+    Fatal,
+}
+
+/// Errors returned by [`AutomapDevice`](crate::AutomapDevice).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AutomapError {
+    /// No ZeRO MkII (matching the configured serial, if any) is connected.
+    NotFound,
+
+    /// The unit has no interface with a usable bulk endpoint pair.
+    NoInterface,
+
+    /// The interface is already claimed, usually by a kernel driver.
+    ///
+    /// `driver` names the kernel driver bound to it, where the OS tells us.
+    InterfaceBusy {
+        interface: u8,
+        driver: Option<String>,
+    },
+
+    /// The OS refused access to the device node.
+    ///
+    /// `devpath` identifies the device (`/dev/bus/usb/BBB/DDD` on Linux).
+    /// On Linux, `suggested_udev_rule` holds a rule that fixes it; see
+    /// [`udev_rule()`](crate::automap::udev::udev_rule).
+    PermissionDenied {
+        devpath: String,
+        suggested_udev_rule: Option<String>,
+    },
+
+    /// No WinUSB driver is bound to the interface (Windows, `windows` feature).
+    DriverNotBound { interface: u8 },
+
+    /// LED or LCD output was sent while the unit is in standalone mode,
+    /// where it would be ignored.
+    ///
+    /// Sends report it as an `std::io::Error` of kind `NotConnected`
+    /// carrying this error; see
+    /// [`SessionState::Claimed`](crate::SessionState::Claimed).
+    NotOnline,
+
+    /// A save to flash or a write was refused because memory protect is on
+    /// in the globals, and the unit would have dropped it.
+    ///
+    /// Reported as an `std::io::Error` of kind `PermissionDenied` carrying
+    /// this error; see
+    /// [`AutomapDevice::save_globals_to_flash()`](crate::AutomapDevice::save_globals_to_flash).
+    MemoryProtected,
+
+    /// The IN endpoint kept stalling, or kept completing reads without
+    /// data, through `attempts` tries with backoff.
+    ///
+    /// Reported by reads as an `std::io::Error` of kind `BrokenPipe`
+    /// carrying this error. A single stall is cleared without an error.
+    Stalled { attempts: u32 },
+
+    /// Other USB error while opening the device.
+    Usb(nusb::Error),
+
+    /// USB transfer error.
+    Io(std::io::Error),
+}
+
+impl AutomapError {
+    /// A suggestion for fixing the error, if there is one.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            AutomapError::NotFound => Some("check the cable and that the unit is powered on"),
+            AutomapError::PermissionDenied {
+                suggested_udev_rule: Some(_),
+                ..
+            } => Some("install the suggested udev rule and replug the unit"),
+            AutomapError::PermissionDenied { .. } => {
+                Some("run with access to the USB device, or as administrator")
+            }
+            AutomapError::DriverNotBound { .. } => Some(
+                "install the WinUSB driver for the interface, e.g. with Zadig, or use \
+                 Backend::MidiStreaming",
+            ),
+            AutomapError::NotOnline => Some(
+                "send OnlineOffline { online: true } first, or enable DeviceConfig::auto_online",
+            ),
+            AutomapError::MemoryProtected => Some(
+                "turn memory protect off in the unit's global settings, or call \
+                 AutomapDevice::override_memory_protect(true)",
+            ),
+            AutomapError::Stalled { .. } => {
+                Some("replug the unit if AutomapDevice::recover() does not help")
+            }
+            AutomapError::InterfaceBusy { .. } => Some(
+                "enable DeviceConfig::detach_kernel_driver(true), or close the application \
+                 holding the interface",
+            ),
+            _ => None,
+        }
+    }
+
+    /// Whether and how the device can carry on after this error.
+    pub fn recovery(&self) -> Recovery {
+        match self {
+            AutomapError::NotOnline | AutomapError::MemoryProtected => Recovery::Retry,
+            AutomapError::NotFound | AutomapError::Stalled { .. } => Recovery::Reconnect,
+            AutomapError::NoInterface
+            | AutomapError::InterfaceBusy { .. }
+            | AutomapError::PermissionDenied { .. }
+            | AutomapError::DriverNotBound { .. } => Recovery::Fatal,
+            AutomapError::Usb(e) if e.kind() == nusb::ErrorKind::Disconnected => {
+                Recovery::Reconnect
+            }
+            AutomapError::Usb(_) => Recovery::Fatal,
+            AutomapError::Io(e) => Self::recovery_of(e),
+        }
+    }
+
+    /// [`recovery()`](Self::recovery) of an I/O error from a device method:
+    /// that of the `AutomapError` it carries, or else judged by its kind.
+    pub fn recovery_of(e: &io::Error) -> Recovery {
+        if let Some(inner) = e.get_ref().and_then(|e| e.downcast_ref::<AutomapError>()) {
+            return inner.recovery();
+        }
+        match e.kind() {
+            io::ErrorKind::TimedOut
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::InvalidData => Recovery::Retry,
+            io::ErrorKind::NotConnected
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::UnexpectedEof => Recovery::Reconnect,
+            _ => Recovery::Fatal,
+        }
+    }
+
+    /// Whether the error is anything but [fatal](Recovery::Fatal).
+    pub fn is_recoverable(&self) -> bool {
+        self.recovery() != Recovery::Fatal
+    }
+}
+
+impl fmt::Display for AutomapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AutomapError::NotFound => write!(f, "device not found")?,
+            AutomapError::NoInterface => write!(f, "no interface with a bulk MIDI endpoint pair")?,
+            AutomapError::InterfaceBusy {
+                interface,
+                driver: Some(driver),
+            } => write!(
+                f,
+                "USB interface {interface} is in use by kernel driver `{driver}`"
+            )?,
+            AutomapError::InterfaceBusy {
+                interface,
+                driver: None,
+            } => write!(f, "USB interface {interface} is in use")?,
+            AutomapError::PermissionDenied { devpath, .. } => {
+                write!(f, "permission denied opening {devpath}")?
+            }
+            AutomapError::DriverNotBound { interface } => {
+                write!(f, "USB interface {interface} has no WinUSB driver bound")?
+            }
+            AutomapError::NotOnline => write!(f, "the unit is not online")?,
+            AutomapError::MemoryProtected => write!(f, "the unit's memory is protected")?,
+            AutomapError::Stalled { attempts } => {
+                write!(f, "the IN endpoint is still stalled after {attempts} tries")?
+            }
+            AutomapError::Usb(e) => write!(f, "USB error: {e}")?,
+            AutomapError::Io(e) => write!(f, "I/O error: {e}")?,
+        }
+        if let Some(hint) = self.hint() {
+            write!(f, " ({hint})")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AutomapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AutomapError::Usb(e) => Some(e),
+            AutomapError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<nusb::Error> for AutomapError {
+    fn from(e: nusb::Error) -> Self {
+        AutomapError::Usb(e)
+    }
+}
+
+impl From<std::io::Error> for AutomapError {
+    fn from(e: std::io::Error) -> Self {
+        AutomapError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interface_busy_message() {
+        let err = AutomapError::InterfaceBusy {
+            interface: 2,
+            driver: Some("snd-usb-audio".into()),
+        };
+        let msg = err.to_string();
+        assert!(msg.starts_with("USB interface 2 is in use by kernel driver `snd-usb-audio`"));
+        assert!(msg.contains("detach_kernel_driver"));
+    }
+
+    #[test]
+    fn test_recovery() {
+        assert_eq!(AutomapError::NotOnline.recovery(), Recovery::Retry);
+        assert!(!AutomapError::NoInterface.is_recoverable());
+
+        let stalled = io::Error::new(
+            io::ErrorKind::BrokenPipe,
+            AutomapError::Stalled { attempts: 5 },
+        );
+        assert_eq!(AutomapError::recovery_of(&stalled), Recovery::Reconnect);
+        let timeout = io::Error::from(io::ErrorKind::TimedOut);
+        assert_eq!(AutomapError::Io(timeout).recovery(), Recovery::Retry);
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert_eq!(AutomapError::recovery_of(&denied), Recovery::Fatal);
+    }
+}
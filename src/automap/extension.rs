@@ -0,0 +1,136 @@
+//! Decoding messages this crate does not know about.
+//!
+//! Firmware variants and other Novation products put their own meanings on
+//! CC numbers and SysEx commands. Rather than fork the decoders, register a
+//! decoder for the CC or command with
+//! [`AutomapDevice::register_decoder()`](crate::AutomapDevice::register_decoder):
+//! it sees each matching message before the built-in decoders, and whatever
+//! it returns is kept as a [`CustomEvent`] for
+//! [`take_custom_events()`](crate::AutomapDevice::take_custom_events).
+
+use std::any::Any;
+use std::time::Instant;
+
+use crate::automap::sysex::NOVATION_ID;
+
+type DecodeFn = Box<dyn FnMut(&[u8]) -> Option<Box<dyn Any + Send>> + Send>;
+
+/// Which messages a custom decoder is offered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtensionKey {
+    /// CCs with this number on the Automap channel.
+    Cc(u8),
+    /// Novation SysEx frames whose command, the first byte after the
+    /// header, is this.
+    SysEx(u8),
+}
+
+/// The output of a custom decoder.
+#[derive(Debug)]
+pub struct CustomEvent {
+    /// When the USB transfer carrying the message completed.
+    pub at: Instant,
+    pub key: ExtensionKey,
+    pub value: Box<dyn Any + Send>,
+}
+
+impl CustomEvent {
+    /// The decoded value, if the decoder produced a `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+
+    /// Takes the decoded value out, or gives the event back if the decoder
+    /// produced something other than a `T`.
+    pub fn downcast<T: Any>(self) -> Result<T, Self> {
+        match self.value.downcast() {
+            Ok(value) => Ok(*value),
+            Err(value) => Err(Self { value, ..self }),
+        }
+    }
+}
+
+/// The decoders registered on a device.
+#[derive(Default)]
+pub(crate) struct Extensions {
+    decoders: Vec<(ExtensionKey, DecodeFn)>,
+}
+
+impl Extensions {
+    pub(crate) fn register<T: Any + Send>(
+        &mut self,
+        key: ExtensionKey,
+        mut f: impl FnMut(&[u8]) -> Option<T> + Send + 'static,
+    ) {
+        self.decoders.push((
+            key,
+            Box::new(move |msg| f(msg).map(|v| Box::new(v) as Box<dyn Any + Send>)),
+        ));
+    }
+
+    /// Offers `msg` to the decoders registered for its key, oldest first,
+    /// and returns the first value decoded. `None` leaves the message to
+    /// the built-in decoders.
+    pub(crate) fn decode(
+        &mut self,
+        msg: &[u8],
+        cc_status: u8,
+    ) -> Option<(ExtensionKey, Box<dyn Any + Send>)> {
+        if self.decoders.is_empty() {
+            return None;
+        }
+        let key = key_of(msg, cc_status)?;
+        self.decoders
+            .iter_mut()
+            .filter(|(k, _)| *k == key)
+            .find_map(|(_, f)| f(msg))
+            .map(|value| (key, value))
+    }
+}
+
+/// The key a decoder for `msg` would be registered under.
+fn key_of(msg: &[u8], cc_status: u8) -> Option<ExtensionKey> {
+    match msg {
+        [status, cc, _] if *status == cc_status => Some(ExtensionKey::Cc(*cc)),
+        // After the ID come the family, two version bytes and two tag
+        // bytes, then the command
+        [0xF0, ..] if msg.starts_with(&NOVATION_ID) => msg
+            .get(NOVATION_ID.len() + 5)
+            .filter(|&&b| b != 0xF7)
+            .map(|&b| ExtensionKey::SysEx(b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Fader(u8);
+
+    #[test]
+    fn test_decoders_run_by_key() {
+        let mut extensions = Extensions::default();
+        extensions.register(ExtensionKey::Cc(0x70), |msg| Some(Fader(msg[2])));
+        extensions.register(ExtensionKey::SysEx(0x7E), |msg| Some(msg.len()));
+
+        let (key, value) = extensions.decode(&[0xBF, 0x70, 9], 0xBF).unwrap();
+        assert_eq!(key, ExtensionKey::Cc(0x70));
+        let event = CustomEvent {
+            at: Instant::now(),
+            key,
+            value,
+        };
+        assert_eq!(event.downcast_ref::<Fader>(), Some(&Fader(9)));
+
+        assert!(extensions.decode(&[0xB0, 0x70, 9], 0xBF).is_none());
+        assert!(extensions.decode(&[0xBF, 0x71, 9], 0xBF).is_none());
+
+        let frame = [
+            0xF0, 0x00, 0x20, 0x29, 0x03, 0x03, 0x12, 0x00, 0x02, 0x00, 0x7E, 0xF7,
+        ];
+        let (_, value) = extensions.decode(&frame, 0xBF).unwrap();
+        assert_eq!(*value.downcast::<usize>().unwrap(), frame.len());
+    }
+}
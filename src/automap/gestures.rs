@@ -0,0 +1,320 @@
+//! Click, double-click and long-press detection on top of raw button events.
+//!
+//! The hardware only reports presses and releases. [`ButtonGestures`] turns
+//! those into higher-level gestures; since some gestures are defined by
+//! nothing happening (a click becomes final once the double-click window
+//! has passed), callers also need to [`poll()`](ButtonGestures::poll) it,
+//! ideally at [`next_deadline()`](ButtonGestures::next_deadline).
+
+use std::time::{Duration, Instant};
+
+use crate::automap::cc::{AutomapButton, Button, PageButton, RowSelect, TransportButton};
+use crate::automap::event::AutomapEvent;
+use crate::automap::timed::TimedEvent;
+
+/// A control that reports press and release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PressSource {
+    Button(Button),
+    Transport(TransportButton),
+    Automap(AutomapButton),
+    Page(PageButton),
+    RowSelect(RowSelect),
+    Preview,
+    SpeedDial,
+}
+
+impl PressSource {
+    /// The source and new pressed state of a press/release event.
+    pub fn from_event(event: &AutomapEvent) -> Option<(PressSource, bool)> {
+        Some(match *event {
+            AutomapEvent::Button { button, pressed } => (PressSource::Button(button), pressed),
+            AutomapEvent::TransportButton { button, pressed } => {
+                (PressSource::Transport(button), pressed)
+            }
+            AutomapEvent::AutomapButton { button, pressed } => {
+                (PressSource::Automap(button), pressed)
+            }
+            AutomapEvent::PageButton { button, pressed } => (PressSource::Page(button), pressed),
+            AutomapEvent::RowSelect { row, selected } => (PressSource::RowSelect(row), selected),
+            AutomapEvent::PreviewButton { pressed } => (PressSource::Preview, pressed),
+            AutomapEvent::SpeedDialButton { pressed } => (PressSource::SpeedDial, pressed),
+            _ => return None,
+        })
+    }
+}
+
+/// Timing thresholds for one button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GestureThresholds {
+    /// Longest gap between two clicks that still makes a double-click.
+    /// Zero reports every click immediately and disables double-clicks.
+    pub double_click: Duration,
+    /// How long a button must be held to become a long press.
+    pub long_press: Duration,
+}
+
+impl Default for GestureThresholds {
+    fn default() -> Self {
+        GestureThresholds {
+            double_click: Duration::from_millis(300),
+            long_press: Duration::from_millis(500),
+        }
+    }
+}
+
+/// What the user did with a button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gesture {
+    /// Pressed and released once, with no second click following.
+    Click,
+    /// Two clicks within the double-click window.
+    DoubleClick,
+    /// Still held after the long-press threshold.
+    LongPress,
+    /// Released after a long press, having been held this long in total.
+    Hold(Duration),
+}
+
+/// A gesture, the button it happened on, and when it was recognised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GestureEvent {
+    pub source: PressSource,
+    pub gesture: Gesture,
+    pub at: Instant,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Down {
+        since: Instant,
+        long_fired: bool,
+        /// Second press of a possible double-click.
+        after_click: bool,
+    },
+    /// Released after a short press; a second press may still follow.
+    Clicked { at: Instant },
+}
+
+/// Gesture recogniser with per-button thresholds.
+#[derive(Debug, Clone, Default)]
+pub struct ButtonGestures {
+    defaults: GestureThresholds,
+    overrides: Vec<(PressSource, GestureThresholds)>,
+    states: Vec<(PressSource, State)>,
+}
+
+impl ButtonGestures {
+    /// A recogniser using `defaults` for every button.
+    pub fn new(defaults: GestureThresholds) -> Self {
+        ButtonGestures {
+            defaults,
+            ..Self::default()
+        }
+    }
+
+    /// Uses `thresholds` for `source` instead of the defaults.
+    pub fn with_thresholds(mut self, source: PressSource, thresholds: GestureThresholds) -> Self {
+        self.overrides.retain(|(s, _)| *s != source);
+        self.overrides.push((source, thresholds));
+        self
+    }
+
+    /// Feeds one raw event, returning the gestures it completes.
+    ///
+    /// Also runs [`poll()`](Self::poll) at the event's time. Events that are
+    /// not presses or releases are ignored.
+    pub fn process(&mut self, event: &TimedEvent) -> Vec<GestureEvent> {
+        let mut out = self.poll(event.at);
+        let Some((source, pressed)) = PressSource::from_event(&event.event) else {
+            return out;
+        };
+        let at = event.at;
+        let thresholds = self.thresholds(source);
+        let state = self.states.iter().position(|(s, _)| *s == source);
+
+        match (state.map(|i| self.states[i].1), pressed) {
+            (None, true) => self.states.push((
+                source,
+                State::Down {
+                    since: at,
+                    long_fired: false,
+                    after_click: false,
+                },
+            )),
+            (Some(State::Clicked { .. }), true) => {
+                self.states[state.unwrap()].1 = State::Down {
+                    since: at,
+                    long_fired: false,
+                    after_click: true,
+                };
+            }
+            (
+                Some(State::Down {
+                    since,
+                    long_fired,
+                    after_click,
+                }),
+                false,
+            ) => {
+                let i = state.unwrap();
+                let emit = |gesture| GestureEvent {
+                    source,
+                    gesture,
+                    at,
+                };
+                if long_fired {
+                    out.push(emit(Gesture::Hold(at - since)));
+                    self.states.remove(i);
+                } else if after_click {
+                    out.push(emit(Gesture::DoubleClick));
+                    self.states.remove(i);
+                } else if thresholds.double_click.is_zero() {
+                    out.push(emit(Gesture::Click));
+                    self.states.remove(i);
+                } else {
+                    self.states[i].1 = State::Clicked { at };
+                }
+            }
+            // Repeated press or stray release: nothing to do
+            _ => {}
+        }
+        out
+    }
+
+    /// Reports gestures that became final by `now` passing: long presses
+    /// and clicks whose double-click window has closed.
+    pub fn poll(&mut self, now: Instant) -> Vec<GestureEvent> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < self.states.len() {
+            let (source, state) = self.states[i];
+            let thresholds = self.thresholds(source);
+            match state {
+                State::Down {
+                    since,
+                    long_fired: false,
+                    after_click,
+                } if now >= since + thresholds.long_press => {
+                    if after_click {
+                        // The first click stands on its own
+                        out.push(GestureEvent {
+                            source,
+                            gesture: Gesture::Click,
+                            at: since + thresholds.long_press,
+                        });
+                    }
+                    out.push(GestureEvent {
+                        source,
+                        gesture: Gesture::LongPress,
+                        at: since + thresholds.long_press,
+                    });
+                    self.states[i].1 = State::Down {
+                        since,
+                        long_fired: true,
+                        after_click: false,
+                    };
+                }
+                State::Clicked { at } if now >= at + thresholds.double_click => {
+                    out.push(GestureEvent {
+                        source,
+                        gesture: Gesture::Click,
+                        at: at + thresholds.double_click,
+                    });
+                    self.states.remove(i);
+                    continue;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        out
+    }
+
+    /// The earliest time at which [`poll()`](Self::poll) could report a
+    /// gesture, if any is in progress.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.states
+            .iter()
+            .filter_map(|&(source, state)| {
+                let thresholds = self.thresholds(source);
+                match state {
+                    State::Down {
+                        since,
+                        long_fired: false,
+                        ..
+                    } => Some(since + thresholds.long_press),
+                    State::Clicked { at } => Some(at + thresholds.double_click),
+                    State::Down { .. } => None,
+                }
+            })
+            .min()
+    }
+
+    fn thresholds(&self, source: PressSource) -> GestureThresholds {
+        self.overrides
+            .iter()
+            .find(|(s, _)| *s == source)
+            .map_or(self.defaults, |(_, t)| *t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A1: PressSource = PressSource::Button(Button::ButtonA1);
+
+    fn press(at: Instant, pressed: bool) -> TimedEvent {
+        TimedEvent {
+            at,
+            event: AutomapEvent::Button {
+                button: Button::ButtonA1,
+                pressed,
+            },
+        }
+    }
+
+    fn gestures(events: &[GestureEvent]) -> Vec<Gesture> {
+        events.iter().map(|e| e.gesture).collect()
+    }
+
+    #[test]
+    fn test_click_and_double_click() {
+        let t0 = Instant::now();
+        let ms = Duration::from_millis;
+        let mut g = ButtonGestures::default();
+
+        assert!(g.process(&press(t0, true)).is_empty());
+        assert!(g.process(&press(t0 + ms(50), false)).is_empty());
+        assert_eq!(g.next_deadline(), Some(t0 + ms(350)));
+        assert_eq!(gestures(&g.poll(t0 + ms(400))), vec![Gesture::Click]);
+
+        g.process(&press(t0 + ms(1000), true));
+        g.process(&press(t0 + ms(1050), false));
+        g.process(&press(t0 + ms(1200), true));
+        let out = g.process(&press(t0 + ms(1250), false));
+        assert_eq!(gestures(&out), vec![Gesture::DoubleClick]);
+        assert_eq!(g.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_long_press_and_hold() {
+        let t0 = Instant::now();
+        let ms = Duration::from_millis;
+        let fast = GestureThresholds {
+            double_click: Duration::ZERO,
+            long_press: ms(200),
+        };
+        let mut g = ButtonGestures::default().with_thresholds(A1, fast);
+
+        g.process(&press(t0, true));
+        assert_eq!(gestures(&g.poll(t0 + ms(250))), vec![Gesture::LongPress]);
+        let out = g.process(&press(t0 + ms(600), false));
+        assert_eq!(gestures(&out), vec![Gesture::Hold(ms(600))]);
+
+        g.process(&press(t0 + ms(700), true));
+        let out = g.process(&press(t0 + ms(750), false));
+        assert_eq!(gestures(&out), vec![Gesture::Click]);
+    }
+}
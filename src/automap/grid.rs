@@ -0,0 +1,279 @@
+//! The A to D button rows as a grid, for clip launchers and step entry.
+//!
+//! A [`Grid`] numbers the 32 buttons by row and column, row 0 being A and
+//! column 0 the leftmost, turns their presses into [`GridEvent`]s and keeps
+//! the LED of every cell: off, on or flashing. The unit cannot flash a
+//! button LED by itself, so the grid switches flashing cells on and off in
+//! time, all in step; like the debouncer it needs
+//! [`led_commands()`](Grid::led_commands) called again at
+//! [`next_deadline()`](Grid::next_deadline).
+//!
+//! ```
+//! # use std::time::Instant;
+//! # use automap::{AutomapCommand, Button, CellLed, Grid};
+//! let mut clips = Grid::<4, 8>::new();
+//! clips[(1, 2)] = CellLed::On;
+//! // The first call sets every LED of the grid, later ones what changed
+//! let cmds = clips.led_commands(Instant::now());
+//! assert_eq!(cmds.len(), 32);
+//! clips.clear(1, 2);
+//! let cmds = clips.led_commands(Instant::now());
+//! assert_eq!(cmds, [AutomapCommand::ButtonLed { button: Button::ButtonB3, on: false }]);
+//! ```
+
+use std::ops::{Index, IndexMut};
+use std::time::{Duration, Instant};
+
+use crate::automap::cc::Button;
+use crate::automap::command::AutomapCommand;
+use crate::automap::event::AutomapEvent;
+use crate::automap::leds::LedState;
+
+/// Buttons in each row of the surface.
+const ROW_LEN: usize = 8;
+
+/// What a cell's LED shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CellLed {
+    #[default]
+    Off,
+    On,
+    /// On and off in turn, half the [flash period](Grid::flash_period)
+    /// each.
+    Flash,
+}
+
+/// A press or release of a grid button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridEvent {
+    pub row: usize,
+    pub col: usize,
+    pub pressed: bool,
+}
+
+/// The first `ROWS` button rows, `COLS` buttons from the left of each.
+/// `Grid<4, 8>` is all of them.
+#[derive(Debug, Clone)]
+pub struct Grid<const ROWS: usize, const COLS: usize> {
+    cells: [[CellLed; COLS]; ROWS],
+    held: [[bool; COLS]; ROWS],
+    flash_period: Duration,
+    /// Flashing cells are lit for the first half of each period since then.
+    epoch: Instant,
+    /// Button LEDs as sent by `led_commands()`.
+    shown: LedState,
+}
+
+impl<const ROWS: usize, const COLS: usize> Default for Grid<ROWS, COLS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> Grid<ROWS, COLS> {
+    /// Every cell off, flashing twice a second.
+    ///
+    /// A grid larger than the surface's four rows of eight buttons does
+    /// not compile.
+    pub fn new() -> Self {
+        const {
+            assert!(
+                ROWS <= 4 && COLS <= ROW_LEN,
+                "the surface has 4 rows of 8 buttons"
+            )
+        };
+        Grid {
+            cells: [[CellLed::Off; COLS]; ROWS],
+            held: [[false; COLS]; ROWS],
+            flash_period: Duration::from_millis(500),
+            epoch: Instant::now(),
+            shown: LedState::default(),
+        }
+    }
+
+    /// How long a flashing cell takes to go on and off once.
+    pub fn flash_period(mut self, period: Duration) -> Self {
+        self.flash_period = period.max(Duration::from_millis(2));
+        self
+    }
+
+    /// The button at `row` and `col`, if the grid has that cell.
+    pub fn button(row: usize, col: usize) -> Option<Button> {
+        if row >= ROWS || col >= COLS {
+            return None;
+        }
+        Button::try_from(Button::ButtonA1 as u8 + (row * ROW_LEN + col) as u8).ok()
+    }
+
+    /// The row and column of `button`, if it is in the grid.
+    pub fn cell_of(button: Button) -> Option<(usize, usize)> {
+        let i = usize::from(button as u8 - Button::ButtonA1 as u8);
+        let (row, col) = (i / ROW_LEN, i % ROW_LEN);
+        (row < ROWS && col < COLS).then_some((row, col))
+    }
+
+    /// Sets the LED of a cell. Cells outside the grid are ignored.
+    pub fn set(&mut self, row: usize, col: usize, led: CellLed) {
+        if let Some(cell) = self.cells.get_mut(row).and_then(|r| r.get_mut(col)) {
+            *cell = led;
+        }
+    }
+
+    /// Switches a cell's LED off.
+    pub fn clear(&mut self, row: usize, col: usize) {
+        self.set(row, col, CellLed::Off);
+    }
+
+    /// Starts a cell's LED flashing.
+    pub fn flash(&mut self, row: usize, col: usize) {
+        self.set(row, col, CellLed::Flash);
+    }
+
+    /// Switches every LED off.
+    pub fn clear_all(&mut self) {
+        self.cells = [[CellLed::Off; COLS]; ROWS];
+    }
+
+    /// Whether a cell's button is held down, as far as
+    /// [`handle()`](Self::handle) has seen.
+    pub fn is_held(&self, row: usize, col: usize) -> bool {
+        self.held
+            .get(row)
+            .and_then(|r| r.get(col))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// The grid event for a press or release of one of its buttons, or
+    /// `None` for any other event.
+    pub fn handle(&mut self, event: &AutomapEvent) -> Option<GridEvent> {
+        let AutomapEvent::Button { button, pressed } = *event else {
+            return None;
+        };
+        let (row, col) = Self::cell_of(button)?;
+        self.held[row][col] = pressed;
+        Some(GridEvent { row, col, pressed })
+    }
+
+    /// Commands bringing the LEDs up to date at `now`, flashing cells
+    /// included. Only what changed since the last call is returned.
+    pub fn led_commands(&mut self, now: Instant) -> Vec<AutomapCommand> {
+        let lit = self.flash_lit(now);
+        let mut target = self.shown.clone();
+        for (row, cells) in self.cells.iter().enumerate() {
+            for (col, led) in cells.iter().enumerate() {
+                let button = Self::button(row, col).expect("cell in grid");
+                let on = match led {
+                    CellLed::Off => false,
+                    CellLed::On => true,
+                    CellLed::Flash => lit,
+                };
+                target.apply(&AutomapCommand::ButtonLed { button, on });
+            }
+        }
+        let out = self.shown.commands_to(&target);
+        self.shown = target;
+        out
+    }
+
+    /// When flashing cells next change, or `None` if none flash.
+    pub fn next_deadline(&self, now: Instant) -> Option<Instant> {
+        if !self
+            .cells
+            .iter()
+            .flatten()
+            .any(|&led| led == CellLed::Flash)
+        {
+            return None;
+        }
+        let half = self.flash_period / 2;
+        let into = now.saturating_duration_since(self.epoch).as_nanos() % half.as_nanos();
+        Some(now + half - Duration::from_nanos(into as u64))
+    }
+
+    /// Whether flashing cells are lit at `now`.
+    fn flash_lit(&self, now: Instant) -> bool {
+        let period = self.flash_period.as_nanos();
+        now.saturating_duration_since(self.epoch).as_nanos() % period < period / 2
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> Index<(usize, usize)> for Grid<ROWS, COLS> {
+    type Output = CellLed;
+
+    /// The LED of the cell at `(row, col)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cell is outside the grid.
+    fn index(&self, (row, col): (usize, usize)) -> &CellLed {
+        &self.cells[row][col]
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> IndexMut<(usize, usize)> for Grid<ROWS, COLS> {
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut CellLed {
+        &mut self.cells[row][col]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_cells_and_flashing() {
+        assert_eq!(Grid::<4, 8>::button(3, 7), Some(Button::ButtonD8));
+        assert_eq!(Grid::<2, 4>::button(2, 0), None);
+        assert_eq!(Grid::<2, 4>::cell_of(Button::ButtonB4), Some((1, 3)));
+        assert_eq!(Grid::<2, 4>::cell_of(Button::ButtonB5), None);
+
+        let mut grid = Grid::<2, 4>::new().flash_period(Duration::from_millis(100));
+        let press = AutomapEvent::Button {
+            button: Button::ButtonA2,
+            pressed: true,
+        };
+        assert_eq!(
+            grid.handle(&press),
+            Some(GridEvent {
+                row: 0,
+                col: 1,
+                pressed: true
+            })
+        );
+        assert!(grid.is_held(0, 1));
+        assert_eq!(
+            grid.handle(&AutomapEvent::Button {
+                button: Button::ButtonC1,
+                pressed: true
+            }),
+            None
+        );
+
+        let start = grid.epoch;
+        assert_eq!(grid.next_deadline(start), None);
+        grid.flash(0, 1);
+        grid[(1, 0)] = CellLed::On;
+        let led = |button, on| AutomapCommand::ButtonLed { button, on };
+        let first = grid.led_commands(start);
+        assert!(first.contains(&led(Button::ButtonA2, true)));
+        assert!(first.contains(&led(Button::ButtonB1, true)));
+        assert!(
+            grid.led_commands(start + Duration::from_millis(20))
+                .is_empty()
+        );
+
+        let off = grid
+            .next_deadline(start + Duration::from_millis(20))
+            .unwrap();
+        assert_eq!(off, start + Duration::from_millis(50));
+        assert_eq!(grid.led_commands(off), [led(Button::ButtonA2, false)]);
+
+        grid.clear_all();
+        assert_eq!(
+            grid.led_commands(off + Duration::from_millis(50)),
+            [led(Button::ButtonB1, false)]
+        );
+        assert_eq!(grid[(0, 1)], CellLed::Off);
+    }
+}
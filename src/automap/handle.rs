@@ -0,0 +1,156 @@
+//! Sending to the device from more than one task.
+
+use std::sync::Arc;
+
+use crate::automap::cc::{Encoder, EncoderPosition, RingMode};
+use crate::automap::command::AutomapCommand;
+use crate::automap::device::Outbox;
+use crate::automap::leds::LedState;
+use crate::automap::session::SessionState;
+use crate::automap::simulator::Simulator;
+use crate::automap::sysex::{AutomapSysEx, DbSimMsg};
+
+/// A cheap, cloneable sender for an open device, from
+/// [`AutomapDevice::handle()`](crate::AutomapDevice::handle).
+///
+/// Handles only write. Events are still read through the device itself,
+/// or through its [subscriptions](crate::AutomapDevice::subscribe), so a
+/// typical setup moves the device into a reader task and hands a clone of
+/// the handle to every component that drives LEDs or the LCD.
+#[derive(Clone)]
+pub struct AutomapHandle {
+    outbox: Arc<Outbox>,
+}
+
+impl AutomapHandle {
+    pub(crate) fn new(outbox: Arc<Outbox>) -> Self {
+        Self { outbox }
+    }
+
+    /// Sends a command, as [`AutomapDevice::send_command`](crate::AutomapDevice::send_command).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the USB write fails.
+    pub async fn send_command(&self, cmd: &AutomapCommand) -> Result<(), std::io::Error> {
+        self.outbox.send_command(cmd).await
+    }
+
+    /// Sends several commands in one USB transfer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the USB write fails.
+    pub async fn send_commands(&self, cmds: &[AutomapCommand]) -> Result<(), std::io::Error> {
+        self.outbox.send_commands(cmds).await
+    }
+
+    /// Shows `position` on an encoder ring in `mode`, as
+    /// [`AutomapDevice::set_ring`](crate::AutomapDevice::set_ring).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the USB write fails.
+    pub async fn set_ring(
+        &self,
+        encoder: Encoder,
+        mode: RingMode,
+        position: EncoderPosition,
+    ) -> Result<(), std::io::Error> {
+        self.outbox.set_ring(encoder, mode, position).await
+    }
+
+    /// Sends a SysEx message, as [`AutomapDevice::send_sysex`](crate::AutomapDevice::send_sysex).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the USB write fails.
+    pub async fn send_sysex(&self, msg: AutomapSysEx<'_>) -> Result<(), std::io::Error> {
+        self.outbox.send_sysex(msg).await
+    }
+
+    /// Sends a Data-Block or Simulation message.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the USB write fails.
+    pub async fn send_dbsim(&self, msg: &DbSimMsg<'_>) -> Result<(), std::io::Error> {
+        self.outbox.write_midi(&msg.to_bytes()).await
+    }
+
+    /// LED and ring states sent through the device and all its handles.
+    pub fn leds(&self) -> LedState {
+        self.outbox.leds()
+    }
+
+    /// Where the device's session stands; LED and LCD output fails unless
+    /// it is online.
+    pub fn session_state(&self) -> SessionState {
+        self.outbox.session.state()
+    }
+
+    /// Plays simulated keys on the unit, as
+    /// [`AutomapDevice::simulator`](crate::AutomapDevice::simulator).
+    pub fn simulator(&self) -> Simulator {
+        Simulator::new(self.clone())
+    }
+
+    /// A sender for MIDI OUT `port`, 1 or 2, as
+    /// [`AutomapDevice::midi_out`](crate::AutomapDevice::midi_out).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `port` is not 1 or 2.
+    pub fn midi_out(&self, port: u8) -> MidiOut {
+        assert!(
+            (1..=2).contains(&port),
+            "the unit has MIDI OUT 1 and 2, got {port}"
+        );
+        MidiOut {
+            outbox: self.outbox.clone(),
+            cable: self.outbox.midi_out_cables[usize::from(port) - 1],
+        }
+    }
+}
+
+/// Sends MIDI out of one of the unit's MIDI OUT sockets, from
+/// [`AutomapHandle::midi_out()`].
+///
+/// Messages go out as they are, whatever the session state, so the unit
+/// can stand in for a MIDI interface.
+#[derive(Clone)]
+pub struct MidiOut {
+    outbox: Arc<Outbox>,
+    cable: u8,
+}
+
+impl MidiOut {
+    /// Sends whole MIDI messages, any number of them in one go. SysEx
+    /// frames are spaced like the device's own, by its
+    /// [`Pacing`](crate::Pacing).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the USB write fails, or `NotConnected` while the
+    /// device is recovering.
+    pub async fn send(&self, midi: &[u8]) -> Result<(), std::io::Error> {
+        self.outbox.write_cable(self.cable, midi).await
+    }
+
+    /// The USB-MIDI cable the messages go out on; see
+    /// [`DeviceConfig::midi_out_cables()`](crate::DeviceConfig::midi_out_cables).
+    pub fn cable(&self) -> u8 {
+        self.cable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle_can_move_between_tasks() {
+        fn assert_send_sync<T: Send + Sync + Clone>() {}
+        assert_send_sync::<AutomapHandle>();
+    }
+}
@@ -0,0 +1,322 @@
+//! Playing the part of Novation's Automap Server.
+//!
+//! With the official software gone, the unit still expects what the server
+//! did: tell it the host is online (Section 11, PDF page 20), keep the link
+//! alive, send LCD text only while the Automap template is loaded, and go
+//! offline again on the way out, after which the unit shows "Automap is
+//! OFFLINE". [`AutomapHost`] wraps an [`AutomapDevice`] and does all that
+//! around a list of [`HostPage`]s:
+//!
+//! - When the user loads one of the unit's own templates, it reports going
+//!   offline and the host stops drawing. When they come back to Automap,
+//!   it reports going online and the host redraws the LCD and the LEDs in
+//!   full, since the template will have changed both.
+//! - The heartbeat is the device's [keep-alive](DeviceConfig::keep_alive)
+//!   echo; [`AutomapHost::config()`] turns it on, and a missed beat shows
+//!   up as [`SessionState::Degraded`](crate::SessionState::Degraded).
+//! - The left-hand page buttons step through the pages. The left display
+//!   shows the page's parameter names above the encoders and their values
+//!   below; the right display shows the page title and number on top and
+//!   a free [status line](AutomapHost::set_status) underneath.
+//!
+//! Events are passed on unchanged, page buttons included, so the app can
+//! act on them too.
+
+use std::time::Duration;
+
+use crate::automap::cc::PageButton;
+use crate::automap::config::DeviceConfig;
+use crate::automap::device::AutomapDevice;
+use crate::automap::event::AutomapEvent;
+use crate::automap::lcd::{Align, LCD_CELL, LCD_COLUMNS, LcdBuffer, LcdScreen, fit};
+use crate::automap::leds::LedState;
+use crate::automap::params::BANK_SIZE;
+use crate::automap::surface::pad_cell;
+use crate::automap::sysex::LcdLine;
+
+/// Interval of the keep-alive echo in [`AutomapHost::config()`].
+pub const HOST_HEARTBEAT: Duration = Duration::from_secs(2);
+
+/// One page of up to eight parameters, one per encoder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostPage {
+    /// Shown on the right display, e.g. the plug-in's name.
+    pub title: String,
+    pub names: Vec<String>,
+    /// Value text of each parameter, as the app last set it.
+    pub values: Vec<String>,
+}
+
+impl HostPage {
+    pub fn new(title: impl Into<String>) -> Self {
+        HostPage {
+            title: title.into(),
+            names: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+
+    /// Adds a parameter with no value shown yet. Names past the eighth are
+    /// not shown.
+    pub fn param(mut self, name: impl Into<String>) -> Self {
+        self.names.push(name.into());
+        self
+    }
+
+    /// Sets the value shown under encoder `slot`, counting from 0.
+    pub fn set_value(&mut self, slot: usize, text: impl Into<String>) {
+        if self.values.len() <= slot {
+            self.values.resize(slot + 1, String::new());
+        }
+        self.values[slot] = text.into();
+    }
+}
+
+/// The host side of the Automap protocol, around one open device.
+pub struct AutomapHost {
+    device: AutomapDevice,
+    pages: Vec<HostPage>,
+    page: usize,
+    status: String,
+    lcd: LcdBuffer,
+    leds: LedState,
+    /// Whether the unit was taking output when last looked at.
+    online: bool,
+    /// Set when the unit comes back online, so the next flush sends every
+    /// LED and not only those the device's shadow says are wrong.
+    redraw_leds: bool,
+}
+
+impl AutomapHost {
+    /// A configuration that behaves like the server did: the
+    /// [handshake](DeviceConfig::handshake) on opening, a
+    /// [keep-alive](DeviceConfig::keep_alive) every [`HOST_HEARTBEAT`], and
+    /// the surface cleared again on closing.
+    pub fn config() -> DeviceConfig {
+        DeviceConfig::new()
+            .handshake(true)
+            .auto_clear(true)
+            .keep_alive(HOST_HEARTBEAT)
+    }
+
+    /// Hosts `pages` on `device`, starting on the first. Nothing is sent
+    /// until [`start()`](Self::start).
+    pub fn new(device: AutomapDevice, pages: Vec<HostPage>) -> Self {
+        AutomapHost {
+            device,
+            pages,
+            page: 0,
+            status: String::new(),
+            lcd: LcdBuffer::new(),
+            leds: LedState::default(),
+            online: false,
+            redraw_leds: true,
+        }
+    }
+
+    /// Tells the unit the host is online and draws the first page.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB write fails.
+    pub async fn start(&mut self) -> Result<(), std::io::Error> {
+        self.device.enter_automap_mode().await?;
+        self.online = true;
+        self.lcd.invalidate();
+        self.redraw_leds = true;
+        self.flush().await
+    }
+
+    pub fn device(&mut self) -> &mut AutomapDevice {
+        &mut self.device
+    }
+
+    pub fn pages(&self) -> &[HostPage] {
+        &self.pages
+    }
+
+    /// The page shown, counting from 0.
+    pub fn page(&self) -> usize {
+        self.page
+    }
+
+    /// Shows page `page`, returning whether it exists. Nothing is sent
+    /// until the next [`flush()`](Self::flush).
+    pub fn set_page(&mut self, page: usize) -> bool {
+        let exists = page < self.pages.len();
+        if exists {
+            self.page = page;
+        }
+        exists
+    }
+
+    pub fn page_mut(&mut self, page: usize) -> Option<&mut HostPage> {
+        self.pages.get_mut(page)
+    }
+
+    /// Sets the right display's bottom line.
+    pub fn set_status(&mut self, text: impl Into<String>) {
+        self.status = text.into();
+    }
+
+    /// The LEDs and rings the host wants lit. They are sent on the next
+    /// [`flush()`](Self::flush), and again whenever the unit comes back
+    /// online.
+    pub fn leds_mut(&mut self) -> &mut LedState {
+        &mut self.leds
+    }
+
+    /// Whether the unit is in Automap mode and taking output.
+    pub fn is_online(&self) -> bool {
+        self.device.session_state().is_online()
+    }
+
+    /// Reads the next events, follows the page buttons and the unit going
+    /// on and offline, and sends what changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB read or write fails.
+    pub async fn next_events(&mut self) -> Result<Vec<AutomapEvent>, std::io::Error> {
+        let events = self.device.read_events().await?;
+        for event in &events {
+            self.follow_page_buttons(event);
+        }
+        self.flush().await?;
+        Ok(events)
+    }
+
+    fn follow_page_buttons(&mut self, event: &AutomapEvent) {
+        let page = match *event {
+            AutomapEvent::PageButton {
+                button: PageButton::PageUpL,
+                pressed: true,
+            } => self.page.checked_sub(1),
+            AutomapEvent::PageButton {
+                button: PageButton::PageDnL,
+                pressed: true,
+            } => Some(self.page + 1),
+            _ => None,
+        };
+        if let Some(page) = page {
+            self.set_page(page);
+        }
+    }
+
+    /// Sends the LCD text and LEDs that differ from what the unit shows.
+    ///
+    /// While a template of the unit's own is loaded nothing is sent; the
+    /// whole surface is sent once it is back in Automap mode.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB write fails.
+    pub async fn flush(&mut self) -> Result<(), std::io::Error> {
+        let online = self.is_online();
+        if online && !self.online {
+            self.lcd.invalidate();
+            self.redraw_leds = true;
+        }
+        self.online = online;
+        if !online {
+            return Ok(());
+        }
+
+        render_page(
+            self.lcd.back(),
+            self.pages.get(self.page),
+            self.page,
+            self.pages.len(),
+            &self.status,
+        );
+        if let Some(msg) = self.lcd.flush()
+            && let Err(e) = self.device.send_sysex(msg).await
+        {
+            self.lcd.invalidate();
+            return Err(e);
+        }
+        let from = if self.redraw_leds {
+            LedState::default()
+        } else {
+            self.device.leds()
+        };
+        for cmd in from.commands_to(&self.leds) {
+            self.device.send_command(&cmd).await?;
+        }
+        self.redraw_leds = false;
+        Ok(())
+    }
+
+    /// Closes the device, which clears the surface and tells the unit the
+    /// host went offline.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB write fails.
+    pub async fn shutdown(self) -> Result<(), std::io::Error> {
+        self.device.close().await
+    }
+}
+
+/// Draws `page`, page `index` of `count`, and the status line into `lcd`.
+fn render_page(
+    lcd: &mut LcdScreen,
+    page: Option<&HostPage>,
+    index: usize,
+    count: usize,
+    status: &str,
+) {
+    let none = Vec::new();
+    let (names, values) = page.map_or((&none, &none), |p| (&p.names, &p.values));
+    for slot in 0..BANK_SIZE {
+        let col = slot * LCD_CELL;
+        let cell =
+            |texts: &Vec<String>| pad_cell(texts.get(slot).map_or("", String::as_str), Align::Left);
+        lcd.write(LcdLine::LeftTop, col, &cell(names));
+        lcd.write(LcdLine::LeftBottom, col, &cell(values));
+    }
+
+    let number = if count > 0 {
+        format!("{}/{count}", index + 1)
+    } else {
+        String::new()
+    };
+    let title = page.map_or("", |p| p.title.as_str());
+    let width = LCD_COLUMNS - number.len() - 1;
+    lcd.write(LcdLine::RightTop, 0, &fit(title, width, Align::Left));
+    lcd.write(
+        LcdLine::RightTop,
+        width,
+        &fit(&number, number.len() + 1, Align::Right),
+    );
+    lcd.write(
+        LcdLine::RightBottom,
+        0,
+        &fit(status, LCD_COLUMNS, Align::Left),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_layout() {
+        let mut page = HostPage::new("Reverb").param("Size").param("Decay");
+        page.set_value(1, "2.4 s");
+        let mut lcd = LcdScreen::default();
+        render_page(&mut lcd, Some(&page), 1, 3, "Octave +1");
+
+        let line = |l| String::from_utf8(lcd.line(l).to_vec()).unwrap();
+        assert!(line(LcdLine::LeftTop).starts_with("Size     Decay    "));
+        assert!(line(LcdLine::LeftBottom).starts_with("         2.4 s    "));
+        assert!(line(LcdLine::RightTop).starts_with("Reverb "));
+        assert!(line(LcdLine::RightTop).ends_with(" 2/3"));
+        assert_eq!(line(LcdLine::RightBottom).trim_end(), "Octave +1");
+
+        // No pages at all blanks the surface
+        render_page(&mut lcd, None, 0, 0, "");
+        assert!(lcd.line(LcdLine::LeftTop).iter().all(|&b| b == b' '));
+        assert!(lcd.line(LcdLine::RightTop).iter().all(|&b| b == b' '));
+    }
+}
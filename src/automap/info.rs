@@ -0,0 +1,98 @@
+//! Which unit a device is talking to, returned by
+//! [`AutomapDevice::info()`](crate::AutomapDevice::info).
+//!
+//! Several units of the same model look alike on the bus; the serial
+//! number tells them apart across reconnects, and the bus and port chain
+//! tell apart units without one.
+
+use std::fmt;
+
+use crate::automap::capabilities::Model;
+
+/// Where a unit is on the USB bus and what it runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// USB serial number, if the unit reports one.
+    pub serial: Option<String>,
+    /// Identifier of the bus, as the operating system names it.
+    pub bus_id: String,
+    /// Address on the bus. It changes when the unit is replugged.
+    pub address: u8,
+    /// Hub ports from the root hub down to the unit, which stay the same
+    /// as long as it is plugged into the same socket.
+    pub port_chain: Vec<u8>,
+    pub product_id: u16,
+    /// USB `bcdDevice` of the unit, e.g. `0x0102` for firmware 1.02.
+    pub firmware_version: u16,
+    /// Main and beta protocol version bytes of the first SysEx frame the
+    /// unit sent, `None` until it has sent one.
+    pub protocol_version: Option<(u8, u8)>,
+    /// What the [handshake](crate::DeviceConfig::handshake) or
+    /// [`capabilities()`](crate::AutomapDevice::capabilities) found,
+    /// `None` before either has run.
+    pub model: Option<Model>,
+}
+
+impl DeviceInfo {
+    pub(crate) fn from_usb(info: &nusb::DeviceInfo) -> Self {
+        DeviceInfo {
+            serial: info.serial_number().map(str::to_owned),
+            bus_id: info.bus_id().to_owned(),
+            address: info.device_address(),
+            port_chain: info.port_chain().to_vec(),
+            product_id: info.product_id(),
+            firmware_version: info.device_version(),
+            protocol_version: None,
+            model: None,
+        }
+    }
+
+    /// The bus and port chain, e.g. `1-2.3`, as Linux names USB devices.
+    pub fn location(&self) -> String {
+        if self.port_chain.is_empty() {
+            return self.bus_id.clone();
+        }
+        let ports: Vec<String> = self.port_chain.iter().map(u8::to_string).collect();
+        format!("{}-{}", self.bus_id, ports.join("."))
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.model {
+            Some(model) => write!(f, "{model:?}")?,
+            None => write!(f, "unit")?,
+        }
+        if let Some(serial) = &self.serial {
+            write!(f, " {serial}")?;
+        }
+        write!(
+            f,
+            " at {}, firmware {:x}.{:02x}",
+            self.location(),
+            self.firmware_version >> 8,
+            self.firmware_version & 0xFF
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_location_and_display() {
+        let info = DeviceInfo {
+            serial: Some("ABC123".to_string()),
+            bus_id: "1".to_string(),
+            address: 7,
+            port_chain: vec![2, 3],
+            product_id: 0x000C,
+            firmware_version: 0x0102,
+            protocol_version: None,
+            model: Some(Model::ZeroMkII),
+        };
+        assert_eq!(info.location(), "1-2.3");
+        assert_eq!(info.to_string(), "ZeroMkII ABC123 at 1-2.3, firmware 1.02");
+    }
+}
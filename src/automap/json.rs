@@ -0,0 +1,520 @@
+//! A JSON message format for driving the unit from web dashboards and other
+//! non-Rust clients.
+//!
+//! Every message is one flat JSON object with a `"type"` field. Events go
+//! out as e.g. `{"type":"button","button":"ButtonA1","pressed":true}`;
+//! clients send back [`JsonRequest`]s such as
+//! `{"type":"button_led","button":"ButtonA1","on":true}` or
+//! `{"type":"lcd_text","line":"LeftTop","col":0,"text":"Hello"}`. Controls
+//! are named as in the Rust enums.
+//!
+//! The format is transport-agnostic: carry it over WebSocket text frames,
+//! lines on a TCP socket, or anything else. A server that requires
+//! authentication should accept nothing but [`JsonRequest::Auth`] until the
+//! client has sent the right token; see [`JsonRequest::authorizes()`].
+
+use std::fmt::{self, Debug, Write};
+
+use crate::automap::cc::{
+    Button, Encoder, EncoderPosition, RingMode, RowSelect, RowSelectLhSet, RowSelectRhSet,
+};
+use crate::automap::command::AutomapCommand;
+use crate::automap::event::{AutomapEvent, ParameterResponse, Wheel};
+use crate::automap::sysex::LcdLine;
+
+/// A request from a client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonRequest {
+    /// `{"type":"auth","token":"..."}`
+    Auth { token: String },
+    /// An LED, ring or other CC command.
+    Command(AutomapCommand),
+    /// `{"type":"lcd_text","line":"LeftTop","col":0,"text":"..."}`
+    LcdText {
+        line: LcdLine,
+        col: u8,
+        text: String,
+    },
+}
+
+impl JsonRequest {
+    /// Whether this is an [`Auth`](Self::Auth) request with `token`.
+    ///
+    /// Compares in constant time, so the token cannot be guessed byte by
+    /// byte from response times.
+    pub fn authorizes(&self, token: &str) -> bool {
+        let JsonRequest::Auth { token: given } = self else {
+            return false;
+        };
+        given.len() == token.len()
+            && given
+                .bytes()
+                .zip(token.bytes())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+/// Why a client message was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonError {
+    /// Not a flat JSON object.
+    Syntax,
+    /// No `"type"` field, or one this format does not know.
+    UnknownType(String),
+    /// A field is missing or has the wrong type or an unknown value.
+    BadField(&'static str),
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::Syntax => write!(f, "malformed JSON object"),
+            JsonError::UnknownType(ty) => write!(f, "unknown message type `{ty}`"),
+            JsonError::BadField(name) => write!(f, "missing or invalid field `{name}`"),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+}
+
+/// Encodes an event as a JSON object.
+pub fn event_to_json(event: &AutomapEvent) -> String {
+    let (ty, fields) = event_fields(event);
+    let mut out = format!("{{\"type\":\"{ty}\"");
+    for (key, value) in fields {
+        let _ = write!(out, ",\"{key}\":");
+        match value {
+            Value::Str(s) => write_string(&mut out, &s),
+            Value::Int(n) => {
+                let _ = write!(out, "{n}");
+            }
+            Value::Bool(b) => {
+                let _ = write!(out, "{b}");
+            }
+        }
+    }
+    out.push('}');
+    out
+}
+
+/// The `"type"` of an event and its other fields, in order.
+pub(crate) fn event_fields(event: &AutomapEvent) -> (&'static str, Vec<(&'static str, Value)>) {
+    use Value::{Bool, Int};
+    let name = |v: &dyn Debug| Value::Str(format!("{v:?}"));
+    match *event {
+        AutomapEvent::Wheel {
+            wheel: Wheel::Mod { value },
+        } => ("mod_wheel", vec![("value", Int(value.into()))]),
+        AutomapEvent::Wheel {
+            wheel: Wheel::Pitch { value },
+        } => ("pitch_wheel", vec![("value", Int(value.into()))]),
+        AutomapEvent::Button { button, pressed } => (
+            "button",
+            vec![("button", name(&button)), ("pressed", Bool(pressed))],
+        ),
+        AutomapEvent::TransportButton { button, pressed } => (
+            "transport_button",
+            vec![("button", name(&button)), ("pressed", Bool(pressed))],
+        ),
+        AutomapEvent::AutomapButton { button, pressed } => (
+            "automap_button",
+            vec![("button", name(&button)), ("pressed", Bool(pressed))],
+        ),
+        AutomapEvent::Encoder { encoder, clicks } => (
+            "encoder",
+            vec![("encoder", name(&encoder)), ("clicks", Int(clicks.into()))],
+        ),
+        AutomapEvent::Pot { pot, value } => (
+            "pot",
+            vec![("pot", name(&pot)), ("value", Int(value.into()))],
+        ),
+        AutomapEvent::Slider { slider, value } => (
+            "slider",
+            vec![("slider", name(&slider)), ("value", Int(value.into()))],
+        ),
+        AutomapEvent::RowSelect { row, selected } => (
+            "row_select",
+            vec![("row", name(&row)), ("selected", Bool(selected))],
+        ),
+        AutomapEvent::LiveEncoder { encoder, clicks } => (
+            "live_encoder",
+            vec![("encoder", name(&encoder)), ("clicks", Int(clicks.into()))],
+        ),
+        AutomapEvent::EncoderRowSelect { selected } => {
+            ("encoder_row_select", vec![("selected", Bool(selected))])
+        }
+        AutomapEvent::RowLhBitmap { bits } => ("row_lh_bitmap", vec![("bits", Int(bits.into()))]),
+        AutomapEvent::RowRhBitmap { bits } => ("row_rh_bitmap", vec![("bits", Int(bits.into()))]),
+        AutomapEvent::EncoderTouch { encoder, touched } => (
+            "encoder_touch",
+            vec![("encoder", name(&encoder)), ("touched", Bool(touched))],
+        ),
+        AutomapEvent::PotTouch { pot, touched } => (
+            "pot_touch",
+            vec![("pot", name(&pot)), ("touched", Bool(touched))],
+        ),
+        AutomapEvent::SliderTouch { slider, touched } => (
+            "slider_touch",
+            vec![("slider", name(&slider)), ("touched", Bool(touched))],
+        ),
+        AutomapEvent::CrossFadeTouch { touched } => {
+            ("crossfade_touch", vec![("touched", Bool(touched))])
+        }
+        AutomapEvent::SpeedDialTouch { touched } => {
+            ("speed_dial_touch", vec![("touched", Bool(touched))])
+        }
+        AutomapEvent::PageButton { button, pressed } => (
+            "page_button",
+            vec![("button", name(&button)), ("pressed", Bool(pressed))],
+        ),
+        AutomapEvent::SustainPedal { pressed } => {
+            ("sustain_pedal", vec![("pressed", Bool(pressed))])
+        }
+        AutomapEvent::ExpressionPedal { value } => {
+            ("expression_pedal", vec![("value", Int(value.into()))])
+        }
+        AutomapEvent::CrossFader { value } => ("crossfader", vec![("value", Int(value.into()))]),
+        AutomapEvent::TouchpadX1 { value } => ("touchpad_x1", vec![("value", Int(value.into()))]),
+        AutomapEvent::TouchpadY1 { value } => ("touchpad_y1", vec![("value", Int(value.into()))]),
+        AutomapEvent::TouchpadX2 { value } => ("touchpad_x2", vec![("value", Int(value.into()))]),
+        AutomapEvent::TouchpadY2 { value } => ("touchpad_y2", vec![("value", Int(value.into()))]),
+        AutomapEvent::Alert { alert_type } => ("alert", vec![("alert", name(&alert_type))]),
+        AutomapEvent::SpeedDial { clicks } => ("speed_dial", vec![("clicks", Int(clicks.into()))]),
+        AutomapEvent::SpeedDialButton { pressed } => {
+            ("speed_dial_button", vec![("pressed", Bool(pressed))])
+        }
+        AutomapEvent::PreviewButton { pressed } => {
+            ("preview_button", vec![("pressed", Bool(pressed))])
+        }
+        AutomapEvent::TransportLockStatus { enabled } => {
+            ("transport_lock", vec![("enabled", Bool(enabled))])
+        }
+        AutomapEvent::TempoMsb { value } => ("tempo_msb", vec![("value", Int(value.into()))]),
+        AutomapEvent::TempoLsb { value } => ("tempo_lsb", vec![("value", Int(value.into()))]),
+        AutomapEvent::EchoResponse { value } => ("echo", vec![("value", Int(value.into()))]),
+        AutomapEvent::ParameterResponse { response } => (
+            "parameter_response",
+            vec![("response", Int(response.into()))],
+        ),
+        AutomapEvent::Parameter {
+            response: ParameterResponse::ProductType(product),
+        } => ("product_type", vec![("product", name(&product))]),
+        AutomapEvent::Parameter {
+            response: ParameterResponse::TransportLock(enabled),
+        } => ("transport_lock", vec![("enabled", Bool(enabled))]),
+        AutomapEvent::TemplateChanged { special } => {
+            ("template_changed", vec![("special", Bool(special))])
+        }
+        AutomapEvent::Raw { cc, value } => (
+            "raw",
+            vec![("cc", Int(cc.into())), ("value", Int(value.into()))],
+        ),
+    }
+}
+
+/// Parses a client request.
+///
+/// # Errors
+///
+/// Fails on malformed JSON, an unknown `"type"`, or a missing or invalid
+/// field.
+pub fn parse_request(json: &str) -> Result<JsonRequest, JsonError> {
+    request_from_fields(&parse_object(json).ok_or(JsonError::Syntax)?)
+}
+
+/// Builds a request from the fields of a message, `"type"` included.
+pub(crate) fn request_from_fields(fields: &[(String, Value)]) -> Result<JsonRequest, JsonError> {
+    let get = |key: &'static str| {
+        fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+            .ok_or(JsonError::BadField(key))
+    };
+    let string = |key| match get(key)? {
+        Value::Str(s) => Ok(s.as_str()),
+        _ => Err(JsonError::BadField(key)),
+    };
+    let boolean = |key| match get(key)? {
+        Value::Bool(b) => Ok(*b),
+        _ => Err(JsonError::BadField(key)),
+    };
+    let byte = |key| match get(key)? {
+        Value::Int(n) => u8::try_from(*n)
+            .ok()
+            .filter(|n| *n < 0x80)
+            .ok_or(JsonError::BadField(key)),
+        _ => Err(JsonError::BadField(key)),
+    };
+
+    let ty = string("type").map_err(|_| JsonError::UnknownType(String::new()))?;
+    let cmd = match ty {
+        "auth" => {
+            return Ok(JsonRequest::Auth {
+                token: string("token")?.to_owned(),
+            });
+        }
+        "lcd_text" => {
+            let name = string("line")?;
+            let line = LcdLine::ALL
+                .into_iter()
+                .find(|l| format!("{l:?}") == name)
+                .ok_or(JsonError::BadField("line"))?;
+            return Ok(JsonRequest::LcdText {
+                line,
+                col: byte("col")?,
+                text: string("text")?.to_owned(),
+            });
+        }
+        "button_led" => AutomapCommand::ButtonLed {
+            button: lookup::<Button>("button", string("button")?, 0x18..=0x37)?,
+            on: boolean("on")?,
+        },
+        "row_select_led" => AutomapCommand::RowSelectLed {
+            row: lookup::<RowSelect>("row", string("row")?, 0x50..=0x57)?,
+            on: boolean("on")?,
+        },
+        "ring_mode" => AutomapCommand::EncoderRingMode {
+            encoder: lookup::<Encoder>("encoder", string("encoder")?, 0x78..=0x7F)?,
+            mode: lookup::<RingMode>("mode", string("mode")?, (0x00..=0x40).step_by(0x10))?,
+        },
+        "ring_value" => AutomapCommand::EncoderRingValue {
+            encoder: lookup::<Encoder>("encoder", string("encoder")?, 0x78..=0x7F)?,
+            position: *EncoderPosition::ALL
+                .get(usize::from(byte("position")?))
+                .ok_or(JsonError::BadField("position"))?,
+        },
+        "row_lh_bitmap" => AutomapCommand::RowLhBitmap {
+            rows: RowSelectLhSet::from_bits_truncate(byte("bits")?),
+        },
+        "row_rh_bitmap" => AutomapCommand::RowRhBitmap {
+            rows: RowSelectRhSet::from_bits_truncate(byte("bits")?),
+        },
+        "transport_lock" => AutomapCommand::TransportLockSet {
+            enabled: boolean("enabled")?,
+        },
+        "all_leds_off" => AutomapCommand::AllLedsOff,
+        "echo" => AutomapCommand::EchoRequest {
+            value: byte("value")?,
+        },
+        other => return Err(JsonError::UnknownType(other.to_owned())),
+    };
+    Ok(JsonRequest::Command(cmd))
+}
+
+/// Finds the control in `codes` whose enum variant is called `name`.
+fn lookup<T: TryFrom<u8> + Debug>(
+    key: &'static str,
+    name: &str,
+    codes: impl IntoIterator<Item = u8>,
+) -> Result<T, JsonError> {
+    codes
+        .into_iter()
+        .filter_map(|code| T::try_from(code).ok())
+        .find(|control| format!("{control:?}") == name)
+        .ok_or(JsonError::BadField(key))
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c < ' ' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Parses `{"key": value, ...}` where values are strings, integers or
+/// booleans. Nested objects and arrays are not part of the format.
+fn parse_object(json: &str) -> Option<Vec<(String, Value)>> {
+    let mut p = Parser {
+        s: json.trim().as_bytes(),
+        i: 0,
+    };
+    p.eat(b'{')?;
+    let mut fields = Vec::new();
+    if !p.try_eat(b'}') {
+        loop {
+            let key = p.string()?;
+            p.eat(b':')?;
+            let value = p.value()?;
+            fields.push((key, value));
+            if p.try_eat(b'}') {
+                break;
+            }
+            p.eat(b',')?;
+        }
+    }
+    (p.i == p.s.len()).then_some(fields)
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    i: usize,
+}
+
+impl Parser<'_> {
+    fn skip_ws(&mut self) {
+        while self.s.get(self.i).is_some_and(u8::is_ascii_whitespace) {
+            self.i += 1;
+        }
+    }
+
+    fn try_eat(&mut self, b: u8) -> bool {
+        self.skip_ws();
+        let hit = self.s.get(self.i) == Some(&b);
+        if hit {
+            self.i += 1;
+        }
+        hit
+    }
+
+    fn eat(&mut self, b: u8) -> Option<()> {
+        self.try_eat(b).then_some(())
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        self.skip_ws();
+        match *self.s.get(self.i)? {
+            b'"' => self.string().map(Value::Str),
+            b't' | b'f' => {
+                let rest = &self.s[self.i..];
+                let (len, v) = if rest.starts_with(b"true") {
+                    (4, true)
+                } else if rest.starts_with(b"false") {
+                    (5, false)
+                } else {
+                    return None;
+                };
+                self.i += len;
+                Some(Value::Bool(v))
+            }
+            _ => {
+                let start = self.i;
+                if self.s[self.i] == b'-' {
+                    self.i += 1;
+                }
+                while self.s.get(self.i).is_some_and(u8::is_ascii_digit) {
+                    self.i += 1;
+                }
+                std::str::from_utf8(&self.s[start..self.i])
+                    .ok()?
+                    .parse()
+                    .ok()
+                    .map(Value::Int)
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.eat(b'"')?;
+        let mut out = Vec::new();
+        loop {
+            match *self.s.get(self.i)? {
+                b'"' => {
+                    self.i += 1;
+                    return String::from_utf8(out).ok();
+                }
+                b'\\' => {
+                    let esc = *self.s.get(self.i + 1)?;
+                    self.i += 2;
+                    let c = match esc {
+                        b'"' | b'\\' | b'/' => esc as char,
+                        b'n' => '\n',
+                        b't' => '\t',
+                        b'r' => '\r',
+                        b'u' => {
+                            let hex = std::str::from_utf8(self.s.get(self.i..self.i + 4)?).ok()?;
+                            self.i += 4;
+                            char::from_u32(u32::from_str_radix(hex, 16).ok()?)?
+                        }
+                        _ => return None,
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                b => {
+                    out.push(b);
+                    self.i += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_json() {
+        let event = AutomapEvent::Button {
+            button: Button::ButtonA1,
+            pressed: true,
+        };
+        assert_eq!(
+            event_to_json(&event),
+            r#"{"type":"button","button":"ButtonA1","pressed":true}"#
+        );
+        let event = AutomapEvent::SpeedDial { clicks: -3 };
+        assert_eq!(
+            event_to_json(&event),
+            r#"{"type":"speed_dial","clicks":-3}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_requests() {
+        let req = parse_request(r#" { "type": "button_led", "button": "ButtonB3", "on": true } "#);
+        assert_eq!(
+            req,
+            Ok(JsonRequest::Command(AutomapCommand::ButtonLed {
+                button: Button::ButtonB3,
+                on: true
+            }))
+        );
+        let req =
+            parse_request(r#"{"type":"ring_mode","encoder":"Encoder2","mode":"CenteredBand"}"#);
+        assert!(matches!(
+            req,
+            Ok(JsonRequest::Command(AutomapCommand::EncoderRingMode {
+                encoder: Encoder::Encoder2,
+                mode: RingMode::CenteredBand
+            }))
+        ));
+        let req =
+            parse_request(r#"{"type":"lcd_text","line":"RightTop","col":9,"text":"Mix \"A\""}"#);
+        assert_eq!(
+            req,
+            Ok(JsonRequest::LcdText {
+                line: LcdLine::RightTop,
+                col: 9,
+                text: "Mix \"A\"".into()
+            })
+        );
+
+        assert_eq!(
+            parse_request(r#"{"type":"button_led","button":"Nope","on":true}"#),
+            Err(JsonError::BadField("button"))
+        );
+        assert_eq!(parse_request("{\"type\":"), Err(JsonError::Syntax));
+        assert!(
+            parse_request(r#"{"type":"auth","token":"s3cret"}"#)
+                .unwrap()
+                .authorizes("s3cret")
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Round-trip latency statistics gathered from echo pings.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Number of samples kept in the rolling window.
+pub const LATENCY_WINDOW: usize = 32;
+
+/// Rolling statistics over the most recent echo round-trips.
+///
+/// Samples older than [`LATENCY_WINDOW`] pings are discarded, so the figures
+/// track the current health of the link rather than its lifetime average.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyStats {
+    samples: VecDeque<Duration>,
+    sent: u64,
+    timeouts: u64,
+}
+
+impl LatencyStats {
+    /// Records a successful round-trip.
+    pub fn record(&mut self, rtt: Duration) {
+        if self.samples.len() == LATENCY_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(rtt);
+        self.sent += 1;
+    }
+
+    /// Records a ping that got no answer in time.
+    pub fn record_timeout(&mut self) {
+        self.sent += 1;
+        self.timeouts += 1;
+    }
+
+    /// Most recent round-trip time.
+    pub fn last(&self) -> Option<Duration> {
+        self.samples.back().copied()
+    }
+
+    /// Fastest round-trip in the window.
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.iter().min().copied()
+    }
+
+    /// Slowest round-trip in the window.
+    pub fn max(&self) -> Option<Duration> {
+        self.samples.iter().max().copied()
+    }
+
+    /// Mean round-trip over the window.
+    pub fn mean(&self) -> Option<Duration> {
+        let n = self.samples.len() as u32;
+        (n > 0).then(|| self.samples.iter().sum::<Duration>() / n)
+    }
+
+    /// Spread between the slowest and fastest round-trip in the window.
+    ///
+    /// A jitter that grows while the mean stays flat is a good hint that the
+    /// USB bus is saturated by other traffic.
+    pub fn jitter(&self) -> Option<Duration> {
+        Some(self.max()? - self.min()?)
+    }
+
+    /// Number of samples currently in the window.
+    pub fn samples(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Total pings sent, including those that timed out.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// Total pings that timed out.
+    pub fn timeouts(&self) -> u64 {
+        self.timeouts
+    }
+
+    /// Fraction of pings that timed out, in `0.0..=1.0`.
+    pub fn loss(&self) -> f32 {
+        if self.sent == 0 {
+            0.0
+        } else {
+            self.timeouts as f32 / self.sent as f32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_stats() {
+        let mut stats = LatencyStats::default();
+        assert_eq!(stats.mean(), None);
+
+        stats.record(Duration::from_millis(2));
+        stats.record(Duration::from_millis(4));
+        stats.record_timeout();
+
+        assert_eq!(stats.last(), Some(Duration::from_millis(4)));
+        assert_eq!(stats.min(), Some(Duration::from_millis(2)));
+        assert_eq!(stats.mean(), Some(Duration::from_millis(3)));
+        assert_eq!(stats.jitter(), Some(Duration::from_millis(2)));
+        assert_eq!(stats.sent(), 3);
+        assert_eq!(stats.timeouts(), 1);
+    }
+
+    #[test]
+    fn test_latency_window_rolls() {
+        let mut stats = LatencyStats::default();
+        stats.record(Duration::from_secs(1));
+        for _ in 0..LATENCY_WINDOW {
+            stats.record(Duration::from_millis(1));
+        }
+        assert_eq!(stats.samples(), LATENCY_WINDOW);
+        assert_eq!(stats.max(), Some(Duration::from_millis(1)));
+    }
+}
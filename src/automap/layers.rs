@@ -0,0 +1,213 @@
+//! Shift layers: holding a button switches what the surface shows and does.
+//!
+//! A [`LayerStack`] holds one [`Layer`] per mapping, layer 0 being the base.
+//! Each layer keeps its own LED shadow and LCD contents; the application
+//! updates inactive layers freely and calls
+//! [`AutomapDevice::show_layer()`](crate::AutomapDevice::show_layer) when
+//! [`process()`](LayerStack::process) reports a switch.
+//!
+//! Shift buttons are consumed by the stack. They should not also be
+//! [`ChordDetector`](crate::ChordDetector) modifiers.
+
+use std::time::Instant;
+
+use crate::automap::command::AutomapCommand;
+use crate::automap::gestures::PressSource;
+use crate::automap::lcd::LcdScreen;
+use crate::automap::leds::LedState;
+use crate::automap::timed::TimedEvent;
+
+/// What one mapping layer shows on the surface.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Layer {
+    pub leds: LedState,
+    pub lcd: LcdScreen,
+}
+
+/// What the stack passes on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerOutput {
+    /// An event to handle with the mapping of `layer`.
+    Event { layer: usize, event: TimedEvent },
+    /// The active layer changed; redraw the surface for `to`.
+    Switched { from: usize, to: usize, at: Instant },
+}
+
+/// Mapping layers selected by held shift buttons.
+#[derive(Debug, Clone)]
+pub struct LayerStack {
+    layers: Vec<Layer>,
+    shifts: Vec<(PressSource, usize)>,
+    /// Layers whose shift button is down, oldest first.
+    held: Vec<usize>,
+    /// Layer each held control was pressed on, so its release goes there too.
+    pressed_on: Vec<(PressSource, usize)>,
+}
+
+impl Default for LayerStack {
+    fn default() -> Self {
+        LayerStack {
+            layers: vec![Layer::default()],
+            shifts: Vec::new(),
+            held: Vec::new(),
+            pressed_on: Vec::new(),
+        }
+    }
+}
+
+impl LayerStack {
+    /// A stack with only the base layer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a layer active while `shift` is held, returning its index.
+    pub fn add_layer(&mut self, shift: PressSource, layer: Layer) -> usize {
+        self.layers.push(layer);
+        let index = self.layers.len() - 1;
+        self.shifts.retain(|(s, _)| *s != shift);
+        self.shifts.push((shift, index));
+        index
+    }
+
+    /// Index of the layer currently shown.
+    ///
+    /// With several shift buttons down, the most recently pressed wins.
+    pub fn active(&self) -> usize {
+        self.held.last().copied().unwrap_or(0)
+    }
+
+    /// A layer by index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of range.
+    pub fn layer(&self, index: usize) -> &Layer {
+        &self.layers[index]
+    }
+
+    /// A layer by index, for updating its LEDs or labels.
+    ///
+    /// Changes to the active layer only reach the surface once sent; see
+    /// [`apply()`](Self::apply).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of range.
+    pub fn layer_mut(&mut self, index: usize) -> &mut Layer {
+        &mut self.layers[index]
+    }
+
+    /// Records an LED command on `layer`.
+    ///
+    /// Returns `true` if the layer is active, i.e. the command should also be
+    /// sent to the device now.
+    pub fn apply(&mut self, layer: usize, cmd: &AutomapCommand) -> bool {
+        self.layers[layer].leds.apply(cmd);
+        layer == self.active()
+    }
+
+    /// Feeds one event, routing it to a layer or switching layers.
+    pub fn process(&mut self, event: TimedEvent) -> Vec<LayerOutput> {
+        let Some((source, pressed)) = PressSource::from_event(&event.event) else {
+            return vec![LayerOutput::Event {
+                layer: self.active(),
+                event,
+            }];
+        };
+
+        if let Some(&(_, index)) = self.shifts.iter().find(|(s, _)| *s == source) {
+            let from = self.active();
+            self.held.retain(|&l| l != index);
+            if pressed {
+                self.held.push(index);
+            }
+            let to = self.active();
+            return if from == to {
+                Vec::new()
+            } else {
+                vec![LayerOutput::Switched {
+                    from,
+                    to,
+                    at: event.at,
+                }]
+            };
+        }
+
+        let layer = if pressed {
+            let layer = self.active();
+            self.pressed_on.retain(|(s, _)| *s != source);
+            self.pressed_on.push((source, layer));
+            layer
+        } else {
+            match self.pressed_on.iter().position(|(s, _)| *s == source) {
+                Some(i) => self.pressed_on.remove(i).1,
+                None => self.active(),
+            }
+        };
+        vec![LayerOutput::Event { layer, event }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::cc::Button;
+    use crate::automap::event::AutomapEvent;
+
+    const SHIFT: PressSource = PressSource::Button(Button::ButtonA8);
+
+    fn press(button: Button, pressed: bool) -> TimedEvent {
+        TimedEvent {
+            at: Instant::now(),
+            event: AutomapEvent::Button { button, pressed },
+        }
+    }
+
+    fn layers(out: &[LayerOutput]) -> Vec<usize> {
+        out.iter()
+            .map(|o| match *o {
+                LayerOutput::Event { layer, .. } => layer,
+                LayerOutput::Switched { to, .. } => 100 + to,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_shift_switches_and_restores() {
+        let mut stack = LayerStack::new();
+        let shifted = stack.add_layer(SHIFT, Layer::default());
+        assert_eq!(shifted, 1);
+
+        assert_eq!(layers(&stack.process(press(Button::ButtonA1, true))), [0]);
+        assert_eq!(layers(&stack.process(press(Button::ButtonA8, true))), [101]);
+        // A1 went down on the base layer, so its release goes there too
+        assert_eq!(layers(&stack.process(press(Button::ButtonA1, false))), [0]);
+        assert_eq!(layers(&stack.process(press(Button::ButtonB1, true))), [1]);
+        assert_eq!(
+            layers(&stack.process(press(Button::ButtonA8, false))),
+            [100]
+        );
+        assert_eq!(layers(&stack.process(press(Button::ButtonB1, false))), [1]);
+        assert_eq!(stack.active(), 0);
+    }
+
+    #[test]
+    fn test_apply_reports_visibility() {
+        let mut stack = LayerStack::new();
+        let shifted = stack.add_layer(SHIFT, Layer::default());
+        let led = AutomapCommand::ButtonLed {
+            button: Button::ButtonB2,
+            on: true,
+        };
+
+        assert!(!stack.apply(shifted, &led));
+        assert_eq!(
+            stack.layer(shifted).leds.button(Button::ButtonB2),
+            Some(true)
+        );
+        assert_eq!(stack.layer(0).leds.button(Button::ButtonB2), None);
+        stack.process(press(Button::ButtonA8, true));
+        assert!(stack.apply(shifted, &led));
+    }
+}
@@ -0,0 +1,465 @@
+//! Control layouts written as CSV, for authoring templates without Rust.
+//!
+//! Each line names one control by its row and column, then what it sends:
+//!
+//! ```text
+//! # row,     column, name,    type, channel, number
+//! encoders,  1,      Cutoff,  cc,   1,       74
+//! encoders,  2,      Reso,    cc,   1,       71
+//! sliders,   1,      Vol 1,   cc,   common,  7
+//! buttons_a, 1,      Kick,    note, 10,      36
+//! pots,      8,      Fine,    nrpn, 2,       1025,  0, 16383
+//! ```
+//!
+//! The rows are those of the template (SL Template Offsets, controls 1 to
+//! 64): `encoders`, `pots`, `sliders`, `buttons_a` to `buttons_d` and
+//! `drumpads`, with columns 1 to 8. The type is one of `spare`, `cc`,
+//! `nrpn`, `rpn`, `note`, `note_off`, `bank_select`, `program`,
+//! `pitch_bend` and `drum_note`. The channel is 1 to 16, `common` or
+//! `keyboard`. The number is the CC, note or parameter number, and may be
+//! left empty for types that have none. Two more columns give the low and
+//! high values, 0 and 127 when left out. Blank lines, lines starting with
+//! `#` and a header line starting with `row` are skipped; fields are not
+//! quoted, so names cannot hold commas.
+//!
+//! A [`Layout`] is written over a [`Template`], which keeps everything it
+//! does not set, or turned into a [`Translator`] doing the same on the host
+//! while the unit is in Automap mode. Errors name the file and line.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::automap::template::{ChannelSpec, ControlType, PortRoute, Template};
+use crate::automap::translator::{MidiTarget, Source, Translator};
+
+/// Offsets in a control's data, from the SL Control Members document.
+const CN_NAME: std::ops::Range<usize> = 0x00..0x08;
+const CN_TYPE: usize = 0x08;
+const CN_LOW: usize = 0x09;
+const CN_HIGH: usize = 0x0B;
+const CN_NUMBER: usize = 0x10;
+const CN_CHANNEL: usize = 0x13;
+
+/// A row of eight controls in a template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Row {
+    Encoders,
+    Pots,
+    Sliders,
+    ButtonsA,
+    ButtonsB,
+    ButtonsC,
+    ButtonsD,
+    Drumpads,
+}
+
+impl Row {
+    const ALL: [(Row, &'static str); 8] = [
+        (Row::Encoders, "encoders"),
+        (Row::Pots, "pots"),
+        (Row::Sliders, "sliders"),
+        (Row::ButtonsA, "buttons_a"),
+        (Row::ButtonsB, "buttons_b"),
+        (Row::ButtonsC, "buttons_c"),
+        (Row::ButtonsD, "buttons_d"),
+        (Row::Drumpads, "drumpads"),
+    ];
+
+    fn parse(s: &str) -> Option<Row> {
+        Self::ALL
+            .iter()
+            .find(|(_, name)| *name == s)
+            .map(|&(row, _)| row)
+    }
+
+    /// The template control number, 1-based, of `column` (1 to 8).
+    pub fn control_number(self, column: u8) -> u8 {
+        self as u8 * 8 + column
+    }
+
+    /// The surface control at `column`, for a [`Translator`]. The drumpads
+    /// send notes the unit does not report in Automap mode, so they have
+    /// none.
+    pub fn source(self, column: u8) -> Option<Source> {
+        let i = column - 1;
+        Some(match self {
+            Row::Encoders => Source::Encoder((0x78 + i).try_into().ok()?),
+            Row::Pots => Source::Pot((0x08 + i).try_into().ok()?),
+            Row::Sliders => Source::Slider((0x10 + i).try_into().ok()?),
+            Row::ButtonsA | Row::ButtonsB | Row::ButtonsC | Row::ButtonsD => {
+                let group = self as u8 - Row::ButtonsA as u8;
+                Source::Button((0x18 + group * 8 + i).try_into().ok()?)
+            }
+            Row::Drumpads => return None,
+        })
+    }
+}
+
+/// One control of a [`Layout`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutControl {
+    pub row: Row,
+    /// 1 to 8.
+    pub column: u8,
+    /// Up to 8 printable ASCII characters.
+    pub name: String,
+    pub control_type: ControlType,
+    pub channel: ChannelSpec,
+    /// CC, note or parameter number; 0 for types that have none.
+    pub number: u16,
+    /// 14-bit low and high values.
+    pub low: u16,
+    pub high: u16,
+    /// Line of the file it came from, 1-based.
+    pub line: usize,
+}
+
+impl LayoutControl {
+    /// Writes the control over `data`, one control's worth of a template.
+    fn write(&self, data: &mut [u8]) {
+        let mut name = [b' '; 8];
+        name[..self.name.len()].copy_from_slice(self.name.as_bytes());
+        data[CN_NAME].copy_from_slice(&name);
+        data[CN_TYPE] = self.control_type as u8;
+        for (at, value) in [
+            (CN_LOW, self.low),
+            (CN_HIGH, self.high),
+            (CN_NUMBER, self.number),
+        ] {
+            data[at] = (value >> 7) as u8;
+            data[at + 1] = (value & 0x7F) as u8;
+        }
+        data[CN_CHANNEL] = self.channel.to_byte();
+    }
+
+    /// What the control sends, on `default_channel` if it takes the common
+    /// or keyboard channel. `None` for types a [`MidiTarget`] cannot send.
+    fn target(&self, default_channel: u8) -> Option<MidiTarget> {
+        let channel = match self.channel {
+            ChannelSpec::Channel(n) => n,
+            ChannelSpec::Common | ChannelSpec::Keyboard => default_channel,
+        };
+        let number = self.number;
+        Some(match self.control_type {
+            ControlType::CC => MidiTarget::Cc {
+                channel,
+                cc: number as u8,
+            },
+            ControlType::NoteOn | ControlType::DrumNote => MidiTarget::Note {
+                channel,
+                note: number as u8,
+            },
+            ControlType::NRPN => MidiTarget::Nrpn {
+                channel,
+                param: number,
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// Why a layout was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutErrorKind {
+    /// The file could not be read.
+    Io(std::io::ErrorKind),
+    /// A line without the six required fields, or with more than eight.
+    Fields {
+        found: usize,
+    },
+    UnknownRow(String),
+    /// Not a number from 1 to 8.
+    BadColumn(String),
+    /// Longer than 8 characters, or not printable ASCII.
+    BadName(String),
+    UnknownType(String),
+    BadChannel(String),
+    /// A number, low or high value out of range for the type.
+    BadNumber(String),
+    /// The row and column were already used on `first_line`.
+    Duplicate {
+        first_line: usize,
+    },
+}
+
+/// A [`LayoutErrorKind`] and where it was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutError {
+    /// The file, if the layout was [loaded](Layout::load) from one.
+    pub file: Option<PathBuf>,
+    /// 1-based, or 0 for errors about the whole file.
+    pub line: usize,
+    pub kind: LayoutErrorKind,
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), 0) => write!(f, "{}: ", file.display())?,
+            (Some(file), line) => write!(f, "{}:{line}: ", file.display())?,
+            (None, 0) => {}
+            (None, line) => write!(f, "line {line}: ")?,
+        }
+        match &self.kind {
+            LayoutErrorKind::Io(kind) => write!(f, "cannot read layout: {kind}"),
+            LayoutErrorKind::Fields { found } => {
+                write!(f, "expected 6 to 8 fields, found {found}")
+            }
+            LayoutErrorKind::UnknownRow(row) => write!(f, "unknown row `{row}`"),
+            LayoutErrorKind::BadColumn(col) => write!(f, "column `{col}` is not 1 to 8"),
+            LayoutErrorKind::BadName(name) => {
+                write!(f, "name `{name}` is not up to 8 printable ASCII characters")
+            }
+            LayoutErrorKind::UnknownType(ty) => write!(f, "unknown control type `{ty}`"),
+            LayoutErrorKind::BadChannel(ch) => {
+                write!(f, "channel `{ch}` is not 1 to 16, `common` or `keyboard`")
+            }
+            LayoutErrorKind::BadNumber(n) => write!(f, "`{n}` is out of range here"),
+            LayoutErrorKind::Duplicate { first_line } => {
+                write!(f, "control already defined on line {first_line}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+/// Controls read from a layout file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Layout {
+    pub controls: Vec<LayoutControl>,
+}
+
+impl Layout {
+    /// Reads a layout from `path`; errors carry the path.
+    pub fn load(path: impl AsRef<Path>) -> Result<Layout, LayoutError> {
+        let path = path.as_ref();
+        let with_file = |mut e: LayoutError| {
+            e.file = Some(path.to_owned());
+            e
+        };
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            with_file(LayoutError {
+                file: None,
+                line: 0,
+                kind: LayoutErrorKind::Io(e.kind()),
+            })
+        })?;
+        Self::parse(&text).map_err(with_file)
+    }
+
+    /// Parses the text of a layout file.
+    pub fn parse(text: &str) -> Result<Layout, LayoutError> {
+        let mut layout = Layout::default();
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("row") {
+                continue;
+            }
+            let err = |kind| LayoutError {
+                file: None,
+                line: line_no,
+                kind,
+            };
+            let control = parse_line(line, line_no).map_err(err)?;
+            if let Some(first) = layout
+                .controls
+                .iter()
+                .find(|c| c.row == control.row && c.column == control.column)
+            {
+                return Err(err(LayoutErrorKind::Duplicate {
+                    first_line: first.line,
+                }));
+            }
+            layout.controls.push(control);
+        }
+        Ok(layout)
+    }
+
+    /// Writes every control over `template`, leaving the rest of each
+    /// control's data (attributes, ports, display type) as it was.
+    pub fn apply(&self, template: &mut Template) {
+        for control in &self.controls {
+            let cn = control.row.control_number(control.column);
+            // Rows and columns are checked while parsing
+            if let Some(data) = template.control_mut(cn) {
+                control.write(data);
+            }
+        }
+    }
+
+    /// A translator sending what each control sends in the layout, to
+    /// USB port 1. Controls on the common or keyboard channel use
+    /// `default_channel`; drumpads, and types other than CCs, notes and
+    /// NRPNs, are left out.
+    pub fn translator(&self, default_channel: u8) -> Translator {
+        self.controls
+            .iter()
+            .filter_map(|c| Some((c.row.source(c.column)?, c.target(default_channel)?)))
+            .fold(Translator::new(), |t, (source, target)| {
+                t.map(source, PortRoute::Usb1, target)
+            })
+    }
+}
+
+fn parse_line(line: &str, line_no: usize) -> Result<LayoutControl, LayoutErrorKind> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    if !(6..=8).contains(&fields.len()) {
+        return Err(LayoutErrorKind::Fields {
+            found: fields.len(),
+        });
+    }
+    let row = Row::parse(fields[0]).ok_or_else(|| LayoutErrorKind::UnknownRow(fields[0].into()))?;
+    let column = fields[1]
+        .parse()
+        .ok()
+        .filter(|c| (1..=8).contains(c))
+        .ok_or_else(|| LayoutErrorKind::BadColumn(fields[1].into()))?;
+    let name = fields[2];
+    if name.len() > 8 || !name.bytes().all(|b| (0x20..0x7F).contains(&b)) {
+        return Err(LayoutErrorKind::BadName(name.into()));
+    }
+    let (control_type, number_max, has_number) = match fields[3] {
+        "spare" => (ControlType::Spare, 0, false),
+        "cc" => (ControlType::CC, 127, true),
+        "nrpn" => (ControlType::NRPN, 16383, true),
+        "rpn" => (ControlType::RPN, 16383, true),
+        "note" => (ControlType::NoteOn, 127, true),
+        "note_off" => (ControlType::NoteOff, 127, true),
+        "bank_select" => (ControlType::BankSelect, 16383, false),
+        "program" => (ControlType::ProgChange, 127, false),
+        "pitch_bend" => (ControlType::PitchBend, 0, false),
+        "drum_note" => (ControlType::DrumNote, 127, true),
+        other => return Err(LayoutErrorKind::UnknownType(other.into())),
+    };
+    let channel = match fields[4] {
+        "common" => ChannelSpec::Common,
+        "keyboard" => ChannelSpec::Keyboard,
+        ch => ch
+            .parse()
+            .ok()
+            .filter(|n| (1..=16).contains(n))
+            .map(ChannelSpec::Channel)
+            .ok_or_else(|| LayoutErrorKind::BadChannel(ch.into()))?,
+    };
+    let value = |s: &str, max: u16, default: u16| {
+        if s.is_empty() {
+            return Ok(default);
+        }
+        s.parse()
+            .ok()
+            .filter(|&n| n <= max)
+            .ok_or_else(|| LayoutErrorKind::BadNumber(s.into()))
+    };
+    let number = match fields[5] {
+        "" if !has_number => 0,
+        "" => return Err(LayoutErrorKind::BadNumber(String::new())),
+        n => value(n, number_max, 0)?,
+    };
+    let low = value(fields.get(6).copied().unwrap_or(""), 16383, 0)?;
+    let high = value(fields.get(7).copied().unwrap_or(""), 16383, 127)?;
+    Ok(LayoutControl {
+        row,
+        column,
+        name: name.into(),
+        control_type,
+        channel,
+        number,
+        low,
+        high,
+        line: line_no,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::cc::{Button, Encoder, Pot};
+    use crate::automap::event::AutomapEvent;
+    use crate::automap::template::HEADER_LEN;
+
+    const LAYOUT: &str = "\
+row, column, name, type, channel, number, low, high
+# the mixer
+encoders, 1, Cutoff, cc, 1, 74
+buttons_b, 2, Kick, note, 10, 36
+
+pots, 8, Fine, nrpn, common, 1025, 0, 16383
+";
+
+    #[test]
+    fn test_parse_apply_and_translate() {
+        let layout = Layout::parse(LAYOUT).unwrap();
+        assert_eq!(layout.controls.len(), 3);
+        assert_eq!(layout.controls[2].line, 6);
+
+        let mut data = vec![0; usize::from(HEADER_LEN)];
+        data[..8].copy_from_slice(b"Mixer   ");
+        let mut template = Template::from_bytes(data).unwrap();
+        layout.apply(&mut template);
+        let cutoff = template.control(1).unwrap();
+        assert_eq!(&cutoff[..8], b"Cutoff  ");
+        assert_eq!(cutoff[CN_TYPE], ControlType::CC as u8);
+        assert_eq!(cutoff[CN_NUMBER..CN_NUMBER + 2], [0, 74]);
+        assert_eq!(cutoff[CN_CHANNEL], 0x40);
+        assert_eq!(cutoff[CN_HIGH..CN_HIGH + 2], [0, 127]);
+        let fine = template.control(16).unwrap();
+        assert_eq!(fine[CN_NUMBER..CN_NUMBER + 2], [8, 1]);
+        assert_eq!(fine[CN_HIGH..CN_HIGH + 2], [0x7F, 0x7F]);
+        assert_eq!(template.control(34).unwrap()[CN_TYPE], 6);
+
+        let translator = layout.translator(3);
+        assert_eq!(
+            translator.mapping(Source::Encoder(Encoder::Encoder1)),
+            Some((PortRoute::Usb1, MidiTarget::Cc { channel: 1, cc: 74 }))
+        );
+        assert_eq!(
+            translator.mapping(Source::Pot(Pot::Pot8)),
+            Some((
+                PortRoute::Usb1,
+                MidiTarget::Nrpn {
+                    channel: 3,
+                    param: 1025
+                }
+            ))
+        );
+        let kick = AutomapEvent::Button {
+            button: Button::ButtonB2,
+            pressed: true,
+        };
+        assert_eq!(translator.translate(&kick).unwrap().bytes, [0x99, 36, 127]);
+    }
+
+    #[test]
+    fn test_errors_name_the_line() {
+        let err = |text| Layout::parse(text).unwrap_err();
+        assert_eq!(
+            err("encoders, 9, X, cc, 1, 1").to_string(),
+            "line 1: column `9` is not 1 to 8"
+        );
+        assert_eq!(
+            err("\nsliders, 1, Volume 10, cc, 1, 7").kind,
+            LayoutErrorKind::BadName("Volume 10".into())
+        );
+        assert_eq!(
+            err("pots, 1, A, cc, 1, 1\npots, 1, B, cc, 1, 2").kind,
+            LayoutErrorKind::Duplicate { first_line: 1 }
+        );
+        assert_eq!(
+            err("pots, 1, A, cc, 17, 1").kind,
+            LayoutErrorKind::BadChannel("17".into())
+        );
+        assert_eq!(
+            err("pots, 1, A, cc, 1, 128").kind,
+            LayoutErrorKind::BadNumber("128".into())
+        );
+        assert_eq!(
+            err("pots, 1, A, sysex, 1, 1").kind,
+            LayoutErrorKind::UnknownType("sysex".into())
+        );
+
+        let missing = Layout::load("/nonexistent/mixer.csv").unwrap_err();
+        assert!(missing.to_string().starts_with("/nonexistent/mixer.csv: "));
+    }
+}
@@ -0,0 +1,448 @@
+//! Host-side copy of the LCD contents.
+
+use crate::automap::sysex::{AutomapSysEx, LcdClear, LcdLine, LcdOp};
+
+/// Columns per LCD line (cursor positions `0..=71`).
+pub const LCD_COLUMNS: usize = 72;
+
+/// Number of LCD lines: top and bottom of the left and right display.
+pub const LCD_LINES: usize = 4;
+
+/// Columns of one label cell. A line holds eight, one above each column
+/// of controls.
+pub const LCD_CELL: usize = LCD_COLUMNS / 8;
+
+/// Where text sits in a field wider than itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Align {
+    #[default]
+    Left,
+    Right,
+    /// Centered, with the odd space on the right.
+    Center,
+}
+
+/// Marks text cut short by [`fit()`].
+const ELLIPSIS: &[u8] = b"..";
+
+/// `text` laid out in exactly `width` columns.
+///
+/// Text too long for the field is cut and ends in `..`, so a cut
+/// label does not pass for a whole one. Characters the LCD cannot show
+/// become `?`.
+pub fn fit(text: &str, width: usize, align: Align) -> Vec<u8> {
+    let mut bytes: Vec<u8> = text
+        .chars()
+        .map(|c| {
+            if (' '..='~').contains(&c) {
+                c as u8
+            } else {
+                b'?'
+            }
+        })
+        .collect();
+    if bytes.len() > width {
+        let keep = width.saturating_sub(ELLIPSIS.len());
+        bytes.truncate(keep);
+        bytes.extend(&ELLIPSIS[..width - keep]);
+    }
+    let spare = width - bytes.len();
+    let before = match align {
+        Align::Left => 0,
+        Align::Right => spare,
+        Align::Center => spare / 2,
+    };
+    let mut out = vec![b' '; width];
+    out[before..before + bytes.len()].copy_from_slice(&bytes);
+    out
+}
+
+/// `value` with `decimals` places and `unit`, laid out in `width` columns,
+/// e.g. `-6.0dB` or ` 440Hz`.
+///
+/// A number is never cut like text: if it does not fit, the unit is left
+/// out, then decimals are dropped, and a field too narrow even for the
+/// integer part is filled with `#`.
+pub fn format_number(
+    value: f64,
+    decimals: usize,
+    unit: &str,
+    width: usize,
+    align: Align,
+) -> Vec<u8> {
+    let with_unit = format!("{value:.decimals$}{unit}");
+    if with_unit.len() <= width {
+        return fit(&with_unit, width, align);
+    }
+    for places in (0..=decimals).rev() {
+        let bare = format!("{value:.places$}");
+        if bare.len() <= width {
+            return fit(&bare, width, align);
+        }
+    }
+    vec![b'#'; width]
+}
+
+/// Full text of both LCDs, one 72-character row per [`LcdLine`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LcdScreen {
+    lines: [[u8; LCD_COLUMNS]; LCD_LINES],
+}
+
+impl Default for LcdScreen {
+    fn default() -> Self {
+        LcdScreen {
+            lines: [[b' '; LCD_COLUMNS]; LCD_LINES],
+        }
+    }
+}
+
+impl LcdScreen {
+    /// Builds a screen from the text readback payload.
+    ///
+    /// The unit returns the lines in wire order (left top, right top, left
+    /// bottom, right bottom); a short payload leaves the rest blank.
+    pub fn from_bytes(text: &[u8]) -> LcdScreen {
+        let mut screen = LcdScreen::default();
+        for (line, chunk) in LcdLine::ALL.iter().zip(text.chunks(LCD_COLUMNS)) {
+            screen.write(*line, 0, chunk);
+        }
+        screen
+    }
+
+    /// The text in the readback format, the inverse of
+    /// [`from_bytes()`](Self::from_bytes).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        LcdLine::ALL
+            .iter()
+            .flat_map(|line| self.line(*line))
+            .copied()
+            .collect()
+    }
+
+    /// Text of one line.
+    pub fn line(&self, line: LcdLine) -> &[u8; LCD_COLUMNS] {
+        &self.lines[line as usize - 1]
+    }
+
+    /// Writes `text` at `col`, truncating at the end of the line.
+    ///
+    /// Control characters and bytes above 0x7E are stored as spaces so the
+    /// line can always be sent back as an LCD text op.
+    pub fn write(&mut self, line: LcdLine, col: usize, text: &[u8]) {
+        let row = &mut self.lines[line as usize - 1];
+        for (cell, &b) in row.iter_mut().skip(col).zip(text) {
+            *cell = if (0x20..0x7F).contains(&b) { b } else { b' ' };
+        }
+    }
+
+    /// Writes `text` in a field of `width` columns at `col`, laid out
+    /// with [`fit()`].
+    pub fn write_field(
+        &mut self,
+        line: LcdLine,
+        col: usize,
+        width: usize,
+        text: &str,
+        align: Align,
+    ) {
+        self.write(line, col, &fit(text, width, align));
+    }
+
+    /// Updates the screen as the unit would on receiving `ops`.
+    ///
+    /// The cursor starts at the left of the top-left line and moves past
+    /// each text op; ops the unit ignores leave the screen unchanged.
+    pub fn apply(&mut self, ops: &[LcdOp<'_>]) {
+        let (mut line, mut col) = (LcdLine::LeftTop, 0);
+        for op in ops {
+            match *op {
+                LcdOp::Cursor { col: c, line: l } => (line, col) = (l, c as usize),
+                LcdOp::Text(text) => {
+                    self.write(line, col, text);
+                    col += text.len();
+                }
+                LcdOp::Clear(LcdClear::FromCursorCount(n)) => {
+                    self.write(line, col, &vec![b' '; n as usize]);
+                }
+                LcdOp::Clear(clear) => {
+                    for l in cleared_lines(clear) {
+                        self.lines[*l as usize - 1] = [b' '; LCD_COLUMNS];
+                    }
+                }
+                LcdOp::End => break,
+                LcdOp::CursorBlink(_) | LcdOp::Unknown(..) => {}
+            }
+        }
+    }
+
+    /// LCD ops that redraw every line of the screen.
+    pub fn to_ops(&self) -> Vec<LcdOp<'_>> {
+        let mut ops = Vec::with_capacity(LCD_LINES * 2 + 1);
+        for line in LcdLine::ALL {
+            ops.push(LcdOp::Cursor { col: 0, line });
+            ops.push(LcdOp::Text(self.line(line)));
+        }
+        ops.push(LcdOp::End);
+        ops
+    }
+
+    /// LCD ops that turn this screen into `target`, touching only the
+    /// characters that differ.
+    ///
+    /// Changed spans close together are sent as one text op, since a
+    /// cursor move and text framing cost more than a few unchanged
+    /// characters. A line that becomes blank is cleared, as is a longer
+    /// span of spaces. Empty if the screens are the same.
+    pub fn diff_ops<'a>(&self, target: &'a LcdScreen) -> Vec<LcdOp<'a>> {
+        let mut ops = Vec::new();
+        for line in LcdLine::ALL {
+            let (cur, want) = (self.line(line), target.line(line));
+            if cur == want {
+                continue;
+            }
+            if want.iter().all(|&b| b == b' ') {
+                ops.push(LcdOp::Clear(line_clear(line)));
+                continue;
+            }
+            for span in changed_spans(cur, want) {
+                ops.push(LcdOp::Cursor {
+                    col: span.start as u8,
+                    line,
+                });
+                let text = &want[span];
+                if text.len() > 2 && text.iter().all(|&b| b == b' ') {
+                    ops.push(LcdOp::Clear(LcdClear::FromCursorCount(text.len() as u8)));
+                } else {
+                    ops.push(LcdOp::Text(text));
+                }
+            }
+        }
+        if !ops.is_empty() {
+            ops.push(LcdOp::End);
+        }
+        ops
+    }
+}
+
+/// Unchanged characters bridged rather than starting a new span: a new
+/// span costs a 3-byte cursor op and 2 bytes of text framing.
+const SPAN_GAP: usize = 5;
+
+/// Column ranges where `want` differs from `cur`, with short gaps merged.
+fn changed_spans(cur: &[u8], want: &[u8]) -> Vec<std::ops::Range<usize>> {
+    let mut spans: Vec<std::ops::Range<usize>> = Vec::new();
+    for col in (0..want.len()).filter(|&col| cur[col] != want[col]) {
+        match spans.last_mut() {
+            Some(span) if col - span.end < SPAN_GAP => span.end = col + 1,
+            _ => spans.push(col..col + 1),
+        }
+    }
+    spans
+}
+
+fn line_clear(line: LcdLine) -> LcdClear {
+    match line {
+        LcdLine::LeftTop => LcdClear::LeftTopLine,
+        LcdLine::LeftBottom => LcdClear::LeftBottomLine,
+        LcdLine::RightTop => LcdClear::RightTopLine,
+        LcdLine::RightBottom => LcdClear::RightBottomLine,
+    }
+}
+
+/// A back buffer to draw into and a front buffer recording what the unit
+/// shows, so only the difference is sent.
+///
+/// ```
+/// use automap::{LcdBuffer, LcdLine};
+///
+/// # async fn run(device: &mut automap::AutomapDevice) -> std::io::Result<()> {
+/// let mut lcd = LcdBuffer::new();
+/// lcd.back().write(LcdLine::LeftTop, 0, b"Volume");
+/// if let Some(msg) = lcd.flush() {
+///     device.send_sysex(msg).await?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LcdBuffer {
+    back: LcdScreen,
+    /// `None` until the first flush, or after [`invalidate()`](Self::invalidate).
+    front: Option<LcdScreen>,
+}
+
+impl LcdBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The screen to draw into. Nothing is sent until [`flush()`](Self::flush).
+    pub fn back(&mut self) -> &mut LcdScreen {
+        &mut self.back
+    }
+
+    /// What the unit shows as of the last flush, if known.
+    pub fn front(&self) -> Option<&LcdScreen> {
+        self.front.as_ref()
+    }
+
+    /// The message that brings the unit up to the back buffer, from
+    /// [`diff_ops()`](LcdScreen::diff_ops), or `None` if nothing changed.
+    /// The first flush redraws every line.
+    ///
+    /// The back buffer counts as shown from here on; if sending the
+    /// message fails, [`invalidate()`](Self::invalidate) the buffer.
+    pub fn flush(&mut self) -> Option<AutomapSysEx<'_>> {
+        let front = self.front.replace(self.back.clone());
+        let ops = match front {
+            Some(front) => front.diff_ops(&self.back),
+            None => self.back.to_ops(),
+        };
+        (!ops.is_empty()).then_some(AutomapSysEx::LcdText(ops))
+    }
+
+    /// Forgets what the unit shows, so the next flush redraws everything,
+    /// e.g. after a failed send or after something else wrote to the LCD.
+    pub fn invalidate(&mut self) {
+        self.front = None;
+    }
+}
+
+/// Lines blanked by a whole-line clear.
+fn cleared_lines(clear: LcdClear) -> &'static [LcdLine] {
+    use LcdLine::*;
+    match clear {
+        LcdClear::BothDisplays => &LcdLine::ALL,
+        LcdClear::BothTopLines => &[LeftTop, RightTop],
+        LcdClear::BothBottomLines => &[LeftBottom, RightBottom],
+        LcdClear::LeftAll => &[LeftTop, LeftBottom],
+        LcdClear::RightAll => &[RightTop, RightBottom],
+        LcdClear::LeftTopLine => &[LeftTop],
+        LcdClear::LeftBottomLine => &[LeftBottom],
+        LcdClear::RightTopLine => &[RightTop],
+        LcdClear::RightBottomLine => &[RightBottom],
+        LcdClear::FromCursorCount(_) => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_screen_from_readback() {
+        let mut text = vec![b' '; LCD_COLUMNS * LCD_LINES];
+        text[27..45].copy_from_slice(b"Automap is OFFLINE");
+        text[LCD_COLUMNS] = 0x00;
+        let screen = LcdScreen::from_bytes(&text);
+
+        assert_eq!(
+            &screen.line(LcdLine::LeftTop)[27..45],
+            b"Automap is OFFLINE"
+        );
+        assert_eq!(screen.line(LcdLine::RightTop)[0], b' ');
+        assert_eq!(screen.to_ops().len(), 9);
+        assert_eq!(
+            screen.to_ops()[1],
+            LcdOp::Text(screen.line(LcdLine::LeftTop))
+        );
+    }
+
+    #[test]
+    fn test_diff_sends_only_changed_characters() {
+        let mut shown = LcdScreen::default();
+        shown.write(LcdLine::LeftTop, 0, b"Volume 64%");
+        shown.write(LcdLine::LeftTop, 30, b"Pan C");
+        shown.write(LcdLine::RightBottom, 0, b"Bye");
+        let mut next = shown.clone();
+        next.write(LcdLine::LeftTop, 8, b"5");
+        next.write(LcdLine::LeftTop, 30, b"     ");
+        next.write(LcdLine::LeftTop, 40, b"L20");
+        next.write(LcdLine::RightBottom, 0, b"   ");
+
+        let ops = shown.diff_ops(&next);
+        assert_eq!(
+            ops,
+            [
+                LcdOp::Cursor {
+                    col: 8,
+                    line: LcdLine::LeftTop
+                },
+                LcdOp::Text(b"5"),
+                LcdOp::Cursor {
+                    col: 30,
+                    line: LcdLine::LeftTop
+                },
+                LcdOp::Clear(LcdClear::FromCursorCount(5)),
+                LcdOp::Cursor {
+                    col: 40,
+                    line: LcdLine::LeftTop
+                },
+                LcdOp::Text(b"L20"),
+                LcdOp::Clear(LcdClear::RightBottomLine),
+                LcdOp::End,
+            ]
+        );
+        let mut applied = shown.clone();
+        applied.apply(&ops);
+        assert_eq!(applied, next);
+        assert!(next.diff_ops(&next).is_empty());
+    }
+
+    #[test]
+    fn test_buffer_flushes_changes_once() {
+        let mut lcd = LcdBuffer::new();
+        lcd.back().write(LcdLine::LeftTop, 0, b"Hi");
+        assert!(matches!(lcd.flush(), Some(AutomapSysEx::LcdText(ops)) if ops.len() == 9));
+        assert!(lcd.flush().is_none());
+        lcd.back().write(LcdLine::LeftTop, 1, b"o");
+        assert!(matches!(lcd.flush(), Some(AutomapSysEx::LcdText(ops)) if ops.len() == 3));
+        lcd.invalidate();
+        assert!(lcd.front().is_none());
+        assert!(lcd.flush().is_some());
+    }
+
+    #[test]
+    fn test_fit_fields() {
+        assert_eq!(fit("Vol", 6, Align::Left), b"Vol   ");
+        assert_eq!(fit("Vol", 6, Align::Right), b"   Vol");
+        assert_eq!(fit("Vol", 6, Align::Center), b" Vol  ");
+        assert_eq!(fit("Resonance", 8, Align::Left), b"Resona..");
+        assert_eq!(fit("Resonance", 1, Align::Left), b".");
+        assert_eq!(fit("Caf\u{e9}", 4, Align::Left), b"Caf?");
+
+        assert_eq!(format_number(-6.04, 1, "dB", 8, Align::Right), b"  -6.0dB");
+        assert_eq!(format_number(440.0, 1, "Hz", 5, Align::Left), b"440.0");
+        assert_eq!(format_number(440.4, 2, "Hz", 4, Align::Left), b"440 ");
+        assert_eq!(format_number(12345.0, 0, "", 3, Align::Left), b"###");
+
+        let mut screen = LcdScreen::default();
+        screen.write_field(LcdLine::LeftTop, 9, 9, "Pan", Align::Center);
+        assert_eq!(&screen.line(LcdLine::LeftTop)[9..18], b"   Pan   ");
+    }
+
+    #[test]
+    fn test_apply_ops() {
+        let mut screen = LcdScreen::default();
+        screen.apply(&[
+            LcdOp::Cursor {
+                col: 9,
+                line: LcdLine::RightTop,
+            },
+            LcdOp::Text(b"Pan"),
+            LcdOp::Text(b" L"),
+            LcdOp::Cursor {
+                col: 0,
+                line: LcdLine::LeftBottom,
+            },
+            LcdOp::Text(b"Vol"),
+            LcdOp::End,
+        ]);
+        assert_eq!(&screen.line(LcdLine::RightTop)[9..14], b"Pan L");
+
+        screen.apply(&[LcdOp::Clear(LcdClear::LeftAll), LcdOp::End]);
+        assert_eq!(screen.line(LcdLine::LeftBottom), &[b' '; LCD_COLUMNS]);
+        assert_eq!(&screen.line(LcdLine::RightTop)[9..12], b"Pan");
+    }
+}
@@ -0,0 +1,385 @@
+//! Host-side model of the surface LEDs.
+//!
+//! The ZeRO MkII has no way to read back individual LED or ring settings
+//! (the LED bitmap readback is a raw dump with an undocumented layout), so
+//! the device keeps a shadow of everything the host has sent. Entries the
+//! host never touched are `None`: the unit powers up with them off, but a
+//! previous application may have changed them.
+
+use crate::automap::cc::{
+    Button, Encoder, EncoderPosition, RingMode, RowSelect, RowSelectLhSet, RowSelectRhSet,
+};
+use crate::automap::command::AutomapCommand;
+use crate::automap::sysex::{pack7, unpack7};
+
+/// Number of bytes in the LED bitmap readback.
+pub const LED_BITMAP_LEN: usize = 20;
+
+// Row-select slots: RS1..RS8 follow the CC numbers 0x50..0x57, REC comes last.
+const ROW_SLOTS: usize = 9;
+const REC_SLOT: usize = 8;
+
+/// Last mode and position the host set on an encoder ring.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RingState {
+    pub mode: Option<RingMode>,
+    pub position: Option<EncoderPosition>,
+}
+
+impl RingState {
+    /// A blanked ring, as left by
+    /// [`AutomapCommand::encoder_ring_off()`]. The mode is unchanged.
+    pub const OFF: RingState = RingState {
+        mode: None,
+        position: Some(EncoderPosition::OFF),
+    };
+
+    /// Whether the ring was last blanked, so no LED of it is lit.
+    pub fn is_off(&self) -> bool {
+        self.position == Some(EncoderPosition::OFF)
+    }
+}
+
+/// Shadow of the LED-related commands sent to the device.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LedState {
+    buttons: [Option<bool>; 32],
+    rows: [Option<bool>; ROW_SLOTS],
+    rings: [RingState; 8],
+}
+
+impl LedState {
+    /// Updates the shadow with a command about to be sent.
+    ///
+    /// Commands that do not touch LEDs are ignored.
+    pub fn apply(&mut self, cmd: &AutomapCommand) {
+        match *cmd {
+            AutomapCommand::ButtonLed { button, on } => {
+                self.buttons[button_slot(button)] = Some(on);
+            }
+            AutomapCommand::RowSelectLed { row, on } => {
+                self.rows[row_slot(row)] = Some(on);
+            }
+            AutomapCommand::RowLhBitmap { rows } => {
+                for (i, slot) in self.rows[..5].iter_mut().enumerate() {
+                    *slot = Some(rows.bits() & (1 << i) != 0);
+                }
+            }
+            AutomapCommand::RowRhBitmap { rows } => {
+                for (i, slot) in self.rows[5..].iter_mut().enumerate() {
+                    *slot = Some(rows.bits() & (1 << i) != 0);
+                }
+            }
+            AutomapCommand::EncoderRingMode { encoder, mode } => {
+                self.rings[ring_slot(encoder)].mode = Some(mode);
+            }
+            AutomapCommand::EncoderRingValue { encoder, position } => {
+                self.rings[ring_slot(encoder)].position = Some(position);
+            }
+            AutomapCommand::AllLedsOff => {
+                self.buttons = [Some(false); 32];
+                self.rows = [Some(false); ROW_SLOTS];
+                for ring in &mut self.rings {
+                    ring.position = Some(EncoderPosition::OFF);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Last state set on a button LED.
+    pub fn button(&self, button: Button) -> Option<bool> {
+        self.buttons[button_slot(button)]
+    }
+
+    /// Last state set on a row-select LED.
+    pub fn row(&self, row: RowSelect) -> Option<bool> {
+        self.rows[row_slot(row)]
+    }
+
+    /// Last state set on the REC LED.
+    pub fn rec(&self) -> Option<bool> {
+        self.rows[REC_SLOT]
+    }
+
+    /// Last mode and position set on an encoder ring.
+    pub fn ring(&self, encoder: Encoder) -> RingState {
+        self.rings[ring_slot(encoder)]
+    }
+
+    /// Commands that show `position` on `encoder`'s ring in `mode`: the
+    /// position, preceded by the mode only if the ring was last set to
+    /// another one or never had one set.
+    pub fn ring_commands(
+        &self,
+        encoder: Encoder,
+        mode: RingMode,
+        position: EncoderPosition,
+    ) -> Vec<AutomapCommand> {
+        let mut out = Vec::with_capacity(2);
+        if self.ring(encoder).mode != Some(mode) {
+            out.push(AutomapCommand::EncoderRingMode { encoder, mode });
+        }
+        out.push(AutomapCommand::EncoderRingValue { encoder, position });
+        out
+    }
+
+    /// Commands that take the surface from this state to `target`.
+    ///
+    /// Only entries that differ are sent. An LED that is unknown in `target`
+    /// but lit here is switched off, which is how the unit comes up; ring
+    /// modes unknown in `target` are left alone since there is no "off"
+    /// mode to return to.
+    pub fn commands_to(&self, target: &LedState) -> Vec<AutomapCommand> {
+        let mut out = Vec::new();
+
+        for (i, (&cur, &want)) in self.buttons.iter().zip(&target.buttons).enumerate() {
+            if let Some(on) = change(cur, want) {
+                let button = Button::try_from(0x18 + i as u8).expect("button slot in range");
+                out.push(AutomapCommand::ButtonLed { button, on });
+            }
+        }
+
+        // Row LEDs go out as bitmaps: RS6 has no single-LED command on the ZeRO
+        let lh = 0..5;
+        if lh
+            .clone()
+            .any(|i| change(self.rows[i], target.rows[i]).is_some())
+        {
+            let bits = row_bits(&target.rows[lh]);
+            out.push(AutomapCommand::RowLhBitmap {
+                rows: RowSelectLhSet::from_bits_truncate(bits),
+            });
+        }
+        let rh = 5..ROW_SLOTS;
+        if rh
+            .clone()
+            .any(|i| change(self.rows[i], target.rows[i]).is_some())
+        {
+            let bits = row_bits(&target.rows[rh]);
+            out.push(AutomapCommand::RowRhBitmap {
+                rows: RowSelectRhSet::from_bits_truncate(bits),
+            });
+        }
+
+        for (i, (cur, want)) in self.rings.iter().zip(&target.rings).enumerate() {
+            let encoder = Encoder::try_from(0x78 + i as u8).expect("encoder slot in range");
+            if let Some(mode) = want.mode
+                && cur.mode != Some(mode)
+            {
+                out.push(AutomapCommand::EncoderRingMode { encoder, mode });
+            }
+            let position = match (cur.position, want.position) {
+                (cur, Some(want)) if cur != Some(want) => Some(want),
+                (Some(cur), None) if cur != EncoderPosition::OFF => Some(EncoderPosition::OFF),
+                _ => None,
+            };
+            if let Some(position) = position {
+                out.push(AutomapCommand::EncoderRingValue { encoder, position });
+            }
+        }
+
+        out
+    }
+}
+
+/// Single-LED commands with the effect of `AllLedsOff`, for units that do
+/// not implement it: every button LED off, both row bitmaps cleared and
+/// every ring at position 0.
+pub fn all_off_commands() -> Vec<AutomapCommand> {
+    let mut off = LedState::default();
+    off.apply(&AutomapCommand::AllLedsOff);
+    LedState::default().commands_to(&off)
+}
+
+/// Raw LED bitmap as returned by the LED bitmap readback.
+///
+/// The reference manual only says the 20 bytes hold every button and ring
+/// LED; which bit is which is undocumented, so the bytes are kept as-is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LedBitmap(pub [u8; LED_BITMAP_LEN]);
+
+impl LedBitmap {
+    /// Decodes the readback payload: 20 7-bit values, then 3 bytes carrying
+    /// their top bits (seven per byte, LSB first), then a spare byte.
+    pub fn from_payload(data: &[u8]) -> Option<LedBitmap> {
+        let bytes = unpack7(data, LED_BITMAP_LEN)?;
+        Some(LedBitmap(bytes.try_into().ok()?))
+    }
+
+    /// The readback payload for this bitmap, spare byte included.
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut data = pack7(&self.0);
+        data.push(0x00);
+        data
+    }
+
+    /// Whether bit `n` of the bitmap is set.
+    pub fn bit(&self, n: usize) -> bool {
+        self.0
+            .get(n / 8)
+            .is_some_and(|byte| byte & (1 << (n % 8)) != 0)
+    }
+}
+
+/// What needs sending to move an LED from `cur` to `want`, if anything.
+fn change(cur: Option<bool>, want: Option<bool>) -> Option<bool> {
+    match (cur, want) {
+        (cur, Some(want)) if cur != Some(want) => Some(want),
+        (Some(true), None) => Some(false),
+        _ => None,
+    }
+}
+
+fn row_bits(slots: &[Option<bool>]) -> u8 {
+    slots
+        .iter()
+        .enumerate()
+        .filter(|(_, on)| on.unwrap_or(false))
+        .fold(0, |bits, (i, _)| bits | (1 << i))
+}
+
+fn button_slot(button: Button) -> usize {
+    (button as u8 - 0x18) as usize
+}
+
+fn row_slot(row: RowSelect) -> usize {
+    (row as u8 - 0x50) as usize
+}
+
+fn ring_slot(encoder: Encoder) -> usize {
+    (encoder as u8 - 0x78) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_off_keeps_mode() {
+        let mut leds = LedState::default();
+        leds.apply(&AutomapCommand::EncoderRingMode {
+            encoder: Encoder::Encoder2,
+            mode: RingMode::CenteredBand,
+        });
+        leds.apply(&AutomapCommand::encoder_ring_off(Encoder::Encoder2));
+        let ring = leds.ring(Encoder::Encoder2);
+        assert!(ring.is_off());
+        assert_eq!(ring.mode, Some(RingMode::CenteredBand));
+        assert_eq!(
+            LedState::default().ring(Encoder::Encoder1),
+            RingState::default()
+        );
+        assert!(!RingState::default().is_off() && RingState::OFF.is_off());
+    }
+
+    #[test]
+    fn test_ring_mode_sent_only_on_change() {
+        let mut leds = LedState::default();
+        let set = |leds: &mut LedState, mode, position| {
+            let cmds = leds.ring_commands(Encoder::Encoder4, mode, position);
+            for cmd in &cmds {
+                leds.apply(cmd);
+            }
+            cmds.len()
+        };
+        assert_eq!(
+            set(&mut leds, RingMode::ContinuousCw, EncoderPosition::Pos3),
+            2
+        );
+        assert_eq!(
+            set(&mut leds, RingMode::ContinuousCw, EncoderPosition::Pos4),
+            1
+        );
+        // Blanking the ring keeps the mode
+        leds.apply(&AutomapCommand::AllLedsOff);
+        assert_eq!(
+            set(&mut leds, RingMode::ContinuousCw, EncoderPosition::Pos4),
+            1
+        );
+        assert_eq!(
+            set(&mut leds, RingMode::CenteredBand, EncoderPosition::Pos6),
+            2
+        );
+    }
+
+    #[test]
+    fn test_all_off_commands_match_all_leds_off() {
+        let mut off = LedState::default();
+        off.apply(&AutomapCommand::AllLedsOff);
+        let mut fallback = LedState::default();
+        let cmds = all_off_commands();
+        for cmd in &cmds {
+            fallback.apply(cmd);
+        }
+        assert_eq!(fallback, off);
+        assert!(!cmds.contains(&AutomapCommand::AllLedsOff));
+    }
+
+    #[test]
+    fn test_commands_to_restores_leds() {
+        let mut before = LedState::default();
+        before.apply(&AutomapCommand::ButtonLed {
+            button: Button::ButtonA1,
+            on: true,
+        });
+
+        let mut now = before.clone();
+        now.apply(&AutomapCommand::ButtonLed {
+            button: Button::ButtonA1,
+            on: false,
+        });
+        now.apply(&AutomapCommand::ButtonLed {
+            button: Button::ButtonB2,
+            on: true,
+        });
+        now.apply(&AutomapCommand::RowSelectLed {
+            row: RowSelect::R1,
+            on: true,
+        });
+        now.apply(&AutomapCommand::EncoderRingValue {
+            encoder: Encoder::Encoder3,
+            position: EncoderPosition::Pos5,
+        });
+
+        let cmds = now.commands_to(&before);
+        assert_eq!(
+            cmds,
+            vec![
+                AutomapCommand::ButtonLed {
+                    button: Button::ButtonA1,
+                    on: true
+                },
+                AutomapCommand::ButtonLed {
+                    button: Button::ButtonB2,
+                    on: false
+                },
+                AutomapCommand::RowRhBitmap {
+                    rows: RowSelectRhSet::empty()
+                },
+                AutomapCommand::EncoderRingValue {
+                    encoder: Encoder::Encoder3,
+                    position: EncoderPosition::Pos0
+                },
+            ]
+        );
+
+        for cmd in &cmds {
+            now.apply(cmd);
+        }
+        assert!(now.commands_to(&before).is_empty());
+    }
+
+    #[test]
+    fn test_led_bitmap_from_payload() {
+        let mut data = vec![0u8; 24];
+        data[0] = 0x01;
+        data[8] = 0x7F;
+        data[21] = 0b10; // top bit of byte 8
+        let bitmap = LedBitmap::from_payload(&data).unwrap();
+        assert_eq!(bitmap.0[0], 0x01);
+        assert_eq!(bitmap.0[8], 0xFF);
+        assert!(bitmap.bit(0) && !bitmap.bit(1) && bitmap.bit(71));
+        assert_eq!(LedBitmap::from_payload(&data[..22]), None);
+    }
+}
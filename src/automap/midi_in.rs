@@ -0,0 +1,23 @@
+//! MIDI arriving on the unit's MIDI IN sockets.
+//!
+//! The unit passes what comes in on its 5-pin sockets to the host on
+//! USB-MIDI cables other than cable 0, which carries the Automap port.
+//! The device sorts them out as it reads, so MIDI from a keyboard plugged
+//! into the unit never shows up as a surface event, and keeps it for
+//! [`AutomapDevice::take_midi_in()`](crate::AutomapDevice::take_midi_in).
+
+use std::time::Instant;
+
+/// Messages kept for `take_midi_in()`; the oldest go first when nobody
+/// takes them, e.g. a clock nobody listens to.
+pub(crate) const MAX_MIDI_IN: usize = 1024;
+
+/// A whole MIDI message read on a cable other than the Automap port's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MidiInMessage {
+    /// When the USB transfer completing the message arrived.
+    pub at: Instant,
+    /// The USB-MIDI cable it came on, which tells the sockets apart.
+    pub cable: u8,
+    pub bytes: Vec<u8>,
+}
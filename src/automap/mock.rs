@@ -0,0 +1,538 @@
+//! A simulated ZeRO MkII for testing without hardware (`mock` feature).
+//!
+//! [`FakeZeroMkII`] stands in for the USB endpoints of an
+//! [`AutomapDevice`](crate::AutomapDevice) opened with
+//! [`open_mock()`](crate::AutomapDevice::open_mock). It answers the way the
+//! firmware does as far as this crate relies on it: echoes, the parameter
+//! requests, LCD and LED readback, data-block reads and writes, saving
+//! to flash and sending the current template. Simulated encoders 1 to 8
+//! turn the matching encoder; other simulated controls do nothing, as
+//! their numbering is not known. It also keeps the LED and LCD state the
+//! host has set, so a test can check what the surface would be showing.
+//!
+//! For robustness tests the fake can also misbehave on cue: each injected
+//! [`Fault`] hits the next read or message it applies to, so a test of
+//! resynchronizing, verifying or reconnecting sees the same failure on
+//! every run. [`FakeZeroMkII::unplug()`] and
+//! [`plug_in()`](FakeZeroMkII::plug_in) take the unit away and bring it
+//! back for [`recover()`](crate::AutomapDevice::recover).
+//!
+//! ```
+//! use automap::{AutomapCommand, AutomapDevice, Button, DeviceConfig, FakeZeroMkII};
+//!
+//! async fn lights_up() -> Result<(), automap::AutomapError> {
+//!     let fake = FakeZeroMkII::new();
+//!     let mut device = AutomapDevice::open_mock(&fake, &DeviceConfig::new()).await?;
+//!     let on = AutomapCommand::ButtonLed { button: Button::ButtonA1, on: true };
+//!     device.send_command(&on).await?;
+//!     assert_eq!(fake.leds().button(Button::ButtonA1), Some(true));
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::future::poll_fn;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+use std::time::{Duration, Instant};
+
+use crate::automap::cc::{AUTOMAP_CC_STATUS, Encoder, ParameterRequestType, ProductType};
+use crate::automap::command::AutomapCommand;
+use crate::automap::event::AutomapEvent;
+use crate::automap::lcd::LcdScreen;
+use crate::automap::leds::{LED_BITMAP_LEN, LedBitmap, LedState};
+use crate::automap::rt;
+use crate::automap::sysex::{
+    AutomapSysEx, DbSimMsg, DbTarget, DecodedMsg, SimCmd, SimHighLevel, decode_frame,
+};
+use crate::midi::{MidiStream, usbmidi_pack_cable, usbmidi_unpack};
+
+/// Template bytes per Upload Template frame sent for
+/// `SendCurrentTemplateToHost`. The firmware's framing is undocumented;
+/// several frames exercise the host's reassembly.
+const UPLOAD_CHUNK: usize = 1024;
+
+/// Size of each simulated data block. Reads past the end return zeros and
+/// writes past it are dropped, as a real unit ignores them.
+const BLOCK_LEN: usize = 0x4000;
+
+/// A one-off failure for [`FakeZeroMkII::inject()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// The next read completes without data.
+    EmptyRead,
+    /// The next read fails as if the IN endpoint had stalled.
+    StallRead,
+    /// The next message to the host arrives with its byte `index`, counting
+    /// from the status byte, XORed with `mask`. Setting bit 7 of a data
+    /// byte puts a stray status byte in the stream.
+    FlipBits { index: usize, mask: u8 },
+    /// The next message to the host is lost.
+    DropReply,
+    /// The next message from the host is lost before the unit sees it.
+    DropRequest,
+    /// The next message to the host, and those behind it, arrive this much
+    /// later, as from a busy unit.
+    DelayReply(Duration),
+    /// The unit is unplugged partway through the next message to the host,
+    /// after its first `packets` USB-MIDI packets are sent.
+    UnplugDuring { packets: usize },
+}
+
+/// What a read of the fake's IN endpoint comes to.
+enum ReadStep {
+    Done(io::Result<usize>),
+    /// Nothing is due before a delayed reply, this long from now.
+    Sleep(Duration),
+}
+
+#[derive(Default)]
+struct Unit {
+    online: bool,
+    transport_lock: bool,
+    leds: LedState,
+    lcd: LcdScreen,
+    template: Vec<u8>,
+    globals: Vec<u8>,
+    /// What was last saved to flash, if anything.
+    flash_template: Option<Vec<u8>>,
+    flash_globals: Option<Vec<u8>>,
+    /// Data blocks of individual controls, by 1-based control number.
+    controls: Vec<(u8, Vec<u8>)>,
+    /// MIDI the host has sent, one message per entry.
+    received: Vec<Vec<u8>>,
+    /// MIDI the host has sent on other cables than the Automap port's, by
+    /// cable.
+    cables: [Vec<u8>; 16],
+    host_stream: MidiStream,
+    /// USB-MIDI packets waiting to be read by the host.
+    outgoing: VecDeque<u8>,
+    /// Packets held back by [`Fault::DelayReply`], each with when it is due.
+    delayed: VecDeque<(Instant, Vec<u8>)>,
+    reader: Option<Waker>,
+    faults: VecDeque<Fault>,
+    /// Most packets handed over by one read, if limited.
+    read_limit: Option<usize>,
+    unplugged: bool,
+}
+
+/// A simulated unit. Clones share the same state, so a test keeps one to
+/// drive and inspect the unit while the device holds another.
+#[derive(Clone, Default)]
+pub struct FakeZeroMkII {
+    unit: Arc<Mutex<Unit>>,
+}
+
+impl FakeZeroMkII {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The contents of the current template, returned by data-block reads
+    /// of [`DbTarget::TemplateHeader`] and by `SendCurrentTemplateToHost`.
+    pub fn with_template(self, bytes: &[u8]) -> Self {
+        self.unit.lock().unwrap().template = bytes.to_vec();
+        self
+    }
+
+    /// The contents of the globals, returned by data-block reads of
+    /// [`DbTarget::Globals`].
+    pub fn with_globals(self, bytes: &[u8]) -> Self {
+        self.unit.lock().unwrap().globals = bytes.to_vec();
+        self
+    }
+
+    /// Hands the host at most `packets` USB-MIDI packets per read, so longer
+    /// messages arrive split across reads.
+    pub fn with_read_limit(self, packets: usize) -> Self {
+        self.unit.lock().unwrap().read_limit = Some(packets.max(1));
+        self
+    }
+
+    /// Queues `fault` to hit the next read or message it applies to.
+    /// Faults of the same kind apply in the order they were injected.
+    pub fn inject(&self, fault: Fault) {
+        self.unit.lock().unwrap().faults.push_back(fault);
+    }
+
+    /// Pulls the cable: packets already sent can still be read, then reads
+    /// and writes fail with `ConnectionAborted`.
+    pub fn unplug(&self) {
+        let mut unit = self.unit.lock().unwrap();
+        unit.unplugged = true;
+        unit.wake_reader();
+    }
+
+    /// Plugs the unit back in. It starts afresh, offline and with its LEDs
+    /// and displays blank, and whatever was in flight is lost. Templates
+    /// and data blocks are kept.
+    pub fn plug_in(&self) {
+        let mut unit = self.unit.lock().unwrap();
+        unit.unplugged = false;
+        unit.online = false;
+        unit.leds = LedState::default();
+        unit.lcd = LcdScreen::default();
+        unit.host_stream = MidiStream::default();
+        unit.outgoing.clear();
+        unit.delayed.clear();
+    }
+
+    pub fn is_plugged_in(&self) -> bool {
+        !self.unit.lock().unwrap().unplugged
+    }
+
+    /// Sends `event` to the host, as if the control had moved.
+    pub fn send_event(&self, event: AutomapEvent) {
+        self.unit.lock().unwrap().send(&event.to_bytes());
+    }
+
+    /// Sends raw MIDI to the host, e.g. a message the decoders do not know.
+    pub fn send_midi(&self, midi: &[u8]) {
+        self.unit.lock().unwrap().send(midi);
+    }
+
+    /// Sends raw MIDI to the host on USB-MIDI `cable`, as if it had come in
+    /// on one of the MIDI IN sockets.
+    pub fn send_cable(&self, cable: u8, midi: &[u8]) {
+        self.unit.lock().unwrap().send_on(cable, midi);
+    }
+
+    /// Whether the host last said it was online.
+    pub fn is_online(&self) -> bool {
+        self.unit.lock().unwrap().online
+    }
+
+    pub fn transport_lock(&self) -> bool {
+        self.unit.lock().unwrap().transport_lock
+    }
+
+    /// The LEDs and rings as the host has set them.
+    pub fn leds(&self) -> LedState {
+        self.unit.lock().unwrap().leds.clone()
+    }
+
+    /// The LCD text as the host has written it.
+    pub fn lcd(&self) -> LcdScreen {
+        self.unit.lock().unwrap().lcd.clone()
+    }
+
+    pub fn template(&self) -> Vec<u8> {
+        self.unit.lock().unwrap().template.clone()
+    }
+
+    pub fn globals(&self) -> Vec<u8> {
+        self.unit.lock().unwrap().globals.clone()
+    }
+
+    /// The template as last saved to flash, or `None` if it never was.
+    pub fn flash_template(&self) -> Option<Vec<u8>> {
+        self.unit.lock().unwrap().flash_template.clone()
+    }
+
+    /// The globals as last saved to flash, or `None` if they never were.
+    pub fn flash_globals(&self) -> Option<Vec<u8>> {
+        self.unit.lock().unwrap().flash_globals.clone()
+    }
+
+    /// Every MIDI message the host has sent so far, oldest first.
+    pub fn received(&self) -> Vec<Vec<u8>> {
+        self.unit.lock().unwrap().received.clone()
+    }
+
+    /// Raw MIDI the host has sent on USB-MIDI `cable`, e.g. to a MIDI OUT
+    /// socket. Cable 0 is the Automap port, whose messages are in
+    /// [`received()`](Self::received) instead.
+    pub fn cable_received(&self, cable: u8) -> Vec<u8> {
+        self.unit.lock().unwrap().cables[usize::from(cable & 0x0F)].clone()
+    }
+
+    /// Takes USB-MIDI packets written by the host.
+    pub(crate) fn write_packets(&self, packets: &[u8]) -> io::Result<()> {
+        let mut unit = self.unit.lock().unwrap();
+        if unit.unplugged {
+            return Err(io::ErrorKind::ConnectionAborted.into());
+        }
+        let mut automap = Vec::with_capacity(packets.len());
+        for packet in packets.chunks(4) {
+            match packet[0] >> 4 {
+                0 => automap.extend_from_slice(packet),
+                cable => {
+                    let midi = usbmidi_unpack(packet);
+                    unit.cables[usize::from(cable)].extend(midi);
+                }
+            }
+        }
+        let midi = usbmidi_unpack(&automap);
+        for msg in unit.host_stream.push(&midi) {
+            if unit.take_fault(|f| *f == Fault::DropRequest).is_some() {
+                continue;
+            }
+            unit.handle(&msg);
+            unit.received.push(msg);
+        }
+        Ok(())
+    }
+
+    /// Waits for packets for the host and reads as many whole ones as fit
+    /// in `buf`.
+    pub(crate) async fn read_packets(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let step = poll_fn(|cx| {
+                let mut unit = self.unit.lock().unwrap();
+                match unit.read(buf) {
+                    Some(step) => Poll::Ready(step),
+                    None => {
+                        unit.reader = Some(cx.waker().clone());
+                        Poll::Pending
+                    }
+                }
+            })
+            .await;
+            match step {
+                ReadStep::Done(result) => return result,
+                ReadStep::Sleep(wait) => rt::sleep(wait).await,
+            }
+        }
+    }
+}
+
+impl Unit {
+    fn send(&mut self, midi: &[u8]) {
+        self.send_on(0, midi);
+    }
+
+    fn send_on(&mut self, cable: u8, midi: &[u8]) {
+        if self.unplugged {
+            return;
+        }
+        let mut packets = usbmidi_pack_cable(midi, cable);
+        let now = Instant::now();
+        let mut due = self.delayed.back().map(|(at, _)| *at);
+        let fault = self.take_fault(|f| {
+            matches!(
+                f,
+                Fault::FlipBits { .. }
+                    | Fault::DropReply
+                    | Fault::DelayReply(_)
+                    | Fault::UnplugDuring { .. }
+            )
+        });
+        match fault {
+            Some(Fault::FlipBits { index, mask }) => {
+                // Each packet carries up to three bytes of the message
+                if let Some(byte) = packets.get_mut(index / 3 * 4 + 1 + index % 3) {
+                    *byte ^= mask;
+                }
+            }
+            Some(Fault::DropReply) => return,
+            Some(Fault::DelayReply(delay)) => due = due.max(Some(now + delay)),
+            Some(Fault::UnplugDuring { packets: sent }) => {
+                packets.truncate(sent * 4);
+                self.unplugged = true;
+            }
+            _ => {}
+        }
+        match due {
+            Some(at) => self.delayed.push_back((at, packets)),
+            None => self.outgoing.extend(packets),
+        }
+        self.wake_reader();
+    }
+
+    fn wake_reader(&mut self) {
+        if let Some(waker) = self.reader.take() {
+            waker.wake();
+        }
+    }
+
+    fn take_fault(&mut self, applies: impl Fn(&Fault) -> bool) -> Option<Fault> {
+        let i = self.faults.iter().position(applies)?;
+        self.faults.remove(i)
+    }
+
+    /// One read, or `None` if it has to wait for the unit to send.
+    fn read(&mut self, buf: &mut [u8]) -> Option<ReadStep> {
+        let now = Instant::now();
+        while let Some((at, _)) = self.delayed.front()
+            && *at <= now
+        {
+            let (_, packets) = self.delayed.pop_front().unwrap();
+            self.outgoing.extend(packets);
+        }
+        match self.take_fault(|f| matches!(f, Fault::EmptyRead | Fault::StallRead)) {
+            Some(Fault::EmptyRead) => return Some(ReadStep::Done(Ok(0))),
+            Some(_) => {
+                return Some(ReadStep::Done(Err(io::ErrorKind::ConnectionReset.into())));
+            }
+            None => {}
+        }
+        if !self.outgoing.is_empty() {
+            let limit = self.read_limit.map_or(usize::MAX, |packets| packets * 4);
+            let n = self.outgoing.len().min(buf.len() / 4 * 4).min(limit);
+            for (slot, byte) in buf.iter_mut().zip(self.outgoing.drain(..n)) {
+                *slot = byte;
+            }
+            return Some(ReadStep::Done(Ok(n)));
+        }
+        if self.unplugged {
+            return Some(ReadStep::Done(Err(io::ErrorKind::ConnectionAborted.into())));
+        }
+        let (at, _) = self.delayed.front()?;
+        Some(ReadStep::Sleep(at.saturating_duration_since(now)))
+    }
+
+    fn send_cc(&mut self, cc: u8, value: u8) {
+        self.send(&[AUTOMAP_CC_STATUS, cc, value]);
+    }
+
+    fn handle(&mut self, msg: &[u8]) {
+        if msg.first() == Some(&0xF0) {
+            self.handle_sysex(msg);
+            return;
+        }
+        let Ok(cmd) = AutomapCommand::decode_command(msg) else {
+            return;
+        };
+        match cmd {
+            AutomapCommand::EchoRequest { value } => self.send_cc(0x63, value),
+            AutomapCommand::ParameterRequest { request_type } => match request_type {
+                ParameterRequestType::UnitProductType => {
+                    self.send_cc(0x67, ProductType::ZeroSLorZeroMKII as u8);
+                }
+                ParameterRequestType::TransportLockState => {
+                    self.send_cc(0x4F, self.transport_lock as u8);
+                }
+            },
+            AutomapCommand::TransportLockSet { enabled } => self.transport_lock = enabled,
+            cmd => self.leds.apply(&cmd),
+        }
+    }
+
+    fn handle_sysex(&mut self, frame: &[u8]) {
+        let Ok((_, _, _, msg)) = decode_frame(frame) else {
+            return;
+        };
+        match msg {
+            DecodedMsg::Automap(AutomapSysEx::OnlineOffline { online }) => self.online = online,
+            DecodedMsg::Automap(AutomapSysEx::LcdText(ops)) => self.lcd.apply(&ops),
+            DecodedMsg::DbSim(DbSimMsg::DbRead {
+                target,
+                cn,
+                offset,
+                len,
+            }) => {
+                let block = self.block(target, cn);
+                let start = usize::from(offset).min(BLOCK_LEN);
+                let end = (start + usize::from(len)).min(BLOCK_LEN);
+                let mut data = block
+                    .get(start..end.min(block.len()))
+                    .unwrap_or(&[])
+                    .to_vec();
+                data.resize(end - start, 0);
+                let reply = DbSimMsg::DbData {
+                    target,
+                    cn,
+                    offset,
+                    data: &data,
+                }
+                .to_bytes();
+                self.send(&reply);
+            }
+            DecodedMsg::DbSim(DbSimMsg::DbWrite {
+                target,
+                cn,
+                offset,
+                data,
+            }) => {
+                let block = self.block(target, cn);
+                let start = usize::from(offset).min(BLOCK_LEN);
+                let end = (start + data.len()).min(BLOCK_LEN);
+                if block.len() < end {
+                    block.resize(end, 0);
+                }
+                block[start..end].copy_from_slice(&data[..end - start]);
+            }
+            DecodedMsg::DbSim(DbSimMsg::Simulate(SimCmd::Encoder {
+                number_1_based: n @ 1..=8,
+                clicks_signed,
+            })) => {
+                let encoder = Encoder::try_from(Encoder::Encoder1 as u8 + n - 1).unwrap();
+                let event = AutomapEvent::Encoder {
+                    encoder,
+                    clicks: clicks_signed,
+                };
+                self.send(&event.to_bytes());
+            }
+            DecodedMsg::DbSim(DbSimMsg::Simulate(SimCmd::LcdTextRequest)) => {
+                let text = self.lcd.to_bytes();
+                let reply = DbSimMsg::Simulate(SimCmd::LcdTextResponse { text }).to_bytes();
+                self.send(&reply);
+            }
+            DecodedMsg::DbSim(DbSimMsg::Simulate(SimCmd::LedBitmapRequest)) => {
+                // Which bit is which LED is undocumented, so report them all off
+                let data = LedBitmap([0; LED_BITMAP_LEN]).to_payload();
+                let reply = DbSimMsg::Simulate(SimCmd::LedBitmapResponse { data }).to_bytes();
+                self.send(&reply);
+            }
+            DecodedMsg::DbSim(DbSimMsg::HighLevel(SimHighLevel::SaveGlobalsToFlash)) => {
+                self.flash_globals = Some(self.globals.clone());
+            }
+            DecodedMsg::DbSim(DbSimMsg::HighLevel(SimHighLevel::SaveCurrentTemplateToFlash)) => {
+                self.flash_template = Some(self.template.clone());
+            }
+            DecodedMsg::DbSim(DbSimMsg::HighLevel(SimHighLevel::SendCurrentTemplateToHost)) => {
+                let template = std::mem::take(&mut self.template);
+                for data in template.chunks(UPLOAD_CHUNK) {
+                    self.send(&AutomapSysEx::UploadTemplate { data }.to_bytes());
+                }
+                self.template = template;
+            }
+            _ => {}
+        }
+    }
+
+    fn block(&mut self, target: DbTarget, cn: Option<u8>) -> &mut Vec<u8> {
+        match target {
+            DbTarget::TemplateHeader => &mut self.template,
+            DbTarget::Globals => &mut self.globals,
+            DbTarget::Control => {
+                let cn = cn.unwrap_or(1);
+                let i = match self.controls.iter().position(|(c, _)| *c == cn) {
+                    Some(i) => i,
+                    None => {
+                        self.controls.push((cn, Vec::new()));
+                        self.controls.len() - 1
+                    }
+                };
+                &mut self.controls[i].1
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::usbmidi_pack;
+    use std::future::Future;
+
+    #[test]
+    fn test_answers_echo_and_keeps_leds() {
+        let fake = FakeZeroMkII::new();
+        let mut packets = usbmidi_pack(&AutomapCommand::EchoRequest { value: 9 }.to_bytes());
+        packets.extend(usbmidi_pack(&[0xBF, 0x18, 0x01]));
+        fake.write_packets(&packets).unwrap();
+
+        assert_eq!(fake.received().len(), 2);
+        assert_eq!(fake.leds().button(crate::Button::ButtonA1), Some(true));
+        // The reply is already queued, so the first poll completes
+        let mut buf = [0u8; 64];
+        let mut cx = std::task::Context::from_waker(Waker::noop());
+        let read = std::pin::pin!(fake.read_packets(&mut buf)).poll(&mut cx);
+        let Poll::Ready(Ok(n)) = read else {
+            panic!("no reply queued");
+        };
+        assert_eq!(usbmidi_unpack(&buf[..n]), [0xBF, 0x63, 9]);
+    }
+}
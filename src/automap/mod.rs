@@ -1,7 +1,69 @@
 #![allow(unused_imports)]
 
+pub mod app;
+pub mod bridge;
+pub mod capabilities;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod capture;
+pub mod chords;
+pub mod config;
+pub mod conformance;
+pub mod control;
+pub mod corpus;
+pub mod crossfader;
+pub mod debounce;
 pub mod device;
+pub mod dsp;
+pub mod error;
+pub mod extension;
+pub mod gestures;
+pub mod grid;
+pub mod handle;
+pub mod host;
+pub mod info;
+pub mod json;
 pub use device::*;
 
+pub mod latency;
+pub mod layers;
+pub mod layout;
+pub mod lcd;
+pub mod leds;
+pub mod midi_in;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod notice;
+pub mod params;
+pub mod probe;
+pub mod proxy;
+pub mod relative;
+pub mod render;
+pub(crate) mod requests;
+pub(crate) mod rt;
+pub mod scene;
+pub mod screensaver;
+#[cfg(feature = "seq")]
+pub mod seq;
+pub mod session;
+pub mod simulator;
+pub mod snapshot;
+pub mod state;
+pub mod stats;
+pub mod stepped;
+pub mod subscribe;
+pub mod surface;
+pub mod tempo;
+pub mod timed;
+pub mod transfer;
+pub mod translator;
+pub mod transport;
+pub mod udev;
+pub mod unknown;
+#[cfg(feature = "webmidi")]
+pub mod webmidi;
+
 pub mod protocol;
 pub use protocol::*;
@@ -0,0 +1,203 @@
+//! Using the unit from another machine over TCP (`net` feature).
+//!
+//! One end runs [`serve()`] next to the controller, e.g. on a headless
+//! Raspberry Pi; the other connects with [`RemoteDevice`] and talks to it
+//! much like a local [`AutomapDevice`].
+//!
+//! The framing is deliberately simple: each MIDI message (a CC or a whole
+//! SysEx frame) is sent as a big-endian `u16` length followed by the bytes.
+//! Only decoded CC events travel from the unit to the client; SysEx replies
+//! such as LCD readback are not forwarded.
+
+use std::io;
+
+#[cfg(feature = "smol")]
+use futures_lite::{AsyncReadExt, AsyncWriteExt};
+#[cfg(feature = "smol")]
+use smol::net::{TcpListener, TcpStream};
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+#[cfg(feature = "tokio")]
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::automap::command::AutomapCommand;
+use crate::automap::device::AutomapDevice;
+use crate::automap::event::AutomapEvent;
+use crate::automap::rt;
+use crate::automap::sysex::AutomapSysEx;
+use crate::automap::timed::TimedEvent;
+
+/// Largest message a frame can carry.
+pub const MAX_FRAME: usize = u16::MAX as usize;
+
+/// Frames one MIDI message for the wire.
+///
+/// # Panics
+///
+/// Panics if `midi` is longer than [`MAX_FRAME`].
+pub fn encode_frame(midi: &[u8]) -> Vec<u8> {
+    let len = u16::try_from(midi.len()).expect("MIDI message fits a frame");
+    let mut out = Vec::with_capacity(midi.len() + 2);
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(midi);
+    out
+}
+
+/// Splits a TCP byte stream back into MIDI messages.
+#[derive(Debug, Clone, Default)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+}
+
+impl FrameDecoder {
+    /// Feeds received bytes, returning the messages completed by them.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        self.buf.extend_from_slice(bytes);
+        let mut out = Vec::new();
+        let mut start = 0;
+        while let Some(header) = self.buf.get(start..start + 2) {
+            let len = usize::from(u16::from_be_bytes([header[0], header[1]]));
+            let Some(body) = self.buf.get(start + 2..start + 2 + len) else {
+                break;
+            };
+            out.push(body.to_vec());
+            start += 2 + len;
+        }
+        self.buf.drain(..start);
+        out
+    }
+}
+
+/// A unit on another machine, reached through [`serve()`].
+pub struct RemoteDevice {
+    stream: TcpStream,
+    decoder: FrameDecoder,
+}
+
+impl RemoteDevice {
+    /// Connects to a [`serve()`] instance, e.g. `"pi.local:7000"`.
+    pub async fn connect(addr: &str) -> Result<RemoteDevice, io::Error> {
+        Ok(RemoteDevice {
+            stream: TcpStream::connect(addr).await?,
+            decoder: FrameDecoder::default(),
+        })
+    }
+
+    /// Sends a command to the unit.
+    pub async fn send_command(&mut self, cmd: &AutomapCommand) -> Result<(), io::Error> {
+        self.send(&cmd.to_bytes()).await
+    }
+
+    /// Sends a SysEx message to the unit.
+    pub async fn send_sysex(&mut self, msg: AutomapSysEx<'_>) -> Result<(), io::Error> {
+        self.send(&msg.to_bytes()).await
+    }
+
+    /// Waits for events from the unit.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`io::ErrorKind::UnexpectedEof`] once the server hangs up.
+    pub async fn read_events(&mut self) -> Result<Vec<AutomapEvent>, io::Error> {
+        let mut buf = [0u8; 256];
+        loop {
+            let n = self.stream.read(&mut buf).await?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let events: Vec<_> = self
+                .decoder
+                .push(&buf[..n])
+                .iter()
+                .filter_map(|msg| AutomapEvent::decode_event(msg).ok())
+                .collect();
+            if !events.is_empty() {
+                return Ok(events);
+            }
+        }
+    }
+
+    async fn send(&mut self, midi: &[u8]) -> Result<(), io::Error> {
+        self.stream.write_all(&encode_frame(midi)).await?;
+        self.stream.flush().await
+    }
+}
+
+/// Serves `device` to [`RemoteDevice`] clients connecting to `addr`.
+///
+/// Clients are served one at a time; when one disconnects the next
+/// connection is accepted. CC messages from the client are rewritten to the
+/// device's configured channel; they bypass [`AutomapDevice::leds()`].
+///
+/// # Errors
+///
+/// Returns when binding fails or the device itself fails.
+pub async fn serve(device: &mut AutomapDevice, addr: &str) -> Result<(), io::Error> {
+    let listener = TcpListener::bind(addr).await?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        serve_client(device, stream).await?;
+    }
+}
+
+enum Step {
+    Device(Result<Vec<TimedEvent>, io::Error>),
+    Client(Result<usize, io::Error>),
+}
+
+/// Bridges one client until it disconnects. Only device errors are returned.
+async fn serve_client(device: &mut AutomapDevice, mut stream: TcpStream) -> Result<(), io::Error> {
+    let mut decoder = FrameDecoder::default();
+    let mut buf = [0u8; 256];
+    loop {
+        let step = rt::race(
+            async { Step::Device(device.read_timed_events().await) },
+            async { Step::Client(stream.read(&mut buf).await) },
+        )
+        .await;
+        match step {
+            Step::Device(events) => {
+                let mut out = Vec::new();
+                for timed in events? {
+                    out.extend(encode_frame(&timed.event.to_bytes()));
+                }
+                if !out.is_empty() && stream.write_all(&out).await.is_err() {
+                    return Ok(());
+                }
+            }
+            Step::Client(Ok(0) | Err(_)) => return Ok(()),
+            Step::Client(Ok(n)) => {
+                for mut msg in decoder.push(&buf[..n]) {
+                    match msg.first() {
+                        Some(status) if status & 0xF0 == 0xB0 && msg.len() == 3 => {
+                            msg[0] = device.config().cc_status();
+                        }
+                        Some(0xF0) => {}
+                        // Not something the unit understands
+                        _ => continue,
+                    }
+                    device.write_midi(&msg).await?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_split_across_reads() {
+        let mut wire = encode_frame(&[0xBF, 0x18, 0x01]);
+        wire.extend(encode_frame(&[0xF0, 0x00, 0x20, 0x29, 0xF7]));
+
+        let mut decoder = FrameDecoder::default();
+        assert!(decoder.push(&wire[..1]).is_empty());
+        assert_eq!(decoder.push(&wire[1..7]), vec![vec![0xBF, 0x18, 0x01]]);
+        assert_eq!(
+            decoder.push(&wire[7..]),
+            vec![vec![0xF0, 0x00, 0x20, 0x29, 0xF7]]
+        );
+    }
+}
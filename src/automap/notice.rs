@@ -0,0 +1,152 @@
+//! Notices about the connection itself, as opposed to input from the surface.
+//!
+//! They can be collected with
+//! [`AutomapDevice::take_notices()`](crate::AutomapDevice::take_notices)
+//! after each read, or received as they happen from a [`NoticeStream`],
+//! so hardware state handling can live in its own task, away from the
+//! code reacting to controls. Like event subscriptions, the streams are fed
+//! by whoever reads the device.
+
+use std::collections::VecDeque;
+use std::future::poll_fn;
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+
+use crate::automap::session::SessionState;
+
+/// Something the device layer noticed about the USB stream.
+///
+/// Collected with [`AutomapDevice::take_notices()`](crate::AutomapDevice::take_notices).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DeviceNotice {
+    /// A USB read held malformed packets. The unpacker skipped `discarded`
+    /// bytes to find the next well-formed packet; events carried by them
+    /// are lost.
+    StreamCorruption { discarded: usize },
+    /// The session moved from one state to another.
+    SessionChanged {
+        from: SessionState,
+        to: SessionState,
+    },
+    /// [`recover()`](crate::AutomapDevice::recover) reopened the unit. A
+    /// freshly opened device starts out connected without a notice.
+    Connected,
+    /// The unit went away; nothing gets through until it is recovered.
+    Disconnected,
+    /// After reconnecting, the LCD text and LEDs sent so far have been
+    /// replayed, so the surface shows what it did before.
+    Resynced,
+    /// Bounded [event receivers](crate::AutomapDevice::broadcast) fell
+    /// behind and `dropped` events were lost between them.
+    QueueOverflow { dropped: u64 },
+    /// A [keep-alive](crate::DeviceConfig::keep_alive) echo went
+    /// unanswered.
+    KeepAliveMissed,
+    /// A flash save or write went ahead without checking memory protect,
+    /// as the [flag](crate::DeviceConfig::memory_protect_flag) is not
+    /// configured. If protect is on, the unit drops it without saying so.
+    MemoryProtectUnknown,
+}
+
+#[derive(Default)]
+struct State {
+    queue: VecDeque<DeviceNotice>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+/// Every notice from the moment it was created, from
+/// [`AutomapDevice::notices()`](crate::AutomapDevice::notices).
+///
+/// Notices queue up until received; drop the stream once it is no longer
+/// needed.
+pub struct NoticeStream {
+    state: Arc<Mutex<State>>,
+}
+
+impl NoticeStream {
+    /// Waits for the next notice.
+    ///
+    /// Returns `None` once the device has been dropped and every queued
+    /// notice has been received.
+    pub async fn recv(&mut self) -> Option<DeviceNotice> {
+        poll_fn(|cx| {
+            let mut state = self.state.lock().unwrap();
+            match state.queue.pop_front() {
+                Some(notice) => Poll::Ready(Some(notice)),
+                None if state.closed => Poll::Ready(None),
+                None => {
+                    state.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Takes the next queued notice without waiting.
+    pub fn try_recv(&mut self) -> Option<DeviceNotice> {
+        self.state.lock().unwrap().queue.pop_front()
+    }
+}
+
+/// The device's side of its notice streams.
+#[derive(Default)]
+pub(crate) struct Notifiers {
+    streams: Vec<Arc<Mutex<State>>>,
+}
+
+impl Notifiers {
+    pub(crate) fn stream(&mut self) -> NoticeStream {
+        let state = Arc::default();
+        self.streams.push(Arc::clone(&state));
+        NoticeStream { state }
+    }
+
+    /// Queues `notice` on every stream, forgetting streams that were
+    /// dropped.
+    pub(crate) fn publish(&mut self, notice: DeviceNotice) {
+        self.streams.retain(|state| Arc::strong_count(state) > 1);
+        for state in &self.streams {
+            let mut state = state.lock().unwrap();
+            state.queue.push_back(notice);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl Drop for Notifiers {
+    fn drop(&mut self) {
+        for state in &self.streams {
+            let mut state = state.lock().unwrap();
+            state.closed = true;
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streams_get_every_notice_until_closed() {
+        let mut notifiers = Notifiers::default();
+        let mut a = notifiers.stream();
+        notifiers.publish(DeviceNotice::Disconnected);
+        let mut b = notifiers.stream();
+        notifiers.publish(DeviceNotice::Connected);
+        drop(notifiers);
+
+        assert_eq!(a.try_recv(), Some(DeviceNotice::Disconnected));
+        assert_eq!(a.try_recv(), Some(DeviceNotice::Connected));
+        assert_eq!(b.try_recv(), Some(DeviceNotice::Connected));
+        assert_eq!(b.try_recv(), None);
+        assert!(b.state.lock().unwrap().closed);
+    }
+}
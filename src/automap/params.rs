@@ -0,0 +1,528 @@
+//! Paging a list of parameters across the eight encoders.
+//!
+//! [`ParamBank`] shows one page of eight parameters at a time: turning an
+//! encoder changes the parameter under it, the page buttons move through
+//! the list, and [`render()`](ParamBank::render) and
+//! [`ring_commands()`](ParamBank::ring_commands) produce the matching LCD
+//! cells and ring LEDs.
+//!
+//! With [`fine_adjust()`](ParamBank::fine_adjust), resting a finger on an
+//! encoder without turning it switches its parameter to a finer step and
+//! shows the exact value until the encoder is let go. That needs the
+//! events' times, so feed the bank with [`process()`](ParamBank::process)
+//! and [`poll()`](ParamBank::poll) it instead of using
+//! [`handle()`](ParamBank::handle).
+
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use crate::automap::cc::{Encoder, EncoderPosition, PageButton, RingMode};
+use crate::automap::command::AutomapCommand;
+use crate::automap::event::AutomapEvent;
+use crate::automap::lcd::{Align, LCD_CELL, LcdScreen};
+use crate::automap::surface::pad_cell;
+use crate::automap::sysex::LcdLine;
+use crate::automap::timed::TimedEvent;
+
+/// Encoders per page.
+pub const BANK_SIZE: usize = 8;
+
+/// How a parameter's value is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamKind {
+    /// Zero to full, like volume or send level.
+    Unipolar,
+    /// Centered on a neutral value, like pan.
+    Bipolar,
+}
+
+impl ParamKind {
+    /// Ring mode suited to the parameter.
+    pub fn ring_mode(self) -> RingMode {
+        match self {
+            ParamKind::Unipolar => RingMode::ContinuousCw,
+            ParamKind::Bipolar => RingMode::CenteredBand,
+        }
+    }
+}
+
+/// One parameter: a name and a value in `0.0..=1.0`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub kind: ParamKind,
+    pub value: f32,
+}
+
+impl Param {
+    /// A parameter at its neutral value: zero, or centered if bipolar.
+    pub fn new(name: impl Into<String>, kind: ParamKind) -> Self {
+        let value = match kind {
+            ParamKind::Unipolar => 0.0,
+            ParamKind::Bipolar => 0.5,
+        };
+        Param {
+            name: name.into(),
+            kind,
+            value,
+        }
+    }
+
+    /// Sets the initial value.
+    pub fn value(mut self, value: f32) -> Self {
+        self.value = value.clamp(0.0, 1.0);
+        self
+    }
+
+    /// The value as shown on the LCD: `64%`, or `L20` / `C` / `R20`.
+    pub fn display_value(&self) -> String {
+        match self.kind {
+            ParamKind::Unipolar => format!("{}%", (self.value * 100.0).round()),
+            ParamKind::Bipolar => {
+                let pan = ((self.value - 0.5) * 200.0).round();
+                if pan < 0.0 {
+                    format!("L{}", -pan)
+                } else if pan > 0.0 {
+                    format!("R{pan}")
+                } else {
+                    "C".to_string()
+                }
+            }
+        }
+    }
+
+    /// The value to two decimals, as shown while fine adjusting: `64.37%`,
+    /// or `L20.50` / `C` / `R20.50`.
+    pub fn exact_value(&self) -> String {
+        match self.kind {
+            ParamKind::Unipolar => format!("{:.2}%", self.value * 100.0),
+            ParamKind::Bipolar => {
+                let pan = (self.value - 0.5) * 200.0;
+                if pan.abs() < 0.005 {
+                    "C".to_string()
+                } else if pan < 0.0 {
+                    format!("L{:.2}", -pan)
+                } else {
+                    format!("R{pan:.2}")
+                }
+            }
+        }
+    }
+}
+
+/// What an event changed in the bank.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BankChange {
+    /// Parameter `index` (into the whole list) took a new value.
+    Value { index: usize, value: f32 },
+    /// Another page is shown; redraw the LCD and rings.
+    Page { page: usize },
+    /// Parameter `index` switched to fine adjustment, or back; redraw its
+    /// value.
+    Fine { index: usize, active: bool },
+}
+
+/// Where a touched encoder stands on the way to fine adjustment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Touch {
+    /// Touched at `since`, not turned yet.
+    Resting {
+        since: Instant,
+    },
+    /// Turned soon after the touch: coarse until let go.
+    Turning,
+    Fine,
+}
+
+/// A list of parameters mapped page by page onto the encoders.
+#[derive(Debug, Clone)]
+pub struct ParamBank {
+    params: Vec<Param>,
+    page: usize,
+    sensitivity: f32,
+    page_up: PageButton,
+    page_down: PageButton,
+    name_line: LcdLine,
+    value_line: LcdLine,
+    /// Step per click and resting time for fine adjustment, if enabled.
+    fine: Option<(f32, Duration)>,
+    touches: [Option<Touch>; BANK_SIZE],
+}
+
+impl ParamBank {
+    /// A bank showing the first page of `params`.
+    ///
+    /// Defaults: one encoder click moves a value by 1%, the left page
+    /// buttons change page, names and values go on the left LCD.
+    pub fn new(params: Vec<Param>) -> Self {
+        ParamBank {
+            params,
+            page: 0,
+            sensitivity: 0.01,
+            page_up: PageButton::PageUpL,
+            page_down: PageButton::PageDnL,
+            name_line: LcdLine::LeftTop,
+            value_line: LcdLine::LeftBottom,
+            fine: None,
+            touches: [None; BANK_SIZE],
+        }
+    }
+
+    /// How far one encoder click moves a value.
+    pub fn sensitivity(mut self, per_click: f32) -> Self {
+        self.sensitivity = per_click;
+        self
+    }
+
+    /// Switches a parameter to `per_click` per encoder click once its
+    /// encoder has been touched for `rest` without turning, until it is let
+    /// go. Turning straight after touching stays at the normal step.
+    pub fn fine_adjust(mut self, per_click: f32, rest: Duration) -> Self {
+        self.fine = Some((per_click, rest));
+        self
+    }
+
+    /// The buttons that move to the next and previous page.
+    pub fn page_buttons(mut self, up: PageButton, down: PageButton) -> Self {
+        self.page_up = up;
+        self.page_down = down;
+        self
+    }
+
+    /// The LCD lines for parameter names and values.
+    pub fn lcd_lines(mut self, names: LcdLine, values: LcdLine) -> Self {
+        self.name_line = names;
+        self.value_line = values;
+        self
+    }
+
+    pub fn params(&self) -> &[Param] {
+        &self.params
+    }
+
+    /// Sets the value of parameter `index`, e.g. when the host changes it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of range.
+    pub fn set_value(&mut self, index: usize, value: f32) {
+        self.params[index].value = value.clamp(0.0, 1.0);
+    }
+
+    /// Number of pages; at least one, even for an empty list.
+    pub fn pages(&self) -> usize {
+        self.params.len().div_ceil(BANK_SIZE).max(1)
+    }
+
+    pub fn page(&self) -> usize {
+        self.page
+    }
+
+    /// Shows `page`, returning `false` if there is no such page.
+    pub fn set_page(&mut self, page: usize) -> bool {
+        if page >= self.pages() {
+            return false;
+        }
+        if page != self.page {
+            self.touches = [None; BANK_SIZE];
+        }
+        self.page = page;
+        true
+    }
+
+    /// Indices of the parameters on the current page.
+    pub fn visible(&self) -> Range<usize> {
+        let start = self.page * BANK_SIZE;
+        start..(start + BANK_SIZE).min(self.params.len())
+    }
+
+    /// Handles an encoder turn or page button press.
+    ///
+    /// Returns `None` for events the bank does not use, for turns of an
+    /// encoder with no parameter under it, and for paging past either end.
+    pub fn handle(&mut self, event: &AutomapEvent) -> Option<BankChange> {
+        match *event {
+            AutomapEvent::Encoder { encoder, clicks } => {
+                let index = self.page * BANK_SIZE + encoder as usize - Encoder::Encoder1 as usize;
+                let param = self.params.get_mut(index)?;
+                param.value = (param.value + clicks as f32 * self.sensitivity).clamp(0.0, 1.0);
+                Some(BankChange::Value {
+                    index,
+                    value: param.value,
+                })
+            }
+            AutomapEvent::PageButton {
+                button,
+                pressed: true,
+            } => {
+                let page = if button == self.page_up {
+                    self.page + 1
+                } else if button == self.page_down {
+                    self.page.checked_sub(1)?
+                } else {
+                    return None;
+                };
+                self.set_page(page).then_some(BankChange::Page { page })
+            }
+            _ => None,
+        }
+    }
+
+    /// Like [`handle()`](Self::handle), also following encoder touches for
+    /// [fine adjustment](Self::fine_adjust).
+    ///
+    /// Also runs [`poll()`](Self::poll) at the event's time; a change it
+    /// reports comes before the event's own.
+    pub fn process(&mut self, event: &TimedEvent) -> Vec<BankChange> {
+        let mut out = self.poll(event.at);
+        let Some((per_click, rest)) = self.fine else {
+            out.extend(self.handle(&event.event));
+            return out;
+        };
+        match event.event {
+            AutomapEvent::EncoderTouch { encoder, touched } => {
+                let slot = encoder as usize - Encoder::Encoder1 as usize;
+                let index = self.page * BANK_SIZE + slot;
+                if index >= self.params.len() {
+                    return out;
+                }
+                let was = self.touches[slot].take();
+                if touched {
+                    self.touches[slot] = Some(Touch::Resting { since: event.at });
+                } else if was == Some(Touch::Fine) {
+                    out.push(BankChange::Fine {
+                        index,
+                        active: false,
+                    });
+                }
+            }
+            AutomapEvent::Encoder { encoder, clicks } => {
+                let slot = encoder as usize - Encoder::Encoder1 as usize;
+                let touch = &mut self.touches[slot];
+                if let Some(Touch::Resting { since }) = *touch {
+                    let settled = event.at >= since + rest;
+                    *touch = Some(if settled { Touch::Fine } else { Touch::Turning });
+                }
+                let index = self.page * BANK_SIZE + slot;
+                let Some(param) = self.params.get_mut(index) else {
+                    return out;
+                };
+                let step = if *touch == Some(Touch::Fine) {
+                    per_click
+                } else {
+                    self.sensitivity
+                };
+                param.value = (param.value + clicks as f32 * step).clamp(0.0, 1.0);
+                out.push(BankChange::Value {
+                    index,
+                    value: param.value,
+                });
+            }
+            ref other => out.extend(self.handle(other)),
+        }
+        out
+    }
+
+    /// Switches encoders touched and left still long enough by `now` to
+    /// fine adjustment.
+    pub fn poll(&mut self, now: Instant) -> Vec<BankChange> {
+        let Some((_, rest)) = self.fine else {
+            return Vec::new();
+        };
+        let mut out = Vec::new();
+        for (slot, touch) in self.touches.iter_mut().enumerate() {
+            if let Some(Touch::Resting { since }) = *touch
+                && now >= since + rest
+            {
+                *touch = Some(Touch::Fine);
+                out.push(BankChange::Fine {
+                    index: self.page * BANK_SIZE + slot,
+                    active: true,
+                });
+            }
+        }
+        out
+    }
+
+    /// When [`poll()`](Self::poll) could next switch an encoder to fine
+    /// adjustment, if one is resting.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let (_, rest) = self.fine?;
+        self.touches
+            .iter()
+            .filter_map(|touch| match touch {
+                Some(Touch::Resting { since }) => Some(*since + rest),
+                _ => None,
+            })
+            .min()
+    }
+
+    /// Writes names and values of the current page into `lcd`.
+    ///
+    /// Each parameter gets the nine-column cell above its encoder; cells
+    /// without a parameter are blanked. Parameters being fine adjusted
+    /// show their [exact value](Param::exact_value).
+    pub fn render(&self, lcd: &mut LcdScreen) {
+        for slot in 0..BANK_SIZE {
+            let col = slot * LCD_CELL;
+            let fine = self.touches[slot] == Some(Touch::Fine);
+            let (name, value) = match self.params.get(self.page * BANK_SIZE + slot) {
+                Some(p) if fine => (p.name.as_str(), p.exact_value()),
+                Some(p) => (p.name.as_str(), p.display_value()),
+                None => ("", String::new()),
+            };
+            lcd.write(self.name_line, col, &pad_cell(name, Align::Left));
+            lcd.write(self.value_line, col, &pad_cell(&value, Align::Left));
+        }
+    }
+
+    /// Ring mode and position commands for all eight encoders.
+    ///
+    /// Rings without a parameter are switched off.
+    pub fn ring_commands(&self) -> Vec<AutomapCommand> {
+        let mut out = Vec::with_capacity(BANK_SIZE * 2);
+        for slot in 0..BANK_SIZE {
+            let encoder =
+                Encoder::try_from(Encoder::Encoder1 as u8 + slot as u8).expect("slot in range");
+            let (mode, position) = match self.params.get(self.page * BANK_SIZE + slot) {
+                Some(p) => {
+                    let mode = p.kind.ring_mode();
+                    (mode, mode.position(p.value))
+                }
+                None => (RingMode::ContinuousCw, EncoderPosition::OFF),
+            };
+            out.push(AutomapCommand::EncoderRingMode { encoder, mode });
+            out.push(AutomapCommand::EncoderRingValue { encoder, position });
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bank() -> ParamBank {
+        let mut params: Vec<Param> = (1..=10)
+            .map(|i| Param::new(format!("Vol {i}"), ParamKind::Unipolar))
+            .collect();
+        params.push(Param::new("Pan", ParamKind::Bipolar));
+        ParamBank::new(params)
+    }
+
+    #[test]
+    fn test_paging_and_turns() {
+        let mut bank = bank();
+        assert_eq!(bank.pages(), 2);
+
+        let turn = AutomapEvent::Encoder {
+            encoder: Encoder::Encoder2,
+            clicks: 50,
+        };
+        assert_eq!(
+            bank.handle(&turn),
+            Some(BankChange::Value {
+                index: 1,
+                value: 0.5
+            })
+        );
+
+        let up = AutomapEvent::PageButton {
+            button: PageButton::PageUpL,
+            pressed: true,
+        };
+        assert_eq!(bank.handle(&up), Some(BankChange::Page { page: 1 }));
+        assert_eq!(bank.handle(&up), None);
+        assert_eq!(bank.visible(), 8..11);
+
+        // Encoder 4 has nothing under it on the last page
+        let empty = AutomapEvent::Encoder {
+            encoder: Encoder::Encoder4,
+            clicks: 1,
+        };
+        assert_eq!(bank.handle(&empty), None);
+    }
+
+    #[test]
+    fn test_render_and_rings() {
+        let mut bank = bank();
+        bank.set_page(1);
+        bank.set_value(10, 0.4);
+        let mut lcd = LcdScreen::default();
+        bank.render(&mut lcd);
+
+        assert_eq!(
+            &lcd.line(LcdLine::LeftTop)[..27],
+            b"Vol 9    Vol 10   Pan      "
+        );
+        assert_eq!(&lcd.line(LcdLine::LeftBottom)[18..27], b"L20      ");
+        assert!(lcd.line(LcdLine::LeftTop)[27..].iter().all(|&b| b == b' '));
+
+        let rings = bank.ring_commands();
+        assert_eq!(
+            rings[4],
+            AutomapCommand::EncoderRingMode {
+                encoder: Encoder::Encoder3,
+                mode: RingMode::CenteredBand
+            }
+        );
+        assert_eq!(
+            rings[7],
+            AutomapCommand::EncoderRingValue {
+                encoder: Encoder::Encoder4,
+                position: EncoderPosition::Pos0
+            }
+        );
+    }
+
+    #[test]
+    fn test_fine_adjust_while_resting() {
+        let t0 = Instant::now();
+        let ms = Duration::from_millis;
+        let at = |t, event| TimedEvent {
+            at: t0 + ms(t),
+            event,
+        };
+        let touch = |touched| AutomapEvent::EncoderTouch {
+            encoder: Encoder::Encoder1,
+            touched,
+        };
+        let turn = AutomapEvent::Encoder {
+            encoder: Encoder::Encoder1,
+            clicks: 1,
+        };
+        let mut bank = bank().fine_adjust(0.001, ms(300));
+
+        // Touching to turn right away stays coarse
+        assert!(bank.process(&at(0, touch(true))).is_empty());
+        bank.process(&at(50, turn));
+        bank.process(&at(400, turn));
+        assert!((bank.params()[0].value - 0.02).abs() < 1e-6);
+        bank.process(&at(500, touch(false)));
+
+        bank.process(&at(1000, touch(true)));
+        assert_eq!(bank.next_deadline(), Some(t0 + ms(1300)));
+        assert_eq!(
+            bank.poll(t0 + ms(1300)),
+            [BankChange::Fine {
+                index: 0,
+                active: true
+            }]
+        );
+        bank.process(&at(1400, turn));
+        assert!((bank.params()[0].value - 0.021).abs() < 1e-6);
+        let mut lcd = LcdScreen::default();
+        bank.render(&mut lcd);
+        assert_eq!(&lcd.line(LcdLine::LeftBottom)[..9], b"2.10%    ");
+
+        assert_eq!(
+            bank.process(&at(1500, touch(false))),
+            [BankChange::Fine {
+                index: 0,
+                active: false
+            }]
+        );
+        bank.render(&mut lcd);
+        assert_eq!(&lcd.line(LcdLine::LeftBottom)[..9], b"2%       ");
+    }
+}
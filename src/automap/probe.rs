@@ -0,0 +1,128 @@
+//! What [`AutomapDevice::probe()`] found out about a connected unit.
+//!
+//! [`AutomapDevice::probe()`]: crate::AutomapDevice::probe
+
+use crate::automap::config::Backend;
+
+/// USB class codes used to recognise the unit's interfaces.
+pub(crate) const CLASS_VENDOR: u8 = 0xFF;
+pub(crate) const CLASS_AUDIO: u8 = 0x01;
+pub(crate) const SUBCLASS_MIDI_STREAMING: u8 = 0x03;
+
+/// Role of a USB interface on the unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterfaceKind {
+    /// The vendor-specific Automap interface.
+    Vendor,
+    /// The class-compliant MIDI streaming interface.
+    MidiStreaming,
+    /// Anything else (audio control, ...).
+    Other { class: u8, subclass: u8 },
+}
+
+impl InterfaceKind {
+    /// Classifies an interface by its class and subclass codes.
+    pub fn from_class(class: u8, subclass: u8) -> Self {
+        match (class, subclass) {
+            (CLASS_VENDOR, _) => InterfaceKind::Vendor,
+            (CLASS_AUDIO, SUBCLASS_MIDI_STREAMING) => InterfaceKind::MidiStreaming,
+            _ => InterfaceKind::Other { class, subclass },
+        }
+    }
+
+    /// Whether `backend` may use an interface of this kind.
+    pub fn serves(self, backend: Backend) -> bool {
+        matches!(
+            (self, backend),
+            (InterfaceKind::Vendor, Backend::Auto | Backend::Vendor)
+                | (
+                    InterfaceKind::MidiStreaming,
+                    Backend::Auto | Backend::MidiStreaming
+                )
+        )
+    }
+}
+
+/// Whether the host could claim an interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterfaceAccess {
+    /// Claimed and released again; opening will work.
+    Available,
+    /// Held by another process or kernel driver.
+    Busy { driver: Option<String> },
+    /// No WinUSB driver is bound to the interface (Windows).
+    ///
+    /// Windows only lets user space talk to an interface through WinUSB; the
+    /// Automap interface has no driver until one is installed, e.g. with
+    /// Zadig. Only reported with the `windows` feature enabled.
+    DriverNotBound,
+    /// Claiming failed for another reason.
+    Error(String),
+}
+
+/// One interface of the unit and whether it can be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceProbe {
+    pub number: u8,
+    pub kind: InterfaceKind,
+    pub access: InterfaceAccess,
+}
+
+/// Report on a connected unit, produced without keeping it open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeReport {
+    pub product: Option<String>,
+    pub serial: Option<String>,
+    /// The vendor and MIDI streaming interfaces, in interface order.
+    pub interfaces: Vec<InterfaceProbe>,
+}
+
+impl ProbeReport {
+    /// The interface that opening with `backend` would use, if it is claimable.
+    pub fn usable(&self, backend: Backend) -> Option<&InterfaceProbe> {
+        let candidates = || {
+            self.interfaces
+                .iter()
+                .filter(move |i| i.kind.serves(backend) && i.access == InterfaceAccess::Available)
+        };
+        // Auto prefers the vendor interface
+        candidates()
+            .find(|i| i.kind == InterfaceKind::Vendor)
+            .or_else(|| candidates().next())
+    }
+
+    /// Whether the vendor interface needs a WinUSB driver installed first.
+    pub fn needs_winusb(&self) -> bool {
+        self.interfaces
+            .iter()
+            .any(|i| i.kind == InterfaceKind::Vendor && i.access == InterfaceAccess::DriverNotBound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usable_falls_back_to_midi() {
+        let report = ProbeReport {
+            product: None,
+            serial: None,
+            interfaces: vec![
+                InterfaceProbe {
+                    number: 1,
+                    kind: InterfaceKind::MidiStreaming,
+                    access: InterfaceAccess::Available,
+                },
+                InterfaceProbe {
+                    number: 2,
+                    kind: InterfaceKind::Vendor,
+                    access: InterfaceAccess::DriverNotBound,
+                },
+            ],
+        };
+        assert_eq!(report.usable(Backend::Auto).map(|i| i.number), Some(1));
+        assert_eq!(report.usable(Backend::Vendor), None);
+        assert!(report.needs_winusb());
+    }
+}
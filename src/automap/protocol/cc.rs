@@ -13,8 +13,46 @@ pub enum RingMode {
     SingleLedCw = 0x40,
 }
 
+impl RingMode {
+    /// The ring position showing `value` (`0.0..=1.0`) in this mode.
+    ///
+    /// Position 0 blanks the ring, so values run over positions 1 to 11
+    /// and even 0.0 lights something.
+    ///
+    /// - The continuous bands and the single LED run from position 1 at
+    ///   0.0 to position 11 at 1.0.
+    /// - [`CenteredBand`](Self::CenteredBand) is bipolar: 0.5 is exactly
+    ///   [`EncoderPosition::CENTER`], lower values extend the band to the
+    ///   left and higher ones to the right.
+    /// - [`DoubleCenter`](Self::DoubleCenter) spreads both ways from the
+    ///   center, so it shows how far `value` is from 0.5 whichever side it
+    ///   is on: centered is a single LED, either end is the full ring.
+    pub fn position(self, value: f32) -> EncoderPosition {
+        let value = value.clamp(0.0, 1.0);
+        match self {
+            RingMode::ContinuousCw
+            | RingMode::ContinuousAcw
+            | RingMode::SingleLedCw
+            | RingMode::CenteredBand => EncoderPosition::from_fraction(value),
+            RingMode::DoubleCenter => {
+                let center = EncoderPosition::CENTER as u8;
+                let max = EncoderPosition::MAX as u8;
+                let i =
+                    center + ((value - 0.5).abs() * 2.0 * f32::from(max - center)).round() as u8;
+                EncoderPosition::ALL[usize::from(i.min(max))]
+            }
+        }
+    }
+
+    /// [`position()`](Self::position) as the raw ring-value CC byte.
+    pub fn raw_value(self, value: f32) -> u8 {
+        self.position(value) as u8
+    }
+}
+
 bitflags::bitflags! {
     /// Control attribute byte 1 flags (CNATTR1)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct Attr1: u8 {
         const SEND_MSB_FIRST = 1 << 0;
         const SEND_2B_VALUE  = 1 << 1;
@@ -25,6 +63,11 @@ bitflags::bitflags! {
     }
 
     /// Control attribute byte 2 flags (CNATTR2)
+    ///
+    /// The `POTMODE_*` values share bits 5-6 and are not independent flags:
+    /// `contains(POTMODE_JUMP)` is always true. Use [`Attr2::pot_mode()`]
+    /// and [`Attr2::set_pot_mode()`] instead.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct Attr2: u8 {
         const SNAPSHOT_SKIP  = 1 << 2;
         const INVERT_VALUE   = 1 << 3;
@@ -35,7 +78,136 @@ bitflags::bitflags! {
     }
 }
 
-#[derive(TryFrom, Clone, Copy, Debug, PartialEq, Eq)]
+/// How a pot or slider takes over a value it does not match (CNATTR2 bits 5-6).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PotMode {
+    /// The value jumps to the control's position.
+    Jump = 0b00,
+    /// Nothing is sent until the control passes the current value.
+    Pickup = 0b01,
+    /// Use the mode set in the globals.
+    Global = 0b10,
+    /// Use the mode set in the template header.
+    Template = 0b11,
+}
+
+impl Attr2 {
+    const POTMODE_MASK: u8 = 0b11 << 5;
+
+    /// The pot mode, from bits 5-6.
+    pub fn pot_mode(self) -> PotMode {
+        match (self.bits() & Self::POTMODE_MASK) >> 5 {
+            0b00 => PotMode::Jump,
+            0b01 => PotMode::Pickup,
+            0b10 => PotMode::Global,
+            _ => PotMode::Template,
+        }
+    }
+
+    /// Replaces the pot mode in bits 5-6, leaving the other bits alone.
+    pub fn set_pot_mode(&mut self, mode: PotMode) {
+        *self = Attr2::from_bits_retain((self.bits() & !Self::POTMODE_MASK) | (mode as u8) << 5);
+    }
+}
+
+/// The CNATTR1/CNATTR2 pair of one template control, built by name.
+///
+/// ```
+/// # use automap::automap::cc::{ControlAttrs, PotMode};
+/// let attrs = ControlAttrs::new().toggle().send_on_release().pot_mode(PotMode::Pickup);
+/// assert_eq!(ControlAttrs::from_bytes(attrs.to_bytes()), attrs);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ControlAttrs {
+    pub attr1: Attr1,
+    pub attr2: Attr2,
+}
+
+impl Default for ControlAttrs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ControlAttrs {
+    /// No attributes set, pot mode [`Jump`](PotMode::Jump).
+    pub const fn new() -> Self {
+        ControlAttrs {
+            attr1: Attr1::empty(),
+            attr2: Attr2::empty(),
+        }
+    }
+
+    /// Decodes the CNATTR1 and CNATTR2 bytes, keeping unknown bits.
+    pub const fn from_bytes([attr1, attr2]: [u8; 2]) -> Self {
+        ControlAttrs {
+            attr1: Attr1::from_bits_retain(attr1),
+            attr2: Attr2::from_bits_retain(attr2),
+        }
+    }
+
+    /// The CNATTR1 and CNATTR2 bytes.
+    pub const fn to_bytes(self) -> [u8; 2] {
+        [self.attr1.bits(), self.attr2.bits()]
+    }
+
+    /// Sends the MS byte of a 2-byte value first.
+    pub fn msb_first(mut self) -> Self {
+        self.attr1 |= Attr1::SEND_MSB_FIRST;
+        self
+    }
+
+    /// Sends a 2-byte value.
+    pub fn two_byte_value(mut self) -> Self {
+        self.attr1 |= Attr1::SEND_2B_VALUE;
+        self
+    }
+
+    /// Sends a value on release as well as on press.
+    pub fn send_on_release(mut self) -> Self {
+        self.attr1 |= Attr1::SEND_ON_RELEASE;
+        self
+    }
+
+    /// Alternates between the high and low value on each press.
+    pub fn toggle(mut self) -> Self {
+        self.attr1 |= Attr1::TOGGLE_VALUE;
+        self
+    }
+
+    /// Steps through the range on each press.
+    pub fn cyclic(mut self) -> Self {
+        self.attr1 |= Attr1::CYCLIC_BUTTON;
+        self
+    }
+
+    /// Sends the raw bytes of the control's SysEx buffer.
+    pub fn raw_data(mut self) -> Self {
+        self.attr1 |= Attr1::RAWDATA_MODE;
+        self
+    }
+
+    /// Leaves the control out of snapshot sends.
+    pub fn skip_snapshot(mut self) -> Self {
+        self.attr2 |= Attr2::SNAPSHOT_SKIP;
+        self
+    }
+
+    /// Inverts the value sent.
+    pub fn invert(mut self) -> Self {
+        self.attr2 |= Attr2::INVERT_VALUE;
+        self
+    }
+
+    /// Sets the pot mode, bits 5-6 of CNATTR2.
+    pub fn pot_mode(mut self, mode: PotMode) -> Self {
+        self.attr2.set_pot_mode(mode);
+        self
+    }
+}
+
+#[derive(TryFrom, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
 #[try_from(repr)]
 pub enum Pot {
@@ -49,7 +221,7 @@ pub enum Pot {
     Pot8 = 0x0F,
 }
 
-#[derive(TryFrom, Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(TryFrom, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
 #[try_from(repr)]
 pub enum Slider {
@@ -63,7 +235,7 @@ pub enum Slider {
     Slider8 = 0x17,
 }
 
-#[derive(TryFrom, Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(TryFrom, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
 #[try_from(repr)]
 pub enum Button {
@@ -101,7 +273,7 @@ pub enum Button {
     ButtonD8 = 0x37,
 }
 
-#[derive(TryFrom, Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(TryFrom, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
 #[try_from(repr)]
 pub enum TransportButton {
@@ -113,7 +285,7 @@ pub enum TransportButton {
     ButtonD6Tl = 0x4D,
 }
 
-#[derive(TryFrom, Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(TryFrom, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
 #[try_from(repr)]
 pub enum AutomapButton {
@@ -125,7 +297,7 @@ pub enum AutomapButton {
     AutomapButton6 = 0x4D,
 }
 
-#[derive(TryFrom, Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(TryFrom, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
 #[try_from(repr)]
 pub enum RowSelect {
@@ -138,7 +310,7 @@ pub enum RowSelect {
     R2 = 0x57,
 }
 
-#[derive(TryFrom, Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(TryFrom, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
 #[try_from(repr)]
 pub enum Encoder {
@@ -207,12 +379,14 @@ pub enum Controls {
     SlidersTouch = 0x6E,
     SpeedDialTouch = 0x6F,
 
+    EncodersDRowSelect = 0x6A, // Compact unit only
+
     // === Off/Online ===
     OffOnLine = 0x6B,
 }
 
 /// Page buttons for LCD navigation (Section 5, PDF page 10)
-#[derive(TryFrom, Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(TryFrom, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
 #[try_from(repr)]
 pub enum PageButton {
@@ -274,7 +448,8 @@ pub enum EncoderPosition {
 }
 
 impl EncoderPosition {
-    /// Minimum position (fully counter-clockwise)
+    /// Lowest ring value. As [`OFF`](Self::OFF) it lights nothing; the
+    /// lowest lit position is `Pos1`.
     pub const MIN: Self = Self::Pos0;
 
     /// Maximum position (fully clockwise)
@@ -282,6 +457,33 @@ impl EncoderPosition {
 
     /// Center position
     pub const CENTER: Self = Self::Pos6;
+
+    /// Ring value 0, which blanks the ring whatever its mode (PDF page 18).
+    pub const OFF: Self = Self::Pos0;
+
+    /// All positions, counter-clockwise to clockwise.
+    pub const ALL: [Self; 12] = [
+        Self::Pos0,
+        Self::Pos1,
+        Self::Pos2,
+        Self::Pos3,
+        Self::Pos4,
+        Self::Pos5,
+        Self::Pos6,
+        Self::Pos7,
+        Self::Pos8,
+        Self::Pos9,
+        Self::Pos10,
+        Self::Pos11,
+    ];
+
+    /// The position nearest to `fraction` of the way round (`0.0..=1.0`),
+    /// from `Pos1` at 0.0 to `Pos11` at 1.0 with 0.5 at the center. Never
+    /// [`OFF`](Self::OFF), so a value at its minimum still shows.
+    pub fn from_fraction(fraction: f32) -> Self {
+        let i = (fraction.clamp(0.0, 1.0) * 10.0).round() as usize;
+        Self::ALL[1 + i]
+    }
 }
 
 bitflags::bitflags! {
@@ -312,3 +514,111 @@ bitflags::bitflags! {
         const REC = 0b1000;  // Record LED
     }
 }
+
+// Display: the variant name, which is also what the CLI accepts. Transport
+// buttons use the function printed on the panel instead.
+macro_rules! display_variant_name {
+    ($($ty:ty),*) => {$(
+        impl std::fmt::Display for $ty {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                std::fmt::Debug::fmt(self, f)
+            }
+        }
+    )*};
+}
+
+display_variant_name!(
+    RingMode,
+    Pot,
+    Slider,
+    Button,
+    AutomapButton,
+    RowSelect,
+    Encoder,
+    PageButton,
+    AlertType,
+    ParameterRequestType,
+    ProductType
+);
+
+impl std::fmt::Display for TransportButton {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TransportButton::ButtonD1Tl => "Rewind",
+            TransportButton::ButtonD2Tl => "FastForward",
+            TransportButton::ButtonD3Tl => "Stop",
+            TransportButton::ButtonD4Tl => "Play",
+            TransportButton::ButtonD5Tl => "Loop",
+            TransportButton::ButtonD6Tl => "Record",
+        })
+    }
+}
+
+impl std::fmt::Display for EncoderPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", *self as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_mode_position() {
+        assert_eq!(RingMode::ContinuousCw.position(1.0), EncoderPosition::MAX);
+        assert_eq!(
+            RingMode::CenteredBand.position(0.5),
+            EncoderPosition::CENTER
+        );
+        assert_eq!(RingMode::CenteredBand.position(0.0), EncoderPosition::Pos1);
+        assert_eq!(RingMode::CenteredBand.position(1.0), EncoderPosition::MAX);
+        assert_eq!(
+            RingMode::DoubleCenter.position(0.0),
+            RingMode::DoubleCenter.position(1.0)
+        );
+        assert_eq!(RingMode::DoubleCenter.raw_value(0.5), 6);
+        for mode in [
+            RingMode::ContinuousCw,
+            RingMode::ContinuousAcw,
+            RingMode::CenteredBand,
+            RingMode::SingleLedCw,
+        ] {
+            assert_ne!(mode.position(0.0), EncoderPosition::OFF);
+        }
+    }
+
+    #[test]
+    fn test_fraction_never_blanks_the_ring() {
+        assert_eq!(EncoderPosition::from_fraction(0.0), EncoderPosition::Pos1);
+        assert_eq!(EncoderPosition::from_fraction(0.5), EncoderPosition::CENTER);
+        assert_eq!(EncoderPosition::from_fraction(1.0), EncoderPosition::MAX);
+    }
+
+    #[test]
+    fn test_pot_mode_and_attrs_roundtrip() {
+        let mut attr2 = Attr2::INVERT_VALUE;
+        for mode in [
+            PotMode::Pickup,
+            PotMode::Template,
+            PotMode::Global,
+            PotMode::Jump,
+        ] {
+            attr2.set_pot_mode(mode);
+            assert_eq!(attr2.pot_mode(), mode);
+            assert!(attr2.contains(Attr2::INVERT_VALUE));
+        }
+
+        let attrs = ControlAttrs::new()
+            .toggle()
+            .send_on_release()
+            .skip_snapshot()
+            .pot_mode(PotMode::Pickup);
+        assert_eq!(attrs.to_bytes(), [0x0C, 0x24]);
+        assert_eq!(ControlAttrs::from_bytes(attrs.to_bytes()), attrs);
+        assert_eq!(
+            ControlAttrs::from_bytes([0x80, 0x83]).to_bytes(),
+            [0x80, 0x83]
+        );
+    }
+}
@@ -1,6 +1,9 @@
-use crate::automap::cc::{
-    AUTOMAP_CC_STATUS, Button, Encoder, EncoderPosition, ParameterRequestType, RingMode, RowSelect,
-    RowSelectLhSet, RowSelectRhSet,
+use crate::automap::{
+    cc::{
+        AUTOMAP_CC_STATUS, Button, Encoder, EncoderPosition, ParameterRequestType, RingMode,
+        RowSelect, RowSelectLhSet, RowSelectRhSet,
+    },
+    sysex::DecodeError,
 };
 
 /// Commands that the host can send TO the device (Host → Device).
@@ -43,7 +46,8 @@ pub enum AutomapCommand {
     RowRhBitmap { rows: RowSelectRhSet },
 
     /// Request parameter from device (Section 7, PDF page 14)
-    /// CC 0x67 - device responds with same CC
+    /// CC 0x67 - device responds with the product type on the same CC, or
+    /// the transport lock state on CC 0x4F
     ParameterRequest { request_type: ParameterRequestType },
 
     /// Echo CC request (Section 7, PDF page 14)
@@ -88,12 +92,107 @@ impl AutomapCommand {
         }
     }
 
+    /// Blanks the ring of `encoder`, e.g. one with no parameter on the
+    /// current page.
+    ///
+    /// The protocol has no separate command for this: ring value 0 turns
+    /// every LED of the ring off in all modes, and the mode is kept for
+    /// the next value.
+    pub fn encoder_ring_off(encoder: Encoder) -> AutomapCommand {
+        AutomapCommand::EncoderRingValue {
+            encoder,
+            position: EncoderPosition::OFF,
+        }
+    }
+
     /// Convenience method to encode as a new Vec
     pub fn to_bytes(self) -> Vec<u8> {
         let mut buf = Vec::new();
         self.encode_into(&mut buf);
         buf
     }
+
+    /// Decodes a CC message sent to the unit, e.g. one seen by a proxy or
+    /// received by a simulated device. The channel is not checked.
+    ///
+    /// The inverse of [`encode_into()`](Self::encode_into). CCs that are
+    /// not commands are [`DecodeError::Unsupported`]; a command with a
+    /// value out of range is [`DecodeError::Invalid`].
+    pub fn decode_command(msg: &[u8]) -> Result<AutomapCommand, DecodeError> {
+        let &[status, nn, vv] = msg else {
+            return Err(DecodeError::Truncated);
+        };
+        if status & 0xF0 != 0xB0 {
+            return Err(DecodeError::Unsupported);
+        }
+        let invalid = |_| DecodeError::Invalid;
+        Ok(match nn {
+            0x18..=0x37 => AutomapCommand::ButtonLed {
+                button: Button::try_from(nn).map_err(invalid)?,
+                on: vv != 0,
+            },
+            0x50..=0x57 => AutomapCommand::RowSelectLed {
+                row: RowSelect::try_from(nn).map_err(invalid)?,
+                on: vv != 0,
+            },
+            0x70..=0x77 => AutomapCommand::EncoderRingValue {
+                encoder: Encoder::try_from(nn + 0x08).map_err(invalid)?,
+                position: *EncoderPosition::ALL
+                    .get(usize::from(vv))
+                    .ok_or(DecodeError::Invalid)?,
+            },
+            0x78..=0x7F => AutomapCommand::EncoderRingMode {
+                encoder: Encoder::try_from(nn).map_err(invalid)?,
+                mode: RingMode::try_from(vv).map_err(invalid)?,
+            },
+            0x4E if vv == 0 => AutomapCommand::AllLedsOff,
+            0x4F => AutomapCommand::TransportLockSet { enabled: vv != 0 },
+            0x60 => AutomapCommand::RowLhBitmap {
+                rows: RowSelectLhSet::from_bits_truncate(vv),
+            },
+            0x61 => AutomapCommand::RowRhBitmap {
+                rows: RowSelectRhSet::from_bits_truncate(vv),
+            },
+            0x63 => AutomapCommand::EchoRequest { value: vv },
+            0x67 => AutomapCommand::ParameterRequest {
+                request_type: match vv {
+                    0x00 => ParameterRequestType::UnitProductType,
+                    0x01 => ParameterRequestType::TransportLockState,
+                    _ => return Err(DecodeError::Invalid),
+                },
+            },
+            _ => return Err(DecodeError::Unsupported),
+        })
+    }
+}
+
+/// Concise human-readable form, e.g. `ButtonB5 LED on`.
+impl std::fmt::Display for AutomapCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        match *self {
+            AutomapCommand::ButtonLed { button, on } => write!(f, "{button} LED {}", on_off(on)),
+            AutomapCommand::RowSelectLed { row, on } => {
+                write!(f, "RowSelect{row} LED {}", on_off(on))
+            }
+            AutomapCommand::EncoderRingMode { encoder, mode } => {
+                write!(f, "{encoder} ring mode {mode}")
+            }
+            AutomapCommand::EncoderRingValue { encoder, position } => {
+                write!(f, "{encoder} ring {position}")
+            }
+            AutomapCommand::TransportLockSet { enabled } => {
+                write!(f, "TransportLock {}", on_off(enabled))
+            }
+            AutomapCommand::AllLedsOff => f.write_str("AllLedsOff"),
+            AutomapCommand::RowLhBitmap { rows } => write!(f, "RowLhBitmap {:#07b}", rows.bits()),
+            AutomapCommand::RowRhBitmap { rows } => write!(f, "RowRhBitmap {:#06b}", rows.bits()),
+            AutomapCommand::ParameterRequest { request_type } => {
+                write!(f, "ParameterRequest {request_type}")
+            }
+            AutomapCommand::EchoRequest { value } => write!(f, "Echo {value}"),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -148,6 +247,12 @@ mod tests {
         assert_eq!(cmd_center.to_bytes(), vec![0xBF, 0x71, 0x06]);
     }
 
+    #[test]
+    fn test_encoder_ring_off() {
+        let cmd = AutomapCommand::encoder_ring_off(Encoder::Encoder4);
+        assert_eq!(cmd.to_bytes(), vec![0xBF, 0x73, 0x00]);
+    }
+
     #[test]
     fn test_all_leds_off() {
         let cmd = AutomapCommand::AllLedsOff;
@@ -177,4 +282,54 @@ mod tests {
         };
         assert_eq!(cmd.to_bytes(), vec![0xBF, 0x67, 0x00]);
     }
+
+    #[test]
+    fn test_decode_command_roundtrip() {
+        let cmds = [
+            AutomapCommand::ButtonLed {
+                button: Button::ButtonC8,
+                on: true,
+            },
+            AutomapCommand::RowSelectLed {
+                row: RowSelect::L4,
+                on: false,
+            },
+            AutomapCommand::EncoderRingMode {
+                encoder: Encoder::Encoder8,
+                mode: RingMode::DoubleCenter,
+            },
+            AutomapCommand::EncoderRingValue {
+                encoder: Encoder::Encoder3,
+                position: EncoderPosition::Pos11,
+            },
+            AutomapCommand::AllLedsOff,
+            AutomapCommand::RowRhBitmap {
+                rows: RowSelectRhSet::REC,
+            },
+            AutomapCommand::ParameterRequest {
+                request_type: ParameterRequestType::TransportLockState,
+            },
+            AutomapCommand::EchoRequest { value: 0x2A },
+        ];
+        for cmd in cmds {
+            assert_eq!(AutomapCommand::decode_command(&cmd.to_bytes()), Ok(cmd));
+        }
+        assert_eq!(
+            AutomapCommand::decode_command(&[0xBF, 0x70, 12]),
+            Err(DecodeError::Invalid)
+        );
+        assert_eq!(
+            AutomapCommand::decode_command(&[0xBF, 0x08, 1]),
+            Err(DecodeError::Unsupported)
+        );
+    }
+
+    #[test]
+    fn test_command_display() {
+        let cmd = AutomapCommand::EncoderRingValue {
+            encoder: Encoder::Encoder2,
+            position: EncoderPosition::CENTER,
+        };
+        assert_eq!(cmd.to_string(), "Encoder2 ring 6");
+    }
 }
@@ -0,0 +1,106 @@
+//! The protocol's magic numbers, for building and picking apart frames by
+//! hand.
+//!
+//! The typed messages ([`AutomapSysEx`], [`DbSimMsg`], [`AutomapCommand`]
+//! and [`AutomapEvent`]) are built from these; reach for them only when
+//! those do not cover what you need. CC numbers of the controls are the
+//! discriminants of the [`cc`](super::cc) enums, e.g.
+//! `Controls::ExpressionPedal as u8`, so they are not repeated here.
+//!
+//! PDF pages refer to the SL MkII MIDI Programmer's Reference.
+//!
+//! [`AutomapSysEx`]: super::sysex::AutomapSysEx
+//! [`DbSimMsg`]: super::sysex::DbSimMsg
+//! [`AutomapCommand`]: super::command::AutomapCommand
+//! [`AutomapEvent`]: super::event::AutomapEvent
+
+pub use super::cc::AUTOMAP_CC_STATUS;
+pub use super::sysex::{EOX, MAX_SYSEX_LEN, NOVATION_ID, PROTO_VER_BETA, PROTO_VER_MAIN};
+pub use super::template::{CONTROL_COUNT, CONTROL_LEN, HEADER_LEN, TEMPLATE_LEN};
+
+// ---- USB ----
+
+/// Focusrite-Novation's USB vendor ID.
+pub const VENDOR_ID: u16 = 0x1235;
+
+/// USB product ID of the ZeRO SL MkII.
+pub const ZERO_MKII_PRODUCT_ID: u16 = 0x000C;
+
+/// Bytes in one USB-MIDI event packet.
+pub const USB_MIDI_PACKET_LEN: usize = 4;
+
+/// Interface number of the class-compliant MIDI streaming interface on the
+/// ZeRO MkII. The device finds it from the descriptors; this is what they
+/// say.
+pub const MIDI_STREAMING_INTERFACE: u8 = 1;
+
+/// Bulk OUT and IN endpoints of the MIDI streaming interface.
+pub const MIDI_STREAMING_ENDPOINTS: (u8, u8) = (0x02, 0x82);
+
+/// Interface number of the vendor-specific Automap interface.
+pub const AUTOMAP_INTERFACE: u8 = 2;
+
+/// Bulk OUT and IN endpoints of the Automap interface.
+pub const AUTOMAP_ENDPOINTS: (u8, u8) = (0x06, 0x86);
+
+// ---- SysEx header ----
+
+/// Byte after [`NOVATION_ID`] in Automap frames.
+pub const FAMILY_AUTOMAP: u8 = 0x03;
+
+/// Byte after [`NOVATION_ID`] in Data-Block and Simulation frames.
+pub const FAMILY_DBSIM: u8 = 0x05;
+
+/// The two bytes after the version in Automap frames.
+pub const AUTOMAP_TAG: [u8; 2] = [0x02, 0x00];
+
+/// The two bytes after the version in Data-Block and Simulation frames.
+pub const DBSIM_TAG: [u8; 2] = [0x00, 0x00];
+
+/// Length of either header, from `F0` up to the command byte.
+pub const SYSEX_HEADER_LEN: usize = 10;
+
+// ---- Automap commands (PDF pages 20 to 25) ----
+
+pub const CMD_ONLINE_OFFLINE: u8 = 0x01;
+pub const CMD_LCD_TEXT: u8 = 0x02;
+pub const CMD_GLOBALS_DOWNLOAD_RAM: u8 = 0x03;
+pub const CMD_PREPARE_OS_DOWNLOAD: u8 = 0x04;
+pub const CMD_UPLOAD_GLOBALS: u8 = 0x05;
+pub const CMD_GLOBALS_DOWNLOAD_RAM_AND_FLASH: u8 = 0x06;
+pub const CMD_UPLOAD_TEMPLATE: u8 = 0x07;
+pub const CMD_UPLOAD_OS: u8 = 0x08;
+
+// ---- LCD text sub-operations ----
+
+/// Ends the text command.
+pub const LCD_END: u8 = 0x00;
+/// Followed by column and line.
+pub const LCD_CURSOR: u8 = 0x01;
+/// Followed by an [`LcdClear`](super::sysex::LcdClear) code.
+pub const LCD_CLEAR: u8 = 0x02;
+/// Followed by 1 to blink, 0 not to.
+pub const LCD_CURSOR_BLINK: u8 = 0x03;
+/// Followed by text up to a `00`.
+pub const LCD_TEXT: u8 = 0x04;
+
+// ---- Data-Block and Simulation main commands (PDF pages 26 to 31) ----
+
+/// Data-block write or read request.
+pub const DB_CHANGE: u8 = 0x68;
+/// Data-block read response.
+pub const DB_RESPONSE: u8 = 0x69;
+/// Simulated control, and the LCD and LED readbacks.
+pub const SIMULATE: u8 = 0x66;
+/// Saves, play mode and the current-template upload.
+pub const SIM_HIGH_LEVEL: u8 = 0x6A;
+
+/// [`DB_CHANGE`] sub-commands writing a control, the template header or
+/// the globals. Adding 3 gives the read request, which [`DB_RESPONSE`]
+/// answers with the same sub-command.
+pub const DB_WRITE_CONTROL: u8 = 0x00;
+pub const DB_WRITE_TEMPLATE_HEADER: u8 = 0x01;
+pub const DB_WRITE_GLOBALS: u8 = 0x02;
+pub const DB_READ_CONTROL: u8 = 0x03;
+pub const DB_READ_TEMPLATE_HEADER: u8 = 0x04;
+pub const DB_READ_GLOBALS: u8 = 0x05;
@@ -1,18 +1,79 @@
 use crate::automap::{
     cc::{
-        AUTOMAP_CC_STATUS, AlertType, AutomapButton, Button, Encoder, PageButton, Pot, ProductType,
-        RingMode, RowSelect, Slider, TransportButton,
+        AUTOMAP_CC_STATUS, AlertType, AutomapButton, Button, Encoder, PageButton,
+        ParameterRequestType, Pot, ProductType, RingMode, RowSelect, Slider, TransportButton,
     },
     sysex::DecodeError,
 };
 
 use derive_more::{Debug, TryFrom};
 
+/// A wheel beside the SL MkII's keyboard; the ZeROs have none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wheel {
+    /// The mod wheel, 0-127, sent as CC 0x01 on the Automap port.
+    Mod { value: u8 },
+    /// The pitch wheel, -8192 to 8191 with 0 at rest. It is an ordinary
+    /// pitch bend on port 1, the keyboard's port, so it only arrives
+    /// through the class-compliant MIDI streaming interface.
+    Pitch { value: i16 },
+}
+
+/// The answer to a [parameter request](crate::AutomapCommand::ParameterRequest),
+/// read by what was asked.
+///
+/// The unit answers the product type request on CC 0x67 and the transport
+/// lock request on CC 0x4F, as a [`AutomapEvent::TransportLockStatus`].
+/// Neither says it is an answer, so that is only known from the requests
+/// sent before it. [`decode()`](Self::decode) takes the request type to
+/// read a value by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterResponse {
+    ProductType(ProductType),
+    TransportLock(bool),
+}
+
+impl ParameterResponse {
+    /// Reads `value` as the answer to `request`, or `None` if it is not a
+    /// valid one.
+    pub fn decode(request: ParameterRequestType, value: u8) -> Option<Self> {
+        match request {
+            ParameterRequestType::UnitProductType => {
+                ProductType::try_from(value).ok().map(Self::ProductType)
+            }
+            ParameterRequestType::TransportLockState => Some(Self::TransportLock(value != 0)),
+        }
+    }
+
+    /// The request this answers.
+    pub fn request_type(&self) -> ParameterRequestType {
+        match self {
+            Self::ProductType(_) => ParameterRequestType::UnitProductType,
+            Self::TransportLock(_) => ParameterRequestType::TransportLockState,
+        }
+    }
+
+    /// The CC the answer is sent on.
+    pub fn cc(&self) -> u8 {
+        match self {
+            Self::ProductType(_) => 0x67,
+            Self::TransportLock(_) => 0x4F,
+        }
+    }
+
+    /// The value as sent.
+    pub fn value(&self) -> u8 {
+        match *self {
+            Self::ProductType(product) => product as u8,
+            Self::TransportLock(enabled) => enabled as u8,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AutomapEvent {
-    ModWheel {
-        cc: u8,
-        value: u8,
+    Wheel {
+        wheel: Wheel,
     },
 
     Button {
@@ -37,12 +98,12 @@ pub enum AutomapEvent {
 
     Pot {
         pot: Pot,
-        value: i8,
+        value: u8, // 0-127
     },
 
     Slider {
         slider: Slider,
-        value: i8,
+        value: u8, // 0-127
     },
 
     RowSelect {
@@ -50,6 +111,20 @@ pub enum AutomapEvent {
         selected: bool,
     },
 
+    /// An encoder turned while the Ableton Live template is loaded, which
+    /// sends CC 0x38-0x3F instead of 0x78-0x7F (Section 5, note 1, PDF
+    /// page 11). The manual gives no value format; the clicks are read the
+    /// way the other encoders send them.
+    LiveEncoder {
+        encoder: Encoder,
+        clicks: i8,
+    },
+
+    /// Encoders-D row select (0x6A), Compact only - Section 6, PDF page 12.
+    EncoderRowSelect {
+        selected: bool,
+    },
+
     /// LH Row-Select LED bitmap (CC 0x60)
     RowLhBitmap {
         bits: u8,
@@ -121,6 +196,9 @@ pub enum AutomapEvent {
 
     /// Alert from device about configuration changes (0x5C)
     /// Section 6, PDF page 10
+    ///
+    /// The CC only names the setting; read its new value with
+    /// [`AutomapDevice::alert_value()`](crate::AutomapDevice::alert_value).
     Alert {
         alert_type: AlertType,
     },
@@ -153,16 +231,84 @@ pub enum AutomapEvent {
         value: u8,
     },
 
+    /// A CC 0x67 answer not matched to a request: no product type request
+    /// was waiting, or the value is not a valid product type.
     ParameterResponse {
         response: u8,
     },
 
+    /// An answer matched to the request it answers. The device decodes
+    /// [`ParameterResponse`](AutomapEvent::ParameterResponse) and
+    /// [`TransportLockStatus`](AutomapEvent::TransportLockStatus) into this
+    /// while a request of that type is waiting.
+    Parameter {
+        response: ParameterResponse,
+    },
+
+    /// The unit loaded a special template (`special`), such as the Automap
+    /// one, or unloaded the last one (0x6B) - Section 6, PDF page 13.
+    ///
+    /// It is sent when the user switches templates on the unit. The
+    /// protocol has no message carrying the template number, nor one that
+    /// selects a template from the host.
+    TemplateChanged {
+        special: bool,
+    },
+
     Raw {
         cc: u8,
         value: u8,
     },
 }
 
+fn encode_clicks(clicks: i8) -> u8 {
+    let magnitude = clicks.unsigned_abs().min(0x3F);
+    if clicks < 0 {
+        0x40 | magnitude
+    } else {
+        magnitude
+    }
+}
+
+/// Rejects messages the unit is not known to send, for the `strict`
+/// feature: values the lenient decoder would read loosely or pass on as
+/// [`AutomapEvent::Raw`].
+#[cfg(feature = "strict")]
+fn check_strict(body: &[u8]) -> Result<(), DecodeError> {
+    let (status, nn, vv) = (body[0], body[1], body[2]);
+    if status & 0xF0 != 0xB0 || nn > 0x7F || vv > 0x7F {
+        return Err(DecodeError::Invalid);
+    }
+    let known = match nn {
+        // Presses and selections, sent as 0 and 1
+        0x18..=0x37 | 0x4E | 0x4F | 0x50..=0x5B | 0x65 | 0x6A | 0x6B => vv <= 0x01,
+        0x40 => vv == 0x00 || vv == 0x7F,
+        // Transport buttons use 0/1, Automap buttons 0x40/0x41
+        0x48..=0x4D => matches!(vv, 0x00 | 0x01 | 0x40 | 0x41),
+        0x5C => AlertType::try_from(vv).is_ok(),
+        // Control index in the low bits, touched in bit 6
+        0x6C..=0x6E => vv & !0x47 == 0,
+        0x6F => vv & !0x41 == 0,
+        0x01
+        | 0x08..=0x17
+        | 0x38..=0x3F
+        | 0x41
+        | 0x42
+        | 0x44..=0x47
+        | 0x5E..=0x61
+        | 0x63
+        | 0x66
+        | 0x67
+        | 0x78..=0x7F => true,
+        _ => return Err(DecodeError::Unsupported),
+    };
+    if known {
+        Ok(())
+    } else {
+        Err(DecodeError::Invalid)
+    }
+}
+
 fn decode_clicks(vv: u8) -> i8 {
     let clockwise = (vv & 0x40) == 0;
     let clicks = vv & 0x3F;
@@ -174,21 +320,40 @@ fn decode_clicks(vv: u8) -> i8 {
 }
 
 impl AutomapEvent {
+    /// Decodes one CC message from the unit, or a pitch bend from the pitch
+    /// wheel.
+    ///
+    /// CCs and values with no known meaning become [`AutomapEvent::Raw`].
+    /// With the `strict` feature they are errors instead:
+    /// [`DecodeError::Unsupported`] for an unknown CC and
+    /// [`DecodeError::Invalid`] for an unexpected value, such as a button
+    /// velocity other than 0 or 1.
     pub fn decode_event(body: &[u8]) -> Result<AutomapEvent, DecodeError> {
         if body.len() != 3 {
             return Err(DecodeError::Truncated);
         }
+        if body[0] & 0xF0 == 0xE0 {
+            let bend = u16::from(body[1] & 0x7F) | u16::from(body[2] & 0x7F) << 7;
+            let wheel = Wheel::Pitch {
+                value: bend as i16 - 0x2000,
+            };
+            return Ok(AutomapEvent::Wheel { wheel });
+        }
+        #[cfg(feature = "strict")]
+        check_strict(body)?;
         let nn = body[1];
         let vv = body[2];
         match nn {
-            0x01 => Ok(AutomapEvent::ModWheel { cc: nn, value: vv }),
+            0x01 => Ok(AutomapEvent::Wheel {
+                wheel: Wheel::Mod { value: vv },
+            }),
             0x08..=0x0F => Ok(AutomapEvent::Pot {
                 pot: Pot::try_from(nn).unwrap(), // safe due to match range
-                value: vv as i8,                 // MIDI values are 0-127
+                value: vv,
             }),
             0x10..=0x17 => Ok(AutomapEvent::Slider {
                 slider: Slider::try_from(nn).unwrap(), // safe due to match range
-                value: vv as i8,                       // MIDI values are 0-127
+                value: vv,
             }),
             // Button groups A, B, C
             0x18..=0x37 => {
@@ -197,6 +362,10 @@ impl AutomapEvent {
                     pressed: vv != 0,
                 })
             }
+            0x38..=0x3F => Ok(AutomapEvent::LiveEncoder {
+                encoder: Encoder::try_from(nn + 0x40).unwrap(), // safe due to match range
+                clicks: decode_clicks(vv),
+            }),
             0x40 => Ok(AutomapEvent::SustainPedal {
                 pressed: vv == 0x7F,
             }),
@@ -244,18 +413,30 @@ impl AutomapEvent {
                 clicks: decode_clicks(vv),
             }),
             0x67 => Ok(AutomapEvent::ParameterResponse { response: vv }),
-            0x6C => Ok(AutomapEvent::EncoderTouch {
-                encoder: Encoder::try_from((vv & 0x0F) + 0x78).unwrap(), // safe due to value range
-                touched: (vv & 0x40) != 0,
-            }),
-            0x6D => Ok(AutomapEvent::PotTouch {
-                pot: Pot::try_from((vv & 0x0F) + 0x08).unwrap(), // safe due to value range
-                touched: (vv & 0x40) != 0,
-            }),
-            0x6E => Ok(AutomapEvent::SliderTouch {
-                slider: Slider::try_from((vv & 0x0F) + 0x10).unwrap(), // safe due to value range
-                touched: (vv & 0x40) != 0,
-            }),
+            0x6A => Ok(AutomapEvent::EncoderRowSelect { selected: vv != 0 }),
+            0x6B => Ok(AutomapEvent::TemplateChanged { special: vv != 0 }),
+            // Index 8-F names no control; pass it on rather than guess
+            0x6C => match Encoder::try_from((vv & 0x0F) + 0x78) {
+                Ok(encoder) => Ok(AutomapEvent::EncoderTouch {
+                    encoder,
+                    touched: (vv & 0x40) != 0,
+                }),
+                Err(_) => Ok(AutomapEvent::Raw { cc: nn, value: vv }),
+            },
+            0x6D => match Pot::try_from((vv & 0x0F) + 0x08) {
+                Ok(pot) => Ok(AutomapEvent::PotTouch {
+                    pot,
+                    touched: (vv & 0x40) != 0,
+                }),
+                Err(_) => Ok(AutomapEvent::Raw { cc: nn, value: vv }),
+            },
+            0x6E => match Slider::try_from((vv & 0x0F) + 0x10) {
+                Ok(slider) => Ok(AutomapEvent::SliderTouch {
+                    slider,
+                    touched: (vv & 0x40) != 0,
+                }),
+                Err(_) => Ok(AutomapEvent::Raw { cc: nn, value: vv }),
+            },
             0x6F => {
                 if vv & 0x1 == 0 {
                     Ok(AutomapEvent::SpeedDialTouch {
@@ -267,7 +448,318 @@ impl AutomapEvent {
                     })
                 }
             }
+            0x78..=0x7F => Ok(AutomapEvent::Encoder {
+                encoder: Encoder::try_from(nn).unwrap(), // safe due to match range
+                clicks: decode_clicks(vv),
+            }),
             _ => Ok(AutomapEvent::Raw { cc: nn, value: vv }),
         }
     }
+
+    /// Encodes the event as the CC message the device sends for it, or the
+    /// pitch bend on channel 1 for the pitch wheel.
+    ///
+    /// The inverse of [`decode_event()`](Self::decode_event), for forwarding
+    /// decoded events to another MIDI port or simulating the device. Pressed
+    /// and touched states use the values the unit itself sends.
+    ///
+    /// This is what a JACK bridge would write to its output port; the crate
+    /// has no JACK backend of its own, see the
+    /// [crate docs](crate#not-provided).
+    pub fn to_bytes(self) -> Vec<u8> {
+        let touch = |index: u8, touched: bool| index | if touched { 0x40 } else { 0x00 };
+        let (nn, vv) = match self {
+            AutomapEvent::Wheel {
+                wheel: Wheel::Mod { value },
+            } => (0x01, value),
+            AutomapEvent::Wheel {
+                wheel: Wheel::Pitch { value },
+            } => {
+                let bend = (value.clamp(-0x2000, 0x1FFF) + 0x2000) as u16;
+                return vec![0xE0, (bend & 0x7F) as u8, (bend >> 7) as u8];
+            }
+            AutomapEvent::Button { button, pressed } => (button as u8, pressed as u8),
+            AutomapEvent::TransportButton { button, pressed } => (button as u8, pressed as u8),
+            AutomapEvent::AutomapButton { button, pressed } => (button as u8, 0x40 | pressed as u8),
+            AutomapEvent::Encoder { encoder, clicks } => (encoder as u8, encode_clicks(clicks)),
+            AutomapEvent::Pot { pot, value } => (pot as u8, value),
+            AutomapEvent::Slider { slider, value } => (slider as u8, value),
+            AutomapEvent::RowSelect { row, selected } => (row as u8, selected as u8),
+            AutomapEvent::LiveEncoder { encoder, clicks } => {
+                (encoder as u8 - 0x40, encode_clicks(clicks))
+            }
+            AutomapEvent::EncoderRowSelect { selected } => (0x6A, selected as u8),
+            AutomapEvent::RowLhBitmap { bits } => (0x60, bits),
+            AutomapEvent::RowRhBitmap { bits } => (0x61, bits),
+            AutomapEvent::EncoderTouch { encoder, touched } => {
+                (0x6C, touch(encoder as u8 - 0x78, touched))
+            }
+            AutomapEvent::PotTouch { pot, touched } => (0x6D, touch(pot as u8 - 0x08, touched)),
+            AutomapEvent::SliderTouch { slider, touched } => {
+                (0x6E, touch(slider as u8 - 0x10, touched))
+            }
+            AutomapEvent::CrossFadeTouch { touched } => (0x6F, touch(0x01, touched)),
+            AutomapEvent::SpeedDialTouch { touched } => (0x6F, touch(0x00, touched)),
+            AutomapEvent::PageButton { button, pressed } => (button as u8, pressed as u8),
+            AutomapEvent::SustainPedal { pressed } => (0x40, if pressed { 0x7F } else { 0x00 }),
+            AutomapEvent::ExpressionPedal { value } => (0x41, value),
+            AutomapEvent::CrossFader { value } => (0x42, value),
+            AutomapEvent::TouchpadX1 { value } => (0x44, value),
+            AutomapEvent::TouchpadY1 { value } => (0x45, value),
+            AutomapEvent::TouchpadX2 { value } => (0x46, value),
+            AutomapEvent::TouchpadY2 { value } => (0x47, value),
+            AutomapEvent::Alert { alert_type } => (0x5C, alert_type as u8),
+            AutomapEvent::SpeedDial { clicks } => (0x66, encode_clicks(clicks)),
+            AutomapEvent::SpeedDialButton { pressed } => (0x65, pressed as u8),
+            AutomapEvent::PreviewButton { pressed } => (0x4E, pressed as u8),
+            AutomapEvent::TransportLockStatus { enabled } => (0x4F, enabled as u8),
+            AutomapEvent::TempoMsb { value } => (0x5E, value),
+            AutomapEvent::TempoLsb { value } => (0x5F, value),
+            AutomapEvent::EchoResponse { value } => (0x63, value),
+            AutomapEvent::ParameterResponse { response } => (0x67, response),
+            AutomapEvent::Parameter { response } => (response.cc(), response.value()),
+            AutomapEvent::TemplateChanged { special } => (0x6B, special as u8),
+            AutomapEvent::Raw { cc, value } => (cc, value),
+        };
+        vec![AUTOMAP_CC_STATUS, nn & 0x7F, vv & 0x7F]
+    }
+
+    /// The value of a pot, slider, pedal, the crossfader or a touchpad
+    /// axis, scaled from 0-127 to 0.0-1.0. `None` for other events.
+    pub fn normalized(&self) -> Option<f32> {
+        let value = match *self {
+            AutomapEvent::Pot { value, .. }
+            | AutomapEvent::Slider { value, .. }
+            | AutomapEvent::ExpressionPedal { value }
+            | AutomapEvent::CrossFader { value }
+            | AutomapEvent::TouchpadX1 { value }
+            | AutomapEvent::TouchpadY1 { value }
+            | AutomapEvent::TouchpadX2 { value }
+            | AutomapEvent::TouchpadY2 { value } => value,
+            _ => return None,
+        };
+        Some(f32::from(value & 0x7F) / 127.0)
+    }
+
+    /// The clicks of an encoder or speed-dial turn. `None` for other
+    /// events.
+    pub fn clicks(&self) -> Option<i8> {
+        match *self {
+            AutomapEvent::Encoder { clicks, .. }
+            | AutomapEvent::LiveEncoder { clicks, .. }
+            | AutomapEvent::SpeedDial { clicks } => Some(clicks),
+            _ => None,
+        }
+    }
+}
+
+/// Concise human-readable form, e.g. `Encoder3 +2` or `ButtonB5 down`.
+impl std::fmt::Display for AutomapEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let press = |pressed: bool| if pressed { "down" } else { "up" };
+        let touch = |touched: bool| if touched { "touched" } else { "released" };
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        match *self {
+            AutomapEvent::Wheel {
+                wheel: Wheel::Mod { value },
+            } => write!(f, "ModWheel {value}"),
+            AutomapEvent::Wheel {
+                wheel: Wheel::Pitch { value },
+            } => write!(f, "PitchWheel {value:+}"),
+            AutomapEvent::Button { button, pressed } => write!(f, "{button} {}", press(pressed)),
+            AutomapEvent::TransportButton { button, pressed } => {
+                write!(f, "{button} {}", press(pressed))
+            }
+            AutomapEvent::AutomapButton { button, pressed } => {
+                write!(f, "{button} {}", press(pressed))
+            }
+            AutomapEvent::Encoder { encoder, clicks } => write!(f, "{encoder} {clicks:+}"),
+            AutomapEvent::Pot { pot, value } => write!(f, "{pot} {value}"),
+            AutomapEvent::Slider { slider, value } => write!(f, "{slider} {value}"),
+            AutomapEvent::RowSelect { row, selected } => {
+                write!(f, "RowSelect{row} {}", press(selected))
+            }
+            AutomapEvent::LiveEncoder { encoder, clicks } => {
+                write!(f, "Live{encoder} {clicks:+}")
+            }
+            AutomapEvent::EncoderRowSelect { selected } => {
+                write!(f, "EncoderRowSelect {}", press(selected))
+            }
+            AutomapEvent::RowLhBitmap { bits } => write!(f, "RowLhBitmap {bits:#07b}"),
+            AutomapEvent::RowRhBitmap { bits } => write!(f, "RowRhBitmap {bits:#06b}"),
+            AutomapEvent::EncoderTouch { encoder, touched } => {
+                write!(f, "{encoder} {}", touch(touched))
+            }
+            AutomapEvent::PotTouch { pot, touched } => write!(f, "{pot} {}", touch(touched)),
+            AutomapEvent::SliderTouch { slider, touched } => {
+                write!(f, "{slider} {}", touch(touched))
+            }
+            AutomapEvent::CrossFadeTouch { touched } => write!(f, "CrossFader {}", touch(touched)),
+            AutomapEvent::SpeedDialTouch { touched } => write!(f, "SpeedDial {}", touch(touched)),
+            AutomapEvent::PageButton { button, pressed } => {
+                write!(f, "{button} {}", press(pressed))
+            }
+            AutomapEvent::SustainPedal { pressed } => write!(f, "Sustain {}", press(pressed)),
+            AutomapEvent::ExpressionPedal { value } => write!(f, "Expression {value}"),
+            AutomapEvent::CrossFader { value } => write!(f, "CrossFader {value}"),
+            AutomapEvent::TouchpadX1 { value } => write!(f, "TouchpadX1 {value}"),
+            AutomapEvent::TouchpadY1 { value } => write!(f, "TouchpadY1 {value}"),
+            AutomapEvent::TouchpadX2 { value } => write!(f, "TouchpadX2 {value}"),
+            AutomapEvent::TouchpadY2 { value } => write!(f, "TouchpadY2 {value}"),
+            AutomapEvent::Alert { alert_type } => write!(f, "Alert {alert_type}"),
+            AutomapEvent::SpeedDial { clicks } => write!(f, "SpeedDial {clicks:+}"),
+            AutomapEvent::SpeedDialButton { pressed } => {
+                write!(f, "SpeedDialButton {}", press(pressed))
+            }
+            AutomapEvent::PreviewButton { pressed } => write!(f, "Preview {}", press(pressed)),
+            AutomapEvent::TransportLockStatus { enabled } => {
+                write!(f, "TransportLock {}", on_off(enabled))
+            }
+            AutomapEvent::TempoMsb { value } => write!(f, "TempoMsb {value}"),
+            AutomapEvent::TempoLsb { value } => write!(f, "TempoLsb {value}"),
+            AutomapEvent::EchoResponse { value } => write!(f, "Echo {value}"),
+            AutomapEvent::ParameterResponse { response } => write!(f, "Parameter {response}"),
+            AutomapEvent::Parameter {
+                response: ParameterResponse::ProductType(product),
+            } => write!(f, "ProductType {product:?}"),
+            AutomapEvent::Parameter {
+                response: ParameterResponse::TransportLock(enabled),
+            } => write!(f, "TransportLock {}", on_off(enabled)),
+            AutomapEvent::TemplateChanged { special: true } => write!(f, "SpecialTemplate loaded"),
+            AutomapEvent::TemplateChanged { special: false } => {
+                write!(f, "SpecialTemplate unloaded")
+            }
+            AutomapEvent::Raw { cc, value } => write!(f, "CC {cc:#04x} {value}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_roundtrip() {
+        let events = [
+            AutomapEvent::Button {
+                button: Button::ButtonC8,
+                pressed: true,
+            },
+            AutomapEvent::AutomapButton {
+                button: AutomapButton::AutomapButton2,
+                pressed: false,
+            },
+            AutomapEvent::SpeedDial { clicks: -5 },
+            AutomapEvent::SliderTouch {
+                slider: Slider::Slider3,
+                touched: true,
+            },
+            AutomapEvent::CrossFadeTouch { touched: false },
+            AutomapEvent::SustainPedal { pressed: true },
+            AutomapEvent::TemplateChanged { special: false },
+            AutomapEvent::LiveEncoder {
+                encoder: Encoder::Encoder8,
+                clicks: -2,
+            },
+            AutomapEvent::EncoderRowSelect { selected: true },
+            AutomapEvent::Wheel {
+                wheel: Wheel::Mod { value: 0x40 },
+            },
+            AutomapEvent::Wheel {
+                wheel: Wheel::Pitch { value: -8192 },
+            },
+            AutomapEvent::Wheel {
+                wheel: Wheel::Pitch { value: 300 },
+            },
+        ];
+        for event in events {
+            assert_eq!(AutomapEvent::decode_event(&event.to_bytes()), Ok(event));
+        }
+    }
+
+    #[test]
+    fn test_unexpected_values() {
+        let velocity = AutomapEvent::decode_event(&[0xBF, 0x18, 0x05]);
+        let unknown_cc = AutomapEvent::decode_event(&[0xBF, 0x43, 0x01]);
+        if cfg!(feature = "strict") {
+            assert_eq!(velocity, Err(DecodeError::Invalid));
+            assert_eq!(unknown_cc, Err(DecodeError::Unsupported));
+        } else {
+            assert!(matches!(
+                velocity,
+                Ok(AutomapEvent::Button { pressed: true, .. })
+            ));
+            assert_eq!(unknown_cc, Ok(AutomapEvent::Raw { cc: 0x43, value: 1 }));
+        }
+    }
+
+    #[test]
+    fn test_touch_index_out_of_range() {
+        for body in [[0xBF, 0x6C, 0x48], [0xBF, 0x6D, 0x4F], [0xBF, 0x6E, 0x0C]] {
+            let event = AutomapEvent::decode_event(&body);
+            if cfg!(feature = "strict") {
+                assert_eq!(event, Err(DecodeError::Invalid));
+            } else {
+                let raw = AutomapEvent::Raw {
+                    cc: body[1],
+                    value: body[2],
+                };
+                assert_eq!(event, Ok(raw));
+            }
+        }
+    }
+
+    #[test]
+    fn test_event_display() {
+        let encoder = AutomapEvent::Encoder {
+            encoder: Encoder::Encoder3,
+            clicks: 2,
+        };
+        assert_eq!(encoder.to_string(), "Encoder3 +2");
+        assert_eq!(AutomapEvent::decode_event(&encoder.to_bytes()), Ok(encoder));
+        let button = AutomapEvent::Button {
+            button: Button::ButtonB5,
+            pressed: true,
+        };
+        assert_eq!(button.to_string(), "ButtonB5 down");
+        let play = AutomapEvent::TransportButton {
+            button: TransportButton::ButtonD4Tl,
+            pressed: false,
+        };
+        assert_eq!(play.to_string(), "Play up");
+    }
+
+    #[test]
+    fn test_normalized() {
+        let slider = AutomapEvent::decode_event(&[0xBF, 0x11, 0x7F]).unwrap();
+        assert_eq!(
+            slider,
+            AutomapEvent::Slider {
+                slider: Slider::Slider2,
+                value: 127,
+            }
+        );
+        assert_eq!(slider.normalized(), Some(1.0));
+        assert_eq!(
+            AutomapEvent::CrossFader { value: 0 }.normalized(),
+            Some(0.0)
+        );
+        let encoder = AutomapEvent::Encoder {
+            encoder: Encoder::Encoder3,
+            clicks: 2,
+        };
+        assert_eq!(encoder.normalized(), None);
+    }
+
+    #[test]
+    fn test_pitch_wheel() {
+        let rest = AutomapEvent::decode_event(&[0xE0, 0x00, 0x40]);
+        let up = AutomapEvent::decode_event(&[0xE3, 0x7F, 0x7F]);
+        let pitch = |value| AutomapEvent::Wheel {
+            wheel: Wheel::Pitch { value },
+        };
+        assert_eq!(rest, Ok(pitch(0)));
+        assert_eq!(up, Ok(pitch(8191)));
+        assert_eq!(pitch(8191).to_string(), "PitchWheel +8191");
+    }
 }
@@ -0,0 +1,36 @@
+//! Members of the unit's global settings.
+//!
+//! Where each setting lives is listed in Novation's "SLMKII Global
+//! Offsets" document, which is only handed out on request and is not in
+//! this repository. The MIDI Programmer's Reference shows a single member,
+//! the drum pad thresholds, in its data-block examples. Display settings
+//! such as LCD contrast or a screensaver timeout are not known to be among
+//! the globals at all, so there are no accessors for them; with the
+//! offsets document at hand, describe them with [`GlobalField::new()`].
+//! The memory-protect flag is among them, at an offset not published;
+//! once known, pass it to
+//! [`DeviceConfig::memory_protect_flag()`](crate::DeviceConfig::memory_protect_flag).
+//!
+//! Read and change members with
+//! [`AutomapDevice::read_global()`](crate::AutomapDevice::read_global) and
+//! [`write_global()`](crate::AutomapDevice::write_global). Changes go to
+//! RAM and are lost at power-off unless the globals are then saved with
+//! [`save_globals_to_flash()`](crate::AutomapDevice::save_globals_to_flash).
+
+/// A run of bytes within the globals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlobalField {
+    /// 0-based offset into the globals, 14 bits.
+    pub offset: u16,
+    pub len: u16,
+}
+
+impl GlobalField {
+    pub const fn new(offset: u16, len: u16) -> Self {
+        Self { offset, len }
+    }
+}
+
+/// The eight drum pad thresholds, one byte per pad (Programmer's
+/// Reference, pages 27-28).
+pub const DRUMPAD_THRESHOLDS: GlobalField = GlobalField::new(0x52, 8);
@@ -6,6 +6,8 @@ use sysex::{AutomapSysEx, LcdClear, LcdLine, LcdOp, PROTO_VER_BETA, PROTO_VER_MA
 
 pub mod cc;
 pub mod command;
+pub mod consts;
 pub mod event;
+pub mod globals;
 pub mod sysex;
 pub mod template;
@@ -1,8 +1,12 @@
+use super::consts::*;
+
 pub const NOVATION_ID: [u8; 5] = [0xF0, 0x00, 0x20, 0x29, 0x03];
 pub const EOX: u8 = 0xF7;
 pub const PROTO_VER_MAIN: u8 = 0x12; // BCD 1.2 per docs
 pub const PROTO_VER_BETA: u8 = 0x00;
 
+pub use crate::midi::MAX_SYSEX_LEN;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProtoFamily {
     Automap0303,
@@ -17,6 +21,8 @@ pub enum DecodeError {
     Truncated,
     Invalid,
     Unsupported,
+    /// Longer than [`MAX_SYSEX_LEN`].
+    Oversize,
 }
 
 // 14-bit helpers used by Data-Block formats
@@ -29,6 +35,37 @@ pub fn unpack_u14(lsb: u8, msb: u8) -> u16 {
     (lsb as u16) | ((msb as u16) << 7)
 }
 
+/// Packs 8-bit data into SysEx-safe bytes the way the unit sends its LED
+/// bitmap (PDF page 31): the low seven bits of every byte in order, then
+/// their top bits, seven to a byte, the first byte's in bit 0.
+///
+/// Templates and globals are 7-bit already and are sent as they are; this
+/// is for data that is not.
+pub fn pack7(data: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = data.iter().map(|b| b & 0x7F).collect();
+    out.extend(
+        data.chunks(7)
+            .map(|chunk| chunk.iter().rev().fold(0, |bits, b| (bits << 1) | (b >> 7))),
+    );
+    out
+}
+
+/// The `len` bytes [`pack7()`] packed into `packed`, or `None` if it is too
+/// short. Anything after them, like the LED bitmap's spare byte, is
+/// ignored.
+pub fn unpack7(packed: &[u8], len: usize) -> Option<Vec<u8>> {
+    let (low, high) = packed.split_at_checked(len)?;
+    if high.len() < len.div_ceil(7) {
+        return None;
+    }
+    Some(
+        low.iter()
+            .enumerate()
+            .map(|(i, b)| (b & 0x7F) | (((high[i / 7] >> (i % 7)) & 1) << 7))
+            .collect(),
+    )
+}
+
 // ============================== AUTOMAP (03:03) ==============================
 
 /// A complete Automap command (the byte after “… 03 03 VV bb 02 00”).
@@ -70,7 +107,7 @@ pub enum LcdOp<'a> {
     Clear(LcdClear),                   // 0x02
     CursorBlink(bool),                 // 0x03 (not implemented by unit)
     Text(&'a [u8]),                    // 0x04 null-terminated at encode
-    Unknown(u8, &'a [u8]),             // passthrough
+    Unknown(u8, &'a [u8]),             // passthrough: the rest of the stream, undecoded
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -81,6 +118,16 @@ pub enum LcdLine {
     RightBottom = 4,
 }
 
+impl LcdLine {
+    /// All lines, in wire order (the order used by LCD text readback).
+    pub const ALL: [LcdLine; 4] = [
+        LcdLine::LeftTop,
+        LcdLine::RightTop,
+        LcdLine::LeftBottom,
+        LcdLine::RightBottom,
+    ];
+}
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LcdClear {
@@ -93,12 +140,59 @@ pub enum LcdClear {
     LeftBottomLine = 0x07,
     RightTopLine = 0x08,
     RightBottomLine = 0x09,
-    FromCursorCount(u8) = 0x0A, // will be emitted as 0x0A, <count>
+    FromCursorCount(u8) = 0x0A, // emitted as 0x0A, <count>; see code()
 }
 
 impl LcdClear {
-    fn discriminant(&self) -> u8 {
-        unsafe { *(self as *const Self as *const u8) }
+    /// Every clear code, with `FromCursorCount` at count 0.
+    pub const ALL: [LcdClear; 10] = [
+        LcdClear::BothDisplays,
+        LcdClear::BothTopLines,
+        LcdClear::BothBottomLines,
+        LcdClear::LeftAll,
+        LcdClear::RightAll,
+        LcdClear::LeftTopLine,
+        LcdClear::LeftBottomLine,
+        LcdClear::RightTopLine,
+        LcdClear::RightBottomLine,
+        LcdClear::FromCursorCount(0),
+    ];
+
+    /// The clear code byte. `FromCursorCount` is followed by its count.
+    pub fn code(self) -> u8 {
+        match self {
+            LcdClear::BothDisplays => 0x01,
+            LcdClear::BothTopLines => 0x02,
+            LcdClear::BothBottomLines => 0x03,
+            LcdClear::LeftAll => 0x04,
+            LcdClear::RightAll => 0x05,
+            LcdClear::LeftTopLine => 0x06,
+            LcdClear::LeftBottomLine => 0x07,
+            LcdClear::RightTopLine => 0x08,
+            LcdClear::RightBottomLine => 0x09,
+            LcdClear::FromCursorCount(_) => 0x0A,
+        }
+    }
+}
+
+/// Decodes a one-byte clear code. `0x0A` needs the count byte that follows
+/// it, so it is rejected here; see [`LcdClear::FromCursorCount`].
+impl TryFrom<u8> for LcdClear {
+    type Error = DecodeError;
+
+    fn try_from(code: u8) -> Result<Self, DecodeError> {
+        Ok(match code {
+            0x01 => LcdClear::BothDisplays,
+            0x02 => LcdClear::BothTopLines,
+            0x03 => LcdClear::BothBottomLines,
+            0x04 => LcdClear::LeftAll,
+            0x05 => LcdClear::RightAll,
+            0x06 => LcdClear::LeftTopLine,
+            0x07 => LcdClear::LeftBottomLine,
+            0x08 => LcdClear::RightTopLine,
+            0x09 => LcdClear::RightBottomLine,
+            _ => return Err(DecodeError::Invalid),
+        })
     }
 }
 
@@ -164,10 +258,14 @@ pub enum SimCmd {
         number_1_based: u8,
         clicks_signed: i8,
     }, // 0x03 (±64)
-    LcdTextRequest,    // 0x04
-    LcdTextResponse,   // 0x05
-    LedBitmapRequest,  // 0x06
-    LedBitmapResponse, // 0x07
+    LcdTextRequest, // 0x04
+    LcdTextResponse {
+        text: Vec<u8>,
+    }, // 0x05 (4 lines x 72 chars)
+    LedBitmapRequest, // 0x06
+    LedBitmapResponse {
+        data: Vec<u8>,
+    }, // 0x07 (20 LED bytes, 7-bit packed)
     Key {
         number_1_based: u8,
         velocity: u8,
@@ -205,22 +303,24 @@ pub enum SimHighLevel {
 impl LcdOp<'_> {
     fn encode_into(&self, out: &mut Vec<u8>, _ver_main: u8, _ver_beta: u8) {
         match self {
-            LcdOp::End => out.push(0x00),
+            LcdOp::End => out.push(LCD_END),
             LcdOp::Cursor { col, line } => {
-                out.extend_from_slice(&[0x01, *col, *line as u8]);
+                out.extend_from_slice(&[LCD_CURSOR, *col, *line as u8]);
             }
             LcdOp::Clear(code) => {
-                out.push(0x02);
-                match code {
-                    LcdClear::FromCursorCount(n) => out.extend_from_slice(&[0x0A, *n]),
-                    other => out.push(other.discriminant()),
+                out.push(LCD_CLEAR);
+                out.push(code.code());
+                if let LcdClear::FromCursorCount(n) = code {
+                    out.push(*n);
                 }
             }
-            LcdOp::CursorBlink(on) => out.extend_from_slice(&[0x03, if *on { 1 } else { 0 }]),
+            LcdOp::CursorBlink(on) => {
+                out.extend_from_slice(&[LCD_CURSOR_BLINK, if *on { 1 } else { 0 }])
+            }
             LcdOp::Text(bytes) => {
-                out.push(0x04);
+                out.push(LCD_TEXT);
                 out.extend_from_slice(bytes);
-                out.push(0x00);
+                out.push(LCD_END);
             }
             LcdOp::Unknown(t, data) => {
                 out.push(*t);
@@ -234,38 +334,40 @@ impl<'a> AutomapSysEx<'a> {
     fn encode_into(&self, out: &mut Vec<u8>) {
         // Header: F0 00 20 29 03 03 VV bb 02 00
         out.extend_from_slice(&NOVATION_ID);
-        out.push(0x03);
+        out.push(FAMILY_AUTOMAP);
 
         out.push(PROTO_VER_MAIN);
         out.push(PROTO_VER_BETA);
-        out.extend_from_slice(&[0x02, 0x00]);
+        out.extend_from_slice(&AUTOMAP_TAG);
 
         match self {
             AutomapSysEx::OnlineOffline {
                 online: to_host_online,
             } => {
-                out.push(0x01);
+                out.push(CMD_ONLINE_OFFLINE);
                 out.push(if *to_host_online { 0x01 } else { 0x00 });
             }
             AutomapSysEx::LcdText(ops) => {
-                out.push(0x02);
+                out.push(CMD_LCD_TEXT);
                 for op in ops {
                     op.encode_into(out, PROTO_VER_MAIN, PROTO_VER_BETA);
                 }
             }
-            AutomapSysEx::GlobalsDownloadRam => out.push(0x03),
-            AutomapSysEx::PrepareOsDownload => out.push(0x04),
+            AutomapSysEx::GlobalsDownloadRam => out.push(CMD_GLOBALS_DOWNLOAD_RAM),
+            AutomapSysEx::PrepareOsDownload => out.push(CMD_PREPARE_OS_DOWNLOAD),
             AutomapSysEx::UploadGlobals { data } => {
-                out.push(0x05);
+                out.push(CMD_UPLOAD_GLOBALS);
                 out.extend_from_slice(data);
             }
-            AutomapSysEx::GlobalsDownloadRamAndFlash => out.push(0x06),
+            AutomapSysEx::GlobalsDownloadRamAndFlash => {
+                out.push(CMD_GLOBALS_DOWNLOAD_RAM_AND_FLASH)
+            }
             AutomapSysEx::UploadTemplate { data } => {
-                out.push(0x07);
+                out.push(CMD_UPLOAD_TEMPLATE);
                 out.extend_from_slice(data);
             }
             AutomapSysEx::UploadOs { data } => {
-                out.push(0x08);
+                out.push(CMD_UPLOAD_OS);
                 out.extend_from_slice(data);
             }
             AutomapSysEx::Unknown { cmd, data } => {
@@ -289,10 +391,10 @@ impl<'a> DbSimMsg<'a> {
     fn encode_into(&self, out: &mut Vec<u8>, ver_main: u8, ver_beta: u8) {
         // Header: F0 00 20 29 03 05 VV bb 00 00
         out.extend_from_slice(&NOVATION_ID);
-        out.push(0x05);
+        out.push(FAMILY_DBSIM);
         out.push(ver_main);
         out.push(ver_beta);
-        out.extend_from_slice(&[0x00, 0x00]);
+        out.extend_from_slice(&DBSIM_TAG);
 
         match self {
             // main 0x68 (Change/Request)
@@ -302,11 +404,11 @@ impl<'a> DbSimMsg<'a> {
                 offset,
                 data,
             } => {
-                out.push(0x68);
+                out.push(DB_CHANGE);
                 out.push(match target {
-                    DbTarget::Control => 0x00,
-                    DbTarget::TemplateHeader => 0x01,
-                    DbTarget::Globals => 0x02,
+                    DbTarget::Control => DB_WRITE_CONTROL,
+                    DbTarget::TemplateHeader => DB_WRITE_TEMPLATE_HEADER,
+                    DbTarget::Globals => DB_WRITE_GLOBALS,
                 });
                 if let Some(c) = cn {
                     out.push(*c);
@@ -321,11 +423,11 @@ impl<'a> DbSimMsg<'a> {
                 offset,
                 len,
             } => {
-                out.push(0x68);
+                out.push(DB_CHANGE);
                 out.push(match target {
-                    DbTarget::Control => 0x03,
-                    DbTarget::TemplateHeader => 0x04,
-                    DbTarget::Globals => 0x05,
+                    DbTarget::Control => DB_READ_CONTROL,
+                    DbTarget::TemplateHeader => DB_READ_TEMPLATE_HEADER,
+                    DbTarget::Globals => DB_READ_GLOBALS,
                 });
                 if let Some(c) = cn {
                     out.push(*c);
@@ -341,11 +443,11 @@ impl<'a> DbSimMsg<'a> {
                 offset,
                 data,
             } => {
-                out.push(0x69);
+                out.push(DB_RESPONSE);
                 out.push(match target {
-                    DbTarget::Control => 0x03,
-                    DbTarget::TemplateHeader => 0x04,
-                    DbTarget::Globals => 0x05,
+                    DbTarget::Control => DB_READ_CONTROL,
+                    DbTarget::TemplateHeader => DB_READ_TEMPLATE_HEADER,
+                    DbTarget::Globals => DB_READ_GLOBALS,
                 });
                 if let Some(c) = cn {
                     out.push(*c);
@@ -356,7 +458,7 @@ impl<'a> DbSimMsg<'a> {
             }
             // Simulation main 0x66 / 0x6A
             DbSimMsg::Simulate(cmd) => {
-                out.push(0x66);
+                out.push(SIMULATE);
                 match cmd {
                     SimCmd::Button {
                         number_1_based,
@@ -378,10 +480,18 @@ impl<'a> DbSimMsg<'a> {
                         let v = (*clicks_signed as i16).clamp(-64, 63);
                         out.extend_from_slice(&[0x03, *number_1_based, (v as i8) as u8]);
                     }
-                    SimCmd::LcdTextRequest => out.extend_from_slice(&[0x04]),
-                    SimCmd::LcdTextResponse => out.extend_from_slice(&[0x05]),
-                    SimCmd::LedBitmapRequest => out.extend_from_slice(&[0x06]),
-                    SimCmd::LedBitmapResponse => out.extend_from_slice(&[0x07]),
+                    // The unit only answers the exact request "04 00 00 20" (X, Y, length
+                    // are not implemented); it always returns the full text of both LCDs.
+                    SimCmd::LcdTextRequest => out.extend_from_slice(&[0x04, 0x00, 0x00, 0x20]),
+                    SimCmd::LcdTextResponse { text } => {
+                        out.extend_from_slice(&[0x05, 0x00, 0x00, 0x20]);
+                        out.extend_from_slice(text);
+                    }
+                    SimCmd::LedBitmapRequest => out.extend_from_slice(&[0x06, 0x00, 0x00, 0x20]),
+                    SimCmd::LedBitmapResponse { data } => {
+                        out.extend_from_slice(&[0x07, 0x00, 0x00, 0x14]);
+                        out.extend_from_slice(data);
+                    }
                     SimCmd::Key {
                         number_1_based,
                         velocity,
@@ -404,7 +514,7 @@ impl<'a> DbSimMsg<'a> {
                 }
             }
             DbSimMsg::HighLevel(h) => {
-                out.push(0x6A);
+                out.push(SIM_HIGH_LEVEL);
                 out.push(match h {
                     SimHighLevel::SaveGlobalsToFlash => 0x00,
                     SimHighLevel::SaveCurrentTemplateToFlash => 0x01,
@@ -418,6 +528,13 @@ impl<'a> DbSimMsg<'a> {
 
         out.push(EOX);
     }
+
+    /// Convenience method to encode as a new Vec
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.encode_into(&mut buf, PROTO_VER_MAIN, PROTO_VER_BETA);
+        buf
+    }
 }
 
 // ============================== Decoding (framing + dispatch) ==============================
@@ -427,7 +544,10 @@ fn split_header(frame: &[u8]) -> Result<(ProtoFamily, u8, u8, &[u8]), DecodeErro
     if !frame.starts_with(&[0xF0]) || !frame.ends_with(&[EOX]) {
         return Err(DecodeError::NotSysEx);
     }
-    if frame.len() < 12 {
+    if frame.len() > MAX_SYSEX_LEN {
+        return Err(DecodeError::Oversize);
+    }
+    if frame.len() < SYSEX_HEADER_LEN + 2 {
         return Err(DecodeError::Truncated);
     }
     if frame[0..5] != NOVATION_ID {
@@ -436,14 +556,18 @@ fn split_header(frame: &[u8]) -> Result<(ProtoFamily, u8, u8, &[u8]), DecodeErro
     let fam = frame[5];
     let ver_main = frame[6];
     let ver_beta = frame[7];
-    let tag_a = frame[8];
-    let tag_b = frame[9];
-    let (family, start) = match (fam, tag_a, tag_b) {
-        (0x03, 0x02, 0x00) => (ProtoFamily::Automap0303, 10),
-        (0x05, 0x00, 0x00) => (ProtoFamily::DbSim0305, 10),
+    let tag = [frame[8], frame[9]];
+    let family = match (fam, tag) {
+        (FAMILY_AUTOMAP, AUTOMAP_TAG) => ProtoFamily::Automap0303,
+        (FAMILY_DBSIM, DBSIM_TAG) => ProtoFamily::DbSim0305,
         _ => return Err(DecodeError::BadFamily),
     };
-    Ok((family, ver_main, ver_beta, &frame[start..frame.len() - 1]))
+    Ok((
+        family,
+        ver_main,
+        ver_beta,
+        &frame[SYSEX_HEADER_LEN..frame.len() - 1],
+    ))
 }
 
 /// Decode a full frame into a semantic command.
@@ -472,16 +596,16 @@ fn decode_automap<'a>(body: &'a [u8]) -> Result<AutomapSysEx<'a>, DecodeError> {
     let cmd = body[0];
     let rest = &body[1..];
     Ok(match cmd {
-        0x01 => AutomapSysEx::OnlineOffline {
+        CMD_ONLINE_OFFLINE => AutomapSysEx::OnlineOffline {
             online: rest.first().copied().unwrap_or(0) != 0,
         },
-        0x02 => AutomapSysEx::LcdText(decode_lcd_ops(rest)?),
-        0x03 => AutomapSysEx::GlobalsDownloadRam,
-        0x04 => AutomapSysEx::PrepareOsDownload,
-        0x05 => AutomapSysEx::UploadGlobals { data: rest },
-        0x06 => AutomapSysEx::GlobalsDownloadRamAndFlash,
-        0x07 => AutomapSysEx::UploadTemplate { data: rest },
-        0x08 => AutomapSysEx::UploadOs { data: rest },
+        CMD_LCD_TEXT => AutomapSysEx::LcdText(decode_lcd_ops(rest)?),
+        CMD_GLOBALS_DOWNLOAD_RAM => AutomapSysEx::GlobalsDownloadRam,
+        CMD_PREPARE_OS_DOWNLOAD => AutomapSysEx::PrepareOsDownload,
+        CMD_UPLOAD_GLOBALS => AutomapSysEx::UploadGlobals { data: rest },
+        CMD_GLOBALS_DOWNLOAD_RAM_AND_FLASH => AutomapSysEx::GlobalsDownloadRamAndFlash,
+        CMD_UPLOAD_TEMPLATE => AutomapSysEx::UploadTemplate { data: rest },
+        CMD_UPLOAD_OS => AutomapSysEx::UploadOs { data: rest },
         c => AutomapSysEx::Unknown { cmd: c, data: rest },
     })
 }
@@ -492,8 +616,8 @@ fn decode_lcd_ops<'a>(mut s: &'a [u8]) -> Result<Vec<LcdOp<'a>>, DecodeError> {
         let t = s[0];
         s = &s[1..];
         let op = match t {
-            0x00 => LcdOp::End,
-            0x01 => {
+            LCD_END => LcdOp::End,
+            LCD_CURSOR => {
                 if s.len() < 2 {
                     return Err(DecodeError::Truncated);
                 }
@@ -508,7 +632,7 @@ fn decode_lcd_ops<'a>(mut s: &'a [u8]) -> Result<Vec<LcdOp<'a>>, DecodeError> {
                 s = &s[2..];
                 LcdOp::Cursor { col, line }
             }
-            0x02 => {
+            LCD_CLEAR => {
                 if s.is_empty() {
                     return Err(DecodeError::Truncated);
                 }
@@ -522,21 +646,10 @@ fn decode_lcd_ops<'a>(mut s: &'a [u8]) -> Result<Vec<LcdOp<'a>>, DecodeError> {
                     s = &s[1..];
                     LcdOp::Clear(LcdClear::FromCursorCount(n))
                 } else {
-                    LcdOp::Clear(match code {
-                        0x01 => LcdClear::BothDisplays,
-                        0x02 => LcdClear::BothTopLines,
-                        0x03 => LcdClear::BothBottomLines,
-                        0x04 => LcdClear::LeftAll,
-                        0x05 => LcdClear::RightAll,
-                        0x06 => LcdClear::LeftTopLine,
-                        0x07 => LcdClear::LeftBottomLine,
-                        0x08 => LcdClear::RightTopLine,
-                        0x09 => LcdClear::RightBottomLine,
-                        _ => return Err(DecodeError::Invalid),
-                    })
+                    LcdOp::Clear(LcdClear::try_from(code)?)
                 }
             }
-            0x03 => {
+            LCD_CURSOR_BLINK => {
                 if s.is_empty() {
                     return Err(DecodeError::Truncated);
                 }
@@ -544,7 +657,7 @@ fn decode_lcd_ops<'a>(mut s: &'a [u8]) -> Result<Vec<LcdOp<'a>>, DecodeError> {
                 s = &s[1..];
                 LcdOp::CursorBlink(on)
             }
-            0x04 => {
+            LCD_TEXT => {
                 // Text until 0x00 (not including)
                 let nul = s
                     .iter()
@@ -555,8 +668,11 @@ fn decode_lcd_ops<'a>(mut s: &'a [u8]) -> Result<Vec<LcdOp<'a>>, DecodeError> {
                 LcdOp::Text(txt)
             }
             x => {
-                // unknown → slurp to next known boundary (here we can't know length; pass empty)
-                LcdOp::Unknown(x, &[])
+                // An unknown op's length can't be known, so it takes the rest
+                // of the stream and decoding stops; re-encoding is lossless.
+                let rest = s;
+                s = &[];
+                LcdOp::Unknown(x, rest)
             }
         };
         out.push(op);
@@ -572,7 +688,7 @@ fn decode_dbsim<'a>(body: &'a [u8]) -> Result<DbSimMsg<'a>, DecodeError> {
     let main = body[0];
     let mut s = &body[1..];
     Ok(match main {
-        0x68 => {
+        DB_CHANGE => {
             // Change/Request
             if s.is_empty() {
                 return Err(DecodeError::Truncated);
@@ -580,11 +696,11 @@ fn decode_dbsim<'a>(body: &'a [u8]) -> Result<DbSimMsg<'a>, DecodeError> {
             let sub = s[0];
             s = &s[1..];
             match sub {
-                0x00..=0x02 => {
+                DB_WRITE_CONTROL..=DB_WRITE_GLOBALS => {
                     // write
                     let (target, need_cn) = match sub {
-                        0x00 => (DbTarget::Control, true),
-                        0x01 => (DbTarget::TemplateHeader, false),
+                        DB_WRITE_CONTROL => (DbTarget::Control, true),
+                        DB_WRITE_TEMPLATE_HEADER => (DbTarget::TemplateHeader, false),
                         _ => (DbTarget::Globals, false),
                     };
                     let cn = if need_cn {
@@ -609,11 +725,11 @@ fn decode_dbsim<'a>(body: &'a [u8]) -> Result<DbSimMsg<'a>, DecodeError> {
                         data: s,
                     }
                 }
-                0x03..=0x05 => {
+                DB_READ_CONTROL..=DB_READ_GLOBALS => {
                     // read
                     let (target, need_cn) = match sub {
-                        0x03 => (DbTarget::Control, true),
-                        0x04 => (DbTarget::TemplateHeader, false),
+                        DB_READ_CONTROL => (DbTarget::Control, true),
+                        DB_READ_TEMPLATE_HEADER => (DbTarget::TemplateHeader, false),
                         _ => (DbTarget::Globals, false),
                     };
                     let cn = if need_cn {
@@ -641,7 +757,7 @@ fn decode_dbsim<'a>(body: &'a [u8]) -> Result<DbSimMsg<'a>, DecodeError> {
                 _ => return Err(DecodeError::Invalid),
             }
         }
-        0x69 => {
+        DB_RESPONSE => {
             // Response
             if s.is_empty() {
                 return Err(DecodeError::Truncated);
@@ -649,9 +765,9 @@ fn decode_dbsim<'a>(body: &'a [u8]) -> Result<DbSimMsg<'a>, DecodeError> {
             let sub = s[0];
             s = &s[1..];
             let (target, need_cn) = match sub {
-                0x03 => (DbTarget::Control, true),
-                0x04 => (DbTarget::TemplateHeader, false),
-                0x05 => (DbTarget::Globals, false),
+                DB_READ_CONTROL => (DbTarget::Control, true),
+                DB_READ_TEMPLATE_HEADER => (DbTarget::TemplateHeader, false),
+                DB_READ_GLOBALS => (DbTarget::Globals, false),
                 _ => return Err(DecodeError::Invalid),
             };
             let cn = if need_cn {
@@ -676,7 +792,7 @@ fn decode_dbsim<'a>(body: &'a [u8]) -> Result<DbSimMsg<'a>, DecodeError> {
                 data: s,
             }
         }
-        0x66 => {
+        SIMULATE => {
             // Simulate
             if s.is_empty() {
                 return Err(DecodeError::Truncated);
@@ -713,9 +829,24 @@ fn decode_dbsim<'a>(body: &'a [u8]) -> Result<DbSimMsg<'a>, DecodeError> {
                     }
                 }
                 0x04 => LcdTextRequest,
-                0x05 => LcdTextResponse,
+                0x05 => {
+                    // X, Y, length echo precedes the payload
+                    if s.len() < 3 {
+                        return Err(DecodeError::Truncated);
+                    }
+                    LcdTextResponse {
+                        text: s[3..].to_vec(),
+                    }
+                }
                 0x06 => LedBitmapRequest,
-                0x07 => LedBitmapResponse,
+                0x07 => {
+                    if s.len() < 3 {
+                        return Err(DecodeError::Truncated);
+                    }
+                    LedBitmapResponse {
+                        data: s[3..].to_vec(),
+                    }
+                }
                 0x08 => {
                     if s.len() < 2 {
                         return Err(DecodeError::Truncated);
@@ -758,7 +889,7 @@ fn decode_dbsim<'a>(body: &'a [u8]) -> Result<DbSimMsg<'a>, DecodeError> {
             };
             DbSimMsg::Simulate(cmd)
         }
-        0x6A => {
+        SIM_HIGH_LEVEL => {
             // High-level
             if s.is_empty() {
                 return Err(DecodeError::Truncated);
@@ -784,6 +915,19 @@ fn decode_dbsim<'a>(body: &'a [u8]) -> Result<DbSimMsg<'a>, DecodeError> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_pack7() {
+        let data: Vec<u8> = (0..9).map(|i| 0x7E + i).collect();
+        let packed = pack7(&data);
+        assert_eq!(packed.len(), 11);
+        assert_eq!(&packed[..3], [0x7E, 0x7F, 0x00]);
+        assert_eq!(&packed[9..], [0b111_1100, 0b11]);
+        assert!(packed.iter().all(|b| b & 0x80 == 0));
+        assert_eq!(unpack7(&packed, 9), Some(data));
+        assert_eq!(unpack7(&packed[..10], 9), None);
+        assert_eq!(pack7(&[]), []);
+    }
+
     #[test]
     fn roundtrip_lcd() {
         let msg = AutomapSysEx::LcdText(vec![
@@ -802,4 +946,63 @@ mod tests {
         };
         assert_eq!(r, msg);
     }
+
+    #[test]
+    fn roundtrip_every_lcd_clear() {
+        for clear in LcdClear::ALL
+            .into_iter()
+            .chain([LcdClear::FromCursorCount(17)])
+        {
+            let msg = AutomapSysEx::LcdText(vec![LcdOp::Clear(clear), LcdOp::End]);
+            let buf = msg.clone().to_bytes();
+            let (_, _, _, DecodedMsg::Automap(r)) = decode_frame(&buf).unwrap() else {
+                panic!()
+            };
+            assert_eq!(r, msg);
+            if !matches!(clear, LcdClear::FromCursorCount(_)) {
+                assert_eq!(LcdClear::try_from(clear.code()), Ok(clear));
+            }
+        }
+        assert_eq!(LcdClear::try_from(0x0A), Err(DecodeError::Invalid));
+    }
+
+    #[test]
+    fn roundtrip_unknown_lcd_op() {
+        let frame = AutomapSysEx::LcdText(vec![
+            LcdOp::Text(b"Hi"),
+            LcdOp::Unknown(0x09, &[0x01, 0x04, b'x', 0x00, 0x00]),
+        ])
+        .to_bytes();
+        let (_, _, _, DecodedMsg::Automap(AutomapSysEx::LcdText(ops))) =
+            decode_frame(&frame).unwrap()
+        else {
+            panic!()
+        };
+        assert_eq!(
+            ops[1],
+            LcdOp::Unknown(0x09, &[0x01, 0x04, b'x', 0x00, 0x00])
+        );
+        assert_eq!(AutomapSysEx::LcdText(ops).to_bytes(), frame);
+    }
+
+    #[test]
+    fn roundtrip_lcd_readback() {
+        let req = DbSimMsg::Simulate(SimCmd::LcdTextRequest).to_bytes();
+        assert_eq!(
+            req,
+            vec![
+                0xF0, 0x00, 0x20, 0x29, 0x03, 0x05, 0x12, 0x00, 0x00, 0x00, 0x66, 0x04, 0x00, 0x00,
+                0x20, 0xF7
+            ]
+        );
+
+        let msg = DbSimMsg::Simulate(SimCmd::LcdTextResponse {
+            text: b"Automap is OFFLINE".to_vec(),
+        });
+        let buf = msg.to_bytes();
+        let (_, _, _, DecodedMsg::DbSim(r)) = decode_frame(&buf).unwrap() else {
+            panic!()
+        };
+        assert_eq!(r, msg);
+    }
 }
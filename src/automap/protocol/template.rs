@@ -1,5 +1,7 @@
 #![allow(dead_code)]
 
+use super::sysex::{AutomapSysEx, DecodedMsg, decode_frame};
+
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ControlType {
@@ -76,6 +78,19 @@ pub enum PortRoute {
     Usb1AndUsb3 = 0x54, // USB1 + USB3
 }
 
+impl PortRoute {
+    /// The MIDI OUT socket, 1 or 2, this route sends to, for
+    /// [`AutomapDevice::midi_out()`](crate::AutomapDevice::midi_out).
+    /// `None` for the USB ports and for not transmitting.
+    pub fn midi_out_port(self) -> Option<u8> {
+        match self {
+            PortRoute::MidiOut1 => Some(1),
+            PortRoute::MidiOut2 => Some(2),
+            _ => None,
+        }
+    }
+}
+
 // Bit positions for the lower 5 bits when using the CNPORTS "Specific" mask form.
 // (Only meaningful if the top bits encode 'Specific' per the "type 6+5" scheme.)
 bitflags::bitflags! {
@@ -134,3 +149,216 @@ impl ChannelSpec {
         }
     }
 }
+
+// ===================== BLOB VALIDATION =====================
+// The template offsets document describes the layout but no checksum, so
+// validation is structural: enough bytes for a header, a readable name, a
+// known template type, and nothing that cannot travel inside a SysEx frame.
+
+/// Size of the template header, from the template offsets document.
+pub const HEADER_LEN: u16 = 406;
+
+const NAME: std::ops::Range<usize> = 0x00..0x08;
+const TEMPLATE_TYPE: usize = 0x33;
+
+/// Why a template blob was rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TemplateError {
+    /// Shorter than a template header.
+    TooShort { len: usize },
+    /// A byte with the top bit set, which no SysEx payload can carry.
+    NotSevenBit { offset: usize },
+    /// A name byte that is neither printable ASCII nor padding.
+    BadName { offset: usize },
+    /// The template-type byte (0x33) is not Normal, Reason3 or Logic.
+    UnknownType(u8),
+    /// The file is not a single Upload Template SysEx message.
+    NotTemplateSysEx,
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TemplateError::TooShort { len } => {
+                write!(
+                    f,
+                    "template is {len} bytes, shorter than its {HEADER_LEN}-byte header"
+                )
+            }
+            TemplateError::NotSevenBit { offset } => {
+                write!(f, "template byte at {offset:#06x} is not 7-bit")
+            }
+            TemplateError::BadName { offset } => {
+                write!(f, "template name byte at {offset:#06x} is not printable")
+            }
+            TemplateError::UnknownType(t) => write!(f, "unknown template type {t:#04x}"),
+            TemplateError::NotTemplateSysEx => {
+                write!(f, "not a single Upload Template SysEx message")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// Checks that `data` looks like a template before it is sent to the unit.
+pub fn validate(data: &[u8]) -> Result<(), TemplateError> {
+    if data.len() < usize::from(HEADER_LEN) {
+        return Err(TemplateError::TooShort { len: data.len() });
+    }
+    if let Some(offset) = data.iter().position(|&b| b > 0x7F) {
+        return Err(TemplateError::NotSevenBit { offset });
+    }
+    if let Some(offset) = data[NAME]
+        .iter()
+        .position(|&b| b != 0x00 && !(0x20..0x7F).contains(&b))
+    {
+        return Err(TemplateError::BadName { offset });
+    }
+    match data[TEMPLATE_TYPE] {
+        0x00..=0x02 => Ok(()),
+        t => Err(TemplateError::UnknownType(t)),
+    }
+}
+
+/// Extracts and validates the template carried by a `.syx` file holding
+/// one Upload Template message.
+pub fn from_syx(file: &[u8]) -> Result<&[u8], TemplateError> {
+    match decode_frame(file) {
+        Ok((_, _, _, DecodedMsg::Automap(AutomapSysEx::UploadTemplate { data }))) => {
+            validate(data)?;
+            Ok(data)
+        }
+        _ => Err(TemplateError::NotTemplateSysEx),
+    }
+}
+
+// ===================== PARSED TEMPLATES =====================
+
+/// Size of one control's data, from the control members document.
+pub const CONTROL_LEN: u16 = 41;
+
+/// Number of controls in a template.
+pub const CONTROL_COUNT: u16 = 90;
+
+/// Size of a whole template: the header, then every control.
+pub const TEMPLATE_LEN: u16 = HEADER_LEN + CONTROL_COUNT * CONTROL_LEN;
+
+/// A template as the unit stores it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    data: Vec<u8>,
+}
+
+impl Template {
+    /// Takes a template's bytes after checking them with [`validate()`].
+    ///
+    /// Only the header is required; controls past the end of `data` are
+    /// missing from [`control()`](Self::control).
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, TemplateError> {
+        validate(&data)?;
+        Ok(Self { data })
+    }
+
+    /// The name, without its padding.
+    pub fn name(&self) -> &str {
+        // Validated as printable ASCII or NUL
+        let name = std::str::from_utf8(&self.data[NAME]).unwrap_or_default();
+        name.trim_end_matches([' ', '\0'])
+    }
+
+    /// The template-type byte: 0 Normal, 1 Reason3 or 2 Logic.
+    pub fn template_type(&self) -> u8 {
+        self.data[TEMPLATE_TYPE]
+    }
+
+    pub fn header(&self) -> &[u8] {
+        &self.data[..usize::from(HEADER_LEN)]
+    }
+
+    /// The data of control `cn`, 1-based as in the data-block messages.
+    pub fn control(&self, cn: u8) -> Option<&[u8]> {
+        let index = usize::from(cn.checked_sub(1)?);
+        if index >= usize::from(CONTROL_COUNT) {
+            return None;
+        }
+        let len = usize::from(CONTROL_LEN);
+        let start = usize::from(HEADER_LEN) + index * len;
+        self.data.get(start..start + len)
+    }
+
+    /// The data of control `cn` for changing. A template that stops short
+    /// of it is padded with zeroed controls.
+    pub fn control_mut(&mut self, cn: u8) -> Option<&mut [u8]> {
+        let index = usize::from(cn.checked_sub(1)?);
+        if index >= usize::from(CONTROL_COUNT) {
+            return None;
+        }
+        let len = usize::from(CONTROL_LEN);
+        let start = usize::from(HEADER_LEN) + index * len;
+        if self.data.len() < start + len {
+            self.data.resize(start + len, 0);
+        }
+        Some(&mut self.data[start..start + len])
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsed_template() {
+        let mut data = vec![0u8; usize::from(HEADER_LEN) + 2 * usize::from(CONTROL_LEN)];
+        data[NAME].copy_from_slice(b"Mixer   ");
+        data[TEMPLATE_TYPE] = 0x02;
+        data[usize::from(HEADER_LEN + CONTROL_LEN)] = 0x33;
+        let template = Template::from_bytes(data).unwrap();
+
+        assert_eq!(template.name(), "Mixer");
+        assert_eq!(template.template_type(), 2);
+        assert_eq!(template.control(2).unwrap()[0], 0x33);
+        assert_eq!(template.control(0), None);
+        assert_eq!(template.control(3), None);
+        let mut padded = template.clone();
+        padded.control_mut(3).unwrap()[0] = 0x21;
+        assert_eq!(padded.control(3).unwrap()[0], 0x21);
+        assert_eq!(padded.control_mut(91), None);
+        assert_eq!(TEMPLATE_LEN, 4096);
+        assert!(Template::from_bytes(vec![0; 10]).is_err());
+    }
+
+    #[test]
+    fn test_validate_and_import() {
+        let mut data = vec![0u8; usize::from(HEADER_LEN)];
+        data[NAME].copy_from_slice(b"Mixer   ");
+        assert_eq!(validate(&data), Ok(()));
+
+        let syx = AutomapSysEx::UploadTemplate { data: &data }.to_bytes();
+        assert_eq!(from_syx(&syx), Ok(&data[..]));
+        assert_eq!(
+            from_syx(&[0xF0, 0xF7]),
+            Err(TemplateError::NotTemplateSysEx)
+        );
+
+        assert_eq!(
+            validate(&data[..10]),
+            Err(TemplateError::TooShort { len: 10 })
+        );
+        data[TEMPLATE_TYPE] = 0x05;
+        assert_eq!(validate(&data), Err(TemplateError::UnknownType(5)));
+        data[0x40] = 0xFF;
+        assert_eq!(
+            validate(&data),
+            Err(TemplateError::NotSevenBit { offset: 0x40 })
+        );
+    }
+}
@@ -0,0 +1,346 @@
+//! Sitting between the unit and another MIDI consumer.
+//!
+//! A [`Proxy`] passes the unit's MIDI on to a downstream byte stream, such
+//! as a virtual MIDI port or a socket, and passes whatever the downstream
+//! sends back to the unit, byte for byte. Each message that goes through is
+//! also handed to the proxy's taps as a [`ProxiedMessage`], which can be
+//! decoded for display, and an [`Injector`] slips extra messages into
+//! either direction while the proxy runs.
+//!
+//! Put between a DAW's Automap driver and the unit, this shows exactly what
+//! the host sends and what the unit answers, which is how the undocumented
+//! parts of the protocol get worked out.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::poll_fn;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+use std::time::Instant;
+
+#[cfg(feature = "smol")]
+use futures_lite::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::automap::cc::AUTOMAP_CC_STATUS;
+use crate::automap::device::AutomapDevice;
+use crate::automap::event::AutomapEvent;
+use crate::automap::rt;
+use crate::automap::sysex::{DecodedMsg, decode_frame};
+use crate::midi::MidiStream;
+
+type Tap = Box<dyn FnMut(&ProxiedMessage) + Send>;
+
+/// Which way a message is travelling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From the unit to the downstream.
+    FromDevice,
+    /// From the downstream to the unit.
+    ToDevice,
+}
+
+/// A copy of one message that went through a [`Proxy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxiedMessage {
+    pub direction: Direction,
+    /// When the message was read, or taken from the [`Injector`].
+    pub at: Instant,
+    /// Whether it came from the [`Injector`] rather than the other side.
+    pub injected: bool,
+    /// The complete MIDI message.
+    pub bytes: Vec<u8>,
+    /// Whether it is a CC on the device's configured Automap channel.
+    automap_channel: bool,
+}
+
+/// What a [`ProxiedMessage`] decodes to.
+#[derive(Debug)]
+pub enum Decoded<'a> {
+    /// A CC from the unit on its Automap channel.
+    Event(AutomapEvent),
+    /// A SysEx frame in the Automap or Data-Block/Simulation format.
+    SysEx(DecodedMsg<'a>),
+    /// Anything else, including CCs sent to the unit and SysEx that does
+    /// not parse. Only the bytes are known.
+    Other,
+}
+
+impl ProxiedMessage {
+    /// Decodes the message as far as this crate understands it.
+    pub fn decode(&self) -> Decoded<'_> {
+        if self.bytes.first() == Some(&0xF0) {
+            return match decode_frame(&self.bytes) {
+                Ok((_, _, _, msg)) => Decoded::SysEx(msg),
+                Err(_) => Decoded::Other,
+            };
+        }
+        if self.direction == Direction::FromDevice
+            && self.automap_channel
+            && let Ok(event) = AutomapEvent::decode_event(&self.bytes)
+        {
+            return Decoded::Event(event);
+        }
+        Decoded::Other
+    }
+
+    /// A message read back from a log written with the `Display` form.
+    pub(crate) fn from_log(direction: Direction, injected: bool, bytes: Vec<u8>) -> Self {
+        Self {
+            direction,
+            at: Instant::now(),
+            injected,
+            automap_channel: bytes.first() == Some(&AUTOMAP_CC_STATUS),
+            bytes,
+        }
+    }
+
+    /// The decoded form as the `Display` line shows it, after the `;`.
+    pub(crate) fn annotation(&self) -> Option<String> {
+        match self.decode() {
+            Decoded::Event(event) => Some(event.to_string()),
+            Decoded::SysEx(msg) => Some(format!("{msg:?}")),
+            Decoded::Other => None,
+        }
+    }
+}
+
+/// One line: the direction (`<` from the unit, `>` to it, `+` marking an
+/// injected message), the bytes in hex, and the decoded form if any.
+impl fmt::Display for ProxiedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arrow = match self.direction {
+            Direction::FromDevice => '<',
+            Direction::ToDevice => '>',
+        };
+        let mark = if self.injected { '+' } else { ' ' };
+        write!(f, "{arrow}{mark}")?;
+        for byte in &self.bytes {
+            write!(f, " {byte:02X}")?;
+        }
+        match self.annotation() {
+            Some(decoded) => write!(f, "  ; {decoded}"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    messages: VecDeque<(Direction, Vec<u8>)>,
+    waker: Option<Waker>,
+}
+
+/// Adds messages to a running [`Proxy`], from [`Proxy::injector()`].
+///
+/// Clones share the same queue. Messages are sent in the order they were
+/// injected, between the ones being forwarded; each should be one complete
+/// MIDI message.
+#[derive(Clone)]
+pub struct Injector {
+    queue: Arc<Mutex<Queue>>,
+}
+
+impl Injector {
+    /// Sends `midi` to the unit as if the downstream had.
+    pub fn to_device(&self, midi: impl Into<Vec<u8>>) {
+        self.push(Direction::ToDevice, midi.into());
+    }
+
+    /// Sends `midi` to the downstream as if the unit had.
+    pub fn to_downstream(&self, midi: impl Into<Vec<u8>>) {
+        self.push(Direction::FromDevice, midi.into());
+    }
+
+    fn push(&self, direction: Direction, midi: Vec<u8>) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.messages.push_back((direction, midi));
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Waits until something has been injected, then takes all of it.
+    async fn take(&self) -> Vec<(Direction, Vec<u8>)> {
+        poll_fn(|cx| {
+            let mut queue = self.queue.lock().unwrap();
+            if queue.messages.is_empty() {
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            } else {
+                Poll::Ready(queue.messages.drain(..).collect())
+            }
+        })
+        .await
+    }
+}
+
+enum Step {
+    Device(Result<Instant, io::Error>),
+    Downstream(Result<usize, io::Error>),
+    Injected(Vec<(Direction, Vec<u8>)>),
+}
+
+/// Forwards MIDI between the unit and a downstream, showing each message to
+/// its taps.
+///
+/// ```
+/// use automap::Proxy;
+///
+/// let proxy = Proxy::new().on_message(|msg| eprintln!("{msg}"));
+/// // Queued now, sent to the unit once the proxy runs
+/// proxy.injector().to_device([0xBF, 0x48, 0x01]);
+/// # drop(proxy);
+/// ```
+pub struct Proxy {
+    taps: Vec<Tap>,
+    injector: Injector,
+}
+
+impl Default for Proxy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Proxy {
+    pub fn new() -> Self {
+        Self {
+            taps: Vec::new(),
+            injector: Injector {
+                queue: Arc::default(),
+            },
+        }
+    }
+
+    /// Calls `f` with every message forwarded or injected, after it has
+    /// been sent.
+    pub fn on_message(mut self, f: impl FnMut(&ProxiedMessage) + Send + 'static) -> Self {
+        self.taps.push(Box::new(f));
+        self
+    }
+
+    /// A handle for adding messages while [`run()`](Self::run) is going.
+    pub fn injector(&self) -> Injector {
+        self.injector.clone()
+    }
+
+    /// Forwards everything the unit sends to `writer`, and every MIDI
+    /// message read from `reader` to the unit, until `reader` ends.
+    ///
+    /// Messages are passed on unchanged, whatever their channel. The proxy
+    /// reads the unit directly, so while it runs
+    /// [`read_events()`](AutomapDevice::read_events) sees nothing and
+    /// subscriptions are not fed.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first read or write error on either side.
+    pub async fn run<R, W>(
+        &mut self,
+        device: &mut AutomapDevice,
+        mut reader: R,
+        mut writer: W,
+    ) -> Result<(), io::Error>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let cc_status = device.config().cc_status();
+        let mut downstream = MidiStream::default();
+        let mut buf = [0u8; 256];
+        loop {
+            let mut from_device = Vec::new();
+            let step = rt::race(
+                async {
+                    Step::Device(device.read_messages(|m| from_device.push(m.to_vec())).await)
+                },
+                rt::race(
+                    async { Step::Downstream(reader.read(&mut buf).await) },
+                    async { Step::Injected(self.injector.take().await) },
+                ),
+            )
+            .await;
+
+            let (at, injected, batch) = match step {
+                Step::Device(at) => {
+                    let batch = from_device.into_iter().map(|m| (Direction::FromDevice, m));
+                    (at?, false, batch.collect::<Vec<_>>())
+                }
+                Step::Downstream(Ok(0)) => return Ok(()),
+                Step::Downstream(n) => {
+                    let batch = downstream.push(&buf[..n?]).into_iter();
+                    let batch = batch.map(|m| (Direction::ToDevice, m));
+                    (Instant::now(), false, batch.collect())
+                }
+                Step::Injected(batch) => (Instant::now(), true, batch),
+            };
+
+            for (direction, bytes) in batch {
+                match direction {
+                    Direction::FromDevice => writer.write_all(&bytes).await?,
+                    Direction::ToDevice => device.write_midi(&bytes).await?,
+                }
+                let msg = ProxiedMessage {
+                    direction,
+                    at,
+                    injected,
+                    automap_channel: bytes.len() == 3 && bytes[0] == cc_status,
+                    bytes,
+                };
+                for tap in &mut self.taps {
+                    tap(&msg);
+                }
+            }
+            writer.flush().await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_and_display() {
+        let msg = ProxiedMessage {
+            direction: Direction::FromDevice,
+            at: Instant::now(),
+            injected: false,
+            bytes: vec![0xBF, 0x18, 0x01],
+            automap_channel: true,
+        };
+        assert!(matches!(
+            msg.decode(),
+            Decoded::Event(AutomapEvent::Button { .. })
+        ));
+        assert!(msg.to_string().starts_with("<  BF 18 01  ; "));
+
+        let sent = ProxiedMessage {
+            direction: Direction::ToDevice,
+            injected: true,
+            ..msg
+        };
+        assert!(matches!(sent.decode(), Decoded::Other));
+        assert_eq!(sent.to_string(), ">+ BF 18 01");
+    }
+
+    #[test]
+    fn test_injector_queues_in_order() {
+        let proxy = Proxy::new();
+        let injector = proxy.injector();
+        injector.to_device([0xBF, 0x48, 0x01]);
+        injector.clone().to_downstream(vec![0xB0, 0x07, 0x40]);
+
+        let queue = injector.queue.lock().unwrap();
+        assert_eq!(
+            queue.messages,
+            [
+                (Direction::ToDevice, vec![0xBF, 0x48, 0x01]),
+                (Direction::FromDevice, vec![0xB0, 0x07, 0x40]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! Turning encoder clicks into an absolute value.
+//!
+//! The encoders only report how far they moved. [`RelativeValue`] keeps the
+//! running total within a range, so a consumer reads a position rather
+//! than integrating clicks itself.
+//!
+//! A fast turn arrives as a burst of one-click events. [`coalesce_clicks()`]
+//! sums each burst into one event, as
+//! [`DeviceConfig::coalesce_clicks()`](crate::DeviceConfig::coalesce_clicks)
+//! does for every read.
+
+use crate::automap::cc::{Encoder, EncoderPosition};
+use crate::automap::command::AutomapCommand;
+use crate::automap::event::AutomapEvent;
+use crate::automap::timed::TimedEvent;
+
+/// A value moved by encoder clicks.
+///
+/// Defaults to the MIDI range `0..=127` in steps of 1, starting at 0 and
+/// stopping at either end.
+///
+/// ```
+/// # use automap::{AutomapEvent, Encoder, RelativeValue};
+/// let mut volume = RelativeValue::new().encoder(Encoder::Encoder1).initial(100.0);
+/// volume.handle(&AutomapEvent::Encoder {
+///     encoder: Encoder::Encoder1,
+///     clicks: 40,
+/// });
+/// assert_eq!(volume.as_u8(), 127);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RelativeValue {
+    value: f32,
+    min: f32,
+    max: f32,
+    step: f32,
+    wrap: bool,
+    encoder: Option<Encoder>,
+    mirror: bool,
+}
+
+impl Default for RelativeValue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RelativeValue {
+    pub fn new() -> Self {
+        RelativeValue {
+            value: 0.0,
+            min: 0.0,
+            max: 127.0,
+            step: 1.0,
+            wrap: false,
+            encoder: None,
+            mirror: false,
+        }
+    }
+
+    /// The range the value stays in. The ends are swapped if given in the
+    /// wrong order.
+    pub fn range(mut self, min: f32, max: f32) -> Self {
+        self.min = min.min(max);
+        self.max = min.max(max);
+        self.value = self.value.clamp(self.min, self.max);
+        self
+    }
+
+    /// How far one click moves the value.
+    pub fn step(mut self, step: f32) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Wraps past either end instead of stopping there: one step past the
+    /// maximum is the minimum, as for a bearing or a list index.
+    pub fn wrap(mut self, wrap: bool) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// The starting value.
+    pub fn initial(mut self, value: f32) -> Self {
+        self.set(value);
+        self
+    }
+
+    /// The encoder whose turns [`handle()`](Self::handle) follows.
+    pub fn encoder(mut self, encoder: Encoder) -> Self {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Makes [`ring_command()`](Self::ring_command) show the value on the
+    /// encoder's ring.
+    pub fn mirror(mut self, mirror: bool) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    pub fn get(&self) -> f32 {
+        self.value
+    }
+
+    /// Sets the value, e.g. when the host changes it, clamped to the range.
+    pub fn set(&mut self, value: f32) {
+        self.value = value.clamp(self.min, self.max);
+    }
+
+    /// The value rounded to a 7-bit MIDI value.
+    pub fn as_u8(&self) -> u8 {
+        self.value.round().clamp(0.0, 127.0) as u8
+    }
+
+    /// How far through the range the value is, `0.0..=1.0`.
+    pub fn fraction(&self) -> f32 {
+        if self.max > self.min {
+            (self.value - self.min) / (self.max - self.min)
+        } else {
+            0.0
+        }
+    }
+
+    /// Moves the value by `clicks` steps. Returns whether it changed.
+    pub fn turn(&mut self, clicks: i8) -> bool {
+        let moved = self.value + f32::from(clicks) * self.step;
+        let next = if self.wrap {
+            let period = self.max - self.min + self.step.abs();
+            if period > 0.0 {
+                self.min + (moved - self.min).rem_euclid(period)
+            } else {
+                self.min
+            }
+        } else {
+            moved.clamp(self.min, self.max)
+        };
+        let changed = next != self.value;
+        self.value = next;
+        changed
+    }
+
+    /// Applies a turn of the configured encoder. Returns whether the value
+    /// changed; other events are ignored.
+    pub fn handle(&mut self, event: &AutomapEvent) -> bool {
+        match *event {
+            AutomapEvent::Encoder { encoder, clicks } if Some(encoder) == self.encoder => {
+                self.turn(clicks)
+            }
+            _ => false,
+        }
+    }
+
+    /// The ring LED command showing the value, if mirroring is on and an
+    /// encoder is set.
+    pub fn ring_command(&self) -> Option<AutomapCommand> {
+        let encoder = self.encoder.filter(|_| self.mirror)?;
+        Some(AutomapCommand::EncoderRingValue {
+            encoder,
+            position: EncoderPosition::from_fraction(self.fraction()),
+        })
+    }
+}
+
+/// Sums the turns of each encoder, and of the speed dial, into one event.
+///
+/// The sum takes the place of the control's first turn and the time of
+/// its last, so it keeps its order among the other events. Turns that
+/// cancel out are dropped. Other events pass through unchanged.
+pub fn coalesce_clicks(events: Vec<TimedEvent>) -> Vec<TimedEvent> {
+    let mut out: Vec<TimedEvent> = Vec::with_capacity(events.len());
+    for timed in events {
+        let Some(clicks) = timed.event.clicks() else {
+            out.push(timed);
+            continue;
+        };
+        let dial = with_clicks(timed.event, 0);
+        match out
+            .iter_mut()
+            .find(|o| o.event.clicks().is_some() && with_clicks(o.event, 0) == dial)
+        {
+            Some(first) => {
+                let sum = first.event.clicks().unwrap_or(0).saturating_add(clicks);
+                first.event = with_clicks(first.event, sum);
+                first.at = timed.at;
+            }
+            None => out.push(timed),
+        }
+    }
+    out.retain(|t| t.event.clicks() != Some(0));
+    out
+}
+
+/// `event` with its clicks replaced, if it has any.
+fn with_clicks(event: AutomapEvent, clicks: i8) -> AutomapEvent {
+    match event {
+        AutomapEvent::Encoder { encoder, .. } => AutomapEvent::Encoder { encoder, clicks },
+        AutomapEvent::LiveEncoder { encoder, .. } => AutomapEvent::LiveEncoder { encoder, clicks },
+        AutomapEvent::SpeedDial { .. } => AutomapEvent::SpeedDial { clicks },
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_clamp_wrap_and_ring() {
+        let mut value = RelativeValue::new().initial(120.0);
+        assert!(value.turn(10));
+        assert_eq!(value.get(), 127.0);
+        assert!(!value.turn(1));
+
+        let mut index = RelativeValue::new().range(0.0, 3.0).wrap(true);
+        index.turn(-1);
+        assert_eq!(index.get(), 3.0);
+        index.turn(2);
+        assert_eq!(index.get(), 1.0);
+
+        let pan = RelativeValue::new()
+            .range(-1.0, 1.0)
+            .encoder(Encoder::Encoder2)
+            .mirror(true);
+        assert_eq!(
+            pan.ring_command(),
+            Some(AutomapCommand::EncoderRingValue {
+                encoder: Encoder::Encoder2,
+                position: EncoderPosition::CENTER,
+            })
+        );
+    }
+    #[test]
+    fn test_coalesce_clicks() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let turn = |encoder, clicks, ms| TimedEvent {
+            at: at(ms),
+            event: AutomapEvent::Encoder { encoder, clicks },
+        };
+        let press = TimedEvent {
+            at: at(1),
+            event: AutomapEvent::PreviewButton { pressed: true },
+        };
+        let events = vec![
+            turn(Encoder::Encoder1, 1, 0),
+            press,
+            turn(Encoder::Encoder2, 1, 1),
+            turn(Encoder::Encoder1, 2, 2),
+            turn(Encoder::Encoder2, -1, 3),
+        ];
+        assert_eq!(
+            coalesce_clicks(events),
+            [turn(Encoder::Encoder1, 3, 2), press]
+        );
+        let fast = vec![
+            turn(Encoder::Encoder1, 100, 0),
+            turn(Encoder::Encoder1, 100, 1),
+        ];
+        assert_eq!(coalesce_clicks(fast), [turn(Encoder::Encoder1, 127, 1)]);
+    }
+}
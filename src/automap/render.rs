@@ -0,0 +1,163 @@
+//! Sending the surface state at a fixed frame rate.
+//!
+//! Writing every LED or LCD change the moment it happens ties the
+//! application's update rate to USB throughput: a meter animating all
+//! eight rings floods the endpoint with commands the eye never sees. A
+//! [`Renderer`] decouples the two. Application code updates a shared
+//! [`RenderTarget`] as often as it likes; once per frame the renderer takes
+//! a snapshot, diffs it against what the surface shows, and sends the
+//! difference as one LED batch and one LCD message.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::automap::app::SurfaceFrame;
+use crate::automap::command::AutomapCommand;
+use crate::automap::handle::AutomapHandle;
+use crate::automap::lcd::LcdBuffer;
+use crate::automap::leds::LedState;
+use crate::automap::rt;
+use crate::automap::sysex::AutomapSysEx;
+
+/// The default frame interval, about 30 frames per second.
+pub const DEFAULT_FRAME_INTERVAL: Duration = Duration::from_micros(33_333);
+
+/// The desired surface state, shared between the application and its
+/// [`Renderer`].
+#[derive(Debug, Clone, Default)]
+pub struct RenderTarget {
+    frame: Arc<Mutex<SurfaceFrame>>,
+}
+
+impl RenderTarget {
+    /// Changes the desired state. Nothing is sent until the next frame.
+    pub fn update<R>(&self, f: impl FnOnce(&mut SurfaceFrame) -> R) -> R {
+        f(&mut self.frame.lock().unwrap())
+    }
+
+    /// A copy of the desired state.
+    pub fn snapshot(&self) -> SurfaceFrame {
+        self.frame.lock().unwrap().clone()
+    }
+}
+
+/// Sends a [`RenderTarget`] to the surface once per frame.
+pub struct Renderer {
+    handle: AutomapHandle,
+    target: RenderTarget,
+    interval: Duration,
+    /// What the LCD shows, if known, and the frame to draw next.
+    lcd: LcdBuffer,
+}
+
+impl Renderer {
+    pub fn new(handle: AutomapHandle) -> Self {
+        Self {
+            handle,
+            target: RenderTarget::default(),
+            interval: DEFAULT_FRAME_INTERVAL,
+            lcd: LcdBuffer::new(),
+        }
+    }
+
+    /// Time between frames. Defaults to [`DEFAULT_FRAME_INTERVAL`].
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// The shared state this renderer draws.
+    pub fn target(&self) -> RenderTarget {
+        self.target.clone()
+    }
+
+    /// Redraws the whole LCD on the next frame, e.g. after something else
+    /// wrote to it.
+    pub fn invalidate(&mut self) {
+        self.lcd.invalidate();
+    }
+
+    /// Sends one frame now.
+    ///
+    /// Returns whether anything had changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB write fails.
+    pub async fn render_frame(&mut self) -> Result<bool, std::io::Error> {
+        let frame = self.target.snapshot();
+        let (commands, msg) = frame_delta(&self.handle.leds(), &mut self.lcd, frame);
+        let changed = !commands.is_empty() || msg.is_some();
+        if !commands.is_empty() {
+            self.handle.send_commands(&commands).await?;
+        }
+        if let Some(msg) = msg
+            && let Err(e) = self.handle.send_sysex(msg).await
+        {
+            self.lcd.invalidate();
+            return Err(e);
+        }
+        Ok(changed)
+    }
+
+    /// Sends a frame every [`interval`](Self::interval) until a write fails.
+    ///
+    /// Frames are scheduled against fixed deadlines; if sending falls behind,
+    /// the missed frames are skipped rather than sent back to back.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that stopped the loop.
+    pub async fn run(mut self) -> Result<(), std::io::Error> {
+        let mut deadline = Instant::now();
+        loop {
+            self.render_frame().await?;
+            deadline += self.interval;
+            let now = Instant::now();
+            if deadline < now {
+                deadline = now;
+            }
+            rt::sleep(deadline - now).await;
+        }
+    }
+}
+
+/// The LED commands and LCD message that turn the shown state into
+/// `frame`, which becomes what `lcd` shows.
+fn frame_delta<'a>(
+    leds: &LedState,
+    lcd: &'a mut LcdBuffer,
+    frame: SurfaceFrame,
+) -> (Vec<AutomapCommand>, Option<AutomapSysEx<'a>>) {
+    let commands = leds.commands_to(&frame.leds);
+    *lcd.back() = frame.lcd;
+    (commands, lcd.flush())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::cc::Button;
+    use crate::automap::sysex::LcdLine;
+
+    #[test]
+    fn test_frame_delta_sends_only_changes() {
+        let target = RenderTarget::default();
+        target.update(|frame| {
+            frame.text(LcdLine::LeftTop, 0, "Mixer");
+            frame.led(&AutomapCommand::ButtonLed {
+                button: Button::ButtonA1,
+                on: true,
+            });
+        });
+        let frame = target.snapshot();
+        let mut lcd = LcdBuffer::new();
+
+        let (commands, msg) = frame_delta(&LedState::default(), &mut lcd, frame.clone());
+        assert_eq!(commands.len(), 1);
+        assert_eq!(msg, Some(AutomapSysEx::LcdText(frame.lcd.to_ops())));
+
+        let (commands, msg) = frame_delta(&frame.leds, &mut lcd, frame.clone());
+        assert!(commands.is_empty() && msg.is_none());
+    }
+}
@@ -0,0 +1,133 @@
+//! Matching parameter responses to the requests they answer.
+//!
+//! The product type request is answered on CC 0x67 and the transport lock
+//! request on CC 0x4F, the same message as an unsolicited
+//! [`AutomapEvent::TransportLockStatus`]. Neither answer says it is one.
+//! The device notes each request as it goes out, oldest first, and turns a
+//! CC 0x67 value into [`AutomapEvent::Parameter`] only while a product type
+//! request is waiting, and a lock status only while a lock request is.
+//!
+//! The RemoteSL and ZeRO SL do not answer the transport lock request, so a
+//! request is forgotten after [`REPLY_TIMEOUT`].
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::automap::cc::ParameterRequestType;
+use crate::automap::device::REPLY_TIMEOUT;
+use crate::automap::event::{AutomapEvent, ParameterResponse};
+
+/// Most requests kept waiting; older ones are forgotten first.
+const MAX_OUTSTANDING: usize = 16;
+
+/// Parameter requests sent and not yet answered, oldest first.
+#[derive(Debug, Default)]
+pub(crate) struct ParameterRequests {
+    outstanding: VecDeque<(Instant, ParameterRequestType)>,
+}
+
+impl ParameterRequests {
+    /// Notes a request sent at `now`.
+    pub(crate) fn sent(&mut self, request_type: ParameterRequestType, now: Instant) {
+        self.expire(now);
+        if self.outstanding.len() == MAX_OUTSTANDING {
+            self.outstanding.pop_front();
+        }
+        self.outstanding.push_back((now, request_type));
+    }
+
+    /// Forgets the request of `request_type` noted at `at`, e.g. when it
+    /// could not be sent.
+    pub(crate) fn withdraw(&mut self, request_type: ParameterRequestType, at: Instant) {
+        if let Some(i) = self
+            .outstanding
+            .iter()
+            .rposition(|&entry| entry == (at, request_type))
+        {
+            self.outstanding.remove(i);
+        }
+    }
+
+    /// Types an event received at `now` by the request it answers: a CC
+    /// 0x67 value by the oldest product type request, a lock status by the
+    /// oldest transport lock request. Anything else, or an answer nothing
+    /// is waiting for, is passed through.
+    pub(crate) fn resolve(&mut self, event: AutomapEvent, now: Instant) -> AutomapEvent {
+        self.expire(now);
+        let (request, value) = match event {
+            AutomapEvent::ParameterResponse { response } => {
+                (ParameterRequestType::UnitProductType, response)
+            }
+            AutomapEvent::TransportLockStatus { enabled } => {
+                (ParameterRequestType::TransportLockState, enabled as u8)
+            }
+            event => return event,
+        };
+        let Some(i) = self.outstanding.iter().position(|&(_, r)| r == request) else {
+            return event;
+        };
+        self.outstanding.remove(i);
+        ParameterResponse::decode(request, value)
+            .map_or(event, |response| AutomapEvent::Parameter { response })
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(at, _)) = self.outstanding.front() {
+            if now.saturating_duration_since(at) < REPLY_TIMEOUT {
+                break;
+            }
+            self.outstanding.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::cc::ProductType;
+
+    #[test]
+    fn test_responses_typed_by_request() {
+        let mut requests = ParameterRequests::default();
+        let now = Instant::now();
+        let raw = AutomapEvent::ParameterResponse { response: 1 };
+        assert_eq!(requests.resolve(raw, now), raw);
+
+        // A lock request the unit never answers does not take the product
+        // type answer after it
+        requests.sent(ParameterRequestType::TransportLockState, now);
+        requests.sent(ParameterRequestType::UnitProductType, now);
+        assert_eq!(
+            requests.resolve(raw, now),
+            AutomapEvent::Parameter {
+                response: ParameterResponse::ProductType(ProductType::ZeroSLorZeroMKII)
+            }
+        );
+        assert_eq!(requests.resolve(raw, now), raw);
+
+        // The lock request is answered by a status report
+        let status = AutomapEvent::TransportLockStatus { enabled: true };
+        assert_eq!(
+            requests.resolve(status, now),
+            AutomapEvent::Parameter {
+                response: ParameterResponse::TransportLock(true)
+            }
+        );
+        assert_eq!(requests.resolve(status, now), status);
+
+        // Withdrawn requests wait for nothing
+        requests.sent(ParameterRequestType::UnitProductType, now);
+        requests.withdraw(ParameterRequestType::UnitProductType, now);
+        assert_eq!(
+            requests.resolve(AutomapEvent::ParameterResponse { response: 2 }, now),
+            AutomapEvent::ParameterResponse { response: 2 }
+        );
+
+        // Not a product type, and unanswered requests are forgotten
+        requests.sent(ParameterRequestType::UnitProductType, now);
+        let bad = AutomapEvent::ParameterResponse { response: 9 };
+        assert_eq!(requests.resolve(bad, now), bad);
+        requests.sent(ParameterRequestType::UnitProductType, now);
+        assert_eq!(requests.resolve(raw, now + REPLY_TIMEOUT), raw);
+    }
+}
@@ -0,0 +1,99 @@
+//! Runtime shims for the helpers the device layer needs beyond plain I/O.
+//!
+//! Like `device.rs`, this module is the only place that knows which async
+//! runtime is selected; everything else calls through these functions.
+
+use std::future::Future;
+use std::time::Duration;
+
+/// A mutex whose guard can be held across `.await`.
+#[cfg(feature = "tokio")]
+pub(crate) use tokio::sync::Mutex;
+
+/// A mutex whose guard can be held across `.await`.
+#[cfg(feature = "smol")]
+pub(crate) use smol::lock::Mutex;
+
+/// Runs `fut` to completion, or gives up after `dur`.
+///
+/// Returns `None` if the deadline elapsed first.
+#[cfg(feature = "tokio")]
+pub(crate) async fn timeout<F: Future>(dur: Duration, fut: F) -> Option<F::Output> {
+    tokio::time::timeout(dur, fut).await.ok()
+}
+
+/// Runs `fut` to completion, or gives up after `dur`.
+///
+/// Returns `None` if the deadline elapsed first.
+#[cfg(feature = "smol")]
+pub(crate) async fn timeout<F: Future>(dur: Duration, fut: F) -> Option<F::Output> {
+    futures_lite::future::or(async { Some(fut.await) }, async {
+        smol::Timer::after(dur).await;
+        None
+    })
+    .await
+}
+
+/// Waits for `dur` to pass.
+#[cfg(feature = "tokio")]
+pub(crate) async fn sleep(dur: Duration) {
+    tokio::time::sleep(dur).await
+}
+
+/// Waits for `dur` to pass.
+#[cfg(feature = "smol")]
+pub(crate) async fn sleep(dur: Duration) {
+    smol::Timer::after(dur).await;
+}
+
+/// Runs both futures, returning the output of whichever finishes first.
+///
+/// The other future is dropped, so both must be safe to cancel.
+#[cfg(feature = "tokio")]
+pub(crate) async fn race<T>(a: impl Future<Output = T>, b: impl Future<Output = T>) -> T {
+    tokio::select! {
+        v = a => v,
+        v = b => v,
+    }
+}
+
+/// Runs both futures, returning the output of whichever finishes first.
+///
+/// The other future is dropped, so both must be safe to cancel.
+#[cfg(feature = "smol")]
+pub(crate) async fn race<T>(a: impl Future<Output = T>, b: impl Future<Output = T>) -> T {
+    futures_lite::future::or(a, b).await
+}
+
+/// Runs futures to completion from blocking code, for the C API.
+#[cfg(all(feature = "capi", feature = "tokio"))]
+pub(crate) struct Executor(tokio::runtime::Runtime);
+
+#[cfg(all(feature = "capi", feature = "tokio"))]
+impl Executor {
+    pub(crate) fn new() -> std::io::Result<Self> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map(Executor)
+    }
+
+    pub(crate) fn block_on<F: Future>(&self, fut: F) -> F::Output {
+        self.0.block_on(fut)
+    }
+}
+
+/// Runs futures to completion from blocking code, for the C API.
+#[cfg(all(feature = "capi", feature = "smol"))]
+pub(crate) struct Executor;
+
+#[cfg(all(feature = "capi", feature = "smol"))]
+impl Executor {
+    pub(crate) fn new() -> std::io::Result<Self> {
+        Ok(Executor)
+    }
+
+    pub(crate) fn block_on<F: Future>(&self, fut: F) -> F::Output {
+        smol::block_on(fut)
+    }
+}
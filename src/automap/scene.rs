@@ -0,0 +1,182 @@
+//! Whole-surface scenes to flip between.
+//!
+//! A [`Scene`] is everything the surface shows: every button and row LED,
+//! the mode and position of every ring, and the text of both displays. An
+//! app keeps one per view, say mixer, device and transport, draws into the
+//! inactive ones as their data changes, and [recalls](Scene::recall) one to
+//! switch views. Only what differs from the surface is sent, so switching
+//! back and forth is quick however much the scenes hold.
+//!
+//! [`Scene::store()`] takes a scene from what a device shows, so one view
+//! can be drawn with the usual commands and kept for later.
+
+use std::time::Duration;
+
+use crate::automap::cc::{Encoder, EncoderPosition};
+use crate::automap::command::AutomapCommand;
+use crate::automap::device::AutomapDevice;
+use crate::automap::lcd::LcdScreen;
+use crate::automap::leds::LedState;
+use crate::automap::rt;
+use crate::automap::sysex::AutomapSysEx;
+
+/// How [`Scene::recall()`] gets from one scene to the next.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Transition {
+    /// Everything changes at once.
+    #[default]
+    Cut,
+    /// LEDs and text change at once, and each ring then moves to its new
+    /// position one step at a time, `step` apart. Rings whose position was
+    /// never set jump straight there.
+    Sweep { step: Duration },
+}
+
+/// Everything the surface shows.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scene {
+    pub leds: LedState,
+    pub lcd: LcdScreen,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the scene with what `device` shows, as far as its shadows
+    /// know: the LEDs and text sent through it and its handles. An LCD
+    /// that was never written is taken as blank.
+    pub fn store(&mut self, device: &AutomapDevice) {
+        self.leds = device.leds();
+        self.lcd = device.lcd().unwrap_or_default();
+    }
+
+    /// Shows the scene on `device`, sending only what differs from what it
+    /// shows now.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB write fails, or `NotConnected` while the
+    /// unit is not online.
+    pub async fn recall(
+        &self,
+        device: &mut AutomapDevice,
+        transition: Transition,
+    ) -> Result<(), std::io::Error> {
+        let ops = match device.lcd() {
+            Some(shown) => shown.diff_ops(&self.lcd),
+            None => self.lcd.to_ops(),
+        };
+        if !ops.is_empty() {
+            device.send_sysex(AutomapSysEx::LcdText(ops)).await?;
+        }
+
+        let handle = device.handle();
+        let frames = frames(&device.leds(), &self.leds, transition);
+        for (i, frame) in frames.iter().enumerate() {
+            if i > 0
+                && let Transition::Sweep { step } = transition
+            {
+                rt::sleep(step).await;
+            }
+            handle.send_commands(frame).await?;
+        }
+        Ok(())
+    }
+}
+
+/// The LED commands from `from` to `to`, in batches to send `transition`'s
+/// step apart. Empty batches are never returned.
+fn frames(from: &LedState, to: &LedState, transition: Transition) -> Vec<Vec<AutomapCommand>> {
+    let mut now = Vec::new();
+    let mut sweeps = Vec::new();
+    for cmd in from.commands_to(to) {
+        if let Transition::Sweep { .. } = transition
+            && let AutomapCommand::EncoderRingValue { encoder, position } = cmd
+            && let Some(start) = from.ring(encoder).position
+        {
+            sweeps.push((encoder, start as u8, position as u8));
+        } else {
+            now.push(cmd);
+        }
+    }
+
+    let mut frames = Vec::new();
+    if !now.is_empty() {
+        frames.push(now);
+    }
+    loop {
+        let frame: Vec<_> = sweeps
+            .iter_mut()
+            .filter(|(_, at, target)| at != target)
+            .map(|(encoder, at, target)| {
+                *at = if *at < *target { *at + 1 } else { *at - 1 };
+                ring_value(*encoder, *at)
+            })
+            .collect();
+        if frame.is_empty() {
+            return frames;
+        }
+        frames.push(frame);
+    }
+}
+
+fn ring_value(encoder: Encoder, position: u8) -> AutomapCommand {
+    AutomapCommand::EncoderRingValue {
+        encoder,
+        position: EncoderPosition::ALL[usize::from(position)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::cc::Button;
+
+    #[test]
+    fn test_sweep_moves_rings_a_step_per_frame() {
+        let mut from = LedState::default();
+        from.apply(&ring_value(Encoder::Encoder1, 2));
+        from.apply(&ring_value(Encoder::Encoder2, 6));
+        let mut to = from.clone();
+        to.apply(&ring_value(Encoder::Encoder1, 5));
+        to.apply(&ring_value(Encoder::Encoder2, 5));
+        to.apply(&ring_value(Encoder::Encoder3, 9));
+        let led = AutomapCommand::ButtonLed {
+            button: Button::ButtonA1,
+            on: true,
+        };
+        to.apply(&led);
+
+        let cut = frames(&from, &to, Transition::Cut);
+        assert_eq!(cut.len(), 1);
+        assert_eq!(cut[0].len(), 4);
+
+        let step = Duration::from_millis(10);
+        let sweep = frames(&from, &to, Transition::Sweep { step });
+        // The LED and the ring never set before go first
+        assert_eq!(sweep[0], [led, ring_value(Encoder::Encoder3, 9)]);
+        assert_eq!(
+            sweep[1],
+            [
+                ring_value(Encoder::Encoder1, 3),
+                ring_value(Encoder::Encoder2, 5)
+            ]
+        );
+        assert_eq!(
+            sweep[2..],
+            [
+                vec![ring_value(Encoder::Encoder1, 4)],
+                vec![ring_value(Encoder::Encoder1, 5)]
+            ]
+        );
+
+        let mut shown = from.clone();
+        for cmd in sweep.iter().flatten() {
+            shown.apply(cmd);
+        }
+        assert!(shown.commands_to(&to).is_empty());
+        assert!(frames(&to, &to, Transition::Sweep { step }).is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+//! An idle screensaver, so text is not left on the displays for hours.
+//!
+//! A [`Screensaver`] reads events for the app. Once no control has moved
+//! for the configured time, it stores the surface as a [`Scene`] and shows
+//! its [`SaverMode`] instead: a message scrolling across both displays, or
+//! lights chasing round the encoder rings. The first control touched puts
+//! the stored scene back. That event only wakes the surface and is not
+//! passed on, so a knob turned in the dark changes nothing.
+//!
+//! While the saver runs, the app should hold off drawing, which would
+//! show through; drawing into a [`Scene`] and recalling it after
+//! [`wake()`](Screensaver::wake) is one way.
+
+use std::time::{Duration, Instant};
+
+use crate::automap::cc::{Encoder, EncoderPosition, RingMode};
+use crate::automap::command::AutomapCommand;
+use crate::automap::device::AutomapDevice;
+use crate::automap::event::AutomapEvent;
+use crate::automap::lcd::{Align, LCD_COLUMNS, fit};
+use crate::automap::params::BANK_SIZE;
+use crate::automap::rt;
+use crate::automap::scene::{Scene, Transition};
+use crate::automap::subscribe::EventFilter;
+use crate::automap::sysex::LcdLine;
+
+/// What the screensaver shows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaverMode {
+    /// Text moving right to left along the top lines of both displays, one
+    /// column per frame. The rest of the surface is dark.
+    Scroll(String),
+    /// A single light going round each encoder ring, each ring a step
+    /// ahead of the one to its left. The displays are blank.
+    RingChase,
+}
+
+/// Reads events, and takes over the surface while nobody uses it.
+#[derive(Debug, Clone)]
+pub struct Screensaver {
+    idle: Duration,
+    mode: SaverMode,
+    frame_time: Duration,
+    last_input: Instant,
+    /// The surface as it was when the saver started, while it runs.
+    saved: Option<Scene>,
+    frame: usize,
+    next_frame: Instant,
+}
+
+impl Screensaver {
+    /// A saver showing `mode` after `idle` without input, at 8 frames per
+    /// second.
+    pub fn new(idle: Duration, mode: SaverMode) -> Self {
+        Screensaver {
+            idle,
+            mode,
+            frame_time: Duration::from_millis(125),
+            last_input: Instant::now(),
+            saved: None,
+            frame: 0,
+            next_frame: Instant::now(),
+        }
+    }
+
+    /// Time between frames of the animation.
+    pub fn frame_time(mut self, frame_time: Duration) -> Self {
+        self.frame_time = frame_time;
+        self
+    }
+
+    /// Whether the saver is showing.
+    pub fn is_active(&self) -> bool {
+        self.saved.is_some()
+    }
+
+    /// Reads the next events from `device`, running the saver in between.
+    ///
+    /// Events that are not input, like echo replies, neither wake the
+    /// surface nor keep the saver from starting. Nothing is started while
+    /// the unit is offline, as output would not reach it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB read or write fails.
+    pub async fn next_events(
+        &mut self,
+        device: &mut AutomapDevice,
+    ) -> Result<Vec<AutomapEvent>, std::io::Error> {
+        loop {
+            let now = Instant::now();
+            let due = if self.is_active() {
+                self.next_frame
+            } else {
+                self.last_input + self.idle
+            };
+            if now >= due {
+                if !device.session_state().is_online() {
+                    self.last_input = now;
+                } else {
+                    if !self.is_active() {
+                        let mut scene = Scene::new();
+                        scene.store(device);
+                        self.saved = Some(scene);
+                        self.frame = 0;
+                    }
+                    self.draw(device).await?;
+                }
+                continue;
+            }
+
+            let Some(events) = rt::timeout(due - now, device.read_events()).await else {
+                continue;
+            };
+            let mut events = events?;
+            if events.iter().any(is_input) {
+                self.last_input = Instant::now();
+                if self.is_active() {
+                    self.wake(device).await?;
+                    events.retain(|event| !is_input(event));
+                }
+            }
+            return Ok(events);
+        }
+    }
+
+    /// Stops the saver, if it is showing, and puts back what the surface
+    /// showed before. Counts as input, so the idle time starts again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB write fails.
+    pub async fn wake(&mut self, device: &mut AutomapDevice) -> Result<(), std::io::Error> {
+        self.last_input = Instant::now();
+        match self.saved.take() {
+            Some(scene) => scene.recall(device, Transition::Cut).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn draw(&mut self, device: &mut AutomapDevice) -> Result<(), std::io::Error> {
+        frame_scene(&self.mode, self.frame)
+            .recall(device, Transition::Cut)
+            .await?;
+        self.frame += 1;
+        self.next_frame = Instant::now() + self.frame_time;
+        Ok(())
+    }
+}
+
+/// Whether `event` comes from someone using the surface.
+fn is_input(event: &AutomapEvent) -> bool {
+    !EventFilter::DEVICE.matches(event)
+}
+
+/// Frame `frame` of `mode`.
+fn frame_scene(mode: &SaverMode, frame: usize) -> Scene {
+    let mut scene = Scene::new();
+    scene.leds.apply(&AutomapCommand::AllLedsOff);
+    match mode {
+        SaverMode::Scroll(text) => {
+            // The two top lines make one band, the message entering on the right
+            let width = LCD_COLUMNS * 2;
+            let mut band = vec![b' '; width];
+            band.extend(fit(text, text.len(), Align::Left));
+            let start = frame % band.len();
+            let shown: Vec<u8> = band
+                .iter()
+                .cycle()
+                .skip(start)
+                .take(width)
+                .copied()
+                .collect();
+            scene.lcd.write(LcdLine::LeftTop, 0, &shown[..LCD_COLUMNS]);
+            scene.lcd.write(LcdLine::RightTop, 0, &shown[LCD_COLUMNS..]);
+        }
+        SaverMode::RingChase => {
+            // Position 0 blanks the ring, so the light goes round 1 to 11
+            let steps = EncoderPosition::ALL.len() - 1;
+            for slot in 0..BANK_SIZE {
+                let encoder =
+                    Encoder::try_from(Encoder::Encoder1 as u8 + slot as u8).expect("slot in range");
+                let position = EncoderPosition::ALL[1 + (frame + slot) % steps];
+                scene.leds.apply(&AutomapCommand::EncoderRingMode {
+                    encoder,
+                    mode: RingMode::SingleLedCw,
+                });
+                scene
+                    .leds
+                    .apply(&AutomapCommand::EncoderRingValue { encoder, position });
+            }
+        }
+    }
+    scene
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::cc::Button;
+
+    #[test]
+    fn test_saver_frames() {
+        let mode = SaverMode::Scroll("Hi".to_owned());
+        let line = |scene: &Scene, l| String::from_utf8(scene.lcd.line(l).to_vec()).unwrap();
+        let first = frame_scene(&mode, 0);
+        assert!(line(&first, LcdLine::LeftTop).trim().is_empty());
+        // Two frames in, the message has come in at the right edge
+        let entering = frame_scene(&mode, 2);
+        assert!(line(&entering, LcdLine::RightTop).ends_with("Hi"));
+        let moved = frame_scene(&mode, 3);
+        assert!(line(&moved, LcdLine::RightTop).ends_with("i "));
+        assert_eq!(first.leds.button(Button::ButtonA1), Some(false));
+
+        let chase = frame_scene(&SaverMode::RingChase, 10);
+        let ring = chase.leds.ring(Encoder::Encoder1);
+        assert_eq!(ring.mode, Some(RingMode::SingleLedCw));
+        assert_eq!(ring.position, Some(EncoderPosition::Pos11));
+        let next = frame_scene(&SaverMode::RingChase, 11);
+        assert_eq!(
+            next.leds.ring(Encoder::Encoder1).position,
+            Some(EncoderPosition::Pos1)
+        );
+        assert_eq!(
+            next.leds.ring(Encoder::Encoder2).position,
+            Some(EncoderPosition::Pos2)
+        );
+    }
+}
@@ -0,0 +1,328 @@
+//! An 8-step sequencer played from the surface (`seq` feature).
+//!
+//! [`StepSequencer`] uses one bank of the surface as its panel. The A-row
+//! buttons switch steps on and off, the encoder above each step sets its
+//! pitch, and the rings show the pitches as bands, the step playing as a
+//! single light. It does no I/O: the app passes in events and MIDI clock,
+//! sends the notes that come out wherever it likes, and sends
+//! [`led_commands()`](StepSequencer::led_commands) to the unit.
+//!
+//! The crate has no MIDI clock input of its own. Real-time bytes from any
+//! port, at the usual 24 pulses per quarter note, go to
+//! [`clock()`](StepSequencer::clock). To run free, call
+//! [`start()`](StepSequencer::start) and then
+//! [`pulse()`](StepSequencer::pulse) every [`pulse_interval()`] instead.
+//! Steps are sixteenth notes, and each note lasts half a step.
+
+use std::time::Duration;
+
+use crate::automap::cc::{Button, Encoder, EncoderPosition, RingMode};
+use crate::automap::command::AutomapCommand;
+use crate::automap::event::AutomapEvent;
+use crate::automap::leds::LedState;
+use crate::automap::params::BANK_SIZE;
+
+/// Number of steps, one per control in a bank.
+pub const STEPS: usize = BANK_SIZE;
+
+/// MIDI clock pulses per step, a sixteenth note at 24 PPQN.
+pub const PULSES_PER_STEP: u32 = 6;
+
+/// Highest step pitch, in semitones above the root, so that every pitch
+/// has its own ring position.
+pub const MAX_OFFSET: u8 = EncoderPosition::MAX as u8 - 1;
+
+/// Time between clock pulses at `bpm`.
+pub fn pulse_interval(bpm: f64) -> Duration {
+    Duration::from_secs_f64(60.0 / bpm / 24.0)
+}
+
+/// One step of the pattern.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Step {
+    /// Whether the step plays a note.
+    pub on: bool,
+    /// Pitch in semitones above the root, up to [`MAX_OFFSET`].
+    pub offset: u8,
+}
+
+/// The sequencer's pattern, play position and what it has shown.
+#[derive(Debug, Clone)]
+pub struct StepSequencer {
+    steps: [Step; STEPS],
+    channel: u8,
+    root: u8,
+    velocity: u8,
+    playing: bool,
+    /// Pulses into the pattern; the next pulse is this one.
+    pulse: u32,
+    current: Option<usize>,
+    /// Note on and not yet off.
+    sounding: Option<u8>,
+    /// LEDs as sent by `led_commands()`.
+    shown: LedState,
+}
+
+impl Default for StepSequencer {
+    fn default() -> Self {
+        StepSequencer {
+            steps: [Step::default(); STEPS],
+            channel: 1,
+            root: 60,
+            velocity: 100,
+            playing: false,
+            pulse: 0,
+            current: None,
+            sounding: None,
+            shown: LedState::default(),
+        }
+    }
+}
+
+impl StepSequencer {
+    /// Defaults: every step off at the root, middle C, sent on channel 1
+    /// at velocity 100.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// MIDI channel of the notes, 1-based.
+    pub fn channel(mut self, channel: u8) -> Self {
+        self.channel = channel.clamp(1, 16);
+        self
+    }
+
+    /// Note number a step at offset 0 plays.
+    pub fn root(mut self, note: u8) -> Self {
+        self.root = note.min(0x7F - MAX_OFFSET);
+        self
+    }
+
+    /// Velocity of every note.
+    pub fn velocity(mut self, velocity: u8) -> Self {
+        self.velocity = velocity.clamp(1, 0x7F);
+        self
+    }
+
+    pub fn steps(&self) -> &[Step; STEPS] {
+        &self.steps
+    }
+
+    /// Replaces step `index`, clamping its offset. Out-of-range indexes are
+    /// ignored.
+    pub fn set_step(&mut self, index: usize, step: Step) {
+        if let Some(slot) = self.steps.get_mut(index) {
+            *slot = Step {
+                offset: step.offset.min(MAX_OFFSET),
+                ..step
+            };
+        }
+    }
+
+    /// The step last played, if any since the last start.
+    pub fn current(&self) -> Option<usize> {
+        self.current
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Edits the pattern from an A-row press or an encoder turn.
+    ///
+    /// Returns `false` for events the sequencer does not use, so the app
+    /// can handle them itself.
+    pub fn handle(&mut self, event: &AutomapEvent) -> bool {
+        match *event {
+            AutomapEvent::Button { button, pressed } => {
+                let Some(index) = step_of_button(button) else {
+                    return false;
+                };
+                if pressed {
+                    self.steps[index].on = !self.steps[index].on;
+                }
+            }
+            AutomapEvent::Encoder { encoder, clicks } => {
+                let index = usize::from(encoder as u8 - Encoder::Encoder1 as u8);
+                let step = &mut self.steps[index];
+                step.offset = (i16::from(step.offset) + i16::from(clicks))
+                    .clamp(0, i16::from(MAX_OFFSET)) as u8;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Follows a MIDI real-time byte: clock, start, continue and stop.
+    /// Other bytes are ignored. Notes to send are appended to `out`.
+    pub fn clock(&mut self, byte: u8, out: &mut Vec<u8>) {
+        match byte {
+            0xF8 => self.pulse(out),
+            0xFA => self.start(out),
+            0xFB => self.playing = true,
+            0xFC => self.stop(out),
+            _ => {}
+        }
+    }
+
+    /// Starts from the top: the next pulse plays the first step.
+    pub fn start(&mut self, out: &mut Vec<u8>) {
+        self.note_off(out);
+        self.playing = true;
+        self.pulse = 0;
+        self.current = None;
+    }
+
+    /// Stops, silencing the note playing. [`clock()`](Self::clock) resumes
+    /// from here on a MIDI continue.
+    pub fn stop(&mut self, out: &mut Vec<u8>) {
+        self.note_off(out);
+        self.playing = false;
+    }
+
+    /// Moves on one clock pulse while playing.
+    pub fn pulse(&mut self, out: &mut Vec<u8>) {
+        if !self.playing {
+            return;
+        }
+        let phase = self.pulse % PULSES_PER_STEP;
+        if phase == 0 {
+            self.note_off(out);
+            let index = (self.pulse / PULSES_PER_STEP) as usize;
+            self.current = Some(index);
+            let step = self.steps[index];
+            if step.on {
+                let note = self.root + step.offset;
+                out.extend_from_slice(&[0x90 | (self.channel - 1), note, self.velocity]);
+                self.sounding = Some(note);
+            }
+        } else if phase == PULSES_PER_STEP / 2 {
+            self.note_off(out);
+        }
+        self.pulse = (self.pulse + 1) % (PULSES_PER_STEP * STEPS as u32);
+    }
+
+    /// Commands bringing the panel up to date with the pattern and play
+    /// position. Only what changed since the last call is returned, so
+    /// this can be called after every event and pulse.
+    pub fn led_commands(&mut self) -> Vec<AutomapCommand> {
+        let mut target = self.shown.clone();
+        for (index, step) in self.steps.iter().enumerate() {
+            let button =
+                Button::try_from(Button::ButtonA1 as u8 + index as u8).expect("step in button row");
+            target.apply(&AutomapCommand::ButtonLed {
+                button,
+                on: step.on,
+            });
+            let encoder = encoder_of_step(index);
+            let mode = if self.current == Some(index) {
+                RingMode::SingleLedCw
+            } else {
+                RingMode::ContinuousCw
+            };
+            // Position 0 is a blank ring, so the lowest pitch shows one light
+            let position = EncoderPosition::ALL[usize::from(step.offset) + 1];
+            target.apply(&AutomapCommand::EncoderRingMode { encoder, mode });
+            target.apply(&AutomapCommand::EncoderRingValue { encoder, position });
+        }
+        let out = self.shown.commands_to(&target);
+        self.shown = target;
+        out
+    }
+
+    fn note_off(&mut self, out: &mut Vec<u8>) {
+        if let Some(note) = self.sounding.take() {
+            out.extend_from_slice(&[0x80 | (self.channel - 1), note, 0]);
+        }
+    }
+}
+
+fn step_of_button(button: Button) -> Option<usize> {
+    let index = (button as u8).checked_sub(Button::ButtonA1 as u8)?;
+    (usize::from(index) < STEPS).then_some(usize::from(index))
+}
+
+fn encoder_of_step(index: usize) -> Encoder {
+    Encoder::try_from(Encoder::Encoder1 as u8 + index as u8).expect("step in encoder row")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steps_play_on_the_clock() {
+        let mut seq = StepSequencer::new().channel(2).root(48);
+        let press = |button| AutomapEvent::Button {
+            button,
+            pressed: true,
+        };
+        assert!(seq.handle(&press(Button::ButtonA1)));
+        assert!(seq.handle(&press(Button::ButtonA3)));
+        assert!(seq.handle(&AutomapEvent::Encoder {
+            encoder: Encoder::Encoder3,
+            clicks: 40,
+        }));
+        assert!(!seq.handle(&press(Button::ButtonB1)));
+        assert_eq!(
+            seq.steps()[2],
+            Step {
+                on: true,
+                offset: MAX_OFFSET
+            }
+        );
+
+        let mut out = Vec::new();
+        seq.clock(0xFA, &mut out);
+        seq.clock(0xF8, &mut out);
+        assert_eq!(out, [0x91, 48, 100]);
+        assert_eq!(seq.current(), Some(0));
+        out.clear();
+        // Off half-way through the step; step 2 is silent
+        for _ in 1..PULSES_PER_STEP * 2 {
+            seq.clock(0xF8, &mut out);
+        }
+        assert_eq!(out, [0x81, 48, 0]);
+        out.clear();
+        seq.clock(0xF8, &mut out);
+        assert_eq!(out, [0x91, 58, 100]);
+        out.clear();
+        seq.clock(0xFC, &mut out);
+        assert_eq!(out, [0x81, 58, 0]);
+        out.clear();
+        seq.clock(0xF8, &mut out);
+        assert!(out.is_empty());
+
+        let first = seq.led_commands();
+        assert!(first.contains(&AutomapCommand::ButtonLed {
+            button: Button::ButtonA3,
+            on: true
+        }));
+        assert!(first.contains(&AutomapCommand::EncoderRingMode {
+            encoder: Encoder::Encoder3,
+            mode: RingMode::SingleLedCw
+        }));
+        assert!(seq.led_commands().is_empty());
+
+        // Continuing plays on from step 4, and the playhead moves off step 3
+        seq.clock(0xFB, &mut out);
+        for _ in 0..PULSES_PER_STEP {
+            seq.clock(0xF8, &mut out);
+        }
+        assert_eq!(seq.current(), Some(3));
+        assert_eq!(
+            seq.led_commands(),
+            [
+                AutomapCommand::EncoderRingMode {
+                    encoder: Encoder::Encoder3,
+                    mode: RingMode::ContinuousCw
+                },
+                AutomapCommand::EncoderRingMode {
+                    encoder: Encoder::Encoder4,
+                    mode: RingMode::SingleLedCw
+                },
+            ]
+        );
+        assert!((pulse_interval(125.0).as_secs_f64() - 0.02).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,164 @@
+//! Where the host's session with the unit stands.
+//!
+//! A device starts out `Claimed`, and is `Online` from
+//! [`enter_automap_mode()`](crate::AutomapDevice::enter_automap_mode), or
+//! any other `OnlineOffline { online: true }`, until
+//! [`release_to_standalone()`](crate::AutomapDevice::release_to_standalone).
+//! The user switching templates on the unit moves it between the two as
+//! well; see [`AutomapEvent::TemplateChanged`](crate::AutomapEvent::TemplateChanged). Trouble on an online link makes it
+//! `Degraded` until the next answered echo, losing the unit makes it
+//! `Detached` until [`recover()`](crate::AutomapDevice::recover) succeeds,
+//! and [`close()`](crate::AutomapDevice::close) ends it in `Closing`.
+//!
+//! The device follows the session from what it sends and reads, and
+//! queues a [`DeviceNotice::SessionChanged`] for each step.
+
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::automap::notice::DeviceNotice;
+
+/// The state of the session, from
+/// [`AutomapDevice::session_state()`](crate::AutomapDevice::session_state).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    /// No USB connection: the unit went away, or is being reopened by
+    /// [`recover()`](crate::AutomapDevice::recover).
+    Detached,
+    /// The interface is claimed, but the host has not told the unit it is
+    /// online, so the unit is in standalone mode and ignores LED and LCD
+    /// output.
+    Claimed,
+    /// The unit is in Automap mode, taking LED and LCD output.
+    Online,
+    /// Online, but a ping or keep-alive went unanswered or an endpoint
+    /// stalled. The next answered echo makes it `Online` again.
+    Degraded,
+    /// [`close()`](crate::AutomapDevice::close) is tidying up.
+    Closing,
+}
+
+impl SessionState {
+    /// Whether LED and LCD output reaches the surface.
+    pub fn is_online(self) -> bool {
+        matches!(
+            self,
+            SessionState::Online | SessionState::Degraded | SessionState::Closing
+        )
+    }
+}
+
+impl fmt::Display for SessionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SessionState::Detached => "detached",
+            SessionState::Claimed => "claimed",
+            SessionState::Online => "online",
+            SessionState::Degraded => "degraded",
+            SessionState::Closing => "closing",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The current state and the steps not yet reported as notices. Shared
+/// between the device and its handles, which also send.
+pub(crate) struct Session {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    state: SessionState,
+    changes: Vec<DeviceNotice>,
+    /// The state before the last step to `Detached`.
+    before_detach: SessionState,
+}
+
+impl Session {
+    pub(crate) fn new(state: SessionState) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                state,
+                changes: Vec::new(),
+                before_detach: state,
+            }),
+        }
+    }
+
+    pub(crate) fn state(&self) -> SessionState {
+        self.inner.lock().unwrap().state
+    }
+
+    /// The state the session was in when it last became `Detached`, or the
+    /// current one if it never has.
+    pub(crate) fn before_detach(&self) -> SessionState {
+        let inner = self.inner.lock().unwrap();
+        match inner.state {
+            SessionState::Detached => inner.before_detach,
+            state => state,
+        }
+    }
+
+    /// Moves to `to`, recording the step if it is one.
+    pub(crate) fn set(&self, to: SessionState) {
+        self.step_if(|_| true, to);
+    }
+
+    /// Moves to `to` only from `from`.
+    pub(crate) fn step(&self, from: SessionState, to: SessionState) {
+        self.step_if(|state| state == from, to);
+    }
+
+    fn step_if(&self, allowed: impl FnOnce(SessionState) -> bool, to: SessionState) {
+        let mut inner = self.inner.lock().unwrap();
+        let from = inner.state;
+        if from != to && allowed(from) {
+            if to == SessionState::Detached {
+                inner.before_detach = from;
+            }
+            inner.state = to;
+            inner
+                .changes
+                .push(DeviceNotice::SessionChanged { from, to });
+        }
+    }
+
+    /// Takes the steps recorded since the last call, oldest first.
+    pub(crate) fn take_changes(&self) -> Vec<DeviceNotice> {
+        std::mem::take(&mut self.inner.lock().unwrap().changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_each_step_once() {
+        let session = Session::new(SessionState::Claimed);
+        session.set(SessionState::Online);
+        session.set(SessionState::Online);
+        session.step(SessionState::Claimed, SessionState::Detached);
+        session.step(SessionState::Online, SessionState::Degraded);
+        assert_eq!(
+            session.take_changes(),
+            [
+                DeviceNotice::SessionChanged {
+                    from: SessionState::Claimed,
+                    to: SessionState::Online,
+                },
+                DeviceNotice::SessionChanged {
+                    from: SessionState::Online,
+                    to: SessionState::Degraded,
+                },
+            ]
+        );
+        assert!(session.take_changes().is_empty());
+        assert!(session.state().is_online());
+
+        session.set(SessionState::Detached);
+        session.set(SessionState::Claimed);
+        session.set(SessionState::Detached);
+        assert_eq!(session.before_detach(), SessionState::Claimed);
+    }
+}
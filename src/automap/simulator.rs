@@ -0,0 +1,163 @@
+//! Playing keys through the simulation messages, for testing firmware and
+//! templates without hands on the keyboard.
+//!
+//! [`SimCmd::Key`] presses one key at a velocity, and the same key at
+//! velocity 0 lets it go, as with MIDI note messages. A [`Simulator`],
+//! from [`AutomapDevice::simulator()`](crate::AutomapDevice::simulator),
+//! builds on that: taps, chords and sequences of them with set hold and
+//! gap times, and [`release_all()`](Simulator::release_all) for keys left
+//! down when one is cut short.
+//!
+//! Keys are numbered from 1 at the bottom of the keyboard, as in the
+//! messages. The ZeRO MkII has no keys of its own; the messages are for
+//! the keyboard models of the SL MkII range.
+
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::automap::handle::AutomapHandle;
+use crate::automap::rt;
+use crate::automap::sysex::{DbSimMsg, SimCmd};
+
+/// Plays simulated keys on a unit.
+///
+/// Clones share the set of keys held down.
+#[derive(Clone)]
+pub struct Simulator {
+    handle: AutomapHandle,
+    hold: Duration,
+    gap: Duration,
+    /// Keys pressed and not yet released.
+    held: Arc<Mutex<BTreeSet<u8>>>,
+}
+
+impl Simulator {
+    /// Holds keys for 100 ms and leaves 50 ms between the steps of a
+    /// sequence.
+    pub(crate) fn new(handle: AutomapHandle) -> Self {
+        Simulator {
+            handle,
+            hold: Duration::from_millis(100),
+            gap: Duration::from_millis(50),
+            held: Arc::default(),
+        }
+    }
+
+    /// How long [`tap()`](Self::tap), [`chord()`](Self::chord) and each
+    /// step of [`play()`](Self::play) hold their keys.
+    pub fn hold(mut self, hold: Duration) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    /// Time between releasing one step of [`play()`](Self::play) and
+    /// pressing the next.
+    pub fn gap(mut self, gap: Duration) -> Self {
+        self.gap = gap;
+        self
+    }
+
+    /// Keys pressed through this simulator and not yet released, lowest
+    /// first.
+    pub fn held(&self) -> Vec<u8> {
+        self.held.lock().unwrap().iter().copied().collect()
+    }
+
+    /// Presses `key` at `velocity`, clamped to `1..=127` so the press is
+    /// not taken for a release.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the USB write fails.
+    pub async fn key_down(&self, key: u8, velocity: u8) -> Result<(), std::io::Error> {
+        self.send_key(key, velocity.clamp(1, 0x7F)).await?;
+        self.held.lock().unwrap().insert(key);
+        Ok(())
+    }
+
+    /// Releases `key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the USB write fails.
+    pub async fn key_up(&self, key: u8) -> Result<(), std::io::Error> {
+        self.send_key(key, 0).await?;
+        self.held.lock().unwrap().remove(&key);
+        Ok(())
+    }
+
+    /// Presses `key`, holds it and lets go.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB write fails.
+    pub async fn tap(&self, key: u8, velocity: u8) -> Result<(), std::io::Error> {
+        self.chord(&[key], velocity).await
+    }
+
+    /// Presses `keys` together, lowest first, holds them and lets go.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB write fails.
+    pub async fn chord(&self, keys: &[u8], velocity: u8) -> Result<(), std::io::Error> {
+        for &key in keys {
+            self.key_down(key, velocity).await?;
+        }
+        rt::sleep(self.hold).await;
+        for &key in keys {
+            self.key_up(key).await?;
+        }
+        Ok(())
+    }
+
+    /// Plays `steps` one after another, each a chord of one key or more,
+    /// with the [gap](Self::gap) between them. An empty step is a rest.
+    ///
+    /// ```no_run
+    /// # async fn scale(device: &automap::AutomapDevice) -> std::io::Result<()> {
+    /// let sim = device.simulator();
+    /// sim.play(&[&[25], &[27], &[29], &[25, 29, 32]], 100).await
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB write fails. Keys already pressed are left
+    /// down; see [`release_all()`](Self::release_all).
+    pub async fn play(&self, steps: &[&[u8]], velocity: u8) -> Result<(), std::io::Error> {
+        for (i, keys) in steps.iter().enumerate() {
+            if i > 0 {
+                rt::sleep(self.gap).await;
+            }
+            if keys.is_empty() {
+                rt::sleep(self.hold).await;
+            } else {
+                self.chord(keys, velocity).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Releases every key still held down, e.g. after a sequence was
+    /// cancelled or failed partway.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a USB write fails.
+    pub async fn release_all(&self) -> Result<(), std::io::Error> {
+        for key in self.held() {
+            self.key_up(key).await?;
+        }
+        Ok(())
+    }
+
+    async fn send_key(&self, key: u8, velocity: u8) -> Result<(), std::io::Error> {
+        let cmd = SimCmd::Key {
+            number_1_based: key,
+            velocity,
+        };
+        self.handle.send_dbsim(&DbSimMsg::Simulate(cmd)).await
+    }
+}
@@ -0,0 +1,22 @@
+//! Captured surface state, for handing the controller back untouched.
+
+use crate::automap::lcd::LcdScreen;
+use crate::automap::leds::LedState;
+
+/// Everything [`AutomapDevice::snapshot_state()`] could learn about the
+/// surface.
+///
+/// LCD text and the transport lock come from the unit's own readback
+/// requests; each is `None` if the unit did not answer. LEDs and rings are
+/// not read back: the unit's LED bitmap does not say which bit is which, so
+/// `leds` is only the host-side shadow of what had been sent through this
+/// device at the time of the snapshot, and restoring a snapshot brings back
+/// the LCD and transport lock as the unit showed them but not its own LEDs.
+///
+/// [`AutomapDevice::snapshot_state()`]: crate::AutomapDevice::snapshot_state
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SurfaceSnapshot {
+    pub lcd: Option<LcdScreen>,
+    pub transport_lock: Option<bool>,
+    pub leds: LedState,
+}
@@ -0,0 +1,339 @@
+//! The last known position of every control.
+//!
+//! The unit only reports a control when it moves. [`ControlState`] keeps
+//! what has been reported so far, so a page drawn for the first time can
+//! show where the sliders and pots already are, and tells listeners about
+//! each change as it is applied.
+//!
+//! A [`Snapshot`] fills it in without waiting: the unit's snapshot function
+//! sends the current position of every pot, slider and other absolute
+//! control at once, and a [`SnapshotCollector`] gathers that burst.
+//!
+//! The host cannot ask for one: the Programmer's Reference documents no
+//! request that makes the unit send its control values. The burst starts
+//! when the user triggers the snapshot function on the unit itself, so an
+//! application wanting the positions at startup has to prompt for it, and
+//! then wait with
+//! [`AutomapDevice::collect_snapshot()`](crate::AutomapDevice::collect_snapshot).
+
+use crate::automap::cc::{Encoder, Pot, Slider};
+use crate::automap::event::AutomapEvent;
+use crate::automap::gestures::PressSource;
+
+type Listener = Box<dyn FnMut(&AutomapEvent) + Send>;
+
+/// Last known values of the surface's controls, updated from events.
+///
+/// Values never reported are `None`. Encoders have no absolute position;
+/// [`encoder_clicks()`](Self::encoder_clicks) is the net number of clicks
+/// seen.
+#[derive(Default)]
+pub struct ControlState {
+    pots: [Option<u8>; 8],
+    sliders: [Option<u8>; 8],
+    encoders: [i32; 8],
+    speed_dial: i32,
+    crossfader: Option<u8>,
+    expression_pedal: Option<u8>,
+    sustain_pedal: Option<bool>,
+    touchpad: [Option<u8>; 4],
+    encoders_touched: [bool; 8],
+    pots_touched: [bool; 8],
+    sliders_touched: [bool; 8],
+    crossfader_touched: bool,
+    speed_dial_touched: bool,
+    held: Vec<PressSource>,
+    listeners: Vec<Listener>,
+}
+
+impl std::fmt::Debug for ControlState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ControlState")
+            .field("pots", &self.pots)
+            .field("sliders", &self.sliders)
+            .field("encoders", &self.encoders)
+            .field("crossfader", &self.crossfader)
+            .field("held", &self.held)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ControlState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls `f` with every event that changes the stored state.
+    pub fn on_change(&mut self, f: impl FnMut(&AutomapEvent) + Send + 'static) {
+        self.listeners.push(Box::new(f));
+    }
+
+    /// Updates the state from `event`. Returns whether anything changed;
+    /// if so, the listeners have been called.
+    pub fn apply(&mut self, event: &AutomapEvent) -> bool {
+        fn set<T: PartialEq>(slot: &mut T, value: T) -> bool {
+            let changed = *slot != value;
+            *slot = value;
+            changed
+        }
+
+        let changed = match *event {
+            AutomapEvent::Pot { pot, value } => set(
+                &mut self.pots[pot as usize - Pot::Pot1 as usize],
+                Some(value),
+            ),
+            AutomapEvent::Slider { slider, value } => set(
+                &mut self.sliders[slider as usize - Slider::Slider1 as usize],
+                Some(value),
+            ),
+            AutomapEvent::Encoder { encoder, clicks } => {
+                self.encoders[encoder as usize - Encoder::Encoder1 as usize] += i32::from(clicks);
+                clicks != 0
+            }
+            AutomapEvent::SpeedDial { clicks } => {
+                self.speed_dial += i32::from(clicks);
+                clicks != 0
+            }
+            AutomapEvent::CrossFader { value } => set(&mut self.crossfader, Some(value)),
+            AutomapEvent::ExpressionPedal { value } => set(&mut self.expression_pedal, Some(value)),
+            AutomapEvent::SustainPedal { pressed } => set(&mut self.sustain_pedal, Some(pressed)),
+            AutomapEvent::TouchpadX1 { value } => set(&mut self.touchpad[0], Some(value)),
+            AutomapEvent::TouchpadY1 { value } => set(&mut self.touchpad[1], Some(value)),
+            AutomapEvent::TouchpadX2 { value } => set(&mut self.touchpad[2], Some(value)),
+            AutomapEvent::TouchpadY2 { value } => set(&mut self.touchpad[3], Some(value)),
+            AutomapEvent::EncoderTouch { encoder, touched } => set(
+                &mut self.encoders_touched[encoder as usize - Encoder::Encoder1 as usize],
+                touched,
+            ),
+            AutomapEvent::PotTouch { pot, touched } => set(
+                &mut self.pots_touched[pot as usize - Pot::Pot1 as usize],
+                touched,
+            ),
+            AutomapEvent::SliderTouch { slider, touched } => set(
+                &mut self.sliders_touched[slider as usize - Slider::Slider1 as usize],
+                touched,
+            ),
+            AutomapEvent::CrossFadeTouch { touched } => set(&mut self.crossfader_touched, touched),
+            AutomapEvent::SpeedDialTouch { touched } => set(&mut self.speed_dial_touched, touched),
+            _ => match PressSource::from_event(event) {
+                Some((source, pressed)) => {
+                    let was = self.held.contains(&source);
+                    self.held.retain(|s| *s != source);
+                    if pressed {
+                        self.held.push(source);
+                    }
+                    was != pressed
+                }
+                None => false,
+            },
+        };
+        if changed {
+            for listener in &mut self.listeners {
+                listener(event);
+            }
+        }
+        changed
+    }
+
+    pub fn pot(&self, pot: Pot) -> Option<u8> {
+        self.pots[pot as usize - Pot::Pot1 as usize]
+    }
+
+    pub fn slider(&self, slider: Slider) -> Option<u8> {
+        self.sliders[slider as usize - Slider::Slider1 as usize]
+    }
+
+    /// Net clicks seen from `encoder`, clockwise positive.
+    pub fn encoder_clicks(&self, encoder: Encoder) -> i32 {
+        self.encoders[encoder as usize - Encoder::Encoder1 as usize]
+    }
+
+    /// Net clicks seen from the speed dial, clockwise positive.
+    pub fn speed_dial_clicks(&self) -> i32 {
+        self.speed_dial
+    }
+
+    pub fn crossfader(&self) -> Option<u8> {
+        self.crossfader
+    }
+
+    pub fn expression_pedal(&self) -> Option<u8> {
+        self.expression_pedal
+    }
+
+    pub fn sustain_pedal(&self) -> Option<bool> {
+        self.sustain_pedal
+    }
+
+    /// The touchpad axes, in the order X1, Y1, X2, Y2.
+    pub fn touchpad(&self) -> [Option<u8>; 4] {
+        self.touchpad
+    }
+
+    pub fn encoder_touched(&self, encoder: Encoder) -> bool {
+        self.encoders_touched[encoder as usize - Encoder::Encoder1 as usize]
+    }
+
+    pub fn pot_touched(&self, pot: Pot) -> bool {
+        self.pots_touched[pot as usize - Pot::Pot1 as usize]
+    }
+
+    pub fn slider_touched(&self, slider: Slider) -> bool {
+        self.sliders_touched[slider as usize - Slider::Slider1 as usize]
+    }
+
+    pub fn crossfader_touched(&self) -> bool {
+        self.crossfader_touched
+    }
+
+    pub fn speed_dial_touched(&self) -> bool {
+        self.speed_dial_touched
+    }
+
+    /// Whether a button, page button, row select or other press control is
+    /// held down.
+    pub fn is_pressed(&self, source: PressSource) -> bool {
+        self.held.contains(&source)
+    }
+
+    /// Applies every value in `snapshot`. Returns whether anything changed.
+    pub fn apply_snapshot(&mut self, snapshot: &Snapshot) -> bool {
+        let mut changed = false;
+        for event in &snapshot.events {
+            changed |= self.apply(event);
+        }
+        changed
+    }
+
+    /// The press controls held down, oldest press first.
+    pub fn held(&self) -> &[PressSource] {
+        &self.held
+    }
+}
+
+/// Positions of the absolute controls, as sent in one snapshot burst.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    /// The last event seen from each control, in the order the controls
+    /// first appeared.
+    pub events: Vec<AutomapEvent>,
+}
+
+impl Snapshot {
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+/// Gathers the events of a snapshot burst into a [`Snapshot`].
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotCollector {
+    snapshot: Snapshot,
+}
+
+impl SnapshotCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `event` if it reports an absolute position, replacing an earlier
+    /// value from the same control. Returns whether it was taken; presses,
+    /// encoder turns and touches are not.
+    pub fn push(&mut self, event: &AutomapEvent) -> bool {
+        if !is_absolute(event) {
+            return false;
+        }
+        let same_control = |e: &AutomapEvent| match (e, event) {
+            (AutomapEvent::Pot { pot: a, .. }, AutomapEvent::Pot { pot: b, .. }) => a == b,
+            (AutomapEvent::Slider { slider: a, .. }, AutomapEvent::Slider { slider: b, .. }) => {
+                a == b
+            }
+            (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        };
+        match self.snapshot.events.iter_mut().find(|e| same_control(e)) {
+            Some(slot) => *slot = *event,
+            None => self.snapshot.events.push(*event),
+        }
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshot.is_empty()
+    }
+
+    pub fn finish(self) -> Snapshot {
+        self.snapshot
+    }
+}
+
+/// Whether `event` carries a position rather than a movement.
+fn is_absolute(event: &AutomapEvent) -> bool {
+    matches!(
+        event,
+        AutomapEvent::Pot { .. }
+            | AutomapEvent::Slider { .. }
+            | AutomapEvent::CrossFader { .. }
+            | AutomapEvent::ExpressionPedal { .. }
+            | AutomapEvent::SustainPedal { .. }
+            | AutomapEvent::TouchpadX1 { .. }
+            | AutomapEvent::TouchpadY1 { .. }
+            | AutomapEvent::TouchpadX2 { .. }
+            | AutomapEvent::TouchpadY2 { .. }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::cc::Button;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_apply_and_notify() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut state = ControlState::new();
+        let log = seen.clone();
+        state.on_change(move |event| log.lock().unwrap().push(*event));
+
+        let slider = AutomapEvent::Slider {
+            slider: Slider::Slider2,
+            value: 90,
+        };
+        assert_eq!(state.slider(Slider::Slider2), None);
+        assert!(state.apply(&slider));
+        assert!(!state.apply(&slider));
+        assert_eq!(state.slider(Slider::Slider2), Some(90));
+
+        let press = AutomapEvent::Button {
+            button: Button::ButtonC1,
+            pressed: true,
+        };
+        assert!(state.apply(&press));
+        assert!(state.is_pressed(PressSource::Button(Button::ButtonC1)));
+
+        assert_eq!(*seen.lock().unwrap(), [slider, press]);
+    }
+
+    #[test]
+    fn test_snapshot_collector() {
+        let mut collector = SnapshotCollector::new();
+        for value in [10, 20] {
+            assert!(collector.push(&AutomapEvent::Pot {
+                pot: Pot::Pot3,
+                value,
+            }));
+        }
+        assert!(collector.push(&AutomapEvent::CrossFader { value: 64 }));
+        assert!(!collector.push(&AutomapEvent::Encoder {
+            encoder: Encoder::Encoder1,
+            clicks: 1,
+        }));
+        let snapshot = collector.finish();
+        assert_eq!(snapshot.events.len(), 2);
+
+        let mut state = ControlState::new();
+        assert!(state.apply_snapshot(&snapshot));
+        assert_eq!(state.pot(Pot::Pot3), Some(20));
+        assert_eq!(state.crossfader(), Some(64));
+    }
+}
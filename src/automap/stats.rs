@@ -0,0 +1,119 @@
+//! Traffic counters, returned by
+//! [`AutomapDevice::stats()`](crate::AutomapDevice::stats).
+//!
+//! Counting starts when the device is opened and again at each
+//! [`reset_stats()`](crate::AutomapDevice::reset_stats), so a long-running
+//! installation can sample the counters, reset them, and chart the
+//! difference. Writes from [handles](crate::AutomapHandle) are counted
+//! with the device's own.
+
+use std::time::{Duration, Instant};
+
+/// MIDI messages counted by kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MessageCounts {
+    /// Control changes, on any channel. Most Automap events and commands
+    /// are these.
+    pub control_change: u64,
+    /// Whole SysEx frames.
+    pub sysex: u64,
+    /// Everything else: notes, pitch bend, real-time bytes.
+    pub other: u64,
+}
+
+impl MessageCounts {
+    pub(crate) fn count(&mut self, msg: &[u8]) {
+        match msg.first() {
+            Some(0xF0) => self.sysex += 1,
+            Some(status) if status & 0xF0 == 0xB0 => self.control_change += 1,
+            Some(_) => self.other += 1,
+            None => {}
+        }
+    }
+
+    /// Messages of every kind.
+    pub fn total(&self) -> u64 {
+        self.control_change + self.sysex + self.other
+    }
+}
+
+/// Counters of what went over the link since they were last reset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceStats {
+    /// When counting started.
+    pub since: Instant,
+    pub sent: MessageCounts,
+    pub received: MessageCounts,
+    /// USB-MIDI bytes written, four per packet.
+    pub bytes_sent: u64,
+    /// USB-MIDI bytes read, four per packet.
+    pub bytes_received: u64,
+    /// Messages on the Automap channel that decoded to no event, and SysEx
+    /// frames dropped for being too long.
+    pub decode_errors: u64,
+    /// Bytes thrown away while resynchronizing on malformed USB-MIDI
+    /// packets.
+    pub discarded_bytes: u64,
+    /// Reads tried again after a stall or an empty transfer.
+    pub read_retries: u64,
+    /// Writes tried again after a stall.
+    pub write_retries: u64,
+    /// Writes that completed, and the time they took altogether, from
+    /// asking for the endpoint to the transfer completing. Pacing waits
+    /// are not included.
+    pub writes: u64,
+    pub write_time: Duration,
+}
+
+impl DeviceStats {
+    pub(crate) fn new(since: Instant) -> Self {
+        DeviceStats {
+            since,
+            sent: MessageCounts::default(),
+            received: MessageCounts::default(),
+            bytes_sent: 0,
+            bytes_received: 0,
+            decode_errors: 0,
+            discarded_bytes: 0,
+            read_retries: 0,
+            write_retries: 0,
+            writes: 0,
+            write_time: Duration::ZERO,
+        }
+    }
+
+    /// Mean time a write took, `None` before any has completed.
+    pub fn mean_write_latency(&self) -> Option<Duration> {
+        (self.writes > 0).then(|| self.write_time / u32::try_from(self.writes).unwrap_or(u32::MAX))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_by_kind() {
+        let mut counts = MessageCounts::default();
+        counts.count(&[0xB0, 0x08, 0x40]);
+        counts.count(&[0xBF, 0x08, 0x40]);
+        counts.count(&[0xF0, 0x00, 0xF7]);
+        counts.count(&[0x90, 0x3C, 0x7F]);
+        counts.count(&[]);
+        assert_eq!(
+            counts,
+            MessageCounts {
+                control_change: 2,
+                sysex: 1,
+                other: 1,
+            }
+        );
+        assert_eq!(counts.total(), 4);
+
+        let mut stats = DeviceStats::new(Instant::now());
+        assert_eq!(stats.mean_write_latency(), None);
+        stats.writes = 4;
+        stats.write_time = Duration::from_millis(2);
+        assert_eq!(stats.mean_write_latency(), Some(Duration::from_micros(500)));
+    }
+}
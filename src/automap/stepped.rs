@@ -0,0 +1,162 @@
+//! Picking one of a few options with a pot or slider.
+//!
+//! A [`SteppedValue`] divides a control's 7-bit travel into equal steps,
+//! say twelve for a choice of key, and reports which one the control is
+//! in. Near a boundary it keeps the step it has until the control is well
+//! past, so a pot resting there does not flip between two options.
+//! [`step_position()`] shows the chosen step on an encoder ring, spread
+//! over its eleven LEDs.
+
+use crate::automap::cc::{EncoderPosition, RingMode};
+use crate::automap::event::AutomapEvent;
+use crate::automap::translator::Source;
+
+/// The ring position showing step `step` of `steps` as a single LED, for
+/// [`RingMode::SingleLedCw`].
+///
+/// Eleven steps or fewer each get their own LED, the first and last at
+/// either end of the ring; with more, neighbouring steps share one.
+pub fn step_position(step: usize, steps: usize) -> EncoderPosition {
+    // Position 0 is a blank ring, so the steps spread over 1 to 11
+    let leds = EncoderPosition::MAX as usize - 1;
+    let i = match steps {
+        0 | 1 => 0,
+        _ => (step.min(steps - 1) * leds + (steps - 1) / 2) / (steps - 1),
+    };
+    EncoderPosition::ALL[1 + i]
+}
+
+/// A continuous control read as one of `steps` options.
+///
+/// ```
+/// # use automap::{AutomapEvent, Pot, Source, SteppedValue};
+/// let mut key = SteppedValue::new(12).source(Source::Pot(Pot::Pot1));
+/// key.handle(&AutomapEvent::Pot { pot: Pot::Pot1, value: 64 });
+/// assert_eq!(key.get(), 6);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SteppedValue {
+    steps: usize,
+    hysteresis: f32,
+    source: Option<Source>,
+    step: usize,
+}
+
+impl SteppedValue {
+    /// `steps` options, at least one, starting at the first. A control has
+    /// to move a fifth of a step past a boundary to change the step.
+    pub fn new(steps: usize) -> Self {
+        SteppedValue {
+            steps: steps.clamp(1, 128),
+            hysteresis: 0.2,
+            source: None,
+            step: 0,
+        }
+    }
+
+    /// How far past a boundary, as a fraction of a step, the control has
+    /// to move to change the step. 0 snaps at the boundary itself; the
+    /// most is half a step.
+    pub fn hysteresis(mut self, fraction: f32) -> Self {
+        self.hysteresis = fraction.clamp(0.0, 0.5);
+        self
+    }
+
+    /// The control whose moves [`handle()`](Self::handle) follows.
+    pub fn source(mut self, source: Source) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// The starting step.
+    pub fn initial(mut self, step: usize) -> Self {
+        self.set(step);
+        self
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// The current step, from 0.
+    pub fn get(&self) -> usize {
+        self.step
+    }
+
+    /// Sets the step, e.g. when the host changes it, clamped to the range.
+    pub fn set(&mut self, step: usize) {
+        self.step = step.min(self.steps - 1);
+    }
+
+    /// Follows the control to 7-bit `value`. Returns whether the step
+    /// changed.
+    pub fn update(&mut self, value: u8) -> bool {
+        let width = 128.0 / self.steps as f32;
+        let margin = width * self.hysteresis;
+        let value = f32::from(value.min(127)) + 0.5;
+        let low = self.step as f32 * width - margin;
+        let high = (self.step + 1) as f32 * width + margin;
+        if value >= low && value < high {
+            return false;
+        }
+        let next = ((value / width) as usize).min(self.steps - 1);
+        let changed = next != self.step;
+        self.step = next;
+        changed
+    }
+
+    /// Applies a move of the configured control. Returns whether the step
+    /// changed; other events are ignored.
+    pub fn handle(&mut self, event: &AutomapEvent) -> bool {
+        match Source::from_event(event) {
+            Some((source, value)) if Some(source) == self.source => self.update(value),
+            _ => false,
+        }
+    }
+
+    /// The ring position showing the step in [`RingMode::SingleLedCw`];
+    /// see [`step_position()`].
+    pub fn ring_position(&self) -> EncoderPosition {
+        step_position(self.step, self.steps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::cc::Pot;
+
+    #[test]
+    fn test_steps_hold_near_boundaries() {
+        let mut stepped = SteppedValue::new(4).source(Source::Pot(Pot::Pot2));
+        let turn = |value| AutomapEvent::Pot {
+            pot: Pot::Pot2,
+            value,
+        };
+        assert!(!stepped.handle(&turn(10)));
+        // Steps are 32 wide; the first boundary is at 32, with 6.4 to spare
+        assert!(!stepped.handle(&turn(36)));
+        assert!(stepped.handle(&turn(38)));
+        assert_eq!(stepped.get(), 1);
+        assert!(!stepped.handle(&turn(28)));
+        assert!(stepped.handle(&turn(24)));
+        assert_eq!(stepped.get(), 0);
+        assert!(stepped.handle(&turn(127)));
+        assert_eq!(stepped.get(), 3);
+        assert!(!stepped.handle(&AutomapEvent::Pot {
+            pot: Pot::Pot1,
+            value: 0
+        }));
+
+        let mut snap = SteppedValue::new(4).hysteresis(0.0);
+        assert!(snap.update(32));
+        assert!(!snap.update(63));
+
+        assert_eq!(step_position(0, 11), EncoderPosition::Pos1);
+        assert_eq!(step_position(10, 11), EncoderPosition::Pos11);
+        assert_eq!(step_position(1, 3), EncoderPosition::Pos6);
+        assert_eq!(step_position(6, 12), EncoderPosition::Pos6);
+        assert_eq!(step_position(0, 1), EncoderPosition::Pos1);
+        assert_eq!(stepped.ring_position(), EncoderPosition::Pos11);
+    }
+}
@@ -0,0 +1,397 @@
+//! Handing events to several independent consumers.
+//!
+//! [`AutomapDevice::subscribe`](crate::AutomapDevice::subscribe) returns a
+//! [`Subscription`] that receives a copy of every event matching its
+//! [`EventFilter`]. The device stays the only reader of the USB endpoint:
+//! whichever task calls `read_events()` (or
+//! [`pump()`](crate::AutomapDevice::pump)) feeds all subscriptions, so a
+//! transport handler, a mixer page and a logger can each watch their own
+//! controls without taking turns on the device.
+//!
+//! Subscriptions buffer without limit. [`AutomapDevice::broadcast`]
+//! instead gives each consumer every event through a bounded
+//! [`EventReceiver`], which drops the oldest events when its consumer falls
+//! behind and reports how many were lost.
+//!
+//! [`AutomapDevice::broadcast`]: crate::AutomapDevice::broadcast
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::poll_fn;
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+
+use crate::automap::event::{AutomapEvent, ParameterResponse};
+use crate::automap::timed::TimedEvent;
+
+bitflags::bitflags! {
+    /// Groups of events a [`Subscription`] is interested in.
+    ///
+    /// Combine groups with `|`, e.g.
+    /// `EventFilter::buttons() | EventFilter::encoders()`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct EventFilter: u16 {
+        /// Surface buttons, automap buttons, page and row-select buttons,
+        /// preview and the speed-dial push.
+        const BUTTONS    = 1 << 0;
+        /// Transport buttons and the transport-lock status.
+        const TRANSPORT  = 1 << 1;
+        const ENCODERS   = 1 << 2;
+        const POTS       = 1 << 3;
+        const SLIDERS    = 1 << 4;
+        const CROSSFADER = 1 << 5;
+        const SPEED_DIAL = 1 << 6;
+        /// Touch sensors on encoders, pots, sliders, crossfader and speed dial.
+        const TOUCH      = 1 << 7;
+        const TOUCHPAD   = 1 << 8;
+        /// Mod and pitch wheels, sustain and expression pedals.
+        const PEDALS     = 1 << 9;
+        const TEMPO      = 1 << 10;
+        /// Alerts, echo and parameter replies, LED bitmaps and raw CCs.
+        const DEVICE     = 1 << 11;
+    }
+}
+
+impl EventFilter {
+    pub const fn buttons() -> Self {
+        Self::BUTTONS
+    }
+
+    pub const fn transport() -> Self {
+        Self::TRANSPORT
+    }
+
+    pub const fn encoders() -> Self {
+        Self::ENCODERS
+    }
+
+    pub const fn pots() -> Self {
+        Self::POTS
+    }
+
+    pub const fn sliders() -> Self {
+        Self::SLIDERS
+    }
+
+    pub const fn touch() -> Self {
+        Self::TOUCH
+    }
+
+    /// The group `event` belongs to.
+    pub fn of(event: &AutomapEvent) -> Self {
+        match event {
+            AutomapEvent::Button { .. }
+            | AutomapEvent::AutomapButton { .. }
+            | AutomapEvent::PageButton { .. }
+            | AutomapEvent::RowSelect { .. }
+            | AutomapEvent::EncoderRowSelect { .. }
+            | AutomapEvent::PreviewButton { .. }
+            | AutomapEvent::SpeedDialButton { .. } => Self::BUTTONS,
+            AutomapEvent::TransportButton { .. }
+            | AutomapEvent::TransportLockStatus { .. }
+            | AutomapEvent::Parameter {
+                response: ParameterResponse::TransportLock(_),
+            } => Self::TRANSPORT,
+            AutomapEvent::Encoder { .. } | AutomapEvent::LiveEncoder { .. } => Self::ENCODERS,
+            AutomapEvent::Pot { .. } => Self::POTS,
+            AutomapEvent::Slider { .. } => Self::SLIDERS,
+            AutomapEvent::CrossFader { .. } => Self::CROSSFADER,
+            AutomapEvent::SpeedDial { .. } => Self::SPEED_DIAL,
+            AutomapEvent::EncoderTouch { .. }
+            | AutomapEvent::PotTouch { .. }
+            | AutomapEvent::SliderTouch { .. }
+            | AutomapEvent::CrossFadeTouch { .. }
+            | AutomapEvent::SpeedDialTouch { .. } => Self::TOUCH,
+            AutomapEvent::TouchpadX1 { .. }
+            | AutomapEvent::TouchpadY1 { .. }
+            | AutomapEvent::TouchpadX2 { .. }
+            | AutomapEvent::TouchpadY2 { .. } => Self::TOUCHPAD,
+            AutomapEvent::Wheel { .. }
+            | AutomapEvent::SustainPedal { .. }
+            | AutomapEvent::ExpressionPedal { .. } => Self::PEDALS,
+            AutomapEvent::TempoMsb { .. } | AutomapEvent::TempoLsb { .. } => Self::TEMPO,
+            AutomapEvent::RowLhBitmap { .. }
+            | AutomapEvent::RowRhBitmap { .. }
+            | AutomapEvent::Alert { .. }
+            | AutomapEvent::EchoResponse { .. }
+            | AutomapEvent::ParameterResponse { .. }
+            | AutomapEvent::Parameter { .. }
+            | AutomapEvent::TemplateChanged { .. }
+            | AutomapEvent::Raw { .. } => Self::DEVICE,
+        }
+    }
+
+    /// Whether `event` passes this filter.
+    pub fn matches(self, event: &AutomapEvent) -> bool {
+        self.intersects(Self::of(event))
+    }
+}
+
+struct Channel {
+    filter: EventFilter,
+    /// Most events queued at once; beyond it the oldest are dropped.
+    capacity: Option<usize>,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    queue: VecDeque<TimedEvent>,
+    /// Events dropped since the receiver last heard about it.
+    lagged: u64,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+/// A stream of the events matching one [`EventFilter`].
+///
+/// Events queue up until received, so a subscription that is never read
+/// keeps growing; drop it once it is no longer needed.
+pub struct Subscription {
+    channel: Arc<Channel>,
+}
+
+impl Subscription {
+    pub fn filter(&self) -> EventFilter {
+        self.channel.filter
+    }
+
+    /// Waits for the next matching event.
+    ///
+    /// Returns `None` once the device has been dropped and every queued
+    /// event has been received.
+    pub async fn recv(&mut self) -> Option<TimedEvent> {
+        poll_fn(|cx| {
+            let mut state = self.channel.state.lock().unwrap();
+            match state.queue.pop_front() {
+                Some(event) => Poll::Ready(Some(event)),
+                None if state.closed => Poll::Ready(None),
+                None => {
+                    state.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Takes the next queued event without waiting.
+    pub fn try_recv(&mut self) -> Option<TimedEvent> {
+        self.channel.state.lock().unwrap().queue.pop_front()
+    }
+}
+
+/// Why [`EventReceiver::recv`] returned no event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// The receiver fell behind and this many events were dropped. The
+    /// next call carries on with the oldest event still queued.
+    Lagged(u64),
+    /// The device was dropped and every queued event has been received.
+    Closed,
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::Lagged(n) => write!(f, "receiver lagged behind, {n} events dropped"),
+            RecvError::Closed => write!(f, "device closed"),
+        }
+    }
+}
+
+impl std::error::Error for RecvError {}
+
+/// One consumer's bounded view of every event, from
+/// [`AutomapDevice::broadcast`](crate::AutomapDevice::broadcast).
+pub struct EventReceiver {
+    channel: Arc<Channel>,
+}
+
+impl EventReceiver {
+    /// Waits for the next event.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RecvError::Lagged`] once after events were dropped because
+    /// the queue was full, and [`RecvError::Closed`] when the device is gone.
+    pub async fn recv(&mut self) -> Result<TimedEvent, RecvError> {
+        poll_fn(|cx| {
+            let mut state = self.channel.state.lock().unwrap();
+            if state.lagged > 0 {
+                return Poll::Ready(Err(RecvError::Lagged(std::mem::take(&mut state.lagged))));
+            }
+            match state.queue.pop_front() {
+                Some(event) => Poll::Ready(Ok(event)),
+                None if state.closed => Poll::Ready(Err(RecvError::Closed)),
+                None => {
+                    state.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Number of events queued and not yet received.
+    pub fn len(&self) -> usize {
+        self.channel.state.lock().unwrap().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The device's side of its subscriptions.
+#[derive(Default)]
+pub(crate) struct Subscribers {
+    channels: Vec<Arc<Channel>>,
+}
+
+impl Subscribers {
+    fn add(&mut self, filter: EventFilter, capacity: Option<usize>) -> Arc<Channel> {
+        let channel = Arc::new(Channel {
+            filter,
+            capacity,
+            state: Mutex::default(),
+        });
+        self.channels.push(channel.clone());
+        channel
+    }
+
+    pub(crate) fn subscribe(&mut self, filter: EventFilter) -> Subscription {
+        Subscription {
+            channel: self.add(filter, None),
+        }
+    }
+
+    pub(crate) fn broadcast(&mut self, capacity: usize) -> EventReceiver {
+        EventReceiver {
+            channel: self.add(EventFilter::all(), Some(capacity.max(1))),
+        }
+    }
+
+    /// Queues a copy of each event for every subscription whose filter
+    /// matches it, forgetting subscriptions that were dropped. Returns how
+    /// many events full receivers dropped to make room.
+    pub(crate) fn publish(&mut self, events: &[TimedEvent]) -> u64 {
+        let mut dropped = 0;
+        self.channels
+            .retain(|channel| Arc::strong_count(channel) > 1);
+        for channel in &self.channels {
+            let mut matching = events
+                .iter()
+                .filter(|timed| channel.filter.matches(&timed.event))
+                .peekable();
+            if matching.peek().is_none() {
+                continue;
+            }
+            let mut state = channel.state.lock().unwrap();
+            for timed in matching {
+                if channel.capacity == Some(state.queue.len()) {
+                    state.queue.pop_front();
+                    state.lagged += 1;
+                    dropped += 1;
+                }
+                state.queue.push_back(*timed);
+            }
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+        dropped
+    }
+}
+
+impl Drop for Subscribers {
+    fn drop(&mut self) {
+        for channel in &self.channels {
+            let mut state = channel.state.lock().unwrap();
+            state.closed = true;
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::cc::{Button, Encoder};
+    use std::time::Instant;
+
+    fn timed(event: AutomapEvent) -> TimedEvent {
+        TimedEvent {
+            at: Instant::now(),
+            event,
+        }
+    }
+
+    #[test]
+    fn test_publish_filters() {
+        let mut subscribers = Subscribers::default();
+        let mut buttons = subscribers.subscribe(EventFilter::buttons());
+        let mut both = subscribers.subscribe(EventFilter::buttons() | EventFilter::encoders());
+
+        let press = timed(AutomapEvent::Button {
+            button: Button::ButtonA1,
+            pressed: true,
+        });
+        let turn = timed(AutomapEvent::Encoder {
+            encoder: Encoder::Encoder1,
+            clicks: 2,
+        });
+        subscribers.publish(&[press, turn]);
+
+        assert_eq!(buttons.try_recv(), Some(press));
+        assert_eq!(buttons.try_recv(), None);
+        assert_eq!(both.try_recv(), Some(press));
+        assert_eq!(both.try_recv(), Some(turn));
+
+        drop(buttons);
+        subscribers.publish(&[press]);
+        assert_eq!(subscribers.channels.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_recv_ends_when_device_drops() {
+        let mut subscribers = Subscribers::default();
+        let mut sub = subscribers.subscribe(EventFilter::all());
+        let turn = timed(AutomapEvent::Encoder {
+            encoder: Encoder::Encoder3,
+            clicks: -1,
+        });
+        subscribers.publish(&[turn]);
+        drop(subscribers);
+
+        assert_eq!(sub.recv().await, Some(turn));
+        assert_eq!(sub.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_reports_lag() {
+        let mut subscribers = Subscribers::default();
+        let mut slow = subscribers.broadcast(2);
+        let mut fast = subscribers.broadcast(2);
+        let turns: Vec<TimedEvent> = (1..=3)
+            .map(|clicks| {
+                timed(AutomapEvent::Encoder {
+                    encoder: Encoder::Encoder1,
+                    clicks,
+                })
+            })
+            .collect();
+
+        subscribers.publish(&turns[..1]);
+        assert_eq!(fast.recv().await, Ok(turns[0]));
+        subscribers.publish(&turns[1..]);
+
+        assert_eq!(slow.recv().await, Err(RecvError::Lagged(1)));
+        assert_eq!(slow.recv().await, Ok(turns[1]));
+        assert_eq!(slow.recv().await, Ok(turns[2]));
+        assert_eq!(fast.len(), 2);
+        assert_eq!(fast.recv().await, Ok(turns[1]));
+    }
+}
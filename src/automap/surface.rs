@@ -0,0 +1,363 @@
+//! Row and column coordinates of the controls, for layout code.
+//!
+//! The protocol splits the surface into a left-hand and a right-hand half,
+//! each with its own LCD: the unit shows the text of the half whose
+//! controls were used last. A half has up to four rows of eight controls,
+//! lettered in the order the SL MkII control map lists them (PDF page 9):
+//!
+//! | Row | Left        | Right       |
+//! |-----|-------------|-------------|
+//! | A   | buttons A   | buttons C   |
+//! | B   | buttons B   | buttons D   |
+//! | C   | encoders    | sliders     |
+//! | D   | pots        |             |
+//!
+//! The ZeRO MkII sends the same CCs from controls placed differently
+//! (Appendix 1), so the rows are positions in this layout rather than on
+//! the panel. Column `n` of a half always sits under label cell `n` of
+//! that half's LCD, which is what [`SurfacePos::lcd_cell()`] gives, and
+//! [`label_for()`] writes a label there.
+//!
+//! ```
+//! use automap::{Encoder, LcdLine, Side, SurfacePos, SurfaceRow};
+//!
+//! let pos = SurfacePos::from(Encoder::Encoder3);
+//! assert_eq!(pos, SurfacePos::new(Side::Left, SurfaceRow::C, 3).unwrap());
+//! let cell = pos.lcd_cell(false);
+//! assert_eq!((cell.line, cell.column()), (LcdLine::LeftTop, 18));
+//! ```
+
+use crate::automap::cc::{Button, Encoder, Pot, Slider};
+use crate::automap::lcd::{Align, LCD_CELL, LCD_COLUMNS, fit};
+use crate::automap::sysex::{LcdLine, LcdOp};
+
+/// Controls per row, and label cells per LCD line.
+pub const COLUMNS: u8 = 8;
+
+/// Which half of the surface, and so which LCD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// A row of controls within one half; see the [module docs](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SurfaceRow {
+    A,
+    B,
+    C,
+    D,
+}
+
+/// Where a control sits: its half, row and 1-based column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SurfacePos {
+    pub side: Side,
+    pub row: SurfaceRow,
+    /// `1..=8`, left to right.
+    pub col: u8,
+}
+
+impl SurfacePos {
+    /// The position, if `col` is in `1..=8` and the row exists on that
+    /// side.
+    pub fn new(side: Side, row: SurfaceRow, col: u8) -> Option<SurfacePos> {
+        let pos = SurfacePos { side, row, col };
+        pos.is_valid().then_some(pos)
+    }
+
+    fn is_valid(self) -> bool {
+        (1..=COLUMNS).contains(&self.col)
+            && !(self.side == Side::Right && self.row == SurfaceRow::D)
+    }
+
+    /// First CC of the row, whose controls follow column by column.
+    fn base_cc(self) -> Option<u8> {
+        if !self.is_valid() {
+            return None;
+        }
+        Some(match (self.side, self.row) {
+            (Side::Left, SurfaceRow::A) => Button::ButtonA1 as u8,
+            (Side::Left, SurfaceRow::B) => Button::ButtonB1 as u8,
+            (Side::Left, SurfaceRow::C) => Encoder::Encoder1 as u8,
+            (Side::Left, SurfaceRow::D) => Pot::Pot1 as u8,
+            (Side::Right, SurfaceRow::A) => Button::ButtonC1 as u8,
+            (Side::Right, SurfaceRow::B) => Button::ButtonD1 as u8,
+            (Side::Right, SurfaceRow::C) => Slider::Slider1 as u8,
+            (Side::Right, SurfaceRow::D) => return None,
+        })
+    }
+
+    fn cc(self) -> Option<u8> {
+        Some(self.base_cc()? + self.col - 1)
+    }
+
+    /// The label cell above this column, on the top or bottom line of its
+    /// half's LCD.
+    pub fn lcd_cell(self, bottom: bool) -> LcdCell {
+        let line = match (self.side, bottom) {
+            (Side::Left, false) => LcdLine::LeftTop,
+            (Side::Left, true) => LcdLine::LeftBottom,
+            (Side::Right, false) => LcdLine::RightTop,
+            (Side::Right, true) => LcdLine::RightBottom,
+        };
+        LcdCell {
+            line,
+            index: self.col,
+        }
+    }
+}
+
+/// Position of `cc` counted from `first`, as the 1-based column.
+fn pos(side: Side, row: SurfaceRow, cc: u8, first: u8) -> SurfacePos {
+    SurfacePos {
+        side,
+        row,
+        col: cc - first + 1,
+    }
+}
+
+impl From<Button> for SurfacePos {
+    fn from(button: Button) -> SurfacePos {
+        let cc = button as u8;
+        let (side, row, first) = match cc {
+            0x18..=0x1F => (Side::Left, SurfaceRow::A, 0x18),
+            0x20..=0x27 => (Side::Left, SurfaceRow::B, 0x20),
+            0x28..=0x2F => (Side::Right, SurfaceRow::A, 0x28),
+            _ => (Side::Right, SurfaceRow::B, 0x30),
+        };
+        pos(side, row, cc, first)
+    }
+}
+
+impl From<Encoder> for SurfacePos {
+    fn from(encoder: Encoder) -> SurfacePos {
+        pos(
+            Side::Left,
+            SurfaceRow::C,
+            encoder as u8,
+            Encoder::Encoder1 as u8,
+        )
+    }
+}
+
+impl From<Pot> for SurfacePos {
+    fn from(pot: Pot) -> SurfacePos {
+        pos(Side::Left, SurfaceRow::D, pot as u8, Pot::Pot1 as u8)
+    }
+}
+
+impl From<Slider> for SurfacePos {
+    fn from(slider: Slider) -> SurfacePos {
+        pos(
+            Side::Right,
+            SurfaceRow::C,
+            slider as u8,
+            Slider::Slider1 as u8,
+        )
+    }
+}
+
+/// Fails if the position holds a different kind of control, or none.
+impl TryFrom<SurfacePos> for Button {
+    type Error = SurfacePos;
+
+    fn try_from(pos: SurfacePos) -> Result<Button, SurfacePos> {
+        match pos.row {
+            SurfaceRow::A | SurfaceRow::B => pos.cc().and_then(|cc| Button::try_from(cc).ok()),
+            _ => None,
+        }
+        .ok_or(pos)
+    }
+}
+
+impl TryFrom<SurfacePos> for Encoder {
+    type Error = SurfacePos;
+
+    fn try_from(pos: SurfacePos) -> Result<Encoder, SurfacePos> {
+        match (pos.side, pos.row) {
+            (Side::Left, SurfaceRow::C) => pos.cc().and_then(|cc| Encoder::try_from(cc).ok()),
+            _ => None,
+        }
+        .ok_or(pos)
+    }
+}
+
+impl TryFrom<SurfacePos> for Pot {
+    type Error = SurfacePos;
+
+    fn try_from(pos: SurfacePos) -> Result<Pot, SurfacePos> {
+        match (pos.side, pos.row) {
+            (Side::Left, SurfaceRow::D) => pos.cc().and_then(|cc| Pot::try_from(cc).ok()),
+            _ => None,
+        }
+        .ok_or(pos)
+    }
+}
+
+impl TryFrom<SurfacePos> for Slider {
+    type Error = SurfacePos;
+
+    fn try_from(pos: SurfacePos) -> Result<Slider, SurfacePos> {
+        match (pos.side, pos.row) {
+            (Side::Right, SurfaceRow::C) => pos.cc().and_then(|cc| Slider::try_from(cc).ok()),
+            _ => None,
+        }
+        .ok_or(pos)
+    }
+}
+
+/// One of the eight label cells of an LCD line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LcdCell {
+    pub line: LcdLine,
+    /// `1..=8`, left to right, matching [`SurfacePos::col`].
+    pub index: u8,
+}
+
+impl LcdCell {
+    /// The cell holding cursor column `col` of `line`, or `None` past the
+    /// end of the line.
+    pub fn at(line: LcdLine, col: usize) -> Option<LcdCell> {
+        (col < LCD_COLUMNS).then(|| LcdCell {
+            line,
+            index: (col / LCD_CELL) as u8 + 1,
+        })
+    }
+
+    /// Cursor column of the first character of the cell.
+    pub fn column(self) -> usize {
+        usize::from(self.index.saturating_sub(1)) * LCD_CELL
+    }
+
+    /// The half of the surface whose LCD shows the cell.
+    pub fn side(self) -> Side {
+        match self.line {
+            LcdLine::LeftTop | LcdLine::LeftBottom => Side::Left,
+            LcdLine::RightTop | LcdLine::RightBottom => Side::Right,
+        }
+    }
+
+    /// The control under the cell in `row`, if there is one.
+    pub fn control(self, row: SurfaceRow) -> Option<SurfacePos> {
+        SurfacePos::new(self.side(), row, self.index)
+    }
+}
+
+/// Text for one label cell, ready to send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Label {
+    pub cell: LcdCell,
+    /// Cut to fit and padded with spaces, the last one separating it from
+    /// the next cell.
+    pub text: [u8; LCD_CELL],
+}
+
+impl Label {
+    /// `text` fitted to `cell`, left-aligned.
+    pub fn new(cell: LcdCell, text: &str) -> Label {
+        Label::aligned(cell, text, Align::Left)
+    }
+
+    /// `text` fitted to `cell` with [`fit()`](crate::automap::lcd::fit).
+    pub fn aligned(cell: LcdCell, text: &str, align: Align) -> Label {
+        Label {
+            cell,
+            text: pad_cell(text, align),
+        }
+    }
+
+    /// The cursor and text ops that draw the label. Follow them with
+    /// [`LcdOp::End`] in the message.
+    pub fn ops(&self) -> [LcdOp<'_>; 2] {
+        [
+            LcdOp::Cursor {
+                col: self.cell.column() as u8,
+                line: self.cell.line,
+            },
+            LcdOp::Text(&self.text),
+        ]
+    }
+}
+
+/// A label on the top line of the LCD, above `control`.
+///
+/// ```
+/// use automap::{AutomapSysEx, LcdOp, Slider, label_for};
+///
+/// let label = label_for(Slider::Slider2, "Bass");
+/// let [cursor, text] = label.ops();
+/// let msg = AutomapSysEx::LcdText(vec![cursor, text, LcdOp::End]);
+/// assert_eq!(label.cell.column(), 9);
+/// assert_eq!(&label.text, b"Bass     ");
+/// # let _ = msg;
+/// ```
+pub fn label_for(control: impl Into<SurfacePos>, text: &str) -> Label {
+    Label::new(control.into().lcd_cell(false), text)
+}
+
+/// `text` fitted to one cell, leaving the last column as the separator.
+pub(crate) fn pad_cell(text: &str, align: Align) -> [u8; LCD_CELL] {
+    let mut out = [b' '; LCD_CELL];
+    out[..LCD_CELL - 1].copy_from_slice(&fit(text, LCD_CELL - 1, align));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_control_round_trips() {
+        for cc in 0x18..=0x37 {
+            let button = Button::try_from(cc).unwrap();
+            assert_eq!(Button::try_from(SurfacePos::from(button)), Ok(button));
+        }
+        for cc in 0..8 {
+            let encoder = Encoder::try_from(0x78 + cc).unwrap();
+            let pot = Pot::try_from(0x08 + cc).unwrap();
+            let slider = Slider::try_from(0x10 + cc).unwrap();
+            assert_eq!(Encoder::try_from(SurfacePos::from(encoder)), Ok(encoder));
+            assert_eq!(Pot::try_from(SurfacePos::from(pot)), Ok(pot));
+            assert_eq!(Slider::try_from(SurfacePos::from(slider)), Ok(slider));
+        }
+        let d1 = SurfacePos::from(Button::ButtonD1);
+        assert_eq!((d1.side, d1.row, d1.col), (Side::Right, SurfaceRow::B, 1));
+        assert!(Encoder::try_from(d1).is_err());
+        assert_eq!(SurfacePos::new(Side::Right, SurfaceRow::D, 1), None);
+        assert_eq!(SurfacePos::new(Side::Left, SurfaceRow::A, 9), None);
+    }
+
+    #[test]
+    fn test_cells_line_up_with_columns() {
+        let slider = SurfacePos::from(Slider::Slider8);
+        let cell = slider.lcd_cell(true);
+        assert_eq!(cell.line, LcdLine::RightBottom);
+        assert_eq!(cell.column(), 63);
+        assert_eq!(LcdCell::at(LcdLine::RightBottom, 70), Some(cell));
+        assert_eq!(LcdCell::at(LcdLine::RightBottom, 72), None);
+        assert_eq!(cell.control(SurfaceRow::C), Some(slider));
+        assert_eq!(
+            LcdCell::at(LcdLine::LeftTop, 9)
+                .unwrap()
+                .control(SurfaceRow::D),
+            Some(SurfacePos::from(Pot::Pot2))
+        );
+    }
+
+    #[test]
+    fn test_label_fits_its_cell() {
+        let label = label_for(Encoder::Encoder8, "Resonance");
+        assert_eq!(label.cell.line, LcdLine::LeftTop);
+        assert_eq!(&label.text, b"Resona.. ");
+        assert_eq!(
+            label.ops()[0],
+            LcdOp::Cursor {
+                col: 63,
+                line: LcdLine::LeftTop
+            }
+        );
+        assert_eq!(&pad_cell("Gain \u{e9}", Align::Right), b"  Gain ? ");
+    }
+}
@@ -0,0 +1,166 @@
+//! Keeping the unit's tempo display and speed dial in step with a shared
+//! tempo, such as an Ableton Link session.
+//!
+//! The crate does not link against Link itself: `rusty_link` builds the
+//! Link C++ library, and is not a dependency of this crate. Anything that
+//! owns a tempo implements [`TempoSession`]; for Link that is a thin
+//! wrapper that captures and commits the app session state, along these
+//! lines:
+//!
+//! ```ignore
+//! struct Link(rusty_link::AblLink, rusty_link::SessionState);
+//!
+//! impl TempoSession for Link {
+//!     fn tempo(&self) -> f64 {
+//!         self.1.tempo()
+//!     }
+//!
+//!     fn set_tempo(&mut self, bpm: f64) {
+//!         self.0.capture_app_session_state(&mut self.1);
+//!         self.1.set_tempo(bpm, self.0.clock_micros());
+//!         self.0.commit_app_session_state(&self.1);
+//!     }
+//! }
+//! ```
+//!
+//! The wrapper has to capture the state again before each
+//! [`tempo()`](TempoSession::tempo) that should see other peers' changes,
+//! e.g. once per frame. [`TempoFollower`] then turns speed-dial nudges and
+//! the unit's own tempo setting (BF 5E/5F) into tempo changes, and shows
+//! the current tempo on the LCD.
+//!
+//! The Automap protocol has no host-to-unit tempo command, so the unit's
+//! internal clock cannot be slaved this way; only the display follows.
+
+use crate::automap::event::AutomapEvent;
+use crate::automap::lcd::LcdScreen;
+use crate::automap::sysex::LcdLine;
+
+/// Tempo range the unit accepts, in BPM.
+pub const TEMPO_RANGE: std::ops::RangeInclusive<f64> = 20.0..=320.0;
+
+/// Something that owns the tempo.
+pub trait TempoSession {
+    /// Current tempo in BPM.
+    fn tempo(&self) -> f64;
+
+    /// Proposes a new tempo, already clamped to [`TEMPO_RANGE`].
+    fn set_tempo(&mut self, bpm: f64);
+}
+
+/// Mirrors a [`TempoSession`] on the unit and feeds nudges back to it.
+#[derive(Debug, Clone)]
+pub struct TempoFollower {
+    shown: Option<f64>,
+    msb: Option<u8>,
+    nudge: f64,
+    line: LcdLine,
+    col: usize,
+}
+
+impl Default for TempoFollower {
+    fn default() -> Self {
+        TempoFollower {
+            shown: None,
+            msb: None,
+            nudge: 0.1,
+            line: LcdLine::RightTop,
+            col: 63,
+        }
+    }
+}
+
+impl TempoFollower {
+    /// Defaults: 0.1 BPM per speed-dial click, tempo at the right end of
+    /// the right display's top line.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many BPM one speed-dial click changes the tempo by.
+    pub fn nudge(mut self, bpm_per_click: f64) -> Self {
+        self.nudge = bpm_per_click;
+        self
+    }
+
+    /// Where the tempo is drawn; it takes nine columns.
+    pub fn position(mut self, line: LcdLine, col: usize) -> Self {
+        self.line = line;
+        self.col = col;
+        self
+    }
+
+    /// Passes a speed-dial turn or a tempo set on the unit to `session`.
+    ///
+    /// Returns the tempo proposed, if the event changed it.
+    pub fn handle(&mut self, event: &AutomapEvent, session: &mut impl TempoSession) -> Option<f64> {
+        let bpm = match *event {
+            AutomapEvent::SpeedDial { clicks } => session.tempo() + clicks as f64 * self.nudge,
+            AutomapEvent::TempoMsb { value } => {
+                self.msb = Some(value);
+                return None;
+            }
+            AutomapEvent::TempoLsb { value } => {
+                let msb = self.msb.take()?;
+                f64::from(u16::from(msb) << 7 | u16::from(value))
+            }
+            _ => return None,
+        };
+        let bpm = bpm.clamp(*TEMPO_RANGE.start(), *TEMPO_RANGE.end());
+        session.set_tempo(bpm);
+        Some(bpm)
+    }
+
+    /// Draws the session's tempo into `lcd` if it changed since last time.
+    ///
+    /// Returns `true` if `lcd` was updated and needs sending.
+    pub fn render(&mut self, session: &impl TempoSession, lcd: &mut LcdScreen) -> bool {
+        // Only a change visible at one decimal counts
+        let bpm = (session.tempo() * 10.0).round() / 10.0;
+        if self.shown == Some(bpm) {
+            return false;
+        }
+        self.shown = Some(bpm);
+        lcd.write(self.line, self.col, format!("{bpm:>5.1} BPM").as_bytes());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Session(f64);
+
+    impl TempoSession for Session {
+        fn tempo(&self) -> f64 {
+            self.0
+        }
+
+        fn set_tempo(&mut self, bpm: f64) {
+            self.0 = bpm;
+        }
+    }
+
+    #[test]
+    fn test_nudge_and_unit_tempo() {
+        let mut session = Session(120.0);
+        let mut follower = TempoFollower::new().nudge(0.5);
+
+        let out = follower.handle(&AutomapEvent::SpeedDial { clicks: -4 }, &mut session);
+        assert_eq!(out, Some(118.0));
+
+        // 140 BPM = 0x01 << 7 | 0x0C
+        assert_eq!(
+            follower.handle(&AutomapEvent::TempoMsb { value: 0x01 }, &mut session),
+            None
+        );
+        follower.handle(&AutomapEvent::TempoLsb { value: 0x0C }, &mut session);
+        assert_eq!(session.0, 140.0);
+
+        let mut lcd = LcdScreen::default();
+        assert!(follower.render(&session, &mut lcd));
+        assert_eq!(&lcd.line(LcdLine::RightTop)[63..], b"140.0 BPM");
+        assert!(!follower.render(&session, &mut lcd));
+    }
+}
@@ -0,0 +1,47 @@
+//! Events stamped with their arrival time.
+
+use std::time::Instant;
+
+use crate::automap::event::AutomapEvent;
+
+/// An event together with the moment the USB transfer carrying it completed.
+///
+/// All events from one transfer share the same timestamp. The unit itself
+/// sends no timing information, and bulk transfers carry no USB frame
+/// number, so this is the best resolution available on the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedEvent {
+    pub at: Instant,
+    pub event: AutomapEvent,
+}
+
+impl TimedEvent {
+    /// One log line: seconds since `start`, then the event, e.g.
+    /// `   12.345678  Encoder3 +2`.
+    ///
+    /// Events from before `start` are logged at zero.
+    pub fn to_log_line(&self, start: Instant) -> String {
+        let t = self.at.saturating_duration_since(start).as_secs_f64();
+        format!("{t:>11.6}  {}", self.event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::cc::Encoder;
+    use std::time::Duration;
+
+    #[test]
+    fn test_log_line() {
+        let start = Instant::now();
+        let timed = TimedEvent {
+            at: start + Duration::from_millis(1500),
+            event: AutomapEvent::Encoder {
+                encoder: Encoder::Encoder3,
+                clicks: -1,
+            },
+        };
+        assert_eq!(timed.to_log_line(start), "   1.500000  Encoder3 -1");
+    }
+}
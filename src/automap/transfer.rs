@@ -0,0 +1,253 @@
+//! Long data-block transfers, split into chunks.
+//!
+//! A whole template is several hundred bytes of DbRead/DbWrite traffic, and
+//! at USB-MIDI rates that takes long enough for a GUI to want a progress
+//! bar and a cancel button. [`Transfer`] carries both: a callback told about
+//! every finished chunk and a [`CancelToken`] checked before the next one
+//! starts, so a cancelled transfer never leaves a chunk half sent.
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Bytes per DbRead/DbWrite message unless [`Transfer::chunk_size`] says
+/// otherwise.
+pub const DEFAULT_CHUNK: u16 = 64;
+
+/// What a transfer is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Reading from the unit.
+    Read,
+    /// Writing to the unit.
+    Write,
+    /// Reading back what was written.
+    Verify,
+    /// Putting back the previous contents after a failed write.
+    Rollback,
+}
+
+/// How far a transfer has got, reported after each chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub phase: Phase,
+    /// Bytes transferred so far in this phase.
+    pub done: usize,
+    /// Bytes this phase will transfer in total.
+    pub total: usize,
+}
+
+impl Progress {
+    /// `done / total`, or 1.0 for an empty transfer.
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.done as f32 / self.total as f32
+        }
+    }
+}
+
+/// Stops a [`Transfer`] from another task or thread.
+///
+/// Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the transfer to stop before its next chunk.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Options for one chunked transfer.
+///
+/// ```
+/// # use automap::{CancelToken, Transfer};
+/// let cancel = CancelToken::new();
+/// let transfer = Transfer::new()
+///     .chunk_size(32)
+///     .cancel_token(cancel.clone())
+///     .on_progress(|p| println!("{:.0}%", p.fraction() * 100.0));
+/// # drop(transfer);
+/// ```
+pub struct Transfer<'a> {
+    chunk: u16,
+    cancel: CancelToken,
+    progress: Option<Box<dyn FnMut(Progress) + Send + 'a>>,
+}
+
+impl Default for Transfer<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Transfer<'a> {
+    pub fn new() -> Self {
+        Self {
+            chunk: DEFAULT_CHUNK,
+            cancel: CancelToken::new(),
+            progress: None,
+        }
+    }
+
+    /// Bytes per message, at least 1. Defaults to [`DEFAULT_CHUNK`].
+    pub fn chunk_size(mut self, bytes: u16) -> Self {
+        self.chunk = bytes.max(1);
+        self
+    }
+
+    /// Checks `token` before each chunk.
+    pub fn cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Calls `f` after each chunk, and once with nothing done when a phase
+    /// starts.
+    pub fn on_progress(mut self, f: impl FnMut(Progress) + Send + 'a) -> Self {
+        self.progress = Some(Box::new(f));
+        self
+    }
+
+    pub(crate) fn report(&mut self, phase: Phase, done: usize, total: usize) {
+        if let Some(f) = &mut self.progress {
+            f(Progress { phase, done, total });
+        }
+    }
+
+    /// Fails with [`Interrupted`](std::io::ErrorKind::Interrupted) once the
+    /// token has been cancelled.
+    pub(crate) fn check_cancelled(&self) -> Result<(), std::io::Error> {
+        if self.cancel.is_cancelled() {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                "transfer cancelled",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The `(offset, len)` of each chunk covering `len` bytes from `offset`.
+    pub(crate) fn chunks(&self, offset: u16, len: u16) -> impl Iterator<Item = (u16, u16)> + use<> {
+        let chunk = self.chunk;
+        let end = u32::from(offset) + u32::from(len);
+        (u32::from(offset)..end)
+            .step_by(usize::from(chunk))
+            .map(move |start| (start as u16, (end - start).min(u32::from(chunk)) as u16))
+    }
+}
+
+/// Why [`write_block_verified()`](crate::AutomapDevice::write_block_verified)
+/// failed.
+#[derive(Debug)]
+pub enum VerifyError {
+    /// The write failed or was cancelled, or the backup could not be read.
+    /// Whatever had been written was put back and verified.
+    Io(std::io::Error),
+
+    /// The bytes read back differ from the ones written at these offsets.
+    /// The previous contents were put back and verified.
+    Mismatch { offsets: Vec<u16> },
+
+    /// Putting back the previous contents failed too, so the block may be
+    /// half written. `offsets` lists the mismatches that triggered the
+    /// rollback, and is empty if the write itself failed.
+    RollbackFailed {
+        offsets: Vec<u16>,
+        error: std::io::Error,
+    },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Io(e) => write!(f, "data-block write failed: {e}"),
+            VerifyError::Mismatch { offsets } => write!(
+                f,
+                "data-block verify failed at {} offset(s) starting at {:#06x}; previous contents restored",
+                offsets.len(),
+                offsets.first().copied().unwrap_or_default()
+            ),
+            VerifyError::RollbackFailed { error, .. } => {
+                write!(
+                    f,
+                    "data-block rollback failed, block may be half written: {error}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VerifyError::Io(e) | VerifyError::RollbackFailed { error: e, .. } => Some(e),
+            VerifyError::Mismatch { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for VerifyError {
+    fn from(e: std::io::Error) -> Self {
+        VerifyError::Io(e)
+    }
+}
+
+/// Offsets where `actual`, read back from `offset`, differs from
+/// `expected`. Bytes missing from a short read count as mismatches.
+pub(crate) fn mismatches(offset: u16, expected: &[u8], actual: &[u8]) -> Vec<u16> {
+    expected
+        .iter()
+        .enumerate()
+        .filter(|&(i, byte)| actual.get(i) != Some(byte))
+        .map(|(i, _)| offset.wrapping_add(i as u16))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_chunks_progress_and_cancel() {
+        let seen = Mutex::new(Vec::new());
+        let cancel = CancelToken::new();
+        let mut transfer = Transfer::new()
+            .chunk_size(64)
+            .cancel_token(cancel.clone())
+            .on_progress(|p| seen.lock().unwrap().push(p.done));
+
+        let chunks: Vec<_> = transfer.chunks(10, 150).collect();
+        assert_eq!(chunks, [(10, 64), (74, 64), (138, 22)]);
+        assert_eq!(transfer.chunks(0, 0).count(), 0);
+
+        transfer.report(Phase::Read, 64, 150);
+        assert!(transfer.check_cancelled().is_ok());
+        cancel.cancel();
+        let err = transfer.check_cancelled().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Interrupted);
+        drop(transfer);
+        assert_eq!(*seen.lock().unwrap(), [64]);
+    }
+
+    #[test]
+    fn test_mismatches() {
+        assert!(mismatches(0x20, &[1, 2, 3], &[1, 2, 3]).is_empty());
+        assert_eq!(mismatches(0x20, &[1, 2, 3, 4], &[1, 9, 3]), [0x21, 0x23]);
+    }
+}
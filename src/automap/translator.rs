@@ -0,0 +1,214 @@
+//! Turning surface events into ordinary MIDI messages.
+//!
+//! In Automap mode the unit sends everything on its hidden port, and the
+//! MIDI a standalone template would have produced never appears. A
+//! [`Translator`] puts it back in software: a table maps each control to a
+//! CC, note or NRPN on a channel and output port, and
+//! [`translate()`](Translator::translate) turns an event into the message to
+//! send there.
+
+use crate::automap::cc::{Button, Encoder, Pot, Slider, TransportButton};
+use crate::automap::event::AutomapEvent;
+use crate::automap::template::PortRoute;
+
+/// A control a [`Translator`] can map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Button(Button),
+    Transport(TransportButton),
+    Encoder(Encoder),
+    Pot(Pot),
+    Slider(Slider),
+    CrossFader,
+    ExpressionPedal,
+    SustainPedal,
+}
+
+impl Source {
+    /// The control an event came from and its value as a 7-bit MIDI value.
+    ///
+    /// Presses are 127 and releases 0. Encoders report clicks relative to
+    /// 64, the "binary offset" encoding most hosts accept for relative
+    /// controllers.
+    pub fn from_event(event: &AutomapEvent) -> Option<(Source, u8)> {
+        let press = |pressed: bool| if pressed { 0x7F } else { 0x00 };
+        Some(match *event {
+            AutomapEvent::Button { button, pressed } => (Source::Button(button), press(pressed)),
+            AutomapEvent::TransportButton { button, pressed } => {
+                (Source::Transport(button), press(pressed))
+            }
+            AutomapEvent::Encoder { encoder, clicks } => (
+                Source::Encoder(encoder),
+                (64 + i16::from(clicks)).clamp(0, 127) as u8,
+            ),
+            AutomapEvent::Pot { pot, value } => (Source::Pot(pot), value & 0x7F),
+            AutomapEvent::Slider { slider, value } => (Source::Slider(slider), value & 0x7F),
+            AutomapEvent::CrossFader { value } => (Source::CrossFader, value & 0x7F),
+            AutomapEvent::ExpressionPedal { value } => (Source::ExpressionPedal, value & 0x7F),
+            AutomapEvent::SustainPedal { pressed } => (Source::SustainPedal, press(pressed)),
+            _ => return None,
+        })
+    }
+}
+
+/// The MIDI message a control produces. Channels are 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiTarget {
+    /// A control change carrying the control's value.
+    Cc { channel: u8, cc: u8 },
+    /// Note on while pressed, note off on release. Continuous controls send
+    /// the note with their value as velocity, and note off at zero.
+    Note { channel: u8, note: u8 },
+    /// A 14-bit NRPN number (CC 99/98) with the value as data entry MSB
+    /// (CC 6).
+    Nrpn { channel: u8, param: u16 },
+}
+
+impl MidiTarget {
+    /// Appends the MIDI bytes sending `value` to `out`.
+    pub fn encode_into(self, value: u8, out: &mut Vec<u8>) {
+        match self {
+            MidiTarget::Cc { channel, cc } => {
+                out.extend_from_slice(&[0xB0 | status_channel(channel), cc & 0x7F, value]);
+            }
+            MidiTarget::Note { channel, note } => {
+                let status = if value == 0 { 0x80 } else { 0x90 };
+                out.extend_from_slice(&[status | status_channel(channel), note & 0x7F, value]);
+            }
+            MidiTarget::Nrpn { channel, param } => {
+                let status = 0xB0 | status_channel(channel);
+                out.extend_from_slice(&[
+                    status,
+                    0x63,
+                    (param >> 7) as u8 & 0x7F,
+                    status,
+                    0x62,
+                    param as u8 & 0x7F,
+                    status,
+                    0x06,
+                    value,
+                ]);
+            }
+        }
+    }
+}
+
+fn status_channel(channel: u8) -> u8 {
+    channel.clamp(1, 16) - 1
+}
+
+/// A translated message and the port it should go out on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Translated {
+    pub port: PortRoute,
+    pub bytes: Vec<u8>,
+}
+
+/// A mapping table from controls to MIDI messages.
+///
+/// ```
+/// # use automap::{AutomapEvent, MidiTarget, Source, Translator};
+/// # use automap::automap::cc::Slider;
+/// # use automap::template::PortRoute;
+/// let translator = Translator::new().map(
+///     Source::Slider(Slider::Slider1),
+///     PortRoute::Usb1,
+///     MidiTarget::Cc { channel: 1, cc: 7 },
+/// );
+/// let out = translator.translate(&AutomapEvent::Slider {
+///     slider: Slider::Slider1,
+///     value: 100,
+/// });
+/// assert_eq!(out.unwrap().bytes, [0xB0, 7, 100]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Translator {
+    table: Vec<(Source, PortRoute, MidiTarget)>,
+}
+
+impl Translator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends `source` as `target` on `port`, replacing any earlier mapping
+    /// for the same control.
+    pub fn map(mut self, source: Source, port: PortRoute, target: MidiTarget) -> Self {
+        self.table.retain(|(s, _, _)| *s != source);
+        self.table.push((source, port, target));
+        self
+    }
+
+    /// Stops translating `source`.
+    pub fn unmap(&mut self, source: Source) {
+        self.table.retain(|(s, _, _)| *s != source);
+    }
+
+    /// Where `source` goes, if mapped.
+    pub fn mapping(&self, source: Source) -> Option<(PortRoute, MidiTarget)> {
+        self.table
+            .iter()
+            .find(|(s, _, _)| *s == source)
+            .map(|&(_, port, target)| (port, target))
+    }
+
+    /// The message `event` should produce, or `None` if its control is not
+    /// mapped or [`PortRoute::None`] is its port.
+    pub fn translate(&self, event: &AutomapEvent) -> Option<Translated> {
+        let (source, value) = Source::from_event(event)?;
+        let (port, target) = self.mapping(source)?;
+        if port == PortRoute::None {
+            return None;
+        }
+        let mut bytes = Vec::with_capacity(9);
+        target.encode_into(value, &mut bytes);
+        Some(Translated { port, bytes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate() {
+        let translator = Translator::new()
+            .map(
+                Source::Button(Button::ButtonA1),
+                PortRoute::MidiOut1,
+                MidiTarget::Note {
+                    channel: 2,
+                    note: 60,
+                },
+            )
+            .map(
+                Source::Encoder(Encoder::Encoder1),
+                PortRoute::Usb2,
+                MidiTarget::Nrpn {
+                    channel: 1,
+                    param: 0x0101,
+                },
+            );
+
+        let press = AutomapEvent::Button {
+            button: Button::ButtonA1,
+            pressed: true,
+        };
+        let out = translator.translate(&press).unwrap();
+        assert_eq!(out.port, PortRoute::MidiOut1);
+        assert_eq!(out.bytes, [0x91, 60, 0x7F]);
+
+        let turn = AutomapEvent::Encoder {
+            encoder: Encoder::Encoder1,
+            clicks: -3,
+        };
+        let out = translator.translate(&turn).unwrap();
+        assert_eq!(
+            out.bytes,
+            [0xB0, 0x63, 0x02, 0xB0, 0x62, 0x01, 0xB0, 0x06, 61]
+        );
+
+        let unmapped = AutomapEvent::CrossFader { value: 3 };
+        assert_eq!(translator.translate(&unmapped), None);
+    }
+}
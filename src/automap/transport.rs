@@ -0,0 +1,164 @@
+//! Keeping the host and the unit agreed on transport lock.
+//!
+//! With transport lock on, buttons D1-D6 send transport events instead of
+//! button events (Section 5, PDF page 10). The host sets it with
+//! [`AutomapCommand::TransportLockSet`], the user toggles it on the unit,
+//! and the unit reports it with
+//! [`AutomapEvent::TransportLockStatus`] after either, or when asked.
+//! [`TransportLock`] tracks what the host wants and what the unit last
+//! said, follows the user's toggles, and can put the lock back after the
+//! user switches templates on the unit.
+//!
+//! It only produces commands; sending them and feeding it events is up to
+//! the caller.
+
+use std::fmt;
+
+use crate::automap::cc::ParameterRequestType;
+use crate::automap::command::AutomapCommand;
+use crate::automap::event::{AutomapEvent, ParameterResponse};
+
+type Listener = Box<dyn FnMut(bool) + Send>;
+
+/// Transport lock as the host wants it and as the unit reports it.
+#[derive(Default)]
+pub struct TransportLock {
+    wanted: Option<bool>,
+    reported: Option<bool>,
+    relock: bool,
+    listeners: Vec<Listener>,
+}
+
+impl fmt::Debug for TransportLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransportLock")
+            .field("wanted", &self.wanted)
+            .field("reported", &self.reported)
+            .field("relock", &self.relock)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TransportLock {
+    /// Nothing wanted or known yet, and no relocking.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends the wanted state again whenever the unit reports a template
+    /// change.
+    pub fn relock_after_template_change(mut self, enabled: bool) -> Self {
+        self.relock = enabled;
+        self
+    }
+
+    /// Calls `f` with the new state each time the unit reports a change.
+    pub fn on_change(&mut self, f: impl FnMut(bool) + Send + 'static) {
+        self.listeners.push(Box::new(f));
+    }
+
+    /// Wants the lock `enabled`, returning the command to send.
+    pub fn set(&mut self, enabled: bool) -> AutomapCommand {
+        self.wanted = Some(enabled);
+        AutomapCommand::TransportLockSet { enabled }
+    }
+
+    /// The command asking the unit for its state. The RemoteSL and ZeRO SL
+    /// do not answer it.
+    pub fn query(&self) -> AutomapCommand {
+        AutomapCommand::ParameterRequest {
+            request_type: ParameterRequestType::TransportLockState,
+        }
+    }
+
+    /// What the unit last reported, `None` until it has.
+    pub fn state(&self) -> Option<bool> {
+        self.reported
+    }
+
+    /// What the host last asked for or the user last chose.
+    pub fn wanted(&self) -> Option<bool> {
+        self.wanted
+    }
+
+    /// Whether the unit has reported the wanted state.
+    pub fn is_synced(&self) -> bool {
+        self.wanted.is_none() || self.wanted == self.reported
+    }
+
+    /// Feeds one event, returning the commands to send in response.
+    ///
+    /// A status report, or a typed answer to [`query()`](Self::query),
+    /// becomes the state, and the wanted state too: a change the host did
+    /// not ask for is the user's, and it stands. After
+    /// a template change the unit's state is unknown until it next
+    /// reports; with relocking on, the wanted state is sent again and then
+    /// queried.
+    pub fn handle(&mut self, event: &AutomapEvent) -> Vec<AutomapCommand> {
+        match *event {
+            AutomapEvent::TransportLockStatus { enabled }
+            | AutomapEvent::Parameter {
+                response: ParameterResponse::TransportLock(enabled),
+            } => {
+                self.wanted = Some(enabled);
+                if self.reported != Some(enabled) {
+                    self.reported = Some(enabled);
+                    for listener in &mut self.listeners {
+                        listener(enabled);
+                    }
+                }
+                Vec::new()
+            }
+            AutomapEvent::TemplateChanged { .. } => {
+                self.reported = None;
+                match self.wanted {
+                    Some(enabled) if self.relock => {
+                        vec![AutomapCommand::TransportLockSet { enabled }, self.query()]
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_follows_the_unit_and_relocks() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut lock = TransportLock::new().relock_after_template_change(true);
+        let log = seen.clone();
+        lock.on_change(move |enabled| log.lock().unwrap().push(enabled));
+
+        assert_eq!(
+            lock.set(true),
+            AutomapCommand::TransportLockSet { enabled: true }
+        );
+        assert!(!lock.is_synced());
+        let on = AutomapEvent::TransportLockStatus { enabled: true };
+        assert!(lock.handle(&on).is_empty());
+        lock.handle(&on);
+        assert!(lock.is_synced());
+
+        let commands = lock.handle(&AutomapEvent::TemplateChanged { special: true });
+        assert_eq!(
+            commands,
+            [
+                AutomapCommand::TransportLockSet { enabled: true },
+                lock.query()
+            ]
+        );
+        assert_eq!(lock.state(), None);
+
+        // The user turns it off on the unit
+        lock.handle(&AutomapEvent::TransportLockStatus { enabled: false });
+        assert_eq!(lock.wanted(), Some(false));
+        assert!(lock.is_synced());
+        assert_eq!(*seen.lock().unwrap(), [true, false]);
+    }
+}
@@ -0,0 +1,39 @@
+//! udev rule generation for unprivileged access on Linux.
+//!
+//! Without a rule, only root can open the unit's USB device node, and
+//! opening fails with [`AutomapError::PermissionDenied`].
+//!
+//! [`AutomapError::PermissionDenied`]: crate::AutomapError::PermissionDenied
+
+/// Where distributions expect locally added udev rules.
+pub const UDEV_RULES_DIR: &str = "/etc/udev/rules.d";
+
+/// Suggested file name for the rule within [`UDEV_RULES_DIR`].
+pub const UDEV_RULE_FILE: &str = "50-novation-automap.rules";
+
+/// udev rule granting the logged-in user access to the given USB device.
+///
+/// The rule tags the device for `uaccess`, so the seat's active user gets
+/// read/write access without being in a dedicated group. Install it into
+/// [`UDEV_RULES_DIR`], then run `udevadm control --reload-rules` and replug
+/// the unit.
+pub fn udev_rule(vendor_id: u16, product_id: u16) -> String {
+    format!(
+        "SUBSYSTEM==\"usb\", ATTRS{{idVendor}}==\"{vendor_id:04x}\", \
+         ATTRS{{idProduct}}==\"{product_id:04x}\", MODE=\"0660\", TAG+=\"uaccess\"\n"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_udev_rule() {
+        assert_eq!(
+            udev_rule(0x1235, 0x000c),
+            "SUBSYSTEM==\"usb\", ATTRS{idVendor}==\"1235\", ATTRS{idProduct}==\"000c\", \
+             MODE=\"0660\", TAG+=\"uaccess\"\n"
+        );
+    }
+}
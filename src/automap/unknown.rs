@@ -0,0 +1,104 @@
+//! Capturing traffic the decoders do not understand.
+//!
+//! `read_events()` hands out what it can decode and quietly drops the
+//! rest: CCs off the Automap channel, malformed messages and SysEx frames
+//! nobody asked for. A hook set with
+//! [`AutomapDevice::on_unknown()`](crate::AutomapDevice::on_unknown) sees
+//! a copy of every such message, bytes and all, so undocumented traffic
+//! can be collected instead of lost.
+
+use std::time::Instant;
+
+use crate::automap::event::AutomapEvent;
+use crate::automap::sysex::{AutomapSysEx, DbSimMsg, DecodedMsg, LcdOp, SimCmd, decode_frame};
+
+/// Why a message was not fully understood.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownKind {
+    /// A CC on the Automap channel with no known meaning, also returned
+    /// as [`AutomapEvent::Raw`].
+    RawCc,
+    /// A message on the Automap channel that is not a well-formed CC.
+    BadCc,
+    /// A message on some other channel, or a system message.
+    OtherChannel,
+    /// A SysEx frame that parses but carries a command, LCD op or
+    /// simulation sub-command this crate does not know.
+    UnknownSysEx,
+    /// A SysEx frame that does not parse: another manufacturer, another
+    /// product, or a truncated body.
+    BadSysEx,
+}
+
+/// A message the decoders could not make sense of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownMessage {
+    /// When the USB transfer carrying it completed.
+    pub at: Instant,
+    pub kind: UnknownKind,
+    /// The complete MIDI message.
+    pub bytes: Vec<u8>,
+}
+
+/// What is not understood about `msg`, a complete message read from a
+/// unit whose Automap CCs use `cc_status`, or `None` if all of it is.
+pub(crate) fn classify(msg: &[u8], cc_status: u8) -> Option<UnknownKind> {
+    if msg.first() == Some(&0xF0) {
+        return match decode_frame(msg) {
+            Err(_) => Some(UnknownKind::BadSysEx),
+            Ok((_, _, _, decoded)) if has_unknown_parts(&decoded) => {
+                Some(UnknownKind::UnknownSysEx)
+            }
+            Ok(_) => None,
+        };
+    }
+    if msg.first() != Some(&cc_status) && msg[0] & 0xF0 != 0xE0 {
+        return Some(UnknownKind::OtherChannel);
+    }
+    match AutomapEvent::decode_event(msg) {
+        Ok(AutomapEvent::Raw { .. }) => Some(UnknownKind::RawCc),
+        Ok(_) => None,
+        Err(_) => Some(UnknownKind::BadCc),
+    }
+}
+
+fn has_unknown_parts(decoded: &DecodedMsg<'_>) -> bool {
+    match decoded {
+        DecodedMsg::Automap(AutomapSysEx::Unknown { .. }) => true,
+        DecodedMsg::Automap(AutomapSysEx::LcdText(ops)) => {
+            ops.iter().any(|op| matches!(op, LcdOp::Unknown(..)))
+        }
+        DecodedMsg::DbSim(DbSimMsg::Simulate(SimCmd::Unknown(..))) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let cc = 0xBF;
+        assert_eq!(classify(&[0xBF, 0x18, 0x01], cc), None);
+        assert_eq!(
+            classify(&[0xB0, 0x18, 0x01], cc),
+            Some(UnknownKind::OtherChannel)
+        );
+        assert_eq!(classify(&[0xBF, 0x18], cc), Some(UnknownKind::BadCc));
+        // The `strict` decoder rejects unknown CCs rather than passing them on
+        let raw = if cfg!(feature = "strict") {
+            UnknownKind::BadCc
+        } else {
+            UnknownKind::RawCc
+        };
+        assert_eq!(classify(&[0xBF, 0x43, 0x01], cc), Some(raw));
+
+        let lcd = AutomapSysEx::LcdText(vec![LcdOp::Unknown(0x09, &[0x01])]).to_bytes();
+        assert_eq!(classify(&lcd, cc), Some(UnknownKind::UnknownSysEx));
+        assert_eq!(
+            classify(&[0xF0, 0x00, 0x20, 0x30, 0xF7], cc),
+            Some(UnknownKind::BadSysEx)
+        );
+    }
+}
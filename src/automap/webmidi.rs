@@ -0,0 +1,148 @@
+//! The protocol over a MIDI port instead of USB (`webmidi` feature), for
+//! WebMIDI in the browser and other MIDI APIs.
+//!
+//! Some systems expose the unit's hidden Automap port as an ordinary MIDI
+//! port, which WebMIDI can open with SysEx access. [`MidiPortLink`] is the
+//! backend for such a port: it turns what `MIDIInput.onmidimessage`
+//! delivers into events and SysEx frames, and commands and SysEx messages
+//! into the bytes and timestamps for `MIDIOutput.send(data, timestamp)`,
+//! with SysEx frames spaced by the configured [`Pacing`]. It does no I/O
+//! and keeps no clock of its own, so the JavaScript side, or a
+//! `wasm-bindgen` wrapper, passes `performance.now()` in.
+//!
+//! The crate as a whole does not build for `wasm32` yet: the USB layer and
+//! an async runtime are always compiled in. This module depends on
+//! neither, so it is what such a build keeps. Session features that live
+//! in [`AutomapDevice`](crate::AutomapDevice), like LED shadows and
+//! keep-alives, are not available over a port.
+
+use crate::automap::command::AutomapCommand;
+use crate::automap::config::{DeviceConfig, Pacing};
+use crate::automap::event::AutomapEvent;
+use crate::automap::sysex::AutomapSysEx;
+use crate::midi::MidiStream;
+
+/// A message read from the port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortMessage {
+    /// A CC on the Automap channel, or pitch bend.
+    Event(AutomapEvent),
+    /// A whole SysEx frame, e.g. a data-block response for a template
+    /// editor. Decode it with
+    /// [`decode_frame()`](crate::automap::sysex::decode_frame).
+    SysEx(Vec<u8>),
+    /// Anything else: other channels, real-time bytes, or a CC that did
+    /// not decode.
+    Other(Vec<u8>),
+}
+
+/// One end of a MIDI port connected to the unit.
+#[derive(Debug)]
+pub struct MidiPortLink {
+    cc_status: u8,
+    pacing: Pacing,
+    rx: MidiStream,
+    /// Earliest time, in milliseconds, the next SysEx frame may go out.
+    next_frame: f64,
+}
+
+impl MidiPortLink {
+    /// A link using the channel, SysEx limit and pacing of `config`. Its
+    /// other settings are about USB and the session and do not apply.
+    pub fn new(config: &DeviceConfig) -> Self {
+        MidiPortLink {
+            cc_status: config.cc_status(),
+            pacing: config.pacing,
+            rx: MidiStream::new(config.max_sysex),
+            next_frame: 0.0,
+        }
+    }
+
+    /// Takes the bytes of one `midimessage` event, or any chunk of a
+    /// stream, and returns the messages they complete.
+    pub fn receive(&mut self, data: &[u8]) -> Vec<PortMessage> {
+        let cc_status = self.cc_status;
+        let mut out = Vec::new();
+        self.rx.push_with(data, |msg| {
+            // Oversized SysEx frames are dropped
+            let Ok(msg) = msg else { return };
+            let event = if msg[0] == cc_status || msg[0] & 0xF0 == 0xE0 {
+                AutomapEvent::decode_event(msg).ok()
+            } else {
+                None
+            };
+            out.push(match event {
+                Some(event) => PortMessage::Event(event),
+                None if msg[0] == 0xF0 => PortMessage::SysEx(msg.to_vec()),
+                None => PortMessage::Other(msg.to_vec()),
+            });
+        });
+        out
+    }
+
+    /// The bytes of `cmd` on the configured channel. Short messages are
+    /// never held back, so they can be sent right away.
+    pub fn command(&self, cmd: &AutomapCommand) -> Vec<u8> {
+        let mut bytes = cmd.to_bytes();
+        bytes[0] = self.cc_status;
+        bytes
+    }
+
+    /// The bytes of `msg` and the time to send them at, given the time
+    /// `now` in milliseconds on the clock of the port's timestamps.
+    ///
+    /// Frames are queued by the MIDI API at their timestamps, so a run of
+    /// them can be sent in one go and still reach the unit spaced out.
+    pub fn sysex(&mut self, msg: AutomapSysEx<'_>, now: f64) -> (Vec<u8>, f64) {
+        let bytes = msg.to_bytes();
+        let at = self.next_frame.max(now);
+        self.next_frame = at + self.pacing.after(bytes.len()).as_secs_f64() * 1000.0;
+        (bytes, at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::cc::{Button, Encoder};
+    use crate::automap::sysex::{LcdClear, LcdOp};
+
+    #[test]
+    fn test_port_messages_and_pacing() {
+        let mut link = MidiPortLink::new(&DeviceConfig::new());
+        let turn = AutomapEvent::Encoder {
+            encoder: Encoder::Encoder1,
+            clicks: 1,
+        };
+        let mut data = turn.to_bytes();
+        data.extend([0x90, 0x3C, 0x7F, 0xF0, 0x00, 0x20]);
+        assert_eq!(
+            link.receive(&data),
+            [
+                PortMessage::Event(turn),
+                PortMessage::Other(vec![0x90, 0x3C, 0x7F])
+            ]
+        );
+        // The rest of the frame in the next message
+        assert_eq!(
+            link.receive(&[0x29, 0xF7]),
+            [PortMessage::SysEx(vec![0xF0, 0x00, 0x20, 0x29, 0xF7])]
+        );
+
+        let led = AutomapCommand::ButtonLed {
+            button: Button::ButtonA1,
+            on: true,
+        };
+        assert_eq!(link.command(&led), [0xBF, 0x18, 0x01]);
+
+        let clear =
+            || AutomapSysEx::LcdText(vec![LcdOp::Clear(LcdClear::BothDisplays), LcdOp::End]);
+        let (first, at) = link.sysex(clear(), 100.0);
+        assert_eq!(at, 100.0);
+        let (_, next) = link.sysex(clear(), 100.0);
+        let wait = Pacing::default().after(first.len()).as_secs_f64() * 1000.0;
+        assert!((next - 100.0 - wait).abs() < 1e-9);
+        // Long after, a frame goes out right away
+        assert_eq!(link.sysex(clear(), 1000.0).1, 1000.0);
+    }
+}
@@ -0,0 +1,244 @@
+//! Command-line tool for inspecting and driving a ZeRO MkII.
+//!
+//! Build with `cargo install automap --features cli`; run `automap help`
+//! for the list of subcommands.
+
+mod tui;
+
+use std::error::Error;
+use std::fmt::Debug;
+use std::time::Instant;
+
+use automap::{
+    AutomapCommand, AutomapDevice, AutomapSysEx, Button, Conformance, DbSimMsg, DbTarget,
+    DeviceConfig, InterfaceAccess, Layout, LcdLine, LcdOp, SimCmd, Transfer, event_to_json,
+    template,
+};
+
+const USAGE: &str = "\
+usage: automap <command> [args]
+
+commands:
+  probe                          show the unit's interfaces and whether they can be opened
+  monitor [--json | --tui]       print events as they arrive, or show the surface live
+  monitor --unknown              also print undecoded messages to stderr
+  led <button> on|off            set a button LED, e.g. `led ButtonA1 on`
+  led all off                    switch off every LED
+  lcd write <line> <col> <text>  write text, e.g. `lcd write LeftTop 0 Hello`
+  template pull <file>           save the current template's header to <file>
+  template push <file>           check and upload a template from <file> (raw or .syx)
+  template layout <csv> <in> <out>
+                                 write the controls of a CSV layout over template <in>
+  globals dump [len]             hex-dump the first [len] bytes of the globals (default 64)
+  simulate button <n> on|off     simulate a press of button <n> (1-based)
+  simulate encoder <n> <clicks>  simulate turning encoder <n>
+  simulate pot <n> <value>       simulate moving pot/slider <n> to <value>
+  conformance                    run the simulation checks and print what came back
+  bridge [addr]                  serve the line protocol to scripts (`net` feature,
+                                 default 127.0.0.1:7001)
+";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    if matches!(args.first(), None | Some(&"help" | &"-h" | &"--help")) {
+        print!("{USAGE}");
+        return;
+    }
+    if let Err(e) = block_on(run(&args)) {
+        eprintln!("automap: {e}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(feature = "smol")]
+fn block_on<F: Future>(fut: F) -> F::Output {
+    smol::block_on(fut)
+}
+
+#[cfg(feature = "tokio")]
+fn block_on<F: Future>(fut: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("tokio runtime")
+        .block_on(fut)
+}
+
+async fn run(args: &[&str]) -> Result<(), Box<dyn Error>> {
+    let config = DeviceConfig::new();
+    match args {
+        ["probe"] => {
+            let report = AutomapDevice::probe(&config).await?;
+            println!("product: {}", report.product.as_deref().unwrap_or("?"));
+            println!("serial:  {}", report.serial.as_deref().unwrap_or("?"));
+            for iface in &report.interfaces {
+                let access = match &iface.access {
+                    InterfaceAccess::Available => "available".to_string(),
+                    InterfaceAccess::Busy { driver: Some(d) } => format!("busy ({d})"),
+                    InterfaceAccess::Busy { driver: None } => "busy".to_string(),
+                    InterfaceAccess::DriverNotBound => "no WinUSB driver".to_string(),
+                    InterfaceAccess::Error(e) => format!("error: {e}"),
+                };
+                println!("interface {}: {:?}, {access}", iface.number, iface.kind);
+            }
+        }
+        ["monitor", "--tui"] => {
+            let mut device = config.open().await?;
+            tui::run(&mut device).await?;
+        }
+        ["monitor", rest @ ..] => {
+            let json = rest == ["--json"];
+            let mut device = config.open().await?;
+            let start = Instant::now();
+            if rest == ["--unknown"] {
+                device.on_unknown(move |msg| {
+                    let t = msg.at.saturating_duration_since(start).as_secs_f64();
+                    let hex: Vec<_> = msg.bytes.iter().map(|b| format!("{b:02X}")).collect();
+                    eprintln!("{t:>11.6}  ? {:?} {}", msg.kind, hex.join(" "));
+                });
+            }
+            loop {
+                for timed in device.read_timed_events().await? {
+                    if json {
+                        println!("{}", event_to_json(&timed.event));
+                    } else {
+                        println!("{}", timed.to_log_line(start));
+                    }
+                }
+            }
+        }
+        ["led", "all", "off"] => {
+            let mut device = config.open().await?;
+            device.clear_all_leds().await?;
+        }
+        ["led", button, state] => {
+            let button = by_name::<Button>(button, 0x18..=0x37)?;
+            let on = on_off(state)?;
+            let mut device = config.open().await?;
+            device
+                .send_command(&AutomapCommand::ButtonLed { button, on })
+                .await?;
+        }
+        ["lcd", "write", line, col, text @ ..] => {
+            let line = LcdLine::ALL
+                .into_iter()
+                .find(|l| format!("{l:?}") == *line)
+                .ok_or_else(|| format!("unknown LCD line `{line}`"))?;
+            let col: u8 = col.parse().map_err(|_| format!("bad column `{col}`"))?;
+            let text = text.join(" ");
+            let mut device = config.open().await?;
+            device
+                .send_sysex(AutomapSysEx::LcdText(vec![
+                    LcdOp::Cursor { col, line },
+                    LcdOp::Text(text.as_bytes()),
+                    LcdOp::End,
+                ]))
+                .await?;
+        }
+        ["template", "pull", file] => {
+            let mut device = config.open().await?;
+            let header = device
+                .read_data_block(DbTarget::TemplateHeader, 0, 0, template::HEADER_LEN)
+                .await?;
+            std::fs::write(file, &header)?;
+            println!("wrote {} bytes to {file}", header.len());
+        }
+        ["template", "push", file] => {
+            let file = std::fs::read(file)?;
+            let data = if file.first() == Some(&0xF0) {
+                template::from_syx(&file)?
+            } else {
+                template::validate(&file)?;
+                &file
+            };
+            let mut device = config.open().await?;
+            device
+                .send_sysex(AutomapSysEx::UploadTemplate { data })
+                .await?;
+        }
+        ["template", "layout", csv, input, output] => {
+            let layout = Layout::load(csv)?;
+            let mut template = template::Template::from_bytes(std::fs::read(input)?)?;
+            layout.apply(&mut template);
+            std::fs::write(output, template.as_bytes())?;
+            println!("wrote {} controls to {output}", layout.controls.len());
+        }
+        ["globals", "dump", rest @ ..] => {
+            let len = match rest {
+                [] => 64,
+                [len] => len.parse().map_err(|_| format!("bad length `{len}`"))?,
+                _ => return Err(USAGE.into()),
+            };
+            let mut device = config.open().await?;
+            let mut transfer = Transfer::new().on_progress(|p| {
+                eprint!("\rreading globals: {}/{} bytes", p.done, p.total);
+            });
+            let data = device
+                .read_block(DbTarget::Globals, 0, 0, len, &mut transfer)
+                .await?;
+            eprintln!();
+            for (i, row) in data.chunks(16).enumerate() {
+                let hex: Vec<String> = row.iter().map(|b| format!("{b:02x}")).collect();
+                println!("{:04x}  {}", i * 16, hex.join(" "));
+            }
+        }
+        ["simulate", kind, n, arg] => {
+            let number_1_based: u8 = n.parse().map_err(|_| format!("bad number `{n}`"))?;
+            let cmd = match *kind {
+                "button" => SimCmd::Button {
+                    number_1_based,
+                    pressed: on_off(arg)?,
+                },
+                "encoder" => SimCmd::Encoder {
+                    number_1_based,
+                    clicks_signed: arg.parse().map_err(|_| format!("bad clicks `{arg}`"))?,
+                },
+                "pot" => SimCmd::PotSlider {
+                    number_1_based,
+                    value: arg.parse().map_err(|_| format!("bad value `{arg}`"))?,
+                },
+                _ => return Err(format!("unknown control `{kind}`").into()),
+            };
+            let mut device = config.open().await?;
+            device.send_dbsim(&DbSimMsg::Simulate(cmd)).await?;
+        }
+        ["conformance"] => {
+            let mut device = config.open().await?;
+            let report = Conformance::zero_mkii().run(&mut device).await?;
+            println!("{report}");
+            if !report.all_passed() {
+                return Err(format!("{} checks failed", report.failed().count()).into());
+            }
+        }
+        #[cfg(feature = "net")]
+        ["bridge", rest @ ..] if rest.len() <= 1 => {
+            let addr = rest.first().copied().unwrap_or("127.0.0.1:7001");
+            let mut device = config.open().await?;
+            eprintln!("bridging on {addr}");
+            automap::serve_lines(&mut device, addr).await?;
+        }
+        _ => return Err(USAGE.into()),
+    }
+    Ok(())
+}
+
+fn on_off(state: &str) -> Result<bool, String> {
+    match state {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected `on` or `off`, got `{state}`")),
+    }
+}
+
+/// Finds the control in `codes` whose variant is called `name`.
+fn by_name<T: TryFrom<u8> + Debug>(
+    name: &str,
+    codes: impl IntoIterator<Item = u8>,
+) -> Result<T, String> {
+    codes
+        .into_iter()
+        .filter_map(|code| T::try_from(code).ok())
+        .find(|control| format!("{control:?}") == name)
+        .ok_or_else(|| format!("unknown control `{name}`"))
+}
@@ -0,0 +1,123 @@
+//! `automap monitor --tui`: a live picture of the surface in the terminal.
+//!
+//! Drawn with plain ANSI escapes, redrawing the whole screen after each
+//! batch of events. Stop it with Ctrl+C.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::time::{Duration, Instant};
+
+use automap::{AutomapDevice, AutomapEvent, LcdLine, LcdScreen, PressSource};
+
+const LOG_LINES: usize = 12;
+const LCD_REFRESH: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct Surface {
+    encoders: [i32; 8],
+    pots: [u8; 8],
+    sliders: [u8; 8],
+    crossfader: u8,
+    held: Vec<PressSource>,
+    lcd: Option<LcdScreen>,
+    log: VecDeque<String>,
+}
+
+impl Surface {
+    fn apply(&mut self, event: &AutomapEvent) {
+        match *event {
+            AutomapEvent::Encoder { encoder, clicks } => {
+                self.encoders[encoder as usize - 0x78] += i32::from(clicks);
+            }
+            AutomapEvent::Pot { pot, value } => self.pots[pot as usize - 0x08] = value,
+            AutomapEvent::Slider { slider, value } => self.sliders[slider as usize - 0x10] = value,
+            AutomapEvent::CrossFader { value } => self.crossfader = value,
+            _ => {
+                if let Some((source, pressed)) = PressSource::from_event(event) {
+                    self.held.retain(|s| *s != source);
+                    if pressed {
+                        self.held.push(source);
+                    }
+                }
+            }
+        }
+        if self.log.len() == LOG_LINES {
+            self.log.pop_front();
+        }
+        self.log.push_back(event.to_string());
+    }
+
+    fn draw(&self) -> String {
+        let mut out = String::from("\x1b[H\x1b[2J");
+        let row = |out: &mut String, label: &str, cells: &mut dyn Iterator<Item = String>| {
+            let _ = write!(out, "{label:<10}");
+            for cell in cells {
+                let _ = write!(out, "{cell:>9}");
+            }
+            out.push('\n');
+        };
+        row(&mut out, "", &mut (1..=8).map(|i| format!("#{i}")));
+        row(
+            &mut out,
+            "encoders",
+            &mut self.encoders.iter().map(i32::to_string),
+        );
+        row(&mut out, "pots", &mut self.pots.iter().map(|v| bar(*v)));
+        row(
+            &mut out,
+            "sliders",
+            &mut self.sliders.iter().map(|v| bar(*v)),
+        );
+        let _ = writeln!(out, "{:<10}{:>9}", "xfader", bar(self.crossfader));
+
+        let held: Vec<String> = self.held.iter().map(|s| format!("{s:?}")).collect();
+        let _ = writeln!(out, "\nheld: {}\n", held.join(", "));
+
+        match &self.lcd {
+            Some(lcd) => {
+                for line in LcdLine::ALL {
+                    let text = String::from_utf8_lossy(lcd.line(line));
+                    let _ = writeln!(out, "|{text}|");
+                }
+            }
+            None => out.push_str("(no LCD readback)\n"),
+        }
+
+        out.push_str("\nevents:\n");
+        for entry in &self.log {
+            let _ = writeln!(out, "  {entry}");
+        }
+        out
+    }
+}
+
+/// A 0..127 value as a small gauge.
+fn bar(value: u8) -> String {
+    let filled = usize::from(value.min(127)) * 5 / 127;
+    format!(
+        "{}{} {value:>3}",
+        "#".repeat(filled),
+        ".".repeat(5 - filled)
+    )
+}
+
+pub async fn run(device: &mut AutomapDevice) -> Result<(), Box<dyn Error>> {
+    let mut surface = Surface::default();
+    let mut lcd_read = Instant::now() - LCD_REFRESH;
+    loop {
+        if lcd_read.elapsed() >= LCD_REFRESH {
+            surface.lcd = device.snapshot_state().await?.lcd;
+            lcd_read = Instant::now();
+        }
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(surface.draw().as_bytes())?;
+        stdout.flush()?;
+        drop(stdout);
+
+        for event in device.read_events().await? {
+            surface.apply(&event);
+        }
+    }
+}
@@ -23,6 +23,27 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! ## Not provided
+//!
+//! - Selecting a template slot from the host. The Programmer's Reference
+//!   has no command for it, and the unit never says which slot is loaded:
+//!   [`AutomapEvent::TemplateChanged`] only reports whether a special
+//!   template, such as the Automap one, came or went. There is no
+//!   `select_template()`; the user picks templates on the unit.
+//! - Asking the unit for its control positions. No request for a snapshot
+//!   is documented; [`AutomapDevice::collect_snapshot()`] gathers one the
+//!   user sends from the unit.
+//! - Capturing the LEDs and ring modes the unit shows. The LED bitmap
+//!   readback has no documented layout and rings cannot be read back, so
+//!   [`AutomapDevice::restore_state()`] restores the LCD and transport lock
+//!   as found, and LEDs only as far as this crate set them.
+//! - A JACK MIDI backend. The `jack` crate was not available to build one
+//!   against, so there is no `jack` feature. A JACK client can still carry
+//!   the surface: re-encode decoded events with [`AutomapEvent::to_bytes()`]
+//!   for its output port, and read what arrives on its input port with
+//!   [`AutomapCommand::decode_command()`] before passing it to
+//!   [`AutomapDevice::send_command()`].
 
 // Ensure exactly one runtime feature is enabled
 #[cfg(all(feature = "tokio", feature = "smol"))]
@@ -35,10 +56,79 @@ pub mod automap;
 pub(crate) mod midi;
 
 // Re-export commonly used types for convenience
+pub use automap::app::{Flow, SurfaceApp, SurfaceFrame, SurfaceRunner};
+#[cfg(feature = "net")]
+pub use automap::bridge::serve_lines;
+pub use automap::bridge::{LineDecoder, event_to_line, parse_line};
+pub use automap::capabilities::{Capabilities, Model};
+pub use automap::capture::{Capture, CaptureFormat, CapturedMessage};
+pub use automap::chords::{Chord, ChordDetector, ChordOutput};
+pub use automap::config::{Backend, DeviceConfig, Pacing};
+pub use automap::conformance::{Check, CheckResult, Conformance, ConformanceReport, Expect};
+pub use automap::control::{ControlId, ControlValue, Pedal, TouchpadAxis};
+pub use automap::corpus;
+pub use automap::crossfader::CrossfaderCurve;
+pub use automap::debounce::{Debouncer, Hysteresis};
+pub use automap::dsp::{Analog, Filter, OnePole, SlewLimiter, SmoothedValue, Smoother};
+pub use automap::error::{AutomapError, Recovery};
+pub use automap::extension::{CustomEvent, ExtensionKey};
+pub use automap::gestures::{
+    ButtonGestures, Gesture, GestureEvent, GestureThresholds, PressSource,
+};
+pub use automap::grid::{CellLed, Grid, GridEvent};
+pub use automap::handle::{AutomapHandle, MidiOut};
+pub use automap::host::{AutomapHost, HOST_HEARTBEAT, HostPage};
+pub use automap::info::DeviceInfo;
+pub use automap::json::{JsonError, JsonRequest, event_to_json, parse_request};
+pub use automap::latency::LatencyStats;
+pub use automap::layers::{Layer, LayerOutput, LayerStack};
+pub use automap::layout::{Layout, LayoutControl, LayoutError, LayoutErrorKind, Row};
+pub use automap::lcd::{Align, LcdBuffer, LcdScreen};
+pub use automap::leds::{LedBitmap, LedState, RingState};
+pub use automap::midi_in::MidiInMessage;
+#[cfg(feature = "mock")]
+pub use automap::mock::{FakeZeroMkII, Fault};
+#[cfg(feature = "net")]
+pub use automap::net::{RemoteDevice, serve};
+pub use automap::notice::{DeviceNotice, NoticeStream};
+pub use automap::params::{BankChange, Param, ParamBank, ParamKind};
+pub use automap::probe::{InterfaceAccess, InterfaceKind, InterfaceProbe, ProbeReport};
+pub use automap::protocol::consts;
+pub use automap::protocol::globals;
+pub use automap::protocol::template;
 pub use automap::protocol::{
-    cc::{Button, Encoder, EncoderPosition, RingMode, RowSelect, RowSelectLhSet, RowSelectRhSet},
+    cc::{
+        AlertType, Button, Encoder, EncoderPosition, PageButton, ParameterRequestType, Pot,
+        ProductType, RingMode, RowSelect, RowSelectLhSet, RowSelectRhSet, Slider,
+    },
     command::AutomapCommand,
-    event::AutomapEvent,
-    sysex::{AutomapSysEx, LcdClear, LcdLine, LcdOp},
+    event::{AutomapEvent, ParameterResponse, Wheel},
+    sysex::{
+        AutomapSysEx, DbSimMsg, DbTarget, LcdClear, LcdLine, LcdOp, SimCmd, SimHighLevel, pack7,
+        unpack7,
+    },
 };
-pub use automap::{AutomapDevice, USB_BUF};
+pub use automap::proxy::{Decoded, Direction, Injector, ProxiedMessage, Proxy};
+pub use automap::relative::{RelativeValue, coalesce_clicks};
+pub use automap::render::{RenderTarget, Renderer};
+pub use automap::scene::{Scene, Transition};
+pub use automap::screensaver::{SaverMode, Screensaver};
+#[cfg(feature = "seq")]
+pub use automap::seq::{Step, StepSequencer};
+pub use automap::session::SessionState;
+pub use automap::simulator::Simulator;
+pub use automap::snapshot::SurfaceSnapshot;
+pub use automap::state::{ControlState, Snapshot, SnapshotCollector};
+pub use automap::stats::{DeviceStats, MessageCounts};
+pub use automap::stepped::{SteppedValue, step_position};
+pub use automap::subscribe::{EventFilter, EventReceiver, RecvError, Subscription};
+pub use automap::surface::{Label, LcdCell, Side, SurfacePos, SurfaceRow, label_for};
+pub use automap::tempo::{TempoFollower, TempoSession};
+pub use automap::timed::TimedEvent;
+pub use automap::transfer::{CancelToken, Phase, Progress, Transfer, VerifyError};
+pub use automap::translator::{MidiTarget, Source, Translated, Translator};
+pub use automap::transport::TransportLock;
+pub use automap::unknown::{UnknownKind, UnknownMessage};
+#[cfg(feature = "webmidi")]
+pub use automap::webmidi::{MidiPortLink, PortMessage};
+pub use automap::{AutomapDevice, REPLY_TIMEOUT, USB_BUF};
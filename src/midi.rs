@@ -113,6 +113,16 @@ pub(crate) fn usbmidi_pack(midi: &[u8]) -> Vec<u8> {
     out
 }
 
+/// Like `usbmidi_pack()`, addressing the packets to virtual cable `cable`
+/// (0-15) instead of cable 0.
+pub(crate) fn usbmidi_pack_cable(midi: &[u8], cable: u8) -> Vec<u8> {
+    let mut out = usbmidi_pack(midi);
+    for packet in out.chunks_exact_mut(4) {
+        packet[0] |= (cable & 0x0F) << 4;
+    }
+    out
+}
+
 /// Converts 4-byte USB-MIDI event packets into raw MIDI bytes.
 ///
 /// This is the inverse of `usbmidi_pack()`. It extracts MIDI data bytes from
@@ -126,79 +136,358 @@ pub(crate) fn usbmidi_pack(midi: &[u8]) -> Vec<u8> {
 /// # Returns
 ///
 /// A vector of raw MIDI bytes extracted from the packets.
+#[allow(dead_code)] // the device unpacks through `usbmidi_unpack_into`
 pub(crate) fn usbmidi_unpack(buf: &[u8]) -> Vec<u8> {
     let mut out = Vec::with_capacity(buf.len());
-    for ev in buf.chunks_exact(4) {
-        let cin = ev[0] & 0x0F;
-        match cin {
-            0x8 | 0x9 | 0xA | 0xB | 0xE | 0x3 => out.extend_from_slice(&ev[1..=3]),
-            0xC | 0xD | 0x2 => out.extend_from_slice(&ev[1..=2]),
-            0x5 | 0xF => out.push(ev[1]),
-            0x4 => out.extend_from_slice(&ev[1..=3]),
-            0x6 => out.extend_from_slice(&ev[1..=2]),
-            0x7 => out.extend_from_slice(&ev[1..=3]),
-            _ => {}
-        }
-    }
+    usbmidi_unpack_into(buf, &mut out);
     out
 }
 
-/// Splits a stream of raw MIDI bytes into complete MIDI messages.
+/// Like `usbmidi_unpack()`, appending to `out`, and resynchronizing on
+/// malformed input.
+///
+/// A packet whose CIN does not agree with its MIDI bytes means the stream
+/// is corrupt or misaligned (seen after a suspend/resume). Rather than
+/// decoding garbage until the alignment happens to come right again, the
+/// unpacker then slides forward one byte at a time until a well-formed
+/// packet starts. All-zero padding packets are skipped silently.
+///
+/// Returns the number of bytes discarded, including a trailing partial
+/// packet.
+pub(crate) fn usbmidi_unpack_into(buf: &[u8], out: &mut Vec<u8>) -> usize {
+    unpack_packets(buf, |_, bytes| out.extend_from_slice(bytes))
+}
+
+/// Like `usbmidi_unpack_into()`, but only cable 0 goes to `out`. The bytes
+/// of each packet on another cable are passed to `other` with its cable
+/// number instead, so the unit's MIDI ports can be told apart.
+pub(crate) fn usbmidi_unpack_cables(
+    buf: &[u8],
+    out: &mut Vec<u8>,
+    mut other: impl FnMut(u8, &[u8]),
+) -> usize {
+    unpack_packets(buf, |cable, bytes| match cable {
+        0 => out.extend_from_slice(bytes),
+        cable => other(cable, bytes),
+    })
+}
+
+/// Calls `f` with the cable and MIDI bytes of every well-formed packet in
+/// `buf`, returning the number of bytes discarded.
+fn unpack_packets(buf: &[u8], mut f: impl FnMut(u8, &[u8])) -> usize {
+    let mut discarded = 0;
+    let mut i = 0;
+    while i + 4 <= buf.len() {
+        let ev = &buf[i..i + 4];
+        let Some(len) = packet_len(ev) else {
+            discarded += 1;
+            i += 1;
+            continue;
+        };
+        f(ev[0] >> 4, &ev[1..=len]);
+        i += 4;
+    }
+    discarded + (buf.len() - i)
+}
+
+/// Number of MIDI bytes in a well-formed USB-MIDI event packet, or `None`
+/// if the CIN and the bytes it carries disagree.
+fn packet_len(ev: &[u8]) -> Option<usize> {
+    let data = |bytes: &[u8]| bytes.iter().all(|&b| b < 0x80);
+    let cin = ev[0] & 0x0F;
+    match cin {
+        0x0 if ev == [0, 0, 0, 0] => Some(0),
+        0x8..=0xB | 0xE if ev[1] >> 4 == cin && data(&ev[2..]) => Some(3),
+        0xC | 0xD if ev[1] >> 4 == cin && data(&ev[2..3]) => Some(2),
+        0x2 if matches!(ev[1], 0xF1 | 0xF3) && data(&ev[2..3]) => Some(2),
+        0x3 if ev[1] == 0xF2 && data(&ev[2..]) => Some(3),
+        0x4 if (ev[1] == 0xF0 || ev[1] < 0x80) && data(&ev[2..]) => Some(3),
+        0x5 if ev[1] == 0xF7 || ev[1] == 0xF6 => Some(1),
+        0x6 if ev[2] == 0xF7 && (ev[1] == 0xF0 || ev[1] < 0x80) => Some(2),
+        0x7 if ev[3] == 0xF7 && (ev[1] == 0xF0 || ev[1] < 0x80) && ev[2] < 0x80 => Some(3),
+        0xF if ev[1] >= 0xF8 => Some(1),
+        _ => None,
+    }
+}
+
+/// Iterates over the complete MIDI messages in a stream of raw MIDI bytes.
 ///
-/// This function parses a byte stream and extracts complete MIDI messages
-/// by analyzing status bytes and message lengths. It handles:
+/// Each item borrows from the input, so splitting a buffer allocates
+/// nothing. It handles:
 /// - System Real-Time messages (single byte)
 /// - SysEx messages (variable length, F0...F7)
 /// - Channel messages (2-3 bytes)
 /// - System Common messages
 ///
-/// Running status is not supported - each message must have its own status byte.
-///
-/// # Arguments
+/// Running status is not supported - each message must have its own status
+/// byte. Stray data bytes are skipped, and a truncated message at the end of
+/// the buffer ends the iteration.
+#[derive(Debug, Clone)]
+pub(crate) struct MidiMessages<'a> {
+    bs: &'a [u8],
+}
+
+impl<'a> MidiMessages<'a> {
+    pub(crate) fn new(bs: &'a [u8]) -> Self {
+        Self { bs }
+    }
+}
+
+impl<'a> Iterator for MidiMessages<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        while let Some(&b0) = self.bs.first() {
+            let len = if (0xF8..=0xFF).contains(&b0) && b0 != 0xF9 && b0 != 0xFD {
+                1
+            } else if b0 < 0x80 {
+                self.bs = &self.bs[1..];
+                continue;
+            } else if b0 == 0xF0 {
+                match self.bs.iter().position(|&b| b == 0xF7) {
+                    Some(i) => i + 1,
+                    None => self.bs.len(),
+                }
+            } else {
+                let need = match b0 {
+                    0xC0..=0xDF | 0xF1 | 0xF3 => 2,
+                    0xF2 => 3,
+                    0x80..=0xBF | 0xE0..=0xEF => 3,
+                    0xF6 => 1,
+                    _ => 1,
+                };
+                if self.bs.len() < need {
+                    self.bs = &[];
+                    return None;
+                }
+                need
+            };
+            let (msg, rest) = self.bs.split_at(len);
+            self.bs = rest;
+            return Some(msg);
+        }
+        None
+    }
+}
+
+/// Splits a stream of raw MIDI bytes into complete MIDI messages.
 ///
-/// * `bs` - Raw MIDI byte stream (may contain multiple messages)
+/// An owning wrapper around [`MidiMessages`], for callers that need to keep
+/// the messages past the input buffer.
+#[allow(dead_code)] // the device reads through `MidiMessages`; kept for the benches
+pub(crate) fn split_midi_messages(bs: &[u8]) -> Vec<Vec<u8>> {
+    MidiMessages::new(bs).map(<[u8]>::to_vec).collect()
+}
+
+/// Longest frame [`decode_frame`](crate::automap::sysex::decode_frame)
+/// accepts, and the default and ceiling of the limit on SysEx buffered from
+/// the device.
+pub const MAX_SYSEX_LEN: usize = 8192;
+
+/// A SysEx frame grew past the configured limit before its closing `F7`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Oversize;
+
+/// Reassembles MIDI messages from bytes that arrive in arbitrary chunks.
 ///
-/// # Returns
+/// `MidiMessages` works on one buffer at a time, so a SysEx longer than a
+/// single USB transfer (LCD readback, template dumps) comes out in pieces.
+/// `MidiStream` keeps an unterminated SysEx across calls to `push()` and
+/// only yields it once the closing `F7` has been seen. Real-Time bytes
+/// interleaved with the SysEx are passed through; any other status byte
+/// aborts it, and the partial frame is dropped.
 ///
-/// A vector where each element is a complete MIDI message.
-pub(crate) fn split_midi_messages(mut bs: &[u8]) -> Vec<Vec<u8>> {
-    let mut out = Vec::new();
-    while !bs.is_empty() {
-        let b0 = bs[0];
-        if (0xF8..=0xFF).contains(&b0) && b0 != 0xF9 && b0 != 0xFD {
-            out.push(vec![b0]);
-            bs = &bs[1..];
-            continue;
-        }
-        if b0 < 0x80 {
-            bs = &bs[1..];
-            continue;
+/// A SysEx longer than the limit is reported as [`Oversize`] and dropped,
+/// along with everything up to the next status byte, so a device that never
+/// sends `F7` cannot grow the buffer without bound.
+#[derive(Debug)]
+pub(crate) struct MidiStream {
+    sysex: Vec<u8>,
+    in_sysex: bool,
+    /// Skipping the rest of an oversized SysEx.
+    discarding: bool,
+    max_sysex: usize,
+}
+
+impl Default for MidiStream {
+    fn default() -> Self {
+        Self::new(MAX_SYSEX_LEN)
+    }
+}
+
+impl MidiStream {
+    /// A stream that buffers SysEx frames of at most `max_sysex` bytes,
+    /// including `F0` and `F7`.
+    pub(crate) fn new(max_sysex: usize) -> Self {
+        Self {
+            sysex: Vec::new(),
+            in_sysex: false,
+            discarding: false,
+            max_sysex,
         }
-        if b0 == 0xF0 {
-            let mut i = 1;
-            while i < bs.len() && bs[i] != 0xF7 {
-                i += 1;
+    }
+
+    /// Feeds raw MIDI bytes, returning every message completed by them.
+    /// Oversized SysEx frames are left out.
+    #[allow(dead_code)] // used by the tests and benches
+    pub(crate) fn push(&mut self, bs: &[u8]) -> Vec<Vec<u8>> {
+        let mut out = Vec::new();
+        self.push_with(bs, |msg| {
+            if let Ok(msg) = msg {
+                out.push(msg.to_vec());
             }
-            if i < bs.len() {
-                i += 1;
+        });
+        out
+    }
+
+    /// Feeds raw MIDI bytes, calling `f` with every message completed by
+    /// them. Only SysEx spanning several calls is copied.
+    pub(crate) fn push_with(&mut self, mut bs: &[u8], mut f: impl FnMut(Result<&[u8], Oversize>)) {
+        while !bs.is_empty() {
+            let b0 = bs[0];
+            let realtime = (0xF8..=0xFF).contains(&b0) && b0 != 0xF9 && b0 != 0xFD;
+            if self.discarding {
+                if realtime {
+                    f(Ok(&[b0]));
+                } else if b0 >= 0x80 && b0 != 0xF7 {
+                    // Back in sync: handle it as the start of a new message
+                    self.discarding = false;
+                    continue;
+                }
+                bs = &bs[1..];
+                continue;
             }
-            out.push(bs[..i].to_vec());
-            bs = &bs[i..];
-            continue;
-        }
-        let need = match b0 {
-            0xC0..=0xDF | 0xF1 | 0xF3 => 2,
-            0xF2 => 3,
-            0x80..=0xBF | 0xE0..=0xEF => 3,
-            0xF6 => 1,
-            _ => 1,
-        };
-        if bs.len() >= need {
-            out.push(bs[..need].to_vec());
-            bs = &bs[need..];
-        } else {
-            break;
+            if self.in_sysex {
+                if realtime {
+                    f(Ok(&[b0]));
+                } else if b0 == 0xF7 {
+                    self.sysex.push(b0);
+                    f(Ok(&self.sysex));
+                    self.sysex.clear();
+                    self.in_sysex = false;
+                } else if b0 >= 0x80 {
+                    // Aborted: handle the status byte as the start of a new message
+                    self.sysex.clear();
+                    self.in_sysex = false;
+                    continue;
+                } else if self.sysex.len() + 1 >= self.max_sysex {
+                    // No room left for the closing F7
+                    self.sysex = Vec::new();
+                    self.in_sysex = false;
+                    self.discarding = true;
+                    f(Err(Oversize));
+                } else {
+                    self.sysex.push(b0);
+                }
+                bs = &bs[1..];
+                continue;
+            }
+            if b0 == 0xF0 {
+                self.in_sysex = true;
+                self.sysex.clear();
+                self.sysex.push(b0);
+                bs = &bs[1..];
+                continue;
+            }
+            let end = bs.iter().position(|&b| b == 0xF0).unwrap_or(bs.len());
+            MidiMessages::new(&bs[..end]).for_each(|msg| f(Ok(msg)));
+            bs = &bs[end..];
         }
     }
-    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_borrow_input() {
+        let bytes = [
+            0xBF, 0x78, 0x01, 0x7F, 0xF8, 0xC0, 0x05, 0xF0, 0x01, 0xF7, 0xBF, 0x79,
+        ];
+        let msgs: Vec<&[u8]> = MidiMessages::new(&bytes).collect();
+        assert_eq!(
+            msgs,
+            [
+                &[0xBF, 0x78, 0x01][..],
+                &[0xF8],
+                &[0xC0, 0x05],
+                &[0xF0, 0x01, 0xF7]
+            ]
+        );
+        assert_eq!(split_midi_messages(&bytes).len(), 4);
+    }
+
+    #[test]
+    fn test_stream_joins_split_sysex() {
+        let mut stream = MidiStream::default();
+        assert_eq!(
+            stream.push(&[0xBF, 0x63, 0x01, 0xF0, 0x00, 0x20]),
+            vec![vec![0xBF, 0x63, 0x01]]
+        );
+        assert_eq!(
+            stream.push(&[0x29, 0xF8, 0xF7, 0xBF, 0x4F, 0x00]),
+            vec![
+                vec![0xF8],
+                vec![0xF0, 0x00, 0x20, 0x29, 0xF7],
+                vec![0xBF, 0x4F, 0x00]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stream_drops_aborted_sysex() {
+        let mut stream = MidiStream::default();
+        assert!(stream.push(&[0xF0, 0x00, 0x20]).is_empty());
+        assert_eq!(
+            stream.push(&[0xBF, 0x63, 0x01]),
+            vec![vec![0xBF, 0x63, 0x01]]
+        );
+    }
+
+    #[test]
+    fn test_stream_resyncs_after_oversize() {
+        let mut stream = MidiStream::new(6);
+        let mut seen = Vec::new();
+        let mut collect = |msg: Result<&[u8], Oversize>| seen.push(msg.map(<[u8]>::to_vec));
+        stream.push_with(&[0xF0, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06], &mut collect);
+        stream.push_with(&[0x07, 0xF7, 0xBF, 0x63, 0x01], &mut collect);
+        stream.push_with(&[0xF0, 0x01, 0xF7], &mut collect);
+        assert_eq!(
+            seen,
+            [
+                Err(Oversize),
+                Ok(vec![0xBF, 0x63, 0x01]),
+                Ok(vec![0xF0, 0x01, 0xF7])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unpack_resyncs_on_garbage() {
+        let mut out = Vec::new();
+        let buf = [
+            0x0B, 0xBF, 0x78, 0x01, // encoder turn
+            0x3A, 0x99, // two stray bytes shifting the alignment
+            0x0B, 0xBF, 0x79, 0x41, // encoder turn
+            0x00, 0x00, 0x00, 0x00, // padding
+            0x0B, 0xBF, // partial packet
+        ];
+        assert_eq!(usbmidi_unpack_into(&buf, &mut out), 4);
+        assert_eq!(out, [0xBF, 0x78, 0x01, 0xBF, 0x79, 0x41]);
+    }
+
+    #[test]
+    fn test_unpack_splits_cables() {
+        let mut packets = usbmidi_pack(&[0xBF, 0x78, 0x01]);
+        packets.extend(usbmidi_pack_cable(&[0x90, 0x3C, 0x64, 0xF8], 1));
+        let mut out = Vec::new();
+        let mut other = Vec::new();
+        let discarded = usbmidi_unpack_cables(&packets, &mut out, |cable, bytes| {
+            other.push((cable, bytes.to_vec()));
+        });
+        assert_eq!(discarded, 0);
+        assert_eq!(out, [0xBF, 0x78, 0x01]);
+        assert_eq!(other, [(1, vec![0x90, 0x3C, 0x64]), (1, vec![0xF8])]);
+    }
 }
@@ -0,0 +1,16 @@
+//! Replays the captures in `tests/golden` through the decoders.
+//!
+//! Add a capture by recording with a `Proxy` tap and
+//! `corpus::append_capture()`, then check the decoded column by eye.
+
+use std::path::Path;
+
+use automap::corpus;
+
+#[test]
+fn test_golden_captures() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mismatches = corpus::check_dir(dir).unwrap();
+    let report: Vec<String> = mismatches.iter().map(ToString::to_string).collect();
+    assert!(report.is_empty(), "{}", report.join("\n"));
+}
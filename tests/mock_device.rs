@@ -0,0 +1,1060 @@
+//! End-to-end tests of `AutomapDevice` against the simulated unit.
+//!
+//! Run with `cargo test --features mock`.
+
+#![cfg(feature = "mock")]
+
+use std::time::{Duration, Instant};
+
+use automap::globals::{DRUMPAD_THRESHOLDS, GlobalField};
+use automap::template::{HEADER_LEN, PortRoute, TEMPLATE_LEN};
+use automap::{
+    AlertType, AutomapCommand, AutomapDevice, AutomapError, AutomapEvent, AutomapHost,
+    AutomapSysEx, Button, Capture, CaptureFormat, Check, Conformance, DbSimMsg, DbTarget,
+    DeviceConfig, DeviceNotice, Direction, Encoder, EncoderPosition, EventFilter, Expect,
+    FakeZeroMkII, Fault, HostPage, LcdLine, LcdOp, Model, Pacing, PageButton, ParameterRequestType,
+    ParameterResponse, Phase, ProductType, RingMode, SaverMode, Scene, Screensaver, SessionState,
+    SimCmd, SimHighLevel, Transfer, Transition, VerifyError, Wheel,
+};
+
+async fn open(fake: &FakeZeroMkII) -> AutomapDevice {
+    let config = DeviceConfig::new().auto_online(true).auto_clear(true);
+    AutomapDevice::open_mock(fake, &config).await.unwrap()
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_online_leds_and_lcd() {
+    let fake = FakeZeroMkII::new();
+    let mut device = open(&fake).await;
+    assert!(fake.is_online());
+
+    device
+        .send_command(&AutomapCommand::ButtonLed {
+            button: Button::ButtonB2,
+            on: true,
+        })
+        .await
+        .unwrap();
+    device
+        .send_sysex(AutomapSysEx::LcdText(vec![
+            LcdOp::Cursor {
+                col: 0,
+                line: LcdLine::LeftTop,
+            },
+            LcdOp::Text(b"Hello"),
+            LcdOp::End,
+        ]))
+        .await
+        .unwrap();
+    assert_eq!(fake.leds().button(Button::ButtonB2), Some(true));
+    assert!(fake.lcd().line(LcdLine::LeftTop).starts_with(b"Hello"));
+
+    device.close().await.unwrap();
+    assert!(!fake.is_online());
+    assert_eq!(fake.leds().button(Button::ButtonB2), Some(false));
+    assert!(fake.lcd().line(LcdLine::LeftTop).iter().all(|&b| b == b' '));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_handshake_takes_over_the_surface() {
+    let fake = FakeZeroMkII::new();
+    let mut device = AutomapDevice::open_mock(&fake, &DeviceConfig::new())
+        .await
+        .unwrap();
+    assert!(fake.is_online());
+    assert_eq!(device.session_state(), SessionState::Online);
+    // Online, product type query, the snapshot's LCD and transport lock
+    // readbacks, LCD clear, LEDs off, then the echo
+    let received = fake.received();
+    assert_eq!(received.len(), 7);
+    assert_eq!(received[5], AutomapCommand::AllLedsOff.to_bytes());
+    assert_eq!(received[6][..2], [0xBF, 0x63]);
+    // Answered from the handshake
+    assert_eq!(device.capabilities().await.unwrap().model, Model::ZeroMkII);
+    assert_eq!(fake.received().len(), 7);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_handshake_keeps_the_surface_it_replaced() {
+    let fake = FakeZeroMkII::new();
+    let config = DeviceConfig::new().handshake(false).auto_online(true);
+    let mut before = AutomapDevice::open_mock(&fake, &config).await.unwrap();
+    before
+        .send_sysex(AutomapSysEx::LcdText(vec![
+            LcdOp::Text(b"Mixer"),
+            LcdOp::End,
+        ]))
+        .await
+        .unwrap();
+    assert!(before.takeover_snapshot().is_none());
+    drop(before);
+
+    let mut device = AutomapDevice::open_mock(&fake, &DeviceConfig::new())
+        .await
+        .unwrap();
+    assert!(fake.lcd().line(LcdLine::LeftTop).iter().all(|&b| b == b' '));
+    let snapshot = device.takeover_snapshot().unwrap().clone();
+    let lcd = snapshot.lcd.as_ref().unwrap();
+    assert!(lcd.line(LcdLine::LeftTop).starts_with(b"Mixer"));
+    device.restore_state(&snapshot).await.unwrap();
+    assert!(fake.lcd().line(LcdLine::LeftTop).starts_with(b"Mixer"));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_queries() {
+    let fake = FakeZeroMkII::new();
+    let mut device = open(&fake).await;
+
+    device.ping().await.unwrap();
+    let caps = device.capabilities().await.unwrap();
+    assert_eq!(caps.model, Model::ZeroMkII);
+
+    device
+        .send_command(&AutomapCommand::TransportLockSet { enabled: true })
+        .await
+        .unwrap();
+    let snapshot = device.snapshot_state().await.unwrap();
+    assert_eq!(snapshot.transport_lock, Some(true));
+    assert!(snapshot.lcd.is_some());
+    assert!(device.read_led_bitmap().await.unwrap().is_some());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_events_arrive_around_replies() {
+    let fake = FakeZeroMkII::new();
+    let mut device = open(&fake).await;
+
+    let press = AutomapEvent::Button {
+        button: Button::ButtonA4,
+        pressed: true,
+    };
+    fake.send_event(press);
+    device.ping().await.unwrap();
+    assert_eq!(device.read_events().await.unwrap(), [press]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_template_download_and_upload() {
+    let template: Vec<u8> = (0..HEADER_LEN).map(|i| (i % 0x7F) as u8).collect();
+    let fake = FakeZeroMkII::new().with_template(&template);
+    let mut device = open(&fake).await;
+
+    let mut transfer = Transfer::new().chunk_size(100);
+    let read = device
+        .read_block(DbTarget::TemplateHeader, 0, 0, HEADER_LEN, &mut transfer)
+        .await
+        .unwrap();
+    assert_eq!(read, template);
+
+    device
+        .write_block_verified(DbTarget::TemplateHeader, 0, 8, b"Renamed", &mut transfer)
+        .await
+        .unwrap();
+    assert_eq!(&fake.template()[8..15], b"Renamed");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_session_lifecycle() {
+    let fake = FakeZeroMkII::new();
+    let config = DeviceConfig::new().handshake(false);
+    let mut device = AutomapDevice::open_mock(&fake, &config).await.unwrap();
+    assert_eq!(device.session_state(), SessionState::Claimed);
+
+    let led = AutomapCommand::ButtonLed {
+        button: Button::ButtonA1,
+        on: true,
+    };
+    let err = device.send_command(&led).await.unwrap_err();
+    let inner = err.get_ref().and_then(|e| e.downcast_ref::<AutomapError>());
+    assert!(matches!(inner, Some(AutomapError::NotOnline)));
+    assert!(fake.received().is_empty());
+    // Queries still go out
+    device.ping().await.unwrap();
+
+    device.enter_automap_mode().await.unwrap();
+    device.handle().send_command(&led).await.unwrap();
+    device.release_to_standalone().await.unwrap();
+    // Released into play mode, and only once
+    device.release_to_standalone().await.unwrap();
+    let play = DbSimMsg::HighLevel(SimHighLevel::ForcePlayMode).to_bytes();
+    let received = fake.received();
+    assert_eq!(received.iter().filter(|m| **m == play).count(), 1);
+    assert_eq!(received.last(), Some(&play));
+    assert_eq!(
+        device.take_notices(),
+        [
+            DeviceNotice::SessionChanged {
+                from: SessionState::Claimed,
+                to: SessionState::Online,
+            },
+            DeviceNotice::SessionChanged {
+                from: SessionState::Online,
+                to: SessionState::Claimed,
+            },
+        ]
+    );
+    assert_eq!(fake.leds().button(Button::ButtonA1), Some(true));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_globals_members() {
+    let fake = FakeZeroMkII::new().with_globals(&[0x20; 0x60]);
+    let mut device = open(&fake).await;
+
+    let thresholds = device.read_global(DRUMPAD_THRESHOLDS).await.unwrap();
+    assert_eq!(thresholds, [0x20; 8]);
+    device
+        .write_global(
+            DRUMPAD_THRESHOLDS,
+            &[0x12, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x34],
+        )
+        .await
+        .unwrap();
+    assert_eq!(fake.globals()[0x52], 0x12);
+    assert_eq!(fake.globals()[0x59], 0x34);
+
+    let short = GlobalField::new(0x10, 2);
+    assert!(device.write_global(short, &[1]).await.is_err());
+    assert!(device.write_global(short, &[1, 0x80]).await.is_err());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_template_change_on_the_unit() {
+    let fake = FakeZeroMkII::new();
+    let mut device = open(&fake).await;
+
+    let unloaded = AutomapEvent::TemplateChanged { special: false };
+    fake.send_event(unloaded);
+    assert_eq!(device.read_events().await.unwrap(), [unloaded]);
+    assert_eq!(device.session_state(), SessionState::Claimed);
+
+    fake.send_event(AutomapEvent::TemplateChanged { special: true });
+    device.read_events().await.unwrap();
+    assert_eq!(device.session_state(), SessionState::Online);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_fetch_current_template() {
+    let mut bytes = vec![0u8; usize::from(TEMPLATE_LEN)];
+    bytes[..8].copy_from_slice(b"Drums   ");
+    bytes[usize::from(HEADER_LEN)] = 0x11;
+    let fake = FakeZeroMkII::new().with_template(&bytes);
+    let mut device = open(&fake).await;
+
+    let press = AutomapEvent::Button {
+        button: Button::ButtonA4,
+        pressed: true,
+    };
+    fake.send_event(press);
+    let template = device.fetch_current_template().await.unwrap();
+    assert_eq!(template.name(), "Drums");
+    assert_eq!(template.as_bytes(), bytes);
+    assert_eq!(template.control(1).unwrap()[0], 0x11);
+    assert_eq!(device.read_events().await.unwrap(), [press]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_save_to_flash() {
+    let fake = FakeZeroMkII::new().with_globals(&[0x20; 0x60]);
+    let mut device = open(&fake).await;
+    let pings = device.latency().sent();
+
+    device
+        .write_global(DRUMPAD_THRESHOLDS, &[0x10; 8])
+        .await
+        .unwrap();
+    assert_eq!(fake.flash_globals(), None);
+    device.save_globals_to_flash().await.unwrap();
+    assert_eq!(fake.flash_globals().unwrap()[0x52], 0x10);
+
+    device.save_current_template_to_flash().await.unwrap();
+    assert!(fake.flash_template().is_some());
+    // Confirmations are not pings
+    assert_eq!(device.latency().sent(), pings);
+    // Nowhere to read memory protect from, so the saves went ahead unchecked
+    assert!(
+        device
+            .take_notices()
+            .contains(&DeviceNotice::MemoryProtectUnknown)
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_memory_protect_refuses_saves() {
+    let flag = GlobalField::new(0x40, 1);
+    let mut globals = vec![0; 0x60];
+    globals[0x40] = 1;
+    let fake = FakeZeroMkII::new().with_globals(&globals);
+    let config = DeviceConfig::new().memory_protect_flag(flag);
+    let mut device = AutomapDevice::open_mock(&fake, &config).await.unwrap();
+
+    assert_eq!(device.memory_protect().await.unwrap(), Some(true));
+    let err = device.save_current_template_to_flash().await.unwrap_err();
+    let inner = err.get_ref().and_then(|e| e.downcast_ref::<AutomapError>());
+    assert!(matches!(inner, Some(AutomapError::MemoryProtected)));
+    assert_eq!(fake.flash_template(), None);
+    let err = device
+        .write_global(DRUMPAD_THRESHOLDS, &[0x10; 8])
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+    assert_eq!(
+        device.read_global(DRUMPAD_THRESHOLDS).await.unwrap(),
+        [0; 8]
+    );
+
+    device.override_memory_protect(true);
+    device.save_current_template_to_flash().await.unwrap();
+    assert!(fake.flash_template().is_some());
+
+    // The flag itself can always be cleared
+    device.override_memory_protect(false);
+    device.write_global(flag, &[0]).await.unwrap();
+    assert_eq!(device.memory_protect().await.unwrap(), Some(false));
+    device
+        .write_global(DRUMPAD_THRESHOLDS, &[0x10; 8])
+        .await
+        .unwrap();
+    assert!(
+        !device
+            .take_notices()
+            .contains(&DeviceNotice::MemoryProtectUnknown)
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_zero_crossfader_on_touchpad_x() {
+    let fake = FakeZeroMkII::new();
+    let mut device = open(&fake).await;
+
+    fake.send_event(AutomapEvent::TouchpadX1 { value: 100 });
+    assert_eq!(
+        device.read_events().await.unwrap(),
+        [AutomapEvent::CrossFader { value: 100 }]
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_pitch_wheel_on_the_keyboard_port() {
+    let fake = FakeZeroMkII::new();
+    let mut device = open(&fake).await;
+
+    fake.send_midi(&[0xE0, 0x00, 0x50, 0x90, 0x3C, 0x40]);
+    assert_eq!(
+        device.read_events().await.unwrap(),
+        [AutomapEvent::Wheel {
+            wheel: Wheel::Pitch { value: 0x800 },
+        }]
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_alert_value() {
+    let mut globals = vec![0; 0x60];
+    globals[0x30] = 0x7E;
+    let fake = FakeZeroMkII::new().with_globals(&globals);
+    let config =
+        DeviceConfig::new().alert_field(AlertType::OctaveChanged, GlobalField::new(0x30, 1));
+    let mut device = AutomapDevice::open_mock(&fake, &config).await.unwrap();
+
+    let alert = AutomapEvent::Alert {
+        alert_type: AlertType::OctaveChanged,
+    };
+    fake.send_event(alert);
+    assert_eq!(device.read_events().await.unwrap(), [alert]);
+    assert_eq!(
+        device.alert_value(AlertType::OctaveChanged).await.unwrap(),
+        Some(vec![0x7E])
+    );
+    assert_eq!(
+        device
+            .alert_value(AlertType::AfterTouchChanged)
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_sysex_frames_are_paced() {
+    let fake = FakeZeroMkII::new();
+    let pacing = Pacing {
+        gap: Duration::from_millis(30),
+        per_byte: Duration::ZERO,
+    };
+    let config = DeviceConfig::new().auto_online(true).pacing(pacing);
+    let mut device = AutomapDevice::open_mock(&fake, &config).await.unwrap();
+
+    let start = Instant::now();
+    let mut transfer = Transfer::new().chunk_size(8);
+    device
+        .write_block(DbTarget::TemplateHeader, 0, 8, b"Renamed!", &mut transfer)
+        .await
+        .unwrap();
+    device
+        .write_block(DbTarget::TemplateHeader, 0, 8, b"Paced", &mut transfer)
+        .await
+        .unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(30));
+    assert_eq!(&fake.template()[8..16], b"Paceded!");
+
+    // Short messages are not held back behind a frame
+    let start = Instant::now();
+    for on in [true, false, true] {
+        device
+            .send_command(&AutomapCommand::ButtonLed {
+                button: Button::ButtonA1,
+                on,
+            })
+            .await
+            .unwrap();
+    }
+    assert!(start.elapsed() < Duration::from_millis(30));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_device_info() {
+    let fake = FakeZeroMkII::new();
+    let mut device = AutomapDevice::open_mock(&fake, &DeviceConfig::new().handshake(false))
+        .await
+        .unwrap();
+    assert_eq!(device.info().protocol_version, None);
+    device.read_global(DRUMPAD_THRESHOLDS).await.unwrap();
+    assert_eq!(device.info().protocol_version, Some((0x12, 0x00)));
+
+    // The handshake learns the model, and the version from its readbacks
+    let device = open(&fake).await;
+    let info = device.info();
+    assert_eq!(info.model, Some(Model::ZeroMkII));
+    assert_eq!(info.firmware_version, 0x0100);
+    assert_eq!(info.protocol_version, Some((0x12, 0x00)));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_notice_stream() {
+    let fake = FakeZeroMkII::new();
+    let config = DeviceConfig::new().handshake(false);
+    let mut device = AutomapDevice::open_mock(&fake, &config).await.unwrap();
+    let mut notices = device.notices();
+    let _slow = device.broadcast(1);
+
+    device.enter_automap_mode().await.unwrap();
+    let press = |pressed| AutomapEvent::Button {
+        button: Button::ButtonA1,
+        pressed,
+    };
+    fake.send_event(press(true));
+    fake.send_event(press(false));
+    device.read_events().await.unwrap();
+
+    assert_eq!(
+        notices.try_recv(),
+        Some(DeviceNotice::SessionChanged {
+            from: SessionState::Claimed,
+            to: SessionState::Online,
+        })
+    );
+    assert_eq!(
+        notices.try_recv(),
+        Some(DeviceNotice::QueueOverflow { dropped: 1 })
+    );
+    assert_eq!(notices.try_recv(), None);
+    // The stream and take_notices() each get every notice
+    assert_eq!(device.take_notices().len(), 2);
+    drop(device);
+    assert_eq!(notices.recv().await, None);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_stats() {
+    let fake = FakeZeroMkII::new();
+    let mut device = open(&fake).await;
+    device.reset_stats();
+
+    device
+        .send_command(&AutomapCommand::ButtonLed {
+            button: Button::ButtonA1,
+            on: true,
+        })
+        .await
+        .unwrap();
+    fake.send_event(AutomapEvent::Button {
+        button: Button::ButtonA1,
+        pressed: true,
+    });
+    device.read_events().await.unwrap();
+
+    let stats = device.stats();
+    assert_eq!(stats.sent.control_change, 1);
+    assert_eq!(stats.bytes_sent, 4);
+    assert_eq!(stats.received.control_change, 1);
+    assert_eq!(stats.bytes_received, 4);
+    assert_eq!(stats.decode_errors, 0);
+    assert!(stats.mean_write_latency().is_some());
+
+    device.reset_stats();
+    assert_eq!(device.stats().sent.total(), 0);
+    assert_eq!(device.stats().mean_write_latency(), None);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_conformance_report() {
+    let fake = FakeZeroMkII::new();
+    let config = DeviceConfig::new().handshake(false);
+    let mut device = AutomapDevice::open_mock(&fake, &config).await.unwrap();
+    let turn = AutomapEvent::Encoder {
+        encoder: Encoder::Encoder3,
+        clicks: 2,
+    };
+    let suite = Conformance::new()
+        .timeout(Duration::from_millis(20))
+        .check(
+            Check::new("encoder 3")
+                .send(SimCmd::Encoder {
+                    number_1_based: 3,
+                    clicks_signed: 2,
+                })
+                .expect(Expect::Event(turn)),
+        )
+        .check(
+            Check::new("button 1")
+                .send(SimCmd::Button {
+                    number_1_based: 1,
+                    pressed: true,
+                })
+                .expect(Expect::Any(EventFilter::buttons())),
+        );
+
+    let report = suite.run(&mut device).await.unwrap();
+    assert!(fake.is_online());
+    assert_eq!(report.passed(), 1);
+    assert_eq!(report.results[0].received, [turn]);
+    // The fake does not simulate buttons
+    let failed: Vec<_> = report.failed().map(|r| r.name.as_str()).collect();
+    assert_eq!(failed, ["button 1"]);
+    assert!(report.to_string().ends_with("1 of 2 passed"));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_coalesced_clicks() {
+    let fake = FakeZeroMkII::new();
+    let config = DeviceConfig::new()
+        .handshake(false)
+        .coalesce_clicks(Duration::ZERO);
+    let mut device = AutomapDevice::open_mock(&fake, &config).await.unwrap();
+    let turn = |encoder, clicks| AutomapEvent::Encoder { encoder, clicks };
+    fake.send_event(turn(Encoder::Encoder1, 1));
+    fake.send_event(turn(Encoder::Encoder4, -1));
+    fake.send_event(turn(Encoder::Encoder1, 1));
+    fake.send_event(turn(Encoder::Encoder1, 3));
+
+    assert_eq!(
+        device.read_events().await.unwrap(),
+        [turn(Encoder::Encoder1, 5), turn(Encoder::Encoder4, -1)]
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_host_pages_and_offline() {
+    let fake = FakeZeroMkII::new();
+    let device = AutomapDevice::open_mock(&fake, &AutomapHost::config())
+        .await
+        .unwrap();
+    let pages = vec![
+        HostPage::new("Mixer").param("Volume").param("Pan"),
+        HostPage::new("Reverb").param("Size"),
+    ];
+    let mut host = AutomapHost::new(device, pages);
+    host.start().await.unwrap();
+    let line = |l| String::from_utf8(fake.lcd().line(l).to_vec()).unwrap();
+    assert!(line(LcdLine::LeftTop).starts_with("Volume   Pan"));
+    assert!(line(LcdLine::RightTop).ends_with("1/2"));
+
+    fake.send_event(AutomapEvent::PageButton {
+        button: PageButton::PageDnL,
+        pressed: true,
+    });
+    host.next_events().await.unwrap();
+    assert_eq!(host.page(), 1);
+    assert!(line(LcdLine::LeftTop).starts_with("Size     "));
+
+    // A template of the unit's own: nothing is drawn until Automap is back
+    fake.send_event(AutomapEvent::TemplateChanged { special: false });
+    host.next_events().await.unwrap();
+    assert!(!host.is_online());
+    let sent = fake.received().len();
+    host.set_status("Octave +1");
+    host.flush().await.unwrap();
+    assert_eq!(fake.received().len(), sent);
+
+    fake.send_event(AutomapEvent::TemplateChanged { special: true });
+    host.next_events().await.unwrap();
+    assert!(host.is_online());
+    assert_eq!(line(LcdLine::RightBottom).trim_end(), "Octave +1");
+
+    host.shutdown().await.unwrap();
+    assert!(!fake.is_online());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_replays_a_capture() {
+    let turn = AutomapEvent::Encoder {
+        encoder: Encoder::Encoder2,
+        clicks: 3,
+    };
+    let press = AutomapEvent::Button {
+        button: Button::ButtonA1,
+        pressed: true,
+    };
+    let mut recorded = Capture::new();
+    recorded.push(Direction::ToDevice, Duration::ZERO, [0xBF, 0x18, 0x01]);
+    recorded.push(
+        Direction::FromDevice,
+        Duration::from_millis(1),
+        turn.to_bytes(),
+    );
+    recorded.push(
+        Direction::FromDevice,
+        Duration::from_millis(5),
+        press.to_bytes(),
+    );
+    let mut file = Vec::new();
+    recorded.write(CaptureFormat::Pcap, &mut file).unwrap();
+    let capture = Capture::from_bytes(&file).unwrap();
+
+    let fake = FakeZeroMkII::new();
+    let mut device = open(&fake).await;
+    let start = Instant::now();
+    capture.replay(&fake).await;
+    assert!(start.elapsed() >= Duration::from_millis(5));
+
+    let mut events = Vec::new();
+    while events.len() < 2 {
+        events.extend(device.read_events().await.unwrap());
+    }
+    assert_eq!(events, [turn, press]);
+    // What the host sent in the recording is left to the code under test
+    assert!(!fake.received().contains(&vec![0xBF, 0x18, 0x01]));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_reads_through_faults() {
+    let fake = FakeZeroMkII::new().with_read_limit(1);
+    let mut device = open(&fake).await;
+    let turn = AutomapEvent::Encoder {
+        encoder: Encoder::Encoder1,
+        clicks: 1,
+    };
+
+    // A frame split over one-packet reads, with a stray status byte in it
+    fake.inject(Fault::StallRead);
+    fake.inject(Fault::EmptyRead);
+    fake.inject(Fault::FlipBits {
+        index: 4,
+        mask: 0x80,
+    });
+    fake.send_midi(&[0xF0, 0x00, 0x20, 0x29, 0x03, 0x03, 0x12, 0x00, 0xF7]);
+    fake.send_event(turn);
+    let mut events = Vec::new();
+    while events.is_empty() {
+        events = device.read_events().await.unwrap();
+    }
+    assert_eq!(events, [turn]);
+    assert_eq!(device.stats().read_retries, 2);
+
+    fake.inject(Fault::DropReply);
+    fake.inject(Fault::DelayReply(Duration::from_millis(20)));
+    fake.send_event(turn);
+    let start = Instant::now();
+    fake.send_event(turn);
+    assert_eq!(device.read_events().await.unwrap(), [turn]);
+    assert!(start.elapsed() >= Duration::from_millis(20));
+
+    // The unit never sees the dropped LED command
+    fake.inject(Fault::DropRequest);
+    let on = AutomapCommand::ButtonLed {
+        button: Button::ButtonA1,
+        on: true,
+    };
+    device.send_command(&on).await.unwrap();
+    assert_eq!(device.leds().button(Button::ButtonA1), Some(true));
+    assert_eq!(fake.leds().button(Button::ButtonA1), Some(false));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_verify_rolls_back_a_corrupt_readback() {
+    let template: Vec<u8> = (0..HEADER_LEN).map(|i| (i % 0x7F) as u8).collect();
+    let fake = FakeZeroMkII::new().with_template(&template);
+    let mut device = open(&fake).await;
+
+    // Corrupt the last data byte of the verify read, once
+    let reply = DbSimMsg::DbData {
+        target: DbTarget::TemplateHeader,
+        cn: None,
+        offset: 8,
+        data: b"Renamed",
+    }
+    .to_bytes();
+    let tap = fake.clone();
+    let mut transfer = Transfer::new().on_progress(move |p| {
+        if p.phase == Phase::Verify && p.done == 0 {
+            tap.inject(Fault::FlipBits {
+                index: reply.len() - 2,
+                mask: 0x01,
+            });
+        }
+    });
+    let err = device
+        .write_block_verified(DbTarget::TemplateHeader, 0, 8, b"Renamed", &mut transfer)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, VerifyError::Mismatch { .. }), "{err:?}");
+    assert_eq!(fake.template(), template);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_recovers_from_unplug_mid_sysex() {
+    let fake = FakeZeroMkII::new();
+    let mut device = open(&fake).await;
+    let on = AutomapCommand::ButtonLed {
+        button: Button::ButtonA1,
+        on: true,
+    };
+    device.send_command(&on).await.unwrap();
+
+    fake.inject(Fault::UnplugDuring { packets: 1 });
+    fake.send_midi(&[0xF0, 0x00, 0x20, 0x29, 0x03, 0x03, 0x12, 0x00, 0xF7]);
+    let err = loop {
+        if let Err(e) = device.read_events().await {
+            break e;
+        }
+    };
+    assert_eq!(err.kind(), std::io::ErrorKind::ConnectionAborted);
+    assert_eq!(device.session_state(), SessionState::Detached);
+    assert!(matches!(
+        device.recover().await,
+        Err(AutomapError::NotFound)
+    ));
+
+    fake.plug_in();
+    assert!(!fake.is_online());
+    device.recover().await.unwrap();
+    assert!(fake.is_online());
+    assert_eq!(fake.leds().button(Button::ButtonA1), Some(true));
+    assert!(device.take_notices().contains(&DeviceNotice::Resynced));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_ring_mode_sent_once() {
+    let fake = FakeZeroMkII::new();
+    let mut device = open(&fake).await;
+    let sent = fake.received().len();
+    let handle = device.handle();
+    for position in [EncoderPosition::Pos2, EncoderPosition::Pos3] {
+        handle
+            .set_ring(Encoder::Encoder1, RingMode::ContinuousCw, position)
+            .await
+            .unwrap();
+    }
+    device
+        .set_ring(
+            Encoder::Encoder1,
+            RingMode::SingleLedCw,
+            EncoderPosition::Pos6,
+        )
+        .await
+        .unwrap();
+    assert_eq!(fake.received().len(), sent + 5);
+    let ring = fake.leds().ring(Encoder::Encoder1);
+    assert_eq!(ring.mode, Some(RingMode::SingleLedCw));
+    assert_eq!(ring.position, Some(EncoderPosition::Pos6));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_scenes_store_and_recall() {
+    let fake = FakeZeroMkII::new();
+    let mut device = open(&fake).await;
+    let lcd = |text: &'static str| {
+        AutomapSysEx::LcdText(vec![
+            LcdOp::Cursor {
+                col: 0,
+                line: LcdLine::LeftTop,
+            },
+            LcdOp::Text(text.as_bytes()),
+            LcdOp::End,
+        ])
+    };
+    let ring = |position| AutomapCommand::EncoderRingValue {
+        encoder: Encoder::Encoder1,
+        position,
+    };
+
+    device.send_sysex(lcd("Mixer")).await.unwrap();
+    device
+        .send_command(&ring(EncoderPosition::Pos9))
+        .await
+        .unwrap();
+    let mut mixer = Scene::new();
+    mixer.store(&device);
+
+    device.send_sysex(lcd("Synth")).await.unwrap();
+    device
+        .send_command(&ring(EncoderPosition::Pos3))
+        .await
+        .unwrap();
+    let synth = {
+        let mut scene = Scene::new();
+        scene.store(&device);
+        scene
+    };
+
+    let step = Duration::from_millis(2);
+    mixer
+        .recall(&mut device, Transition::Sweep { step })
+        .await
+        .unwrap();
+    let line = |l| String::from_utf8(fake.lcd().line(l).to_vec()).unwrap();
+    assert!(line(LcdLine::LeftTop).starts_with("Mixer"));
+    assert_eq!(
+        fake.leds().ring(Encoder::Encoder1).position,
+        Some(EncoderPosition::Pos9)
+    );
+    let ring_values = fake
+        .received()
+        .iter()
+        .filter(|m| m[..2] == [0xBF, 0x70])
+        .count();
+    // Two sets, then a step for each position from 3 to 9
+    assert_eq!(ring_values, 2 + 6);
+
+    // Nothing to send for the scene already shown
+    let sent = fake.received().len();
+    mixer.recall(&mut device, Transition::Cut).await.unwrap();
+    assert_eq!(fake.received().len(), sent);
+    synth.recall(&mut device, Transition::Cut).await.unwrap();
+    assert!(line(LcdLine::LeftTop).starts_with("Synth"));
+    assert_eq!(fake.received().len(), sent + 2);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_screensaver_wakes_on_input() {
+    let fake = FakeZeroMkII::new();
+    let mut device = open(&fake).await;
+    device
+        .send_sysex(AutomapSysEx::LcdText(vec![
+            LcdOp::Cursor {
+                col: 0,
+                line: LcdLine::LeftTop,
+            },
+            LcdOp::Text(b"Volume"),
+            LcdOp::End,
+        ]))
+        .await
+        .unwrap();
+    let on = AutomapCommand::ButtonLed {
+        button: Button::ButtonA1,
+        on: true,
+    };
+    device.send_command(&on).await.unwrap();
+
+    let mut saver = Screensaver::new(Duration::from_millis(20), SaverMode::RingChase)
+        .frame_time(Duration::from_millis(5));
+    let turn = AutomapEvent::Encoder {
+        encoder: Encoder::Encoder1,
+        clicks: 1,
+    };
+    // Someone turns a knob while the saver runs
+    fake.inject(Fault::DelayReply(Duration::from_millis(60)));
+    fake.send_event(turn);
+    // The turn only woke the surface
+    assert_eq!(saver.next_events(&mut device).await.unwrap(), []);
+    assert!(!saver.is_active());
+    let line = |l| String::from_utf8(fake.lcd().line(l).to_vec()).unwrap();
+    assert!(line(LcdLine::LeftTop).starts_with("Volume"));
+    assert_eq!(fake.leds().button(Button::ButtonA1), Some(true));
+    let chases = fake
+        .received()
+        .iter()
+        .filter(|m| m[..2] == [0xBF, 0x70])
+        .count();
+    assert!(chases >= 2, "{chases} frames");
+
+    fake.send_event(turn);
+    assert_eq!(saver.next_events(&mut device).await.unwrap(), [turn]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_midi_out_sockets() {
+    let fake = FakeZeroMkII::new();
+    let device = open(&fake).await;
+    let sent = fake.received().len();
+    device.midi_out(1).send(&[0x90, 60, 100]).await.unwrap();
+    let sysex = [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7];
+    let port = PortRoute::MidiOut2.midi_out_port().unwrap();
+    let out2 = device.handle().midi_out(port);
+    out2.send(&sysex).await.unwrap();
+    out2.send(&[0xF8]).await.unwrap();
+
+    assert_eq!(fake.cable_received(1), [0x90, 60, 100]);
+    assert_eq!(fake.cable_received(2), [&sysex[..], &[0xF8]].concat());
+    // Nothing reached the Automap port
+    assert_eq!(fake.received().len(), sent);
+
+    let config = DeviceConfig::new().midi_out_cables(4, 5);
+    let other = AutomapDevice::open_mock(&fake, &config).await.unwrap();
+    assert_eq!(other.midi_out(2).cable(), 5);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_midi_in_kept_apart_from_events() {
+    let fake = FakeZeroMkII::new().with_read_limit(2);
+    let mut device = open(&fake).await;
+    let turn = AutomapEvent::Encoder {
+        encoder: Encoder::Encoder2,
+        clicks: 1,
+    };
+    // Looks like a surface event, but arrives on a MIDI IN cable
+    fake.send_cable(1, &turn.to_bytes());
+    fake.send_cable(1, &[0xF0, 0x7E, 0x00, 0x06, 0x02, 0x00, 0xF7]);
+    fake.send_event(turn);
+
+    let mut events = Vec::new();
+    while events.is_empty() {
+        events = device.read_events().await.unwrap();
+    }
+    assert_eq!(events, [turn]);
+    let midi: Vec<_> = device
+        .take_midi_in()
+        .into_iter()
+        .map(|msg| (msg.cable, msg.bytes))
+        .collect();
+    assert_eq!(
+        midi,
+        [
+            (1, turn.to_bytes()),
+            (1, vec![0xF0, 0x7E, 0x00, 0x06, 0x02, 0x00, 0xF7])
+        ]
+    );
+    assert!(device.take_midi_in().is_empty());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_simulator_plays_and_releases_keys() {
+    let fake = FakeZeroMkII::new();
+    let device = open(&fake).await;
+    let sent = fake.received().len();
+    let sim = device
+        .simulator()
+        .hold(Duration::from_millis(1))
+        .gap(Duration::ZERO);
+    sim.play(&[&[25], &[], &[25, 29]], 200).await.unwrap();
+    sim.key_down(40, 0).await.unwrap();
+    assert_eq!(sim.held(), [40]);
+    sim.release_all().await.unwrap();
+    assert!(sim.held().is_empty());
+
+    let key = |number_1_based, velocity| {
+        DbSimMsg::Simulate(SimCmd::Key {
+            number_1_based,
+            velocity,
+        })
+        .to_bytes()
+    };
+    assert_eq!(
+        fake.received()[sent..],
+        [
+            key(25, 127),
+            key(25, 0),
+            key(25, 127),
+            key(29, 127),
+            key(25, 0),
+            key(29, 0),
+            key(40, 1),
+            key(40, 0),
+        ]
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_parameter_responses_typed_by_request() {
+    let fake = FakeZeroMkII::new();
+    let mut device = open(&fake).await;
+    device
+        .handle()
+        .send_command(&AutomapCommand::ParameterRequest {
+            request_type: ParameterRequestType::UnitProductType,
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        device.read_events().await.unwrap(),
+        [AutomapEvent::Parameter {
+            response: ParameterResponse::ProductType(ProductType::ZeroSLorZeroMKII)
+        }]
+    );
+
+    // The lock state comes back on its own CC
+    device
+        .handle()
+        .send_command(&AutomapCommand::ParameterRequest {
+            request_type: ParameterRequestType::TransportLockState,
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        device.read_events().await.unwrap(),
+        [AutomapEvent::Parameter {
+            response: ParameterResponse::TransportLock(false)
+        }]
+    );
+
+    // Not asked for, so left as it came
+    let unsolicited = AutomapEvent::ParameterResponse { response: 1 };
+    fake.send_event(unsolicited);
+    assert_eq!(device.read_events().await.unwrap(), [unsolicited]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_keep_alive_answered_while_awaiting_a_reply() {
+    let fake = FakeZeroMkII::new();
+    let config = DeviceConfig::new()
+        .auto_online(true)
+        .keep_alive(Duration::from_millis(20));
+    let mut device = AutomapDevice::open_mock(&fake, &config).await.unwrap();
+    device.take_notices();
+    // Idle, so the read sends a keep-alive; the ping then reads its echo first
+    assert!(device.read_events().await.unwrap().is_empty());
+    device.ping().await.unwrap();
+    assert!(device.read_events().await.unwrap().is_empty());
+
+    assert!(device.read_events().await.unwrap().is_empty());
+    assert!(
+        !device
+            .take_notices()
+            .contains(&DeviceNotice::KeepAliveMissed)
+    );
+    assert_eq!(device.session_state(), SessionState::Online);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_recover_goes_back_online_by_hand() {
+    let fake = FakeZeroMkII::new();
+    let config = DeviceConfig::new().handshake(false);
+    let mut device = AutomapDevice::open_mock(&fake, &config).await.unwrap();
+    device.enter_automap_mode().await.unwrap();
+    device
+        .send_sysex(AutomapSysEx::LcdText(vec![
+            LcdOp::Text(b"Back"),
+            LcdOp::End,
+        ]))
+        .await
+        .unwrap();
+
+    fake.unplug();
+    while device.read_events().await.is_ok() {}
+    fake.plug_in();
+    device.recover().await.unwrap();
+    assert!(fake.is_online());
+    assert_eq!(device.session_state(), SessionState::Online);
+    assert!(fake.lcd().line(LcdLine::LeftTop).starts_with(b"Back"));
+}